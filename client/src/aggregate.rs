@@ -0,0 +1,414 @@
+//! Filling in papers' metadata from catalogues besides Semantic Scholar,
+//! matched by DOI, or by dblp key for dblp.
+//!
+//! Semantic Scholar still finds the papers and their citations; the others
+//! only contribute titles, URLs, years, and venues.  For each paper, the
+//! most complete record wins, and any fields it's missing are taken from
+//! the next most complete, ties going to whichever catalogue was listed
+//! first.  Where each field came from is kept in [`Graph::provenance`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tokio::task::JoinSet;
+
+use crate::dblp;
+use crate::graph::Graph;
+use crate::semantic_scholar::{normalize_doi, Error, ProtoPaper};
+
+const OPENALEX_URI: &str = "https://api.openalex.org/works";
+const CROSSREF_URI: &str = "https://api.crossref.org/works";
+/// How many DOIs are looked up per request; both APIs cap their filters
+/// around here.
+const DOIS_PER_REQUEST: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    SemanticScholar,
+    OpenAlex,
+    Crossref,
+    Dblp,
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "s2" | "semanticscholar" => Ok(Provider::SemanticScholar),
+            "openalex" => Ok(Provider::OpenAlex),
+            "crossref" => Ok(Provider::Crossref),
+            "dblp" => Ok(Provider::Dblp),
+            other => Err(format!(
+                "unknown source {other:?}; try s2, openalex, crossref, or dblp"
+            )),
+        }
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provider::SemanticScholar => write!(f, "s2"),
+            Provider::OpenAlex => write!(f, "openalex"),
+            Provider::Crossref => write!(f, "crossref"),
+            Provider::Dblp => write!(f, "dblp"),
+        }
+    }
+}
+
+/// Parse a comma-separated list of providers, putting Semantic Scholar
+/// last if it isn't listed, since it's always needed for the citations.
+pub fn parse_providers(s: &str) -> Result<Vec<Provider>, String> {
+    let mut providers = Vec::new();
+    for provider in s.split(',').filter(|provider| !provider.trim().is_empty()) {
+        let provider = provider.parse()?;
+        if !providers.contains(&provider) {
+            providers.push(provider);
+        }
+    }
+    if !providers.contains(&Provider::SemanticScholar) {
+        providers.push(Provider::SemanticScholar);
+    }
+    Ok(providers)
+}
+
+/// One catalogue's metadata for a paper.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Record {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub year: Option<u32>,
+    pub venue: Option<String>,
+}
+
+impl Record {
+    fn completeness(&self) -> usize {
+        [
+            self.title.is_some(),
+            self.url.is_some(),
+            self.year.is_some(),
+            self.venue.is_some(),
+        ]
+        .into_iter()
+        .filter(|&known| known)
+        .count()
+    }
+}
+
+impl From<&ProtoPaper> for Record {
+    fn from(paper: &ProtoPaper) -> Self {
+        Self {
+            title: Some(paper.title().to_string()).filter(|title| !title.is_empty()),
+            url: paper.url().filter(|url| !url.is_empty()).map(String::from),
+            year: paper.year(),
+            venue: paper.venue().map(String::from),
+        }
+    }
+}
+
+/// Merge `records`, given in the order their providers were listed, into
+/// one, along with which provider each field came from.
+pub fn merge(records: &[(Provider, Record)]) -> (Record, BTreeMap<String, String>) {
+    let mut ranked: Vec<&(Provider, Record)> = records.iter().collect();
+    // stable, so ties keep the listed order
+    ranked.sort_by_key(|(_provider, record)| std::cmp::Reverse(record.completeness()));
+    let mut merged = Record::default();
+    let mut provenance = BTreeMap::new();
+    fn pick<T: Clone>(
+        field: &str,
+        ranked: &[&(Provider, Record)],
+        get: impl Fn(&Record) -> &Option<T>,
+        provenance: &mut BTreeMap<String, String>,
+    ) -> Option<T> {
+        let (provider, value) = ranked
+            .iter()
+            .find_map(|(provider, record)| get(record).clone().map(|value| (provider, value)))?;
+        provenance.insert(field.into(), provider.to_string());
+        Some(value)
+    }
+    merged.title = pick("title", &ranked, |record| &record.title, &mut provenance);
+    merged.url = pick("url", &ranked, |record| &record.url, &mut provenance);
+    merged.year = pick("year", &ranked, |record| &record.year, &mut provenance);
+    merged.venue = pick("venue", &ranked, |record| &record.venue, &mut provenance);
+    (merged, provenance)
+}
+
+#[derive(Deserialize)]
+struct OpenAlexWorks {
+    results: Vec<OpenAlexWork>,
+}
+
+#[derive(Deserialize)]
+struct OpenAlexWork {
+    doi: Option<String>,
+    title: Option<String>,
+    publication_year: Option<u32>,
+    primary_location: Option<OpenAlexLocation>,
+}
+
+#[derive(Deserialize)]
+struct OpenAlexLocation {
+    landing_page_url: Option<String>,
+    source: Option<OpenAlexSource>,
+}
+
+#[derive(Deserialize)]
+struct OpenAlexSource {
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrossrefResponse {
+    message: CrossrefItems,
+}
+
+#[derive(Deserialize)]
+struct CrossrefItems {
+    items: Vec<CrossrefWork>,
+}
+
+#[derive(Deserialize)]
+struct CrossrefWork {
+    #[serde(rename = "DOI")]
+    doi: String,
+    #[serde(default)]
+    title: Vec<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    #[serde(rename = "container-title", default)]
+    container_title: Vec<String>,
+    issued: Option<CrossrefDate>,
+}
+
+#[derive(Deserialize)]
+struct CrossrefDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<Option<u32>>>,
+}
+
+/// What `provider` looks `paper` up by.
+fn key(provider: Provider, paper: &ProtoPaper) -> Option<String> {
+    match provider {
+        Provider::SemanticScholar => None,
+        Provider::OpenAlex | Provider::Crossref => paper.external_id("DOI").map(normalize_doi),
+        Provider::Dblp => paper.external_id("DBLP").map(String::from),
+    }
+}
+
+/// Look up `keys`, as given by [`key`], on `provider`.
+async fn get_records(
+    client: &reqwest::Client,
+    provider: Provider,
+    keys: &[String],
+) -> Result<HashMap<String, Record>, Error> {
+    if provider == Provider::Dblp {
+        return dblp::get_records(client, keys.to_vec()).await;
+    }
+    let mut requests = JoinSet::new();
+    for chunk in keys.chunks(DOIS_PER_REQUEST) {
+        let request = match provider {
+            Provider::SemanticScholar | Provider::Dblp => continue,
+            Provider::OpenAlex => client.get(OPENALEX_URI).query(&[
+                ("filter", format!("doi:{}", chunk.join("|"))),
+                ("per-page", DOIS_PER_REQUEST.to_string()),
+            ]),
+            Provider::Crossref => client.get(CROSSREF_URI).query(&[
+                (
+                    "filter",
+                    chunk
+                        .iter()
+                        .map(|doi| format!("doi:{doi}"))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                ("rows", DOIS_PER_REQUEST.to_string()),
+            ]),
+        }
+        .send();
+        requests.spawn(async move {
+            let text = request
+                .await
+                .map_err(Error::Request)?
+                .text()
+                .await
+                .map_err(Error::Request)?;
+            parse_records(provider, text)
+        });
+    }
+    let mut records = HashMap::new();
+    while let Some(batch) = requests.join_next().await {
+        records.extend(batch.map_err(Error::Join)??);
+    }
+    Ok(records)
+}
+
+fn parse_records(provider: Provider, text: String) -> Result<Vec<(String, Record)>, Error> {
+    Ok(match provider {
+        Provider::SemanticScholar | Provider::Dblp => Vec::new(),
+        Provider::OpenAlex => serde_json::from_str::<OpenAlexWorks>(&text)
+            .map_err(|err| Error::Serialization(err, text))?
+            .results
+            .into_iter()
+            .filter_map(|work| {
+                let doi = work.doi?;
+                let doi = doi.strip_prefix("https://doi.org/").unwrap_or(&doi);
+                let location = work.primary_location;
+                let (url, venue) = location.map_or((None, None), |location| {
+                    (
+                        location.landing_page_url,
+                        location.source.and_then(|source| source.display_name),
+                    )
+                });
+                Some((
+                    normalize_doi(doi),
+                    Record {
+                        title: work.title,
+                        url,
+                        year: work.publication_year,
+                        venue,
+                    },
+                ))
+            })
+            .collect(),
+        Provider::Crossref => serde_json::from_str::<CrossrefResponse>(&text)
+            .map_err(|err| Error::Serialization(err, text))?
+            .message
+            .items
+            .into_iter()
+            .map(|work| {
+                (
+                    normalize_doi(&work.doi),
+                    Record {
+                        title: work.title.into_iter().next(),
+                        url: work.url,
+                        year: work
+                            .issued
+                            .and_then(|issued| issued.date_parts.into_iter().next())
+                            .and_then(|parts| parts.into_iter().next().flatten()),
+                        venue: work.container_title.into_iter().next(),
+                    },
+                )
+            })
+            .collect(),
+    })
+}
+
+/// Fill in the metadata of the papers in `graph` from each of `providers`,
+/// recording where it came from.
+///
+/// Nothing is fetched if Semantic Scholar is the only provider.
+pub async fn enrich(graph: &mut Graph, providers: &[Provider]) -> Result<(), Error> {
+    if providers
+        .iter()
+        .all(|&provider| provider == Provider::SemanticScholar)
+    {
+        return Ok(());
+    }
+    let client = reqwest::Client::new();
+    let mut by_provider = HashMap::new();
+    for &provider in providers {
+        if provider == Provider::SemanticScholar {
+            continue;
+        }
+        let mut keys: Vec<String> = graph
+            .citations
+            .papers()
+            .filter_map(|paper| key(provider, paper))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        eprintln!("looking up {} papers on {provider}", keys.len());
+        by_provider.insert(provider, get_records(&client, provider, &keys).await?);
+    }
+
+    let papers = graph.citations.take_papers();
+    for paper in papers {
+        let Some(id) = paper.id() else {
+            graph.citations.insert_paper(paper);
+            continue;
+        };
+        let records: Vec<(Provider, Record)> = providers
+            .iter()
+            .filter_map(|&provider| match provider {
+                Provider::SemanticScholar => Some((provider, Record::from(&paper))),
+                _ => Some((
+                    provider,
+                    by_provider
+                        .get(&provider)?
+                        .get(&key(provider, &paper)?)?
+                        .clone(),
+                )),
+            })
+            .collect();
+        if records.len() < 2 {
+            graph.citations.insert_paper(paper);
+            continue;
+        }
+        let (merged, provenance) = merge(&records);
+        graph.provenance.insert(id.to_string(), provenance);
+        let title = merged.title.unwrap_or_else(|| paper.title().to_string());
+        graph.citations.insert_paper(
+            paper
+                .with_title(title)
+                .with_url(merged.url)
+                .with_year(merged.year)
+                .with_venue(merged.venue),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_most_complete_record_wins_and_gaps_are_filled() {
+        let s2 = Record {
+            title: Some("Attention is all you need".into()),
+            url: Some("https://www.semanticscholar.org/paper/1".into()),
+            ..Default::default()
+        };
+        let openalex = Record {
+            title: Some("Attention Is All You Need".into()),
+            year: Some(2017),
+            venue: Some("NeurIPS".into()),
+            ..Default::default()
+        };
+        let crossref = Record {
+            title: Some("Attention is All you Need".into()),
+            year: Some(2018),
+            venue: Some("Advances in Neural Information Processing Systems".into()),
+            ..Default::default()
+        };
+        let providers = parse_providers("crossref, openalex").unwrap();
+        assert_eq!(
+            providers,
+            [
+                Provider::Crossref,
+                Provider::OpenAlex,
+                Provider::SemanticScholar
+            ]
+        );
+        let records: Vec<(Provider, Record)> = providers
+            .into_iter()
+            .zip([crossref.clone(), openalex, s2.clone()])
+            .collect();
+
+        let (merged, provenance) = merge(&records);
+        assert_eq!(
+            merged,
+            Record {
+                url: s2.url,
+                ..crossref
+            }
+        );
+        assert_eq!(provenance["title"], "crossref");
+        assert_eq!(provenance["url"], "s2");
+        assert_eq!(provenance["year"], "crossref");
+        assert!(parse_providers("s2,scopus").is_err());
+    }
+}
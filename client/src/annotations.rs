@@ -0,0 +1,129 @@
+//! Notes, tags, and colors kept beside a graph rather than in it, so
+//! they survive building the graph again.
+//!
+//! The file maps Semantic Scholar paper ids, or DOIs, to annotations, in
+//! TOML or, for `.yaml` and `.yml` files, YAML:
+//!
+//! ```toml
+//! ["DOI:10.1145/3313831.3376540"]
+//! note = "the survey to start from"
+//! tags = ["survey", "hci"]
+//! color = "#ffcc00"
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+pub enum Error {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => std::fmt::Debug::fmt(err, f),
+            Error::Toml(err) => std::fmt::Display::fmt(err, f),
+            Error::Yaml(err) => std::fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Toml(err) => Some(err),
+            Error::Yaml(err) => Some(err),
+        }
+    }
+}
+
+/// What the user has to say about one paper.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Annotation {
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Any color Graphviz and browsers both understand, e.g. `#ffcc00`
+    /// or `gold`.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl Annotation {
+    /// Add `other`'s tags to these, and take its note and color where it
+    /// has them.
+    pub fn merge(&mut self, other: &Annotation) {
+        if other.note.is_some() {
+            self.note.clone_from(&other.note);
+        }
+        for tag in &other.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        if other.color.is_some() {
+            self.color.clone_from(&other.color);
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|ours| ours.eq_ignore_ascii_case(tag))
+    }
+}
+
+/// Annotations by the id they were written under.
+pub type Annotations = BTreeMap<String, Annotation>;
+
+pub fn load(path: &Path) -> Result<Annotations, Error> {
+    let src = std::fs::read_to_string(path).map_err(Error::Io)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&src).map_err(Error::Yaml),
+        _ => toml::from_str(&src).map_err(Error::Toml),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_yaml_say_the_same() {
+        let toml: Annotations = toml::from_str(
+            r##"
+            ["DOI:10.1000/a"]
+            note = "start here"
+            tags = ["survey"]
+            color = "#ffcc00"
+
+            [b]
+            tags = ["method"]
+            "##,
+        )
+        .unwrap();
+        let yaml: Annotations = serde_yaml::from_str(
+            r##"
+            "DOI:10.1000/a":
+              note: start here
+              tags: [survey]
+              color: "#ffcc00"
+            b:
+              tags: [method]
+            "##,
+        )
+        .unwrap();
+        assert_eq!(toml, yaml);
+        assert_eq!(toml["b"].note, None);
+    }
+}
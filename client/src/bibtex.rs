@@ -0,0 +1,137 @@
+//! Writing papers found in a graph back out as BibTeX, to pull them into
+//! a bibliography.
+
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::semantic_scholar::ProtoPaper;
+
+/// Title words too common to tell papers apart by in a key.
+const STOP_WORDS: &[&str] = &["a", "an", "the", "on", "of", "in", "for", "and", "to"];
+
+/// A key in the usual `surname` `year` `first word of title` shape, e.g.
+/// `vaswani2017attention`, ASCII only so every BibTeX accepts it.
+fn key(paper: &ProtoPaper) -> String {
+    let ascii = |word: &str| -> String {
+        word.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let surname = paper
+        .authors()
+        .first()
+        .and_then(|author| author.name().split_whitespace().last())
+        .map(ascii)
+        .unwrap_or_default();
+    let word = paper
+        .title()
+        .split_whitespace()
+        .map(ascii)
+        .find(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+        .unwrap_or_default();
+    let year = paper
+        .year()
+        .map(|year| year.to_string())
+        .unwrap_or_default();
+    let key = format!("{surname}{year}{word}");
+    if key.is_empty() {
+        "paper".into()
+    } else {
+        key
+    }
+}
+
+/// Escape the characters BibTeX or LaTeX would otherwise act on.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '&' | '%' | '$' | '#' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Write each of `papers` as a BibTeX entry, giving papers that would
+/// share a key a letter to tell them apart.
+pub fn write<'a>(
+    out: &mut impl Write,
+    papers: impl IntoIterator<Item = &'a ProtoPaper>,
+) -> std::io::Result<()> {
+    let mut keys = HashSet::new();
+    for paper in papers {
+        let base = key(paper);
+        let key = std::iter::once(base.clone())
+            .chain(('b'..='z').map(|suffix| format!("{base}{suffix}")))
+            .find(|key| !keys.contains(key))
+            .unwrap_or(base);
+        keys.insert(key.clone());
+
+        let kind = if paper.venue().is_some() {
+            "article"
+        } else {
+            "misc"
+        };
+        writeln!(out, "@{kind}{{{key},")?;
+        writeln!(out, "  title = {{{{{}}}}},", escape(paper.title()))?;
+        if !paper.authors().is_empty() {
+            let authors: Vec<String> = paper
+                .authors()
+                .iter()
+                .map(|author| escape(author.name()))
+                .collect();
+            writeln!(out, "  author = {{{}}},", authors.join(" and "))?;
+        }
+        if let Some(venue) = paper.venue() {
+            writeln!(out, "  journal = {{{}}},", escape(venue))?;
+        }
+        if let Some(year) = paper.year() {
+            writeln!(out, "  year = {{{year}}},")?;
+        }
+        if let Some(doi) = paper.external_id("DOI") {
+            writeln!(out, "  doi = {{{doi}}},")?;
+        }
+        if let Some(arxiv) = paper.external_id("ArXiv") {
+            writeln!(out, "  eprint = {{{arxiv}}},")?;
+            writeln!(out, "  archiveprefix = {{arXiv}},")?;
+        }
+        if let Some(url) = paper.url().filter(|url| !url.is_empty()) {
+            writeln!(out, "  url = {{{url}}},")?;
+        }
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use endpoints::Author;
+
+    #[test]
+    fn entries_round_trip_through_the_importer() {
+        let paper = ProtoPaper::new("a", "The Attention & Everything")
+            .with_year(Some(2017))
+            .with_url(Some("https://example.com/a".into()))
+            .with_authors(vec![
+                Author::new("Ashish Vaswani"),
+                Author::new("Noam Shazeer"),
+            ]);
+        let twin = ProtoPaper::new("b", "Attention")
+            .with_year(Some(2017))
+            .with_url(Some("https://example.com/b".into()))
+            .with_authors(vec![Author::new("A. Vaswani")]);
+        let mut bib = Vec::new();
+        write(&mut bib, [&paper, &twin]).unwrap();
+        let bib = String::from_utf8(bib).unwrap();
+        assert!(bib.contains("@misc{vaswani2017attention,"), "{bib}");
+        assert!(bib.contains("@misc{vaswani2017attentionb,"), "{bib}");
+        assert!(bib.contains(r"title = {{The Attention \& Everything}},"));
+        assert!(bib.contains("author = {Ashish Vaswani and Noam Shazeer},"));
+        let ids = crate::id_import::try_from_bibtex(bib).unwrap();
+        assert_eq!(ids.len(), 2);
+    }
+}
@@ -0,0 +1,13 @@
+//! Books, which Semantic Scholar mostly doesn't know, looked up by ISBN
+//! on Google Books instead.
+
+#[cfg(feature = "native")]
+mod google;
+#[cfg(feature = "native")]
+pub use google::GoogleBooks;
+
+pub struct Book {
+    pub isbn: String,
+    pub title: String,
+    pub url: Option<String>,
+}
@@ -0,0 +1,78 @@
+//! Looking books up on Google Books over HTTP.
+
+use serde::Deserialize;
+use tokio::task::JoinSet;
+
+use super::Book;
+use crate::semantic_scholar::Error;
+
+const GOOGLE_BOOKS_URI: &str = "https://www.googleapis.com/books/v1/volumes";
+
+#[derive(Default)]
+pub struct GoogleBooks {
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct Volumes {
+    #[serde(default)]
+    items: Vec<Volume>,
+}
+
+#[derive(Deserialize)]
+struct Volume {
+    #[serde(rename = "volumeInfo")]
+    volume_info: VolumeInfo,
+}
+
+#[derive(Deserialize)]
+struct VolumeInfo {
+    title: String,
+    #[serde(rename = "infoLink")]
+    info_link: Option<String>,
+}
+
+impl GoogleBooks {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Look up each ISBN, skipping any Google Books doesn't have.
+    pub async fn get_books(&self, isbns: Vec<String>) -> Result<Vec<Book>, Error> {
+        let mut requests = JoinSet::new();
+        for isbn in isbns {
+            let request = self
+                .client
+                .get(GOOGLE_BOOKS_URI)
+                .query(&[("q", format!("isbn:{isbn}"))])
+                .send();
+            requests.spawn(async move {
+                let volumes_txt = request
+                    .await
+                    .map_err(Error::Request)?
+                    .text()
+                    .await
+                    .map_err(Error::Request)?;
+                let volumes = serde_json::from_str::<Volumes>(volumes_txt.as_ref())
+                    .map_err(|err| Error::Serialization(err, volumes_txt))?;
+                Ok::<_, Error>((isbn, volumes))
+            });
+        }
+        let mut books = Vec::new();
+        while let Some(volumes) = requests.join_next().await {
+            let (isbn, volumes) = volumes.map_err(Error::Join)??;
+            let Some(volume) = volumes.items.into_iter().next() else {
+                eprintln!("no book found for ISBN {isbn}");
+                continue;
+            };
+            books.push(Book {
+                isbn,
+                title: volume.volume_info.title,
+                url: volume.volume_info.info_link,
+            });
+        }
+        Ok(books)
+    }
+}
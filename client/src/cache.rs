@@ -0,0 +1,131 @@
+//! Papers already fetched, kept on disk so later builds needn't ask
+//! Semantic Scholar for them again.
+//!
+//! Each paper is a JSON file named for an id it was requested by, and is
+//! fetched again once older than [`MAX_AGE`].
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::semantic_scholar::Paper;
+
+/// How long a cached paper is trusted; citations keep accruing.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// How much is cached.
+pub struct Stats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file for `id`, with anything that can't be in a file name,
+    /// like the slashes in DOIs, escaped.
+    fn path(&self, id: &str) -> PathBuf {
+        let mut name = String::with_capacity(id.len());
+        for c in id.chars() {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+                name.push(c);
+            } else {
+                name.extend(format!("_{:02x}", c as u32).chars());
+            }
+        }
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// The paper requested as `id`, if it's cached and fresh.
+    pub fn get(&self, id: &str) -> Option<Paper> {
+        self.get_within(id, MAX_AGE)
+    }
+
+    /// The paper requested as `id`, if it's cached and no older than
+    /// `max_age`.
+    pub fn get_within(&self, id: &str, max_age: Duration) -> Option<Paper> {
+        let path = self.path(id);
+        let age = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > max_age {
+            return None;
+        }
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    /// Cache `paper` under each of `ids`.
+    pub fn put(&self, ids: &[&str], paper: &Paper) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_vec(paper)?;
+        for id in ids {
+            std::fs::write(self.path(id), &json)?;
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> std::io::Result<Vec<std::fs::DirEntry>> {
+        match std::fs::read_dir(&self.dir) {
+            Ok(entries) => Ok(entries
+                .collect::<std::io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+                .collect()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn stats(&self) -> std::io::Result<Stats> {
+        let entries = self.entries()?;
+        let mut bytes = 0;
+        for entry in &entries {
+            bytes += entry.metadata()?.len();
+        }
+        Ok(Stats {
+            entries: entries.len(),
+            bytes,
+        })
+    }
+
+    /// Remove every cached paper, returning how many there were.
+    pub fn clear(&self) -> std::io::Result<usize> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            std::fs::remove_file(entry.path())?;
+        }
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn papers_round_trip_under_each_id() {
+        let dir = std::env::temp_dir().join(format!("cache-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let paper = Paper::new("a", "A", Vec::new());
+        cache.put(&["DOI:10.1000/A", "a"], &paper).unwrap();
+
+        assert!(cache.get("DOI:10.1000/A") == Some(paper.clone()));
+        assert!(cache.get("a") == Some(paper));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.stats().unwrap().entries, 2);
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert!(cache.get("a").is_none());
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
@@ -0,0 +1,448 @@
+//! Papers and the citations between them, kept as a petgraph graph with a
+//! node for each id, so passes over it follow edges rather than scanning
+//! and comparing every citation's ids.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use petgraph::algo::tarjan_scc;
+use petgraph::stable_graph::{NodeIndex, StableDiGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use petgraph::Direction;
+
+use crate::graph::Reference;
+use crate::intern::Id;
+use crate::semantic_scholar::ProtoPaper;
+
+/// An id in the graph, which may only be cited rather than a paper in it.
+#[derive(Debug, Clone)]
+struct Node {
+    id: Id,
+    /// What's known of the paper, usually once, but more than once if it
+    /// was described differently in different places.
+    papers: Vec<ProtoPaper>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CitationGraph {
+    graph: StableDiGraph<Node, Reference>,
+    index: HashMap<Id, NodeIndex>,
+}
+
+impl CitationGraph {
+    pub fn new(
+        papers: impl IntoIterator<Item = ProtoPaper>,
+        references: impl IntoIterator<Item = Reference>,
+    ) -> Self {
+        let mut citations = CitationGraph::default();
+        citations.extend(papers);
+        citations.extend(references);
+        citations
+    }
+
+    /// The node for `id`, added if there isn't one.
+    fn node(&mut self, id: &str) -> NodeIndex {
+        if let Some(&node) = self.index.get(id) {
+            return node;
+        }
+        let id: Id = id.into();
+        let node = self.graph.add_node(Node {
+            id: id.clone(),
+            papers: Vec::new(),
+        });
+        self.index.insert(id, node);
+        node
+    }
+
+    /// Drop `node` if it's no longer a paper or cited.
+    fn forget_if_unused(&mut self, node: NodeIndex) {
+        let unused = self.graph[node].papers.is_empty()
+            && self.graph.neighbors_undirected(node).next().is_none();
+        if unused {
+            if let Some(removed) = self.graph.remove_node(node) {
+                self.index.remove(&removed.id);
+            }
+        }
+    }
+
+    fn forget_unused(&mut self) {
+        let nodes: Vec<NodeIndex> = self.graph.node_indices().collect();
+        for node in nodes {
+            self.forget_if_unused(node);
+        }
+    }
+
+    /// Add `paper`, returning whether it wasn't already there.  Papers
+    /// without an id can't be cited or drawn, so they're left out.
+    pub fn insert_paper(&mut self, paper: ProtoPaper) -> bool {
+        let Some(id) = paper.id() else {
+            return false;
+        };
+        let node = self.node(id);
+        let papers = &mut self.graph[node].papers;
+        if papers.contains(&paper) {
+            return false;
+        }
+        papers.push(paper);
+        true
+    }
+
+    pub fn papers(&self) -> impl Iterator<Item = &ProtoPaper> {
+        self.graph.node_weights().flat_map(|node| &node.papers)
+    }
+
+    /// The ids of the papers, each once.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.graph
+            .node_weights()
+            .filter(|node| !node.papers.is_empty())
+            .map(|node| &*node.id)
+    }
+
+    pub fn paper_count(&self) -> usize {
+        self.graph
+            .node_weights()
+            .map(|node| node.papers.len())
+            .sum()
+    }
+
+    pub fn contains_paper(&self, id: &str) -> bool {
+        self.index
+            .get(id)
+            .is_some_and(|&node| !self.graph[node].papers.is_empty())
+    }
+
+    /// Keep only the papers `keep` picks.  Their citations stay until
+    /// dropped themselves.
+    pub fn retain_papers(&mut self, mut keep: impl FnMut(&ProtoPaper) -> bool) {
+        let nodes: Vec<NodeIndex> = self.graph.node_indices().collect();
+        for node in nodes {
+            self.graph[node].papers.retain(&mut keep);
+            self.forget_if_unused(node);
+        }
+    }
+
+    /// Remove every paper, leaving the citations.
+    pub fn take_papers(&mut self) -> Vec<ProtoPaper> {
+        let papers = self
+            .graph
+            .node_weights_mut()
+            .flat_map(|node| std::mem::take(&mut node.papers))
+            .collect();
+        self.forget_unused();
+        papers
+    }
+
+    /// Add `reference`, returning whether it wasn't already there.  It
+    /// shares its ids with the nodes it joins.
+    pub fn insert_reference(&mut self, mut reference: Reference) -> bool {
+        let citing = self.node(&reference.referencer);
+        let cited = self.node(&reference.referencee);
+        if self.graph.find_edge(citing, cited).is_some() {
+            return false;
+        }
+        reference.referencer = self.graph[citing].id.clone();
+        reference.referencee = self.graph[cited].id.clone();
+        self.graph.add_edge(citing, cited, reference);
+        true
+    }
+
+    pub fn references(&self) -> impl Iterator<Item = &Reference> {
+        self.graph.edge_weights()
+    }
+
+    pub fn reference_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    pub fn contains_reference(&self, reference: &Reference) -> bool {
+        match (
+            self.index.get(&reference.referencer),
+            self.index.get(&reference.referencee),
+        ) {
+            (Some(&citing), Some(&cited)) => self.graph.find_edge(citing, cited).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Keep only the citations `keep` picks.
+    pub fn retain_references(&mut self, mut keep: impl FnMut(&Reference) -> bool) {
+        self.graph.retain_edges(|graph, edge| keep(&graph[edge]));
+        self.forget_unused();
+    }
+
+    /// Replace each citation with what `map` makes of it, if anything.
+    pub fn map_references(&mut self, mut map: impl FnMut(Reference) -> Option<Reference>) {
+        let edges: Vec<_> = self.graph.edge_indices().collect();
+        let references: Vec<Reference> = edges
+            .into_iter()
+            .filter_map(|edge| self.graph.remove_edge(edge))
+            .collect();
+        for reference in references {
+            if let Some(reference) = map(reference) {
+                self.insert_reference(reference);
+            }
+        }
+        self.forget_unused();
+    }
+
+    /// The same papers, with every citation turned around.
+    pub fn reversed(mut self) -> Self {
+        self.map_references(|reference| {
+            Some(Reference {
+                referencer: reference.referencee,
+                referencee: reference.referencer,
+            })
+        });
+        self
+    }
+
+    /// How many papers in the graph cite `id`.
+    pub fn cited_by_count(&self, id: &str) -> usize {
+        self.index.get(id).map_or(0, |&node| {
+            self.graph
+                .neighbors_directed(node, Direction::Incoming)
+                .count()
+        })
+    }
+
+    /// The paper with `id`, as first described if it was more than once.
+    pub fn paper(&self, id: &str) -> Option<&ProtoPaper> {
+        self.index
+            .get(id)
+            .and_then(|&node| self.graph[node].papers.first())
+    }
+
+    /// The ids `id` cites, whether or not they're papers in the graph.
+    pub fn cited(&self, id: &str) -> impl Iterator<Item = &str> {
+        let node = self.index.get(id).copied();
+        node.into_iter().flat_map(move |node| {
+            self.graph
+                .neighbors_directed(node, Direction::Outgoing)
+                .map(|other| &*self.graph[other].id)
+        })
+    }
+
+    /// The ids `id` cites or is cited by, other than itself.
+    pub fn neighbors(&self, id: &str) -> impl Iterator<Item = &str> {
+        let node = self.index.get(id).copied();
+        node.into_iter().flat_map(move |node| {
+            self.graph
+                .edges_directed(node, Direction::Outgoing)
+                .map(|edge| edge.target())
+                .chain(
+                    self.graph
+                        .edges_directed(node, Direction::Incoming)
+                        .map(|edge| edge.source()),
+                )
+                .filter(move |&other| other != node)
+                .map(|other| &*self.graph[other].id)
+        })
+    }
+
+    /// The papers in an order to read them: each after the papers it
+    /// cites, oldest and then most cited first where that leaves a choice.
+    /// Papers citing each other in a cycle are read oldest first.
+    pub fn reading_order(&self) -> Vec<&ProtoPaper> {
+        // oldest, then most cited, then by id; papers without a year last
+        let key = |node: NodeIndex| {
+            let paper = self.graph[node].papers.first();
+            (
+                paper.and_then(ProtoPaper::year).unwrap_or(u32::MAX),
+                Reverse(
+                    self.graph
+                        .neighbors_directed(node, Direction::Incoming)
+                        .count(),
+                ),
+                &self.graph[node].id,
+            )
+        };
+        // strongly connected components, each a cycle or a lone paper
+        let mut components = tarjan_scc(&self.graph);
+        for component in &mut components {
+            component.sort_by_cached_key(|&node| key(node));
+        }
+        let mut component_of = HashMap::<NodeIndex, usize>::new();
+        for (i, component) in components.iter().enumerate() {
+            component_of.extend(component.iter().map(|&node| (node, i)));
+        }
+        // how many other components each cites that haven't been read
+        let mut unread = vec![HashSet::<usize>::new(); components.len()];
+        for edge in self.graph.edge_references() {
+            let (citing, cited) = (component_of[&edge.source()], component_of[&edge.target()]);
+            if citing != cited {
+                unread[citing].insert(cited);
+            }
+        }
+        let mut unread: Vec<usize> = unread.iter().map(HashSet::len).collect();
+        let mut ready: BinaryHeap<Reverse<(_, usize)>> = unread
+            .iter()
+            .enumerate()
+            .filter(|(_i, &count)| count == 0)
+            .map(|(i, _count)| Reverse((key(components[i][0]), i)))
+            .collect();
+        let mut order = Vec::new();
+        while let Some(Reverse((_key, i))) = ready.pop() {
+            order.extend(
+                components[i]
+                    .iter()
+                    .filter_map(|&node| self.graph[node].papers.first()),
+            );
+            let citing: HashSet<usize> = components[i]
+                .iter()
+                .flat_map(|&node| self.graph.neighbors_directed(node, Direction::Incoming))
+                .map(|node| component_of[&node])
+                .filter(|&j| j != i)
+                .collect();
+            for j in citing {
+                unread[j] -= 1;
+                if unread[j] == 0 {
+                    ready.push(Reverse((key(components[j][0]), j)));
+                }
+            }
+        }
+        order
+    }
+
+    /// How many citations, followed either way, each id is from the
+    /// nearest of `from`.  Ids that can't be reached are left out.
+    pub fn distances<'a>(&self, from: impl IntoIterator<Item = &'a str>) -> HashMap<String, usize> {
+        let mut distances = HashMap::<String, usize>::new();
+        let mut queue = VecDeque::new();
+        for id in from {
+            if self.index.contains_key(id) && !distances.contains_key(id) {
+                distances.insert(id.to_string(), 0);
+                queue.push_back(id);
+            }
+        }
+        while let Some(id) = queue.pop_front() {
+            let distance = distances[id] + 1;
+            for neighbor in self.neighbors(id) {
+                if !distances.contains_key(neighbor) {
+                    distances.insert(neighbor.to_string(), distance);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Repeatedly drop papers with at most `max_degree` citations in each
+    /// direction, for up to `passes` passes, and the citations left
+    /// dangling by that.  Papers `max_degree` gives no limit for are kept.
+    pub fn prune(&mut self, passes: usize, max_degree: impl Fn(&str) -> Option<usize>) {
+        let degree = |graph: &StableDiGraph<Node, Reference>, node, direction| {
+            graph.neighbors_directed(node, direction).count()
+        };
+        for _ in 0..passes {
+            let dropped: Vec<NodeIndex> = self
+                .graph
+                .node_indices()
+                .filter(|&node| {
+                    let node_weight = &self.graph[node];
+                    node_weight.papers.is_empty()
+                        || max_degree(&node_weight.id).is_some_and(|max_degree| {
+                            degree(&self.graph, node, Direction::Incoming) <= max_degree
+                                && degree(&self.graph, node, Direction::Outgoing) <= max_degree
+                        })
+                })
+                .collect();
+            if dropped.is_empty() {
+                break;
+            }
+            for node in dropped {
+                if let Some(removed) = self.graph.remove_node(node) {
+                    self.index.remove(&removed.id);
+                }
+            }
+        }
+    }
+}
+
+impl Extend<ProtoPaper> for CitationGraph {
+    fn extend<T: IntoIterator<Item = ProtoPaper>>(&mut self, papers: T) {
+        for paper in papers {
+            self.insert_paper(paper);
+        }
+    }
+}
+
+impl Extend<Reference> for CitationGraph {
+    fn extend<T: IntoIterator<Item = Reference>>(&mut self, references: T) {
+        for reference in references {
+            self.insert_reference(reference);
+        }
+    }
+}
+
+impl PartialEq for CitationGraph {
+    fn eq(&self, other: &Self) -> bool {
+        self.papers().collect::<HashSet<_>>() == other.papers().collect::<HashSet<_>>()
+            && self.references().collect::<HashSet<_>>()
+                == other.references().collect::<HashSet<_>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(referencer: &str, referencee: &str) -> Reference {
+        Reference {
+            referencer: referencer.into(),
+            referencee: referencee.into(),
+        }
+    }
+
+    #[test]
+    fn citations_outlive_the_papers_they_name() {
+        let mut citations = CitationGraph::new(
+            ["a", "b"].map(|id| ProtoPaper::new(id, id)),
+            [
+                reference("a", "b"),
+                reference("a", "b"),
+                reference("b", "x"),
+            ],
+        );
+        assert_eq!(citations.reference_count(), 2);
+        assert!(!citations.contains_paper("x"));
+        assert_eq!(citations.cited_by_count("x"), 1);
+
+        citations.retain_papers(|paper| paper.id() != Some("b"));
+        assert!(citations.contains_reference(&reference("a", "b")));
+        assert_eq!(citations.ids().collect::<Vec<_>>(), vec!["a"]);
+
+        citations.retain_references(|reference| &*reference.referencer != "b");
+        assert!(!citations.index.contains_key("x"));
+        assert!(citations.index.contains_key("b"));
+    }
+
+    #[test]
+    fn papers_are_read_after_what_they_cite() {
+        let paper = |id: &str, year: u32| ProtoPaper::new(id, id).with_year(Some(year));
+        // c and d cite each other, and d is older
+        let citations = CitationGraph::new(
+            [
+                paper("a", 2020),
+                paper("b", 2010),
+                paper("c", 2015),
+                paper("d", 2014),
+                paper("e", 2001),
+            ],
+            [
+                reference("a", "b"),
+                reference("a", "c"),
+                reference("c", "d"),
+                reference("d", "c"),
+                reference("c", "b"),
+                reference("b", "missing"),
+            ],
+        );
+        let order: Vec<&str> = citations
+            .reading_order()
+            .into_iter()
+            .filter_map(ProtoPaper::id)
+            .collect();
+        assert_eq!(order, vec!["e", "b", "d", "c", "a"]);
+    }
+}
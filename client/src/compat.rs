@@ -0,0 +1,287 @@
+//! Keeping old command lines working as the CLI is reorganized.
+//!
+//! Arguments are rewritten before argh sees them: invocations from before
+//! subcommands, `client refs.bib --max-depth 3`, become `client build
+//! refs.bib --max-depth 3` with a warning, and options that apply to every
+//! subcommand are moved in front of it, wherever they were written.  Any
+//! options from the environment or [config file](crate::config) not given
+//! are added then too.
+
+use std::path::Path;
+
+use argh::FromArgs;
+
+use crate::config::{self, Config, Setting};
+use crate::fixture;
+
+pub const SUBCOMMANDS: &[&str] = &[
+    "build",
+    "search",
+    "render",
+    "impact",
+    "diff",
+    "cache",
+    "serve",
+    "export-bib",
+    "neighborhood",
+    "lint",
+    "server",
+    "man",
+];
+/// Options taken by the top level rather than any subcommand, which
+/// argh needs before the subcommand.
+pub const GLOBAL_OPTIONS: &[&str] = &[
+    "--config",
+    "--base-uri",
+    "--api-key-env",
+    "--output-format",
+    "--output",
+    "--newline",
+    "--split-by-cluster",
+    "--report-file",
+    "--report-max-nodes",
+    "--report",
+    "--common-references",
+    "--stats",
+    "--highlight-author",
+    "--annotations",
+    "--filter-tag",
+    "--aggregate",
+    "--cache-dir",
+    "--request-timeout",
+    "--max-concurrent-requests",
+    "--progress",
+    "--error-format",
+];
+pub const GLOBAL_SWITCHES: &[&str] = &["--no-attribution", "--bom", "--offline"];
+/// Options of any subcommand that take a value, so the value isn't
+/// mistaken for a positional argument.
+const SUBCOMMAND_OPTIONS: &[&str] = &[
+    "--max-depth",
+    "--connectivity",
+    "--fields-of-study",
+    "--only-author",
+    "--policy",
+    "--min-citation-count",
+    "--max-papers-per-depth",
+    "--max-total-papers",
+    "--simulate",
+    "--recommend",
+    "--library",
+    "--update",
+    "--scan-pdfs",
+    "--source",
+    "--depth",
+    "--max-citations-per-paper",
+    "--dot",
+    "--limit",
+    "--year",
+    "--port",
+    "--address",
+    "--history",
+    "--stream",
+    "--max-runtime",
+    "--min-cited-by",
+    "--radius",
+];
+const HELP: &[&str] = &["--help", "help"];
+
+/// How many arguments `arg` takes up, itself included.
+fn width(arg: &str) -> usize {
+    if GLOBAL_OPTIONS.contains(&arg) || SUBCOMMAND_OPTIONS.contains(&arg) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Rewrite `args` (without the program name) into the current structure,
+/// along with warnings for anything deprecated.
+pub fn rewrite(command: &str, args: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut global = Vec::<String>::new();
+    let mut rest = Vec::<String>::new();
+    let mut subcommand = None;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let end = (i + width(arg)).min(args.len());
+        if arg == "--" {
+            rest.extend_from_slice(&args[i..]);
+            break;
+        } else if arg == "--source" && args.get(i + 1).is_some_and(|value| value == "fixture") {
+            // `--source fixture <dir>` is one option with two values,
+            // which argh can't take
+            rest.push(arg.into());
+            rest.push(format!(
+                "{}{}",
+                fixture::SOURCE_PREFIX,
+                args.get(i + 2).map_or("", String::as_str)
+            ));
+            i = (i + 3).min(args.len());
+            continue;
+        } else if GLOBAL_OPTIONS.contains(&arg) || GLOBAL_SWITCHES.contains(&arg) {
+            global.extend_from_slice(&args[i..end]);
+        } else if subcommand.is_none() && !arg.starts_with('-') && SUBCOMMANDS.contains(&arg) {
+            subcommand = Some(arg.to_string());
+        } else {
+            rest.extend_from_slice(&args[i..end]);
+        }
+        i = end;
+    }
+    let is_help = args.iter().any(|arg| HELP.contains(&arg.as_str()));
+    if subcommand.is_none() && !rest.is_empty() && !is_help {
+        warnings.push(format!(
+            "`{command} <bibliography> [options]` is deprecated; use `{command} build <bibliography> [options]`"
+        ));
+        subcommand = Some("build".into());
+    }
+    let rewritten = global.into_iter().chain(subcommand).chain(rest).collect();
+    (rewritten, warnings)
+}
+
+/// Add the options in `config` that `args`, as rewritten by [`rewrite`],
+/// doesn't already have: global ones first, and the subcommand's right
+/// after it.
+pub fn with_config(args: Vec<String>, config: &Config) -> Vec<String> {
+    let given = |setting: &&Setting| args.iter().any(|arg| arg == setting.option());
+    let mut subcommand = None;
+    let mut i = 0;
+    while i < args.len() {
+        if SUBCOMMANDS.contains(&args[i].as_str()) {
+            subcommand = Some(i);
+            break;
+        }
+        i += width(&args[i]);
+    }
+    let global = config.global.iter().filter(|option| !given(option));
+    let Some(subcommand) = subcommand else {
+        return global
+            .flat_map(Setting::args)
+            .cloned()
+            .chain(args.iter().cloned())
+            .collect();
+    };
+    let options = config
+        .subcommands
+        .get(&args[subcommand])
+        .into_iter()
+        .flatten()
+        .filter(|option| !given(option));
+    global
+        .flat_map(Setting::args)
+        .chain(&args[..=subcommand])
+        .chain(options.flat_map(Setting::args))
+        .chain(&args[subcommand + 1..])
+        .cloned()
+        .collect()
+}
+
+/// Like [`argh::from_env`], but rewriting the arguments with [`rewrite`]
+/// and adding any from the config file first.
+pub fn from_env<T: FromArgs>() -> T {
+    let args: Vec<String> = std::env::args().collect();
+    let command = args
+        .first()
+        .and_then(|command| Path::new(command).file_name())
+        .map_or("client".into(), |name| name.to_string_lossy().into_owned());
+    let (rewritten, warnings) = rewrite(&command, args.get(1..).unwrap_or_default());
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    let config_path = rewritten
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| rewritten.get(i + 1))
+        .cloned()
+        .or_else(|| std::env::var(config::CONFIG_VAR).ok());
+    let config = Config::load(config_path.as_deref().map(Path::new))
+        .and_then(|config| Ok(config.overridden_by(Config::from_vars(std::env::vars())?)));
+    let rewritten = match config {
+        Ok(config) => with_config(rewritten, &config),
+        Err(err) => {
+            eprintln!("can't read the config: {err}");
+            std::process::exit(1)
+        }
+    };
+    let rewritten: Vec<&str> = rewritten.iter().map(String::as_str).collect();
+    match T::from_args(&[&command], &rewritten) {
+        Ok(parsed) => parsed,
+        Err(early_exit) => match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                std::process::exit(0)
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {command} --help for more information.",
+                    early_exit.output
+                );
+                std::process::exit(1)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Command};
+
+    fn rewrite_str(args: &str) -> (Vec<String>, Vec<String>) {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        rewrite("client", &args)
+    }
+
+    #[test]
+    fn old_invocations_become_builds() {
+        let (args, warnings) =
+            rewrite_str("refs.bib --max-depth 3 --output-format svg --edge-weights --bom");
+        assert_eq!(
+            args,
+            [
+                "--output-format",
+                "svg",
+                "--bom",
+                "build",
+                "refs.bib",
+                "--max-depth",
+                "3",
+                "--edge-weights"
+            ]
+        );
+        assert_eq!(warnings.len(), 1);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let cli = Cli::from_args(&["client"], &args).unwrap();
+        let Command::Build(build) = cli.command else {
+            panic!("not a build");
+        };
+        assert_eq!(build.bibliography.as_deref(), Some("refs.bib"));
+        assert_eq!(build.max_depth, 3);
+    }
+
+    #[test]
+    fn current_invocations_only_have_global_options_moved() {
+        let (args, warnings) = rewrite_str("impact 10.1000/1 --base-uri localhost --depth 1");
+        assert_eq!(
+            args,
+            [
+                "--base-uri",
+                "localhost",
+                "impact",
+                "10.1000/1",
+                "--depth",
+                "1"
+            ]
+        );
+        assert!(warnings.is_empty());
+        // an option's value is never taken for a subcommand
+        let (args, _warnings) = rewrite_str("--output build build");
+        assert_eq!(args, ["--output", "build", "build"]);
+        let (args, _warnings) = rewrite_str("build refs.bib --source fixture tests/papers");
+        assert_eq!(
+            args,
+            ["build", "refs.bib", "--source", "fixture:tests/papers"]
+        );
+    }
+}
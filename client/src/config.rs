@@ -0,0 +1,319 @@
+//! Settings read from `citation-graph.toml`, or the file given by
+//! `--config`, so long invocations needn't be retyped.
+//!
+//! Top-level keys are the options taken before any subcommand, and a
+//! table named for a subcommand holds its options, e.g.
+//!
+//! ```toml
+//! base_uri = "localhost:8000"
+//! output_format = "svg"
+//!
+//! [build]
+//! max_depth = 3
+//! fields_of_study = ["Computer Science", "Mathematics"]
+//! edge_weights = true
+//! ```
+//!
+//! Each key is the option's name, with underscores or dashes.  Switches
+//! are turned on with `true`, and lists are joined with commas.
+//!
+//! Any option can also be set by an environment variable named for it,
+//! `CITATION_GRAPH_BASE_URI` or, for a subcommand's options,
+//! `CITATION_GRAPH_BUILD_MAX_DEPTH`, with switches set to `true` or
+//! `false`.  `CITATION_GRAPH_CONFIG` names the config file.  Options given
+//! on the command line win over the environment, which wins over the file.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use toml::Value;
+
+use crate::compat::{GLOBAL_OPTIONS, GLOBAL_SWITCHES, SUBCOMMANDS};
+
+/// Where settings are looked for when `--config` isn't given.
+pub const DEFAULT_PATH: &str = "citation-graph.toml";
+/// What the environment variables setting options start with.
+pub const ENV_PREFIX: &str = "CITATION_GRAPH_";
+/// The environment variable naming the config file.
+pub const CONFIG_VAR: &str = "CITATION_GRAPH_CONFIG";
+
+pub enum Error {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    /// A key whose value can't be written as an option.
+    Invalid(String),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => std::fmt::Debug::fmt(err, f),
+            Error::Toml(err) => std::fmt::Display::fmt(err, f),
+            Error::Invalid(key) => write!(
+                f,
+                "{key:?} in the config should be a string, number, boolean, or list of strings"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Toml(err) => Some(err),
+            Error::Invalid(_key) => None,
+        }
+    }
+}
+
+/// One option from a config file, as it would be given on the command
+/// line.
+#[derive(Debug, PartialEq)]
+pub enum Setting {
+    /// The option and its value, if it takes one.
+    Args(Vec<String>),
+    /// A switch turned off, which is left out even where it's turned on
+    /// by a setting this overrides.
+    Off(String),
+}
+
+impl Setting {
+    pub fn option(&self) -> &str {
+        match self {
+            Setting::Args(args) => &args[0],
+            Setting::Off(option) => option,
+        }
+    }
+
+    /// The arguments to give, none for a switch that's off.
+    pub fn args(&self) -> &[String] {
+        match self {
+            Setting::Args(args) => args,
+            Setting::Off(_option) => &[],
+        }
+    }
+}
+
+/// Options from a config file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub global: Vec<Setting>,
+    /// By subcommand name.
+    pub subcommands: BTreeMap<String, Vec<Setting>>,
+}
+
+/// Write one setting as arguments.
+fn to_args(key: &str, value: &Value) -> Result<Setting, Error> {
+    let option = format!("--{}", key.replace('_', "-"));
+    let value = match value {
+        Value::Boolean(true) => return Ok(Setting::Args(vec![option])),
+        Value::Boolean(false) => return Ok(Setting::Off(option)),
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map(String::from))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::Invalid(key.into()))?
+            .join(","),
+        Value::Datetime(_) | Value::Table(_) => return Err(Error::Invalid(key.into())),
+    };
+    Ok(Setting::Args(vec![option, value]))
+}
+
+impl Config {
+    pub fn parse(src: &str) -> Result<Self, Error> {
+        let table: toml::Table = src.parse().map_err(Error::Toml)?;
+        let mut config = Config::default();
+        for (key, value) in &table {
+            match value {
+                Value::Table(options) => {
+                    let subcommand = config.subcommands.entry(key.clone()).or_default();
+                    for (key, value) in options {
+                        subcommand.push(to_args(key, value)?);
+                    }
+                }
+                value => config.global.push(to_args(key, value)?),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Gather options from `CITATION_GRAPH_*` variables in `vars`.
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, Error> {
+        let mut config = Config::default();
+        for (var, value) in vars {
+            let Some(key) = var.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            if var == CONFIG_VAR {
+                continue;
+            }
+            let key = key.to_lowercase();
+            let value = match value.as_str() {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                _ => Value::String(value),
+            };
+            // global options like --cache-dir can start like a subcommand
+            let global = GLOBAL_OPTIONS
+                .iter()
+                .chain(GLOBAL_SWITCHES)
+                .any(|option| option[2..].replace('-', "_") == key);
+            let subcommand =
+                SUBCOMMANDS
+                    .iter()
+                    .filter(|_subcommand| !global)
+                    .find_map(|&subcommand| {
+                        let rest = key.strip_prefix(&subcommand.replace('-', "_"))?;
+                        Some((subcommand, rest.strip_prefix('_')?))
+                    });
+            match subcommand {
+                Some((subcommand, key)) => config
+                    .subcommands
+                    .entry(subcommand.into())
+                    .or_default()
+                    .push(to_args(key, &value)?),
+                None => config.global.push(to_args(&key, &value)?),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Replace any options in this config that `overrides` also has.
+    pub fn overridden_by(mut self, overrides: Config) -> Self {
+        fn merge(options: &mut Vec<Setting>, overrides: Vec<Setting>) {
+            options.retain(|option| {
+                !overrides
+                    .iter()
+                    .any(|other| other.option() == option.option())
+            });
+            options.extend(overrides);
+        }
+        merge(&mut self.global, overrides.global);
+        for (subcommand, options) in overrides.subcommands {
+            merge(self.subcommands.entry(subcommand).or_default(), options);
+        }
+        self
+    }
+
+    /// Read the config at `path`, or if none was given, at
+    /// [`DEFAULT_PATH`] if there's one there.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let src = match path {
+            Some(path) => std::fs::read_to_string(path),
+            None => match std::fs::read_to_string(DEFAULT_PATH) {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(Self::default())
+                }
+                src => src,
+            },
+        };
+        Self::parse(&src.map_err(Error::Io)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compat;
+
+    #[test]
+    fn the_command_line_wins_over_the_config() {
+        let config = Config::parse(
+            r#"
+                base_uri = "localhost:8000"
+                bom = false
+                [build]
+                max_depth = 3
+                fields_of_study = ["Computer Science", "Mathematics"]
+                edge_weights = true
+            "#,
+        )
+        .unwrap();
+        let args: Vec<String> = ["--base-uri", "example.com", "build", "refs.bib"]
+            .map(String::from)
+            .into();
+        let args = compat::with_config(args, &config);
+        assert_eq!(
+            args,
+            [
+                "--base-uri",
+                "example.com",
+                "build",
+                "--edge-weights",
+                "--fields-of-study",
+                "Computer Science,Mathematics",
+                "--max-depth",
+                "3",
+                "refs.bib",
+            ]
+        );
+        assert!(matches!(
+            Config::parse("[build]\nmax_depth = { deep = true }"),
+            Err(Error::Invalid(_))
+        ));
+
+        let env = Config::from_vars([
+            (
+                "CITATION_GRAPH_BUILD_MAX_DEPTH".to_string(),
+                "5".to_string(),
+            ),
+            ("CITATION_GRAPH_BOM".to_string(), "true".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ])
+        .unwrap();
+        let args: Vec<String> = ["build", "refs.bib", "--edge-weights"]
+            .map(String::from)
+            .into();
+        let args = compat::with_config(args, &config.overridden_by(env));
+        assert_eq!(
+            args,
+            [
+                "--base-uri",
+                "localhost:8000",
+                "--bom",
+                "build",
+                "--fields-of-study",
+                "Computer Science,Mathematics",
+                "--max-depth",
+                "5",
+                "refs.bib",
+                "--edge-weights",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_environment_can_turn_off_a_switch_and_set_global_options() {
+        let config = Config::parse("bom = true\n[build]\nedge_weights = true").unwrap();
+        let env = Config::from_vars([
+            ("CITATION_GRAPH_BOM".to_string(), "false".to_string()),
+            (
+                "CITATION_GRAPH_CACHE_DIR".to_string(),
+                "/tmp/cache".to_string(),
+            ),
+            (
+                "CITATION_GRAPH_EXPORT_BIB_FORMAT".to_string(),
+                "ris".to_string(),
+            ),
+            (
+                "CITATION_GRAPH_BUILD_EDGE_WEIGHTS".to_string(),
+                "false".to_string(),
+            ),
+        ])
+        .unwrap();
+        assert!(env.subcommands.contains_key("export-bib"));
+        let args: Vec<String> = ["build", "refs.bib"].map(String::from).into();
+        let args = compat::with_config(args, &config.overridden_by(env));
+        assert_eq!(args, ["--cache-dir", "/tmp/cache", "build", "refs.bib"]);
+    }
+}
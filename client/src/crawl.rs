@@ -0,0 +1,1032 @@
+//! Searching outward from the seed papers through their references.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Write;
+use std::pin::{pin, Pin};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc;
+use futures::future::{self, Either, FusedFuture, FutureExt};
+use futures::{stream, SinkExt, StreamExt};
+use serde::Serialize;
+
+use crate::citation_graph::CitationGraph;
+use crate::graph::{Graph, Reference};
+use crate::intern::{Id, Interner};
+use crate::policy::{Candidate, ExpansionPolicy};
+#[cfg(feature = "native")]
+use crate::semantic_scholar::SemanticScholar;
+use crate::semantic_scholar::{self, Paper, PaperId, ProtoPaper, Resolution};
+
+/// How many chunks are fetched at once, and how many fetched can wait for
+/// the graph to be built from them.
+const FETCH_AHEAD: usize = 4;
+/// How many more times a chunk that failed for a passing reason is tried.
+const CHUNK_RETRIES: u32 = 2;
+/// How long to wait before trying a chunk again, doubled each time.
+const CHUNK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// A fetched chunk, or how many papers it asked for and why it failed.
+type Fetched = Result<Vec<Paper>, (usize, semantic_scholar::Error)>;
+
+/// Somewhere papers can be fetched from.
+// the futures needn't be `Send`, and in a browser can't be
+#[allow(async_fn_in_trait)]
+pub trait PaperSource {
+    /// Get the papers in the same order as `paper_ids`, with `None` for
+    /// any that couldn't be found.
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error>;
+
+    /// For each paper in `paper_ids`, count how many times it cites each
+    /// of its references in its text, keyed by `(citing id, cited id)`.
+    async fn get_reference_contexts(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error>;
+
+    /// Wait for `duration`, before trying a chunk again and for the
+    /// search's time limit.
+    #[cfg(feature = "native")]
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    /// Wait for `duration`, before trying a chunk again and for the
+    /// search's time limit, on whatever timers there are.
+    #[cfg(not(feature = "native"))]
+    async fn sleep(&self, duration: Duration);
+}
+
+#[cfg(feature = "native")]
+impl PaperSource for SemanticScholar {
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+        SemanticScholar::get_paper_batch(self, paper_ids).await
+    }
+
+    async fn get_reference_contexts(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+        SemanticScholar::get_reference_contexts(self, paper_ids).await
+    }
+}
+
+/// What decides how far the search goes.
+pub struct Options {
+    pub max_depth: usize,
+    /// Which staged papers are expanded.
+    pub policy: Box<dyn ExpansionPolicy>,
+    pub fields_of_study: Option<Vec<String>>,
+    /// Only expand papers by the author going by this name.
+    pub only_author: Option<String>,
+    pub min_citation_count: Option<usize>,
+    pub max_papers_per_depth: Option<usize>,
+    pub max_total_papers: Option<usize>,
+    pub edge_weights: bool,
+    /// Stop at the first failed request past the seeds and keep what was
+    /// found, rather than failing the whole search.
+    pub keep_partial: bool,
+    /// Fetch the references of papers certain to be expanded at the next
+    /// depth while this one is still being fetched.  Leave this off when
+    /// a review might turn them down, or their requests are wasted.
+    pub prefetch: bool,
+    /// Where to say how the search is going as it goes.
+    pub progress: Option<mpsc::UnboundedSender<Progress>>,
+    /// Stop after this long and keep what was found, whatever
+    /// `keep_partial` says.
+    pub max_runtime: Option<Duration>,
+}
+
+impl Options {
+    /// Expand what `policy` picks out to `max_depth`, with nothing
+    /// filtered, budgeted, or timed, and every switch off.
+    pub fn new(max_depth: usize, policy: Box<dyn ExpansionPolicy>) -> Self {
+        Self {
+            max_depth,
+            policy,
+            fields_of_study: None,
+            only_author: None,
+            min_citation_count: None,
+            max_papers_per_depth: None,
+            max_total_papers: None,
+            edge_weights: false,
+            keep_partial: false,
+            prefetch: false,
+            progress: None,
+            max_runtime: None,
+        }
+    }
+}
+
+/// A step in the search, for showing it live.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Progress {
+    /// The references of `papers` papers are being fetched at `depth`.
+    DepthStarted { depth: usize, papers: usize },
+    /// A batch of `papers` papers arrived at `depth`.
+    BatchFetched { depth: usize, papers: usize },
+    /// `depth` found `papers` papers, making `total` in all.
+    PapersDiscovered {
+        depth: usize,
+        papers: usize,
+        total: usize,
+    },
+}
+
+/// How the search's progress is shown on stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// Lines for people, as requests are sent.
+    #[default]
+    Text,
+    /// A JSON object for each [`Progress`], a line each, for scripts.
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(ProgressFormat::Text),
+            "json" => Ok(ProgressFormat::Json),
+            other => Err(format!(
+                "unknown progress format {other:?}; try text or json"
+            )),
+        }
+    }
+}
+
+/// Write each of `progress` to `out` as a line of JSON, with the papers
+/// `fetched` so far, the papers whose references are `queued` at the
+/// depth, and the seconds `elapsed` since the first.
+pub async fn write_json(
+    mut progress: mpsc::UnboundedReceiver<Progress>,
+    mut out: impl Write,
+) -> std::io::Result<()> {
+    let start = Instant::now();
+    let (mut fetched, mut queued) = (0, 0);
+    while let Some(progress) = progress.next().await {
+        match progress {
+            Progress::DepthStarted { papers, .. } => queued = papers,
+            Progress::BatchFetched { papers, .. } => fetched += papers,
+            Progress::PapersDiscovered { .. } => {}
+        }
+        let mut event = serde_json::to_value(&progress)?;
+        event["fetched"] = fetched.into();
+        event["queued"] = queued.into();
+        // to the millisecond is plenty for a progress bar
+        event["elapsed"] = ((start.elapsed().as_secs_f64() * 1000.0).round() / 1000.0).into();
+        writeln!(out, "{event}")?;
+    }
+    out.flush()
+}
+
+fn report(options: &Options, progress: Progress) {
+    if let Some(sender) = &options.progress {
+        // nobody listening is fine
+        let _ = sender.unbounded_send(progress);
+    }
+}
+
+/// The unpruned result of a search.
+pub struct Crawl {
+    pub graph: Graph,
+    /// The ids of the seed papers that were found.
+    pub seeds: Vec<String>,
+    /// The ids of the seed papers found, by the ids they were asked for.
+    pub found: HashMap<String, String>,
+    /// The ids of the seed papers Semantic Scholar couldn't find, as they
+    /// were asked for.
+    pub unresolved: Vec<String>,
+    /// How many papers had their references followed at each depth.
+    pub expanded_per_depth: Vec<usize>,
+    /// What stopped the search early, if [`Options::keep_partial`] let it
+    /// keep going.
+    pub interrupted: Option<semantic_scholar::Error>,
+    /// The chunks left out because they failed while others at the same
+    /// depth came in.
+    pub failed_chunks: Vec<FailedChunk>,
+}
+
+/// A chunk of papers that couldn't be fetched even when tried again.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedChunk {
+    pub depth: usize,
+    /// How many papers it asked for.
+    pub papers: usize,
+    pub error: String,
+}
+
+struct StagingData {
+    citation_count: usize,
+    paper: Paper,
+}
+
+type Staging = HashMap<Id, StagingData>;
+
+/// Stage `papers`, using the Semantic Scholar ID as the key and setting
+/// the citation count to 1.
+fn stage(staging: &mut Staging, ids: &mut Interner, papers: impl IntoIterator<Item = Paper>) {
+    for paper in papers {
+        let id = ids.intern(paper.id());
+        if let Some(staged) = staging.insert(
+            id.clone(),
+            StagingData {
+                citation_count: 1,
+                paper,
+            },
+        ) {
+            staging.get_mut(&id).unwrap().citation_count = std::cmp::max(1, staged.citation_count);
+        }
+    }
+}
+
+fn from_staging(staging: &Staging) -> CitationGraph {
+    CitationGraph::new(
+        staging
+            .values()
+            .map(|data| <Paper as Into<ProtoPaper>>::into(data.paper.clone())),
+        [],
+    )
+}
+
+/// Search out from `seeds` through `source` as far as `options` allow.
+pub async fn crawl(
+    source: &impl PaperSource,
+    seeds: Vec<(PaperId, Resolution)>,
+    options: &Options,
+) -> Result<Crawl, semantic_scholar::Error> {
+    crawl_with_review(
+        source,
+        seeds,
+        options,
+        |_depth, frontier| vec![true; frontier.len()],
+        |_found| {},
+    )
+    .await
+}
+
+fn candidate(staged: &StagingData) -> Candidate<'_> {
+    Candidate {
+        paper: &staged.paper,
+        citations: staged.citation_count,
+    }
+}
+
+/// Whether `staged` is expanded at `depth`, before any budget or review.
+fn qualifies(options: &Options, depth: usize, staged: &StagingData) -> bool {
+    if let Some(author) = &options.only_author {
+        if !staged.paper.has_author(author) {
+            return false;
+        }
+    }
+    match options.min_citation_count {
+        Some(min_citation_count) if depth > 0 => {
+            staged.paper.citation_count().unwrap_or_default() >= min_citation_count
+        }
+        _ => options.policy.qualifies(depth, &candidate(staged)),
+    }
+}
+
+/// The references of `paper` that are followed when it's expanded.
+fn followed_references<'a>(paper: &'a Paper, options: &Options) -> Vec<&'a ProtoPaper> {
+    paper
+        .references()
+        .iter()
+        .filter(|reference| reference.id().is_some())
+        .filter(|reference| match &options.fields_of_study {
+            Some(fields) => reference.is_in_fields_of_study(fields),
+            None => true,
+        })
+        .collect()
+}
+
+fn batch_ids<'a>(references: &'a [&ProtoPaper]) -> impl Iterator<Item = PaperId> + 'a {
+    references
+        .iter()
+        .filter_map(|reference| reference.id())
+        .map(|id| PaperId::SemanticScholar(id.to_string()))
+}
+
+/// How many chunks fetching `paper_ids` takes, each a request.
+fn chunk_count(paper_ids: &[PaperId]) -> usize {
+    endpoints::batch_chunks(paper_ids).len()
+}
+
+/// Wait out `max_runtime`, or forever if there's none.
+async fn until(source: &impl PaperSource, max_runtime: Option<Duration>) {
+    match max_runtime {
+        Some(max_runtime) => source.sleep(max_runtime).await,
+        None => future::pending().await,
+    }
+}
+
+/// Wait on `request` until `deadline`, failing once the search is out of
+/// time.
+async fn in_time<T>(
+    options: &Options,
+    deadline: Pin<&mut impl FusedFuture<Output = ()>>,
+    request: impl Future<Output = Result<T, semantic_scholar::Error>>,
+) -> Result<T, semantic_scholar::Error> {
+    let Some(max_runtime) = options.max_runtime else {
+        return request.await;
+    };
+    if deadline.is_terminated() {
+        return Err(semantic_scholar::Error::OutOfTime(max_runtime));
+    }
+    match future::select(deadline, pin!(request)).await {
+        Either::Left(((), _request)) => Err(semantic_scholar::Error::OutOfTime(max_runtime)),
+        Either::Right((response, _deadline)) => response,
+    }
+}
+
+/// Fetch the papers in `chunk`, trying it again on its own if it fails
+/// for a passing reason.
+async fn fetch_chunk(source: &impl PaperSource, chunk: Vec<PaperId>) -> Fetched {
+    let mut retries = 0;
+    loop {
+        match source.get_paper_batch(chunk.clone()).await {
+            Err(err) if err.is_transient() && retries < CHUNK_RETRIES => {
+                let delay = CHUNK_RETRY_DELAY * 2u32.pow(retries);
+                retries += 1;
+                eprintln!("warning: trying a chunk again in {delay:?}: {err:?}");
+                source.sleep(delay).await;
+            }
+            papers => {
+                return papers
+                    .map(|papers| papers.into_iter().flatten().collect())
+                    .map_err(|err| (chunk.len(), err))
+            }
+        }
+    }
+}
+
+/// Fetch the papers of each batch in `batches`, in the order sent, up to
+/// [`FETCH_AHEAD`] chunks at a time, passing each chunk to `fetched` in
+/// order as it arrives.  Stops once either channel is closed.
+async fn fetch(
+    source: &impl PaperSource,
+    batches: mpsc::UnboundedReceiver<Vec<PaperId>>,
+    mut fetched: mpsc::Sender<Fetched>,
+) {
+    let chunks = batches.flat_map(|paper_ids| {
+        let chunks: Vec<_> = endpoints::batch_chunks(&paper_ids)
+            .map(<[PaperId]>::to_vec)
+            .collect();
+        stream::iter(chunks)
+    });
+    let mut in_flight = pin!(chunks
+        .map(|chunk| fetch_chunk(source, chunk))
+        .buffered(FETCH_AHEAD));
+    while let Some(papers) = in_flight.next().await {
+        if fetched.send(papers).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Like [`crawl`], but `review` is shown the papers about to be expanded
+/// at each depth and picks which actually are, marking them `true`, and
+/// `observe` is shown what's found as it's found: the seeds, then what
+/// each depth adds.
+///
+/// Papers are fetched alongside building the graph rather than in turn:
+/// with [`Options::prefetch`], a paper certain to be expanded at the next
+/// depth has its references fetched as soon as that's known, while this
+/// depth's are still coming in.
+pub async fn crawl_with_review(
+    source: &impl PaperSource,
+    seeds: Vec<(PaperId, Resolution)>,
+    options: &Options,
+    review: impl FnMut(usize, &[&Paper]) -> Vec<bool>,
+    observe: impl FnMut(&Graph),
+) -> Result<Crawl, semantic_scholar::Error> {
+    let (batches_tx, batches_rx) = mpsc::unbounded();
+    let (fetched_tx, fetched_rx) = mpsc::channel(FETCH_AHEAD);
+    let build = pin!(build(
+        source, seeds, options, review, observe, batches_tx, fetched_rx
+    ));
+    // the fetch is dropped with whatever it has in flight once the graph
+    // is built
+    match future::select(build, pin!(fetch(source, batches_rx, fetched_tx))).await {
+        Either::Left((crawl, _fetch)) => crawl,
+        Either::Right(((), build)) => build.await,
+    }
+}
+
+/// The graph-building half of [`crawl_with_review`], asking for papers
+/// through `batches` and receiving them on `fetched`.
+async fn build(
+    source: &impl PaperSource,
+    seeds: Vec<(PaperId, Resolution)>,
+    options: &Options,
+    mut review: impl FnMut(usize, &[&Paper]) -> Vec<bool>,
+    mut observe: impl FnMut(&Graph),
+    batches: mpsc::UnboundedSender<Vec<PaperId>>,
+    mut fetched: mpsc::Receiver<Fetched>,
+) -> Result<Crawl, semantic_scholar::Error> {
+    let mut deadline = pin!(until(source, options.max_runtime).fuse());
+    let (paper_ids, resolutions): (Vec<_>, Vec<_>) = seeds.into_iter().unzip();
+    let source_ids: Vec<String> = paper_ids.iter().map(PaperId::to_string).collect();
+    let mut staging = Staging::default();
+    let mut ids = Interner::default();
+    // one request before the loop to avoid creating a special cases
+    let seed_papers = in_time(
+        options,
+        deadline.as_mut(),
+        source.get_paper_batch(paper_ids),
+    )
+    .await?;
+    let (sources, unresolved): (Vec<_>, Vec<_>) = source_ids
+        .into_iter()
+        .zip(&seed_papers)
+        .partition(|(_id, paper)| paper.is_some());
+    let found: HashMap<String, String> = sources
+        .into_iter()
+        .filter_map(|(id, paper)| Some((id, paper.as_ref()?.id().to_string())))
+        .collect();
+    let sources: HashSet<String> = found.keys().cloned().collect();
+    let unresolved: Vec<String> = unresolved.into_iter().map(|(id, _paper)| id).collect();
+    let match_confidence: HashMap<String, f64> = seed_papers
+        .iter()
+        .zip(resolutions)
+        .filter(|(_paper, resolution)| *resolution != Resolution::Exact)
+        .filter_map(|(paper, resolution)| {
+            paper
+                .as_ref()
+                .map(|paper| (paper.id().to_string(), resolution.confidence()))
+        })
+        .collect();
+    let seeds: Vec<String> = seed_papers
+        .iter()
+        .flatten()
+        .map(|paper| paper.id().to_string())
+        .collect();
+    stage(&mut staging, &mut ids, seed_papers.into_iter().flatten());
+    let mut citations = from_staging(&staging);
+    observe(&Graph {
+        citations: citations.clone(),
+        match_confidence: match_confidence.clone(),
+        sources: sources.clone(),
+        seeds: seeds.iter().cloned().collect(),
+        ..Default::default()
+    });
+    let budgeted = options.max_papers_per_depth.is_some() || options.max_total_papers.is_some();
+    // a budget or review can turn down a paper that qualifies
+    let prefetching = options.prefetch && !budgeted;
+    let mut expanded_count = 0;
+    let mut expanded_per_depth = Vec::<usize>::new();
+    let mut edge_weights = HashMap::<Reference, usize>::new();
+    let mut interrupted = None;
+    let mut failed_chunks = Vec::<FailedChunk>::new();
+    // papers whose references were fetched ahead for the next depth, and
+    // how many chunks that took
+    let mut prefetched = HashSet::<Id>::new();
+    let mut prefetched_chunks = 0;
+
+    // And now the rest of the requests.
+    for depth in 0..options.max_depth {
+        // events say as much to whoever's listening for them
+        if options.progress.is_none() {
+            eprintln!("depth={depth}");
+        }
+        let mut staged_citations = CitationGraph::default();
+        let mut remove_staged = Vec::<Id>::default();
+        let mut batched_papers = Vec::<PaperId>::default();
+
+        let mut frontier: Vec<(&Id, &StagingData)> = staging
+            .iter()
+            .filter(|(_id, staged)| qualifies(options, depth, staged))
+            .collect();
+        if budgeted {
+            frontier.sort_by(|(_a_id, a), (_b_id, b)| {
+                options.policy.prefer(&candidate(a), &candidate(b))
+            });
+            let remaining = options
+                .max_total_papers
+                .map_or(usize::MAX, |max| max.saturating_sub(expanded_count));
+            frontier.truncate(
+                options
+                    .max_papers_per_depth
+                    .unwrap_or(usize::MAX)
+                    .min(remaining),
+            );
+        }
+        let papers: Vec<&Paper> = frontier.iter().map(|(_id, staged)| &staged.paper).collect();
+        let approved = review(depth, &papers);
+        let frontier: Vec<(&Id, &StagingData)> = frontier
+            .into_iter()
+            .zip(approved)
+            .filter_map(|(staged, approved)| approved.then_some(staged))
+            .collect();
+        for (id, staged) in frontier {
+            let references = followed_references(&staged.paper, options);
+            staged_citations.extend(
+                references
+                    .iter()
+                    .filter_map(|reference| reference.id())
+                    .map(|ref_id| Reference {
+                        referencer: id.clone(),
+                        referencee: ids.intern(ref_id),
+                    }),
+            );
+            staged_citations.extend(references.iter().map(|&paper| paper.clone()));
+            if !prefetched.contains(id) {
+                batched_papers.extend(batch_ids(&references));
+            }
+            remove_staged.push(id.clone());
+        }
+        prefetched.clear();
+        expanded_count += remove_staged.len();
+        expanded_per_depth.push(remove_staged.len());
+        report(
+            options,
+            Progress::DepthStarted {
+                depth,
+                papers: remove_staged.len(),
+            },
+        );
+        if options.edge_weights {
+            let paper_ids = remove_staged.iter().map(|id| id.to_string()).collect();
+            let contexts = source.get_reference_contexts(paper_ids);
+            let contexts = match in_time(options, deadline.as_mut(), contexts).await {
+                Ok(contexts) => contexts,
+                Err(err @ semantic_scholar::Error::OutOfTime(_)) => {
+                    interrupted = Some(err);
+                    HashMap::new()
+                }
+                Err(err) if options.keep_partial => {
+                    interrupted = Some(err);
+                    HashMap::new()
+                }
+                Err(err) => return Err(err),
+            };
+            edge_weights.extend(
+                contexts
+                    .into_iter()
+                    .map(|((referencer, referencee), count)| {
+                        (
+                            Reference {
+                                referencer: ids.intern(&referencer),
+                                referencee: ids.intern(&referencee),
+                            },
+                            count,
+                        )
+                    }),
+            );
+        }
+        for id in remove_staged {
+            staging.remove(&id);
+        }
+        let mut chunks = std::mem::take(&mut prefetched_chunks) + chunk_count(&batched_papers);
+        if interrupted.is_none() && !batched_papers.is_empty() {
+            // the fetcher only hangs up once this has
+            let _ = batches.unbounded_send(batched_papers);
+        }
+        // citations of papers not staged yet, in case a later chunk has them
+        let mut unmatched = HashMap::<Id, usize>::new();
+        // a chunk that fails is left out if others come in, as the depth
+        // is still worth having without it
+        let mut arrived = false;
+        let mut failed = Vec::<(usize, semantic_scholar::Error)>::new();
+        while chunks > 0 && interrupted.is_none() {
+            chunks -= 1;
+            let next = match future::select(deadline.as_mut(), fetched.next()).await {
+                Either::Left(((), _next)) => {
+                    interrupted = options.max_runtime.map(semantic_scholar::Error::OutOfTime);
+                    break;
+                }
+                Either::Right((next, _deadline)) => next,
+            };
+            let new_papers = match next {
+                Some(Ok(papers)) => papers,
+                Some(Err(failure)) => {
+                    failed.push(failure);
+                    continue;
+                }
+                None => break,
+            };
+            arrived = true;
+            report(
+                options,
+                Progress::BatchFetched {
+                    depth,
+                    papers: new_papers.len(),
+                },
+            );
+            let new_ids: Vec<Id> = new_papers
+                .iter()
+                .map(|paper| ids.intern(paper.id()))
+                .collect();
+            let reference_increments: Vec<Id> = new_papers
+                .iter()
+                .flat_map(|paper| paper.references())
+                .filter_map(|reference| reference.id())
+                .map(|ref_id| ids.intern(ref_id))
+                .collect();
+            stage(&mut staging, &mut ids, new_papers);
+            for id in new_ids {
+                if let (Some(count), Some(staged)) = (unmatched.remove(&id), staging.get_mut(&id)) {
+                    staged.citation_count += count;
+                }
+            }
+            for ref_id in reference_increments {
+                match staging.get_mut(&ref_id) {
+                    Some(staged) => staged.citation_count += 1,
+                    None => *unmatched.entry(ref_id).or_default() += 1,
+                }
+            }
+            if prefetching && depth + 1 < options.max_depth {
+                // counts only grow, so these will be expanded next
+                let mut ahead = Vec::<PaperId>::new();
+                for (id, staged) in &staging {
+                    if !prefetched.contains(id) && qualifies(options, depth + 1, staged) {
+                        ahead.extend(batch_ids(&followed_references(&staged.paper, options)));
+                        prefetched.insert(id.clone());
+                    }
+                }
+                if !ahead.is_empty() {
+                    prefetched_chunks += chunk_count(&ahead);
+                    let _ = batches.unbounded_send(ahead);
+                }
+            }
+        }
+        if !arrived && !failed.is_empty() && interrupted.is_none() {
+            let (_papers, err) = failed.swap_remove(0);
+            if !options.keep_partial {
+                return Err(err);
+            }
+            interrupted = Some(err);
+        }
+        for (papers, err) in failed {
+            eprintln!("warning: left out {papers} papers at depth {depth}: {err:?}");
+            failed_chunks.push(FailedChunk {
+                depth,
+                papers,
+                error: err.to_string(),
+            });
+        }
+        observe(&Graph {
+            edge_weights: staged_citations
+                .references()
+                .filter_map(|reference| {
+                    let weight = edge_weights.get(reference)?;
+                    Some((reference.clone(), *weight))
+                })
+                .collect(),
+            citations: staged_citations.clone(),
+            ..Default::default()
+        });
+        let discovered = staged_citations.paper_count();
+        citations.extend(staged_citations.papers().cloned());
+        citations.extend(staged_citations.references().cloned());
+        report(
+            options,
+            Progress::PapersDiscovered {
+                depth,
+                papers: discovered,
+                total: citations.paper_count(),
+            },
+        );
+        if let Some(err) = &interrupted {
+            eprintln!("warning: stopped at depth {depth}, keeping what was found: {err:?}");
+            break;
+        }
+    }
+
+    Ok(Crawl {
+        graph: Graph {
+            citations,
+            match_confidence,
+            edge_weights,
+            sources,
+            seeds: seeds.iter().cloned().collect(),
+            ..Default::default()
+        },
+        seeds,
+        found,
+        unresolved,
+        expanded_per_depth,
+        interrupted,
+        failed_chunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Network;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    /// Options following every reference, to `max_depth`.
+    fn options(max_depth: usize) -> Options {
+        Options::new(max_depth, Box::new(crate::policy::Connectivity(1.0)))
+    }
+
+    /// A network whose batches fail after the first `allowed`.
+    struct Flaky {
+        network: Network,
+        allowed: std::sync::atomic::AtomicUsize,
+    }
+
+    impl PaperSource for Flaky {
+        async fn get_paper_batch(
+            &self,
+            paper_ids: Vec<PaperId>,
+        ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+            let allowed = &self.allowed;
+            match allowed.fetch_update(SeqCst, SeqCst, |allowed| allowed.checked_sub(1)) {
+                Ok(_) => self.network.get_paper_batch(paper_ids).await,
+                Err(_) => Err(semantic_scholar::Error::Refused("down".into())),
+            }
+        }
+
+        async fn get_reference_contexts(
+            &self,
+            paper_ids: Vec<String>,
+        ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+            self.network.get_reference_contexts(paper_ids).await
+        }
+    }
+
+    fn flaky(allowed: usize) -> Flaky {
+        let mut network = Network::default();
+        network.add("0", &["a"]);
+        network.add("a", &["b"]);
+        network.add("b", &[]);
+        Flaky {
+            network,
+            allowed: allowed.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_batch_keeps_the_graph_so_far() {
+        let seeds = vec![(PaperId::SemanticScholar("0".into()), Resolution::Exact)];
+        let mut options = options(4);
+        assert!(crawl(&flaky(2), seeds.clone(), &options).await.is_err());
+
+        options.keep_partial = true;
+        let partial = crawl(&flaky(2), seeds, &options).await.unwrap();
+        assert!(partial.interrupted.is_some());
+        assert!(partial.graph.citations.contains_paper("a"));
+    }
+
+    /// A network that refuses any batch asking for `refused`.
+    struct Picky {
+        network: Network,
+        refused: String,
+    }
+
+    impl PaperSource for Picky {
+        async fn get_paper_batch(
+            &self,
+            paper_ids: Vec<PaperId>,
+        ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+            if paper_ids.iter().any(|id| id.to_string() == self.refused) {
+                return Err(semantic_scholar::Error::Refused("no".into()));
+            }
+            self.network.get_paper_batch(paper_ids).await
+        }
+
+        async fn get_reference_contexts(
+            &self,
+            paper_ids: Vec<String>,
+        ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+            self.network.get_reference_contexts(paper_ids).await
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_chunk_is_left_out_of_its_depth() {
+        // the seed's references are fetched in order, so r0 is in the
+        // first, full chunk and r500 alone in the second
+        let references: Vec<String> = (0..=endpoints::MAX_BATCH_IDS)
+            .map(|i| format!("r{i}"))
+            .collect();
+        let mut network = Network::default();
+        network.add(
+            "0",
+            &references.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        for (i, reference) in references.iter().enumerate() {
+            network.add(reference, &[&format!("s{i}")]);
+        }
+        let source = Picky {
+            network,
+            refused: "r0".into(),
+        };
+        let seeds = vec![(PaperId::SemanticScholar("0".into()), Resolution::Exact)];
+        let found = crawl(&source, seeds, &options(2)).await.unwrap();
+        assert!(found.interrupted.is_none());
+        assert_eq!(found.failed_chunks.len(), 1);
+        assert_eq!(found.failed_chunks[0].depth, 0);
+        assert_eq!(found.failed_chunks[0].papers, endpoints::MAX_BATCH_IDS);
+        // only papers that arrived are expanded at the next depth
+        let citations = &found.graph.citations;
+        assert!(citations.contains_paper("s500"));
+        assert!(!citations.contains_paper("s0"));
+        assert!(!citations.contains_paper("s499"));
+    }
+
+    /// A network slow to answer, keeping the most batches it was asked
+    /// for at once.
+    #[derive(Default)]
+    struct Slow {
+        network: Network,
+        asked: std::sync::atomic::AtomicUsize,
+        most_asked: std::sync::atomic::AtomicUsize,
+    }
+
+    impl PaperSource for Slow {
+        async fn get_paper_batch(
+            &self,
+            paper_ids: Vec<PaperId>,
+        ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+            let asked = self.asked.fetch_add(1, SeqCst) + 1;
+            self.most_asked.fetch_max(asked, SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.asked.fetch_sub(1, SeqCst);
+            self.network.get_paper_batch(paper_ids).await
+        }
+
+        async fn get_reference_contexts(
+            &self,
+            paper_ids: Vec<String>,
+        ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+            self.network.get_reference_contexts(paper_ids).await
+        }
+    }
+
+    #[tokio::test]
+    async fn a_depths_chunks_are_fetched_together() {
+        let references: Vec<String> = (0..3 * endpoints::MAX_BATCH_IDS)
+            .map(|i| format!("r{i}"))
+            .collect();
+        let mut source = Slow::default();
+        source.network.add(
+            "0",
+            &references.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        for reference in &references {
+            source.network.add(reference, &[]);
+        }
+        let seeds = vec![(PaperId::SemanticScholar("0".into()), Resolution::Exact)];
+        let found = crawl(&source, seeds, &options(1)).await.unwrap();
+        assert!(found.graph.citations.contains_paper("r1499"));
+        assert_eq!(source.most_asked.load(SeqCst), 3);
+    }
+
+    /// A network that never answers past the seed "0", nor for the
+    /// contexts of any paper.
+    struct Hung(Network);
+
+    impl PaperSource for Hung {
+        async fn get_paper_batch(
+            &self,
+            paper_ids: Vec<PaperId>,
+        ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+            if paper_ids.iter().any(|id| id.to_string() == "0") {
+                return self.0.get_paper_batch(paper_ids).await;
+            }
+            std::future::pending().await
+        }
+
+        async fn get_reference_contexts(
+            &self,
+            _paper_ids: Vec<String>,
+        ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn a_search_out_of_time_keeps_what_it_found() {
+        let seeds = vec![(PaperId::SemanticScholar("0".into()), Resolution::Exact)];
+        let mut options = Options {
+            max_runtime: Some(Duration::from_millis(50)),
+            ..options(4)
+        };
+        let hung = Hung(flaky(usize::MAX).network);
+        let found = crawl(&hung, seeds.clone(), &options).await.unwrap();
+        assert!(matches!(
+            found.interrupted,
+            Some(semantic_scholar::Error::OutOfTime(_limit))
+        ));
+        assert!(found.graph.citations.contains_paper("0"));
+
+        options.edge_weights = true;
+        let found = crawl(&hung, seeds, &options).await.unwrap();
+        assert!(matches!(
+            found.interrupted,
+            Some(semantic_scholar::Error::OutOfTime(_limit))
+        ));
+
+        let unanswered = vec![(PaperId::SemanticScholar("a".into()), Resolution::Exact)];
+        assert!(matches!(
+            crawl(&hung, unanswered, &options).await,
+            Err(semantic_scholar::Error::OutOfTime(_limit))
+        ));
+    }
+
+    #[tokio::test]
+    async fn progress_is_written_a_json_object_a_line() {
+        let (progress_tx, progress_rx) = mpsc::unbounded();
+        progress_tx
+            .unbounded_send(Progress::DepthStarted {
+                depth: 0,
+                papers: 2,
+            })
+            .unwrap();
+        for papers in [3, 4] {
+            progress_tx
+                .unbounded_send(Progress::BatchFetched { depth: 0, papers })
+                .unwrap();
+        }
+        drop(progress_tx);
+        let mut out = Vec::new();
+        write_json(progress_rx, &mut out).await.unwrap();
+
+        let events: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["event"], "depth_started");
+        assert_eq!(events[0]["queued"], 2);
+        assert_eq!(events[2]["fetched"], 7);
+        assert!(events[2]["elapsed"].is_f64());
+    }
+
+    #[tokio::test]
+    async fn only_references_in_the_fields_of_study_are_followed() {
+        let mut network = Network::default();
+        network.add("0", &["biology", "computing", "unclassified"]);
+        for id in ["biology", "computing", "unclassified"] {
+            network.add(id, &[&format!("{id}'s reference")]);
+        }
+        // the seed is expanded whatever its field
+        network.classify("0", &["Biology"]);
+        network.classify("biology", &["Biology"]);
+        network.classify("computing", &["Biology", "Computer Science"]);
+        let options = Options {
+            fields_of_study: Some(vec!["computer science".into()]),
+            ..options(2)
+        };
+        let found = crawl(&network, network.seeds(1), &options).await.unwrap();
+        assert_eq!(found.expanded_per_depth, vec![1, 2]);
+        let citations = &found.graph.citations;
+        assert!(citations.contains_paper("computing's reference"));
+        assert!(citations.contains_paper("unclassified's reference"));
+        assert!(!citations.contains_paper("biology"));
+        assert!(!citations.contains_paper("biology's reference"));
+    }
+
+    #[tokio::test]
+    async fn only_references_cited_enough_are_expanded() {
+        let mut network = Network::default();
+        network.add("0", &["obscure", "borderline", "classic", "uncounted"]);
+        for id in ["obscure", "borderline", "classic", "uncounted"] {
+            network.add(id, &[&format!("{id}'s reference")]);
+        }
+        // the seed is expanded however little it's cited
+        network.count_citations("0", 1);
+        network.count_citations("obscure", 9);
+        network.count_citations("borderline", 10);
+        network.count_citations("classic", 5000);
+        let options = Options {
+            min_citation_count: Some(10),
+            ..options(2)
+        };
+        let found = crawl(&network, network.seeds(1), &options).await.unwrap();
+        assert_eq!(found.expanded_per_depth, vec![1, 2]);
+        let citations = &found.graph.citations;
+        assert!(citations.contains_paper("borderline's reference"));
+        assert!(citations.contains_paper("classic's reference"));
+        assert!(!citations.contains_paper("obscure's reference"));
+        assert!(!citations.contains_paper("uncounted's reference"));
+    }
+
+    #[tokio::test]
+    async fn seeds_not_found_are_listed() {
+        let seeds = vec![
+            (PaperId::SemanticScholar("0".into()), Resolution::Exact),
+            (PaperId::Doi("10.1000/typo".into()), Resolution::Exact),
+        ];
+        let options = options(1);
+        let found = crawl(&flaky(usize::MAX), seeds, &options).await.unwrap();
+        assert_eq!(found.seeds, vec!["0"]);
+        assert_eq!(found.unresolved, vec!["DOI:10.1000/typo"]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Papers named by their dblp keys, as computer science bibliographies
+//! exported from dblp.org often are instead of by DOI.
+//!
+//! Semantic Scholar can't look papers up by dblp key, so each record is
+//! fetched from dblp as BibTeX and its DOI or arXiv id used instead.
+
+use std::collections::HashMap;
+
+use biblatex::{Bibliography, ChunksExt, Entry};
+use tokio::task::JoinSet;
+
+use crate::aggregate::Record;
+use crate::semantic_scholar::{Error, PaperId, Resolution};
+
+const DBLP_RECORD_URI: &str = "https://dblp.org/rec";
+const ARXIV_REGEX: &str = r#"arxiv\.org/abs/(?<id>[^\s/v]+(/\d+)?)"#;
+
+/// Fetch each dblp record in `keys` as BibTeX, skipping any that can't be.
+async fn get_entries(
+    client: &reqwest::Client,
+    keys: Vec<String>,
+) -> Result<Vec<(String, Entry)>, Error> {
+    let mut requests = JoinSet::new();
+    for key in keys {
+        let request = client.get(format!("{DBLP_RECORD_URI}/{key}.bib")).send();
+        requests.spawn(async move {
+            let bibtex = request
+                .await
+                .map_err(Error::Request)?
+                .text()
+                .await
+                .map_err(Error::Request)?;
+            Ok::<_, Error>((key, bibtex))
+        });
+    }
+    let mut entries = Vec::new();
+    while let Some(bibtex) = requests.join_next().await {
+        let (key, bibtex) = bibtex.map_err(Error::Join)??;
+        match Bibliography::parse(&bibtex)
+            .ok()
+            .and_then(|bibliography| bibliography.into_iter().next())
+        {
+            Some(entry) => entries.push((key, entry)),
+            None => eprintln!("no dblp record found for {key}"),
+        }
+    }
+    Ok(entries)
+}
+
+fn field(entry: &Entry, name: &str) -> Option<String> {
+    Some(entry.get(name)?.format_verbatim()).filter(|value| !value.is_empty())
+}
+
+/// The id Semantic Scholar knows the paper in `entry` by, if any.
+fn paper_id(entry: &Entry) -> Option<PaperId> {
+    if let Some(doi) = field(entry, "doi") {
+        return PaperId::try_from(doi.as_str()).ok();
+    }
+    let arxiv_regex = regex::Regex::new(ARXIV_REGEX).unwrap();
+    let url = field(entry, "url")?;
+    if let Some(caps) = arxiv_regex.captures(&url) {
+        return Some(PaperId::ArXiv(caps["id"].to_string()));
+    }
+    PaperId::try_from(url.as_str()).ok()
+}
+
+impl From<&Entry> for Record {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            title: field(entry, "title"),
+            url: field(entry, "url"),
+            year: field(entry, "year").and_then(|year| year.parse().ok()),
+            venue: field(entry, "booktitle").or_else(|| field(entry, "journal")),
+        }
+    }
+}
+
+/// Replace any dblp ids in `ids` with ones Semantic Scholar can look up,
+/// dropping those dblp has no DOI or arXiv id for.
+///
+/// Also returns the dblp ids that were replaced, so they can be counted
+/// among the graph's sources.  Any dblp ids are an error if `offline`.
+pub async fn resolve(
+    ids: Vec<(PaperId, Resolution)>,
+    offline: bool,
+) -> Result<(Vec<(PaperId, Resolution)>, Vec<String>), Error> {
+    let (dblp_ids, mut ids): (Vec<_>, Vec<_>) = ids
+        .into_iter()
+        .partition(|(id, _resolution)| matches!(id, PaperId::Dblp(_)));
+    if dblp_ids.is_empty() {
+        return Ok((ids, Vec::new()));
+    }
+    if offline {
+        return Err(Error::Offline("looking up dblp keys"));
+    }
+    let resolutions: HashMap<String, Resolution> = dblp_ids
+        .into_iter()
+        .filter_map(|(id, resolution)| match id {
+            PaperId::Dblp(key) => Some((key, resolution)),
+            _ => None,
+        })
+        .collect();
+    let keys = resolutions.keys().cloned().collect();
+    let mut resolved = Vec::new();
+    for (key, entry) in get_entries(&reqwest::Client::new(), keys).await? {
+        match paper_id(&entry) {
+            Some(id) => {
+                ids.push((id, resolutions[&key]));
+                resolved.push(PaperId::Dblp(key).to_string());
+            }
+            None => eprintln!("dblp has no DOI or arXiv id for {key}"),
+        }
+    }
+    Ok((ids, resolved))
+}
+
+/// Look up the dblp record of each of `keys`.
+pub async fn get_records(
+    client: &reqwest::Client,
+    keys: Vec<String>,
+) -> Result<HashMap<String, Record>, Error> {
+    Ok(get_entries(client, keys)
+        .await?
+        .iter()
+        .map(|(key, entry)| (key.clone(), Record::from(entry)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_without_dois_fall_back_to_arxiv() {
+        let (id, resolution) =
+            PaperId::resolve("https://dblp.org/rec/journals/corr/VaswaniSPUJGKP17.html").unwrap();
+        assert_eq!(id.to_string(), "DBLP:journals/corr/VaswaniSPUJGKP17");
+        assert_eq!(resolution, Resolution::Exact);
+
+        let bibtex = r#"
+            @article{DBLP:journals/corr/VaswaniSPUJGKP17,
+              title = {Attention Is All You Need},
+              journal = {CoRR},
+              volume = {abs/1706.03762},
+              year = {2017},
+              url = {http://arxiv.org/abs/1706.03762},
+            }
+        "#;
+        let bibliography = Bibliography::parse(bibtex).unwrap();
+        let entry = bibliography.iter().next().unwrap();
+        assert_eq!(paper_id(entry).unwrap().to_string(), "ARXIV:1706.03762");
+        assert_eq!(
+            Record::from(entry),
+            Record {
+                title: Some("Attention Is All You Need".into()),
+                url: Some("http://arxiv.org/abs/1706.03762".into()),
+                year: Some(2017),
+                venue: Some("CoRR".into()),
+            }
+        );
+    }
+}
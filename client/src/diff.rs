@@ -0,0 +1,233 @@
+//! Comparing two saved graphs, e.g. of the same bibliography months
+//! apart.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::graph::{Graph, Reference};
+use crate::output::escape;
+use crate::semantic_scholar::ProtoPaper;
+
+/// How many papers are listed as gaining the most citations.
+const TOP_RISERS: usize = 10;
+
+/// What changed between two graphs.
+pub struct Diff<'a> {
+    pub added_papers: Vec<&'a ProtoPaper>,
+    pub removed_papers: Vec<&'a ProtoPaper>,
+    pub added_references: Vec<&'a Reference>,
+    pub removed_references: Vec<&'a Reference>,
+    /// Papers in both graphs whose citations within the graph grew, by
+    /// how much, most first.
+    pub risers: Vec<(&'a ProtoPaper, usize)>,
+}
+
+/// Each paper in `graph` by id, taking the first of any duplicates.
+fn by_id(graph: &Graph) -> HashMap<&str, &ProtoPaper> {
+    let mut by_id = HashMap::new();
+    for paper in graph.sorted_papers() {
+        if let Some(id) = paper.id() {
+            by_id.entry(id).or_insert(paper);
+        }
+    }
+    by_id
+}
+
+fn in_degrees(graph: &Graph) -> HashMap<&str, usize> {
+    graph
+        .citations
+        .ids()
+        .map(|id| (id, graph.citations.cited_by_count(id)))
+        .collect()
+}
+
+fn sorted_by_title(mut papers: Vec<&ProtoPaper>) -> Vec<&ProtoPaper> {
+    papers.sort_by(|a, b| a.title().cmp(b.title()).then_with(|| a.id().cmp(&b.id())));
+    papers
+}
+
+fn sorted<'a>(references: impl Iterator<Item = &'a Reference>) -> Vec<&'a Reference> {
+    let mut references: Vec<&Reference> = references.collect();
+    references.sort_by(|a, b| (&a.referencer, &a.referencee).cmp(&(&b.referencer, &b.referencee)));
+    references
+}
+
+/// Find what changed from `old` to `new`.
+pub fn diff<'a>(old: &'a Graph, new: &'a Graph) -> Diff<'a> {
+    let old_papers = by_id(old);
+    let new_papers = by_id(new);
+    let added_papers = new_papers
+        .iter()
+        .filter(|(id, _paper)| !old_papers.contains_key(*id))
+        .map(|(_id, paper)| *paper)
+        .collect();
+    let removed_papers = old_papers
+        .iter()
+        .filter(|(id, _paper)| !new_papers.contains_key(*id))
+        .map(|(_id, paper)| *paper)
+        .collect();
+    let old_in_degrees = in_degrees(old);
+    let mut risers: Vec<(&ProtoPaper, usize)> = in_degrees(new)
+        .into_iter()
+        .filter(|(id, _count)| old_papers.contains_key(id))
+        .filter_map(|(id, count)| {
+            let old_count = old_in_degrees.get(id).copied().unwrap_or_default();
+            let paper = *new_papers.get(id)?;
+            (count > old_count).then_some((paper, count - old_count))
+        })
+        .collect();
+    risers.sort_by(|(a, a_gain), (b, b_gain)| b_gain.cmp(a_gain).then_with(|| a.id().cmp(&b.id())));
+    risers.truncate(TOP_RISERS);
+    Diff {
+        added_papers: sorted_by_title(added_papers),
+        removed_papers: sorted_by_title(removed_papers),
+        added_references: sorted(
+            new.citations
+                .references()
+                .filter(|reference| !old.citations.contains_reference(reference)),
+        ),
+        removed_references: sorted(
+            old.citations
+                .references()
+                .filter(|reference| !new.citations.contains_reference(reference)),
+        ),
+        risers,
+    }
+}
+
+fn write_paper_list(out: &mut impl Write, papers: &[&ProtoPaper]) -> std::io::Result<()> {
+    for paper in papers {
+        match paper.url().filter(|url| !url.is_empty()) {
+            Some(url) => writeln!(out, "- [{}]({url})", paper.title())?,
+            None => writeln!(out, "- {}", paper.title())?,
+        }
+    }
+    writeln!(out)
+}
+
+/// Write `diff` as a Markdown report.
+pub fn write_report(out: &mut impl Write, diff: &Diff) -> std::io::Result<()> {
+    writeln!(out, "# Citation graph changes")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} papers added, {} removed; {} citations added, {} removed.",
+        diff.added_papers.len(),
+        diff.removed_papers.len(),
+        diff.added_references.len(),
+        diff.removed_references.len()
+    )?;
+    writeln!(out)?;
+    if !diff.added_papers.is_empty() {
+        writeln!(out, "## Added papers")?;
+        writeln!(out)?;
+        write_paper_list(out, &diff.added_papers)?;
+    }
+    if !diff.removed_papers.is_empty() {
+        writeln!(out, "## Removed papers")?;
+        writeln!(out)?;
+        write_paper_list(out, &diff.removed_papers)?;
+    }
+    if !diff.risers.is_empty() {
+        writeln!(out, "## Most newly cited")?;
+        writeln!(out)?;
+        for (paper, gain) in &diff.risers {
+            writeln!(out, "- {} (+{gain})", paper.title())?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Write both graphs overlaid as a Graphviz digraph, with what was added
+/// in green and what was removed in dashed red.
+pub fn write_dot(out: &mut impl Write, old: &Graph, new: &Graph) -> std::io::Result<()> {
+    let old_papers = by_id(old);
+    let new_papers = by_id(new);
+    let mut ids: Vec<&str> = old_papers
+        .keys()
+        .chain(new_papers.keys())
+        .copied()
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    writeln!(out, "digraph {{")?;
+    for id in ids {
+        let (paper, style) = match (old_papers.get(id), new_papers.get(id)) {
+            (_, Some(paper)) if !old_papers.contains_key(id) => {
+                (paper, ",color=darkgreen,added=true")
+            }
+            (Some(paper), None) => (paper, ",color=red,style=dashed,removed=true"),
+            (_, Some(paper)) => (paper, ""),
+            (None, None) => continue,
+        };
+        writeln!(
+            out,
+            "    \"{}\" [label=\"{}\",URL=\"{}\"{style}];",
+            escape(id),
+            escape(paper.title()),
+            paper.url().unwrap_or_default(),
+        )?;
+    }
+    let all: HashSet<&Reference> = old
+        .citations
+        .references()
+        .chain(new.citations.references())
+        .collect();
+    for reference in sorted(all.into_iter()) {
+        let style = match (
+            old.citations.contains_reference(reference),
+            new.citations.contains_reference(reference),
+        ) {
+            (false, true) => " [color=darkgreen,added=true]",
+            (true, false) => " [color=red,style=dashed,removed=true]",
+            _ => "",
+        };
+        writeln!(
+            out,
+            "    {:?} -> {:?}{style};",
+            reference.referencer, reference.referencee
+        )?;
+    }
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(referencer: &str, referencee: &str) -> Reference {
+        Reference {
+            referencer: referencer.into(),
+            referencee: referencee.into(),
+        }
+    }
+
+    #[test]
+    fn changes_are_found_both_ways() {
+        let mut old = Graph::default();
+        old.citations
+            .extend(["a", "b", "c"].map(|id| ProtoPaper::new(id, id)));
+        old.citations
+            .extend([reference("a", "b"), reference("c", "b")]);
+        let mut new = Graph::default();
+        new.citations
+            .extend(["a", "b", "d"].map(|id| ProtoPaper::new(id, id)));
+        new.citations.extend([
+            reference("a", "b"),
+            reference("d", "b"),
+            reference("d", "a"),
+        ]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(diff.added_papers, vec![&ProtoPaper::new("d", "d")]);
+        assert_eq!(diff.removed_papers, vec![&ProtoPaper::new("c", "c")]);
+        assert_eq!(
+            diff.added_references,
+            vec![&reference("d", "a"), &reference("d", "b")]
+        );
+        assert_eq!(diff.removed_references, vec![&reference("c", "b")]);
+        // b's citations only changed hands, but a gained one
+        assert_eq!(diff.risers, vec![(&ProtoPaper::new("a", "a"), 1)]);
+    }
+}
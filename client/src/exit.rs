@@ -0,0 +1,167 @@
+//! How a failed run is reported, so scripts wrapping the client can tell a
+//! failure worth retrying from one that needs fixing.  Each kind of
+//! failure exits with its own code, taken from `sysexits.h` where one
+//! fits, and with `--error-format json` the error is written to stderr as
+//! a JSON object rather than text.
+
+use std::error::Error;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use crate::id_import;
+use crate::semantic_scholar;
+
+/// How errors are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            other => Err(format!("unknown error format {other:?}; try text or json")),
+        }
+    }
+}
+
+/// The API key was to be read from an environment variable that isn't
+/// set.
+pub struct MissingApiKey(pub String);
+
+impl std::fmt::Debug for MissingApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no API key: {} isn't set", self.0)
+    }
+}
+
+impl std::fmt::Display for MissingApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl Error for MissingApiKey {}
+
+/// What kind of thing went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Anything not covered below, including bad command lines.
+    Other,
+    /// The bibliography couldn't be read or parsed.
+    Bibliography,
+    /// Semantic Scholar or the proxy couldn't be reached.
+    Network,
+    /// Semantic Scholar or the proxy asked for fewer requests.
+    RateLimited,
+    ApiKeyMissing,
+    /// The search ran past `--max-runtime`, though what it found was
+    /// written.
+    OutOfTime,
+}
+
+impl Failure {
+    /// Classify `err` by the first error in its chain that says what
+    /// happened.
+    pub fn of(err: &(dyn Error + 'static)) -> Self {
+        let mut cause = Some(err);
+        while let Some(err) = cause {
+            if let Some(err) = err.downcast_ref::<semantic_scholar::Error>() {
+                match err {
+                    semantic_scholar::Error::RateLimited(_reason) => return Failure::RateLimited,
+                    semantic_scholar::Error::Request(_err) => return Failure::Network,
+                    semantic_scholar::Error::OutOfTime(_limit) => return Failure::OutOfTime,
+                    _ => {}
+                }
+            }
+            if err.is::<reqwest::Error>() {
+                return Failure::Network;
+            }
+            if err.is::<id_import::Error>() {
+                return Failure::Bibliography;
+            }
+            if err.is::<MissingApiKey>() {
+                return Failure::ApiKeyMissing;
+            }
+            cause = err.source();
+        }
+        Failure::Other
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Failure::Other => 1,
+            // EX_DATAERR
+            Failure::Bibliography => 65,
+            // EX_UNAVAILABLE
+            Failure::Network => 69,
+            // EX_TEMPFAIL
+            Failure::RateLimited => 75,
+            // EX_CONFIG
+            Failure::ApiKeyMissing => 78,
+            // what timeout(1) exits with
+            Failure::OutOfTime => 124,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Failure::Other => "other",
+            Failure::Bibliography => "bad_bibliography",
+            Failure::Network => "network",
+            Failure::RateLimited => "rate_limited",
+            Failure::ApiKeyMissing => "api_key_missing",
+            Failure::OutOfTime => "out_of_time",
+        }
+    }
+
+    /// Whether trying again later might work.
+    fn transient(self) -> bool {
+        matches!(self, Failure::Network | Failure::RateLimited)
+    }
+}
+
+/// Write `err` to stderr in `format`, returning the code to exit with.
+pub fn report(err: &(dyn Error + 'static), format: ErrorFormat) -> ExitCode {
+    let failure = Failure::of(err);
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {err:?}"),
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            serde_json::json!({
+                "error": failure.name(),
+                "message": err.to_string(),
+                "transient": failure.transient(),
+                "exit_code": failure.code(),
+            })
+        ),
+    }
+    ExitCode::from(failure.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_found_anywhere_in_the_chain() {
+        let rate_limited: Box<dyn Error> =
+            Box::new(semantic_scholar::Error::RateLimited("slow down".into()));
+        assert_eq!(Failure::of(&*rate_limited), Failure::RateLimited);
+
+        let bibliography = id_import::try_from_bibtex("@article{").unwrap_err();
+        assert_eq!(Failure::of(&bibliography), Failure::Bibliography);
+
+        let missing: Box<dyn Error> = Box::new(MissingApiKey("S2_API_KEY".into()));
+        assert_eq!(Failure::of(&*missing), Failure::ApiKeyMissing);
+
+        let other: Box<dyn Error> = "nothing to search for".into();
+        assert_eq!(Failure::of(&*other), Failure::Other);
+    }
+}
@@ -0,0 +1,134 @@
+//! Papers read from canned responses in a directory instead of fetched,
+//! so builds can be tested without the network or an API key.
+//!
+//! The directory holds `papers.json`, an object from the id a paper is
+//! requested by, like `DOI:10.1000/a` or a Semantic Scholar id, to the
+//! paper as `/paper/batch` returns it, and optionally `contexts.json`, a
+//! list of `{"citing", "cited", "count"}` objects for `--edge-weights`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::crawl::PaperSource;
+use crate::semantic_scholar::{self, Paper, PaperId, SemanticScholar};
+
+const PAPERS_FILE: &str = "papers.json";
+const CONTEXTS_FILE: &str = "contexts.json";
+/// What `--source` is given to read from a fixture, followed by a
+/// colon and the directory.
+pub const SOURCE_PREFIX: &str = "fixture:";
+
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => std::fmt::Debug::fmt(err, f),
+            Error::Json(err) => std::fmt::Debug::fmt(err, f),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Json(err) => Some(err),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Context {
+    citing: String,
+    cited: String,
+    count: usize,
+}
+
+pub struct Fixture {
+    papers: HashMap<String, Paper>,
+    contexts: HashMap<(String, String), usize>,
+}
+
+impl Fixture {
+    /// Read the fixture in `dir`.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let papers = std::fs::read_to_string(dir.join(PAPERS_FILE)).map_err(Error::Io)?;
+        let papers = serde_json::from_str(&papers).map_err(Error::Json)?;
+        let contexts: Vec<Context> = match std::fs::read_to_string(dir.join(CONTEXTS_FILE)) {
+            Ok(contexts) => serde_json::from_str(&contexts).map_err(Error::Json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(Error::Io(err)),
+        };
+        Ok(Self {
+            papers,
+            contexts: contexts
+                .into_iter()
+                .map(|context| ((context.citing, context.cited), context.count))
+                .collect(),
+        })
+    }
+}
+
+impl PaperSource for Fixture {
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+        Ok(paper_ids
+            .iter()
+            .map(|id| self.papers.get(&id.to_string()).cloned())
+            .collect())
+    }
+
+    async fn get_reference_contexts(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+        Ok(self
+            .contexts
+            .iter()
+            .filter(|((citing, _cited), _count)| paper_ids.contains(citing))
+            .map(|(reference, &count)| (reference.clone(), count))
+            .collect())
+    }
+}
+
+/// Either the API or a fixture standing in for it.
+pub enum Source<'a> {
+    Api(&'a SemanticScholar),
+    Fixture(Fixture),
+}
+
+impl PaperSource for Source<'_> {
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+        match self {
+            Source::Api(api) => api.get_paper_batch(paper_ids).await,
+            Source::Fixture(fixture) => fixture.get_paper_batch(paper_ids).await,
+        }
+    }
+
+    async fn get_reference_contexts(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+        match self {
+            Source::Api(api) => api.get_reference_contexts(paper_ids).await,
+            Source::Fixture(fixture) => fixture.get_reference_contexts(paper_ids).await,
+        }
+    }
+}
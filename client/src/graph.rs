@@ -0,0 +1,1036 @@
+//! The citation graph itself and the analyses run over it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::annotations::{Annotation, Annotations};
+use crate::books::Book;
+use crate::citation_graph::CitationGraph;
+use crate::intern::Id;
+use crate::semantic_scholar::{normalize_doi, Paper, PaperId, ProtoPaper};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reference {
+    pub referencer: Id,
+    pub referencee: Id,
+}
+
+/// How a graph was built, so its exports can say.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Legend {
+    /// The bibliography searched from, by file name.
+    pub bibliography: String,
+    /// When the search ran, as `YYYY-MM-DD`.
+    pub date: String,
+    pub max_depth: usize,
+    pub connectivity: f64,
+    /// How many papers the search expanded at each depth.
+    pub expanded_per_depth: Vec<usize>,
+    /// The chunks of papers the search had to leave out.
+    pub failed_chunks: Vec<crate::crawl::FailedChunk>,
+}
+
+/// `time` as a UTC `YYYY-MM-DD` date.
+pub fn date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        / 86_400;
+    // Howard Hinnant's civil_from_days, for years starting in March
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// The finished graph and what's known about its papers.
+#[derive(Default)]
+pub struct Graph {
+    pub citations: CitationGraph,
+    /// How sure we are of each seed that wasn't matched exactly, by id.
+    pub match_confidence: HashMap<String, f64>,
+    /// How many times each citation is made in the citing paper's text.
+    pub edge_weights: HashMap<Reference, usize>,
+    /// Papers added on Semantic Scholar's recommendation rather than
+    /// found by following references.
+    pub recommended: HashSet<String>,
+    /// Papers already in the user's bibliography or library, as opposed
+    /// to ones they might need to get.
+    pub have: HashSet<String>,
+    /// The papers searched out from, which pruning never drops.
+    pub seeds: HashSet<String>,
+    /// The papers by the author picked out with `--highlight-author`.
+    pub highlighted: HashSet<String>,
+    /// The user's notes, tags, and colors, by id, from `--annotations`.
+    pub annotations: HashMap<String, Annotation>,
+    /// The ids the seeds were looked up by, e.g. `DOI:10.1000/182`, so an
+    /// update can tell which are new.
+    pub sources: HashSet<String>,
+    /// Papers that weren't in the graph this one updates.
+    pub added: HashSet<String>,
+    /// Citations that weren't in the graph this one updates.
+    pub added_references: HashSet<Reference>,
+    /// Which catalogue each paper's title, url, year, and venue came from,
+    /// when more than Semantic Scholar was asked.
+    pub provenance: HashMap<String, BTreeMap<String, String>>,
+    /// The key each seed has in the bibliography, by id.
+    pub bibtex_keys: HashMap<String, String>,
+    /// How the graph was built, if it was rather than loaded.
+    pub legend: Option<Legend>,
+}
+
+/// Titles shorter than this, once normalized, are too generic ("Introduction")
+/// to take as proof two papers are the same.
+const MIN_DEDUPE_TITLE_LENGTH: usize = 20;
+/// DOIs arXiv mints for its preprints.
+const ARXIV_DOI_PREFIX: &str = "10.48550/";
+
+/// Lowercase `title` and reduce it to single-spaced words.
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Pruning stops after this many passes, whether or not more would drop.
+const PRUNE_PASSES: usize = 10;
+
+/// Community detection gives up after this many passes, converged or not.
+const MAX_COMMUNITY_PASSES: usize = 100;
+
+impl Graph {
+    /// Group the papers into communities by greedily moving each into
+    /// whichever neighbouring community most improves modularity (the
+    /// first phase of the Louvain method), treating citations as
+    /// undirected.
+    ///
+    /// Papers are visited in id order and ties go to the paper's current
+    /// community, then the smallest, so the same graph always gives the
+    /// same communities.  These are returned largest first, each sorted
+    /// by id.
+    pub fn communities(&self) -> Vec<Vec<String>> {
+        let mut ids: Vec<&str> = self.citations.ids().collect();
+        ids.sort_unstable();
+        let index: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        let neighbors: Vec<Vec<usize>> = ids
+            .iter()
+            .map(|&id| {
+                self.citations
+                    .neighbors(id)
+                    .filter_map(|neighbor| index.get(neighbor).copied())
+                    .collect()
+            })
+            .collect();
+        let degrees: Vec<usize> = neighbors.iter().map(Vec::len).collect();
+        let edge_count = degrees.iter().sum::<usize>() as f64 / 2.0;
+
+        let mut labels: Vec<usize> = (0..ids.len()).collect();
+        let mut totals = degrees.clone();
+        for _ in 0..MAX_COMMUNITY_PASSES {
+            if edge_count == 0.0 {
+                break;
+            }
+            let mut moved = false;
+            for node in 0..ids.len() {
+                let current = labels[node];
+                totals[current] -= degrees[node];
+                let mut links = BTreeMap::<usize, usize>::new();
+                for &neighbor in &neighbors[node] {
+                    *links.entry(labels[neighbor]).or_default() += 1;
+                }
+                let gain = |label: usize, links: usize| {
+                    links as f64 / edge_count
+                        - totals[label] as f64 * degrees[node] as f64
+                            / (2.0 * edge_count * edge_count)
+                };
+                let mut best = (
+                    current,
+                    gain(current, links.get(&current).copied().unwrap_or(0)),
+                );
+                for (&label, &links) in &links {
+                    let gain = gain(label, links);
+                    if gain > best.1 + f64::EPSILON {
+                        best = (label, gain);
+                    }
+                }
+                labels[node] = best.0;
+                totals[best.0] += degrees[node];
+                moved |= best.0 != current;
+            }
+            if !moved {
+                break;
+            }
+        }
+
+        let mut communities = BTreeMap::<usize, Vec<String>>::new();
+        for (node, label) in labels.into_iter().enumerate() {
+            communities
+                .entry(label)
+                .or_default()
+                .push(ids[node].to_string());
+        }
+        let mut communities: Vec<Vec<String>> = communities.into_values().collect();
+        communities.sort_by_key(|community| std::cmp::Reverse(community.len()));
+        communities
+    }
+
+    /// Repeatedly drop papers other than the seeds with at most one
+    /// citation in each direction, and the references left dangling by
+    /// that.
+    ///
+    /// If `depth_weighted`, papers a citation from a seed are kept too,
+    /// and those further out are dropped with up to one fewer citation
+    /// than their distance, so strays far from the bibliography go first.
+    pub fn prune(&mut self, depth_weighted: bool) {
+        let seeds = &self.seeds;
+        let distances =
+            depth_weighted.then(|| self.citations.distances(seeds.iter().map(String::as_str)));
+        self.citations.prune(PRUNE_PASSES, |id| {
+            if seeds.contains(id) {
+                return None;
+            }
+            match distances.as_ref().and_then(|distances| distances.get(id)) {
+                Some(&distance) if distance <= 1 => None,
+                Some(&distance) => Some(distance - 1),
+                None => Some(1),
+            }
+        });
+        let citations = &self.citations;
+        self.added.retain(|id| citations.contains_paper(id));
+        self.provenance
+            .retain(|id, _provenance| citations.contains_paper(id));
+        self.added_references
+            .retain(|reference| citations.contains_reference(reference));
+    }
+
+    /// The papers by id, then title, so exports are the same from run to
+    /// run whatever order the sets are in.
+    pub fn sorted_papers(&self) -> Vec<&ProtoPaper> {
+        let mut papers: Vec<&ProtoPaper> = self.citations.papers().collect();
+        papers.sort_by(|a, b| a.id().cmp(&b.id()).then_with(|| a.title().cmp(b.title())));
+        papers
+    }
+
+    /// The citations by citing paper, then cited paper.
+    pub fn sorted_references(&self) -> Vec<&Reference> {
+        let mut references: Vec<&Reference> = self.citations.references().collect();
+        references.sort_unstable();
+        references
+    }
+
+    /// Drop citations of a paper by itself and those naming a paper that
+    /// isn't in the graph, along with their weights, returning how many
+    /// were dropped.  A citation listed twice is already one, as the
+    /// references are a set.
+    pub fn sanitize(&mut self) -> usize {
+        let ids: HashSet<String> = self.citations.ids().map(str::to_string).collect();
+        let before = self.citations.reference_count();
+        self.citations.retain_references(|reference| {
+            reference.referencer != reference.referencee
+                && ids.contains(&*reference.referencer)
+                && ids.contains(&*reference.referencee)
+        });
+        let citations = &self.citations;
+        self.edge_weights
+            .retain(|reference, _weight| citations.contains_reference(reference));
+        self.added_references
+            .retain(|reference| citations.contains_reference(reference));
+        before - self.citations.reference_count()
+    }
+
+    /// Add recommended papers not already in the graph, along with their
+    /// references to papers that are.
+    pub fn add_recommendations(&mut self, papers: impl IntoIterator<Item = Paper>) {
+        for paper in papers {
+            if self.citations.contains_paper(paper.id()) {
+                continue;
+            }
+            let references: Vec<Reference> = paper
+                .references()
+                .iter()
+                .filter_map(|reference| reference.id())
+                .filter(|&ref_id| self.citations.contains_paper(ref_id))
+                .map(|ref_id| Reference {
+                    referencer: paper.id().into(),
+                    referencee: ref_id.into(),
+                })
+                .collect();
+            self.citations.extend(references);
+            self.recommended.insert(paper.id().to_string());
+            self.citations.insert_paper(paper.into());
+        }
+    }
+
+    /// Add books as papers with no references of their own.
+    ///
+    /// Semantic Scholar sometimes lists a book among a paper's references
+    /// under its own id, so any paper with the same title is taken to be
+    /// the book and merged into it.
+    pub fn add_books(&mut self, books: impl IntoIterator<Item = Book>) {
+        let normalize = |title: &str| {
+            title
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        };
+        for book in books {
+            let id = PaperId::Isbn(book.isbn).to_string();
+            let book_id: Id = id.as_str().into();
+            let title = normalize(&book.title);
+            let duplicates: HashSet<String> = self
+                .citations
+                .papers()
+                .filter(|paper| normalize(paper.title()) == title)
+                .filter_map(|paper| paper.id().map(str::to_string))
+                .collect();
+            self.citations
+                .retain_papers(|paper| !paper.id().is_some_and(|id| duplicates.contains(id)));
+            self.citations.map_references(|mut reference| {
+                if duplicates.contains(&*reference.referencer) {
+                    reference.referencer = book_id.clone();
+                }
+                if duplicates.contains(&*reference.referencee) {
+                    reference.referencee = book_id.clone();
+                }
+                Some(reference)
+            });
+            self.citations
+                .insert_paper(ProtoPaper::new(&id, &book.title).with_url(book.url));
+        }
+    }
+
+    /// Papers cited by at least `min_seeds` of the seed papers, with how
+    /// many, most first, ties broken by id.
+    pub fn common_references(&self, min_seeds: usize) -> Vec<(&ProtoPaper, usize)> {
+        let mut counts = HashMap::<&str, usize>::new();
+        for seed in &self.seeds {
+            for id in self.citations.cited(seed) {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+        let mut common: Vec<(&ProtoPaper, usize)> = counts
+            .into_iter()
+            .filter(|&(_id, count)| count >= min_seeds)
+            .filter_map(|(id, count)| Some((self.citations.paper(id)?, count)))
+            .collect();
+        common.sort_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.id().cmp(&b.id()))
+        });
+        common
+    }
+
+    /// Give each paper its TL;DR from `tldrs`, keyed by id.
+    pub fn add_tldrs(&mut self, tldrs: &HashMap<String, String>) {
+        for paper in self.citations.take_papers() {
+            let tldr = paper.id().and_then(|id| tldrs.get(id)).cloned();
+            self.citations.insert_paper(match tldr {
+                Some(tldr) => paper.with_tldr(Some(tldr)),
+                None => paper,
+            });
+        }
+    }
+
+    /// Highlight the papers by the author going by `name`.
+    pub fn highlight_author(&mut self, name: &str) {
+        self.highlighted = self
+            .citations
+            .papers()
+            .filter(|paper| paper.has_author(name))
+            .filter_map(|paper| paper.id().map(str::to_string))
+            .collect();
+    }
+
+    /// Each paper's id by every name it can be given: the id itself and,
+    /// if it has one, its DOI as written by [`PaperId`].
+    fn ids_by_name(&self) -> HashMap<String, String> {
+        let mut ids = HashMap::<String, String>::new();
+        for paper in self.citations.papers() {
+            let Some(id) = paper.id() else {
+                continue;
+            };
+            ids.insert(id.to_string(), id.to_string());
+            if let Some(doi) = paper.external_id("DOI") {
+                let doi = PaperId::Doi(normalize_doi(doi)).to_string();
+                ids.insert(doi, id.to_string());
+            }
+        }
+        ids
+    }
+
+    /// The id of the paper `name` names, by id or DOI.
+    pub fn find(&self, name: &str) -> Option<String> {
+        self.ids_by_name().remove(&PaperId::canonical(name))
+    }
+
+    /// Attach each of `annotations` to the paper it names by id or DOI,
+    /// merging them with any it already has, and return the names that
+    /// matched no paper.
+    pub fn annotate<'a>(&mut self, annotations: &'a Annotations) -> Vec<&'a str> {
+        let ids = self.ids_by_name();
+        let mut unmatched = Vec::<&str>::new();
+        for (name, annotation) in annotations {
+            match ids.get(&PaperId::canonical(name)) {
+                Some(id) => self
+                    .annotations
+                    .entry(id.clone())
+                    .or_default()
+                    .merge(annotation),
+                None => unmatched.push(name),
+            }
+        }
+        unmatched
+    }
+
+    /// Only the papers within `radius` citations, followed either way, of
+    /// the paper `id`, and the citations between them.
+    pub fn neighborhood(&self, id: &str, radius: usize) -> Graph {
+        let distances = self.citations.distances([id]);
+        self.subgraph(
+            distances
+                .iter()
+                .filter(|(_id, &distance)| distance <= radius)
+                .map(|(id, _distance)| id.as_str()),
+        )
+    }
+
+    /// Only the papers tagged with any of `tags`, ignoring case, and the
+    /// citations between them.
+    pub fn tagged(&self, tags: &[String]) -> Graph {
+        self.subgraph(
+            self.annotations
+                .iter()
+                .filter(|(_id, annotation)| tags.iter().any(|tag| annotation.has_tag(tag)))
+                .map(|(id, _annotation)| id.as_str()),
+        )
+    }
+
+    /// Paper ids, most cited within the graph first, ties broken by id.
+    pub fn ranked_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.citations.ids().collect();
+        ids.sort_unstable_by(|a, b| {
+            let in_degree = |id| self.citations.cited_by_count(id);
+            in_degree(b).cmp(&in_degree(a)).then_with(|| a.cmp(b))
+        });
+        ids
+    }
+
+    /// The graph condensed to a node per publication year, each citation
+    /// between papers counted toward an edge between their years,
+    /// weighted by how many there are.  Papers without a year are left
+    /// out.
+    pub fn by_year(&self) -> Graph {
+        let mut years = BTreeMap::<u32, usize>::new();
+        let mut year_of = HashMap::<&str, u32>::new();
+        for paper in self.citations.papers() {
+            let (Some(id), Some(year)) = (paper.id(), paper.year()) else {
+                continue;
+            };
+            if year_of.insert(id, year).is_none() {
+                *years.entry(year).or_default() += 1;
+            }
+        }
+        let mut edge_weights = HashMap::<Reference, usize>::new();
+        for reference in self.citations.references() {
+            let (Some(citing), Some(cited)) = (
+                year_of.get(reference.referencer.as_ref()),
+                year_of.get(reference.referencee.as_ref()),
+            ) else {
+                continue;
+            };
+            let reference = Reference {
+                referencer: citing.to_string().into(),
+                referencee: cited.to_string().into(),
+            };
+            *edge_weights.entry(reference).or_default() += 1;
+        }
+        Graph {
+            citations: CitationGraph::new(
+                years.iter().map(|(year, count)| {
+                    let papers = if *count == 1 { "paper" } else { "papers" };
+                    ProtoPaper::new(&year.to_string(), &format!("{year} ({count} {papers})"))
+                        .with_year(Some(*year))
+                }),
+                edge_weights.keys().cloned(),
+            ),
+            edge_weights,
+            legend: self.legend.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The part of the graph made of just the papers in `ids`.
+    pub fn subgraph<'a>(&self, ids: impl IntoIterator<Item = &'a str>) -> Graph {
+        let ids: HashSet<&str> = ids.into_iter().collect();
+        let contains = |id: &str| ids.contains(id);
+        let contains_reference = |reference: &Reference| {
+            contains(reference.referencer.as_ref()) && contains(reference.referencee.as_ref())
+        };
+        Graph {
+            citations: CitationGraph::new(
+                self.citations
+                    .papers()
+                    .filter(|paper| paper.id().is_some_and(contains))
+                    .cloned(),
+                self.citations
+                    .references()
+                    .filter(|reference| contains_reference(reference))
+                    .cloned(),
+            ),
+            match_confidence: self
+                .match_confidence
+                .iter()
+                .filter(|(id, _confidence)| contains(id))
+                .map(|(id, &confidence)| (id.clone(), confidence))
+                .collect(),
+            edge_weights: self
+                .edge_weights
+                .iter()
+                .filter(|(reference, _weight)| contains_reference(reference))
+                .map(|(reference, &weight)| (reference.clone(), weight))
+                .collect(),
+            recommended: self
+                .recommended
+                .iter()
+                .filter(|id| contains(id))
+                .cloned()
+                .collect(),
+            have: self
+                .have
+                .iter()
+                .filter(|id| contains(id))
+                .cloned()
+                .collect(),
+            seeds: self
+                .seeds
+                .iter()
+                .filter(|id| contains(id))
+                .cloned()
+                .collect(),
+            highlighted: self
+                .highlighted
+                .iter()
+                .filter(|id| contains(id))
+                .cloned()
+                .collect(),
+            annotations: self
+                .annotations
+                .iter()
+                .filter(|(id, _annotation)| contains(id))
+                .map(|(id, annotation)| (id.clone(), annotation.clone()))
+                .collect(),
+            sources: self.sources.clone(),
+            added: self
+                .added
+                .iter()
+                .filter(|id| contains(id))
+                .cloned()
+                .collect(),
+            added_references: self
+                .added_references
+                .iter()
+                .filter(|reference| contains_reference(reference))
+                .cloned()
+                .collect(),
+            provenance: self
+                .provenance
+                .iter()
+                .filter(|(id, _provenance)| contains(id))
+                .map(|(id, provenance)| (id.clone(), provenance.clone()))
+                .collect(),
+            bibtex_keys: self
+                .bibtex_keys
+                .iter()
+                .filter(|(id, _key)| contains(id))
+                .map(|(id, key)| (id.clone(), key.clone()))
+                .collect(),
+            legend: self.legend.clone(),
+        }
+    }
+
+    /// Merge papers that are the same work under different ids, like an
+    /// arXiv preprint and its journal version, returning how many were
+    /// merged away.
+    ///
+    /// Papers are the same if they share a DOI or arXiv id, or their
+    /// titles match once case and punctuation are ignored.  Each group
+    /// is merged into the paper with a non-arXiv DOI if there is one, then
+    /// the most cited, and its citations are redirected there.
+    pub fn dedupe(&mut self) -> usize {
+        let papers = self.sorted_papers();
+        let ids: Vec<&str> = papers.iter().filter_map(|paper| paper.id()).collect();
+        let index: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        let mut parents: Vec<usize> = (0..ids.len()).collect();
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+        let mut first_with_key = HashMap::<String, usize>::new();
+        for paper in &papers {
+            let Some(&i) = paper.id().and_then(|id| index.get(id)) else {
+                continue;
+            };
+            let title = normalize_title(paper.title());
+            let keys = [
+                paper
+                    .external_id("DOI")
+                    .map(|doi| format!("doi:{}", normalize_doi(doi))),
+                paper
+                    .external_id("ArXiv")
+                    .map(|arxiv| format!("arxiv:{arxiv}")),
+                (title.len() >= MIN_DEDUPE_TITLE_LENGTH).then(|| format!("title:{title}")),
+            ];
+            for key in keys.into_iter().flatten() {
+                let j = *first_with_key.entry(key).or_insert(i);
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut groups = HashMap::<usize, Vec<&ProtoPaper>>::new();
+        for paper in &papers {
+            if let Some(&i) = paper.id().and_then(|id| index.get(id)) {
+                groups.entry(root(&mut parents, i)).or_default().push(paper);
+            }
+        }
+        let mut canonical = HashMap::<String, String>::new();
+        let mut keep = HashSet::<&ProtoPaper>::new();
+        for group in groups.into_values() {
+            let best = *group
+                .iter()
+                .max_by(|a, b| {
+                    let journal_doi = |paper: &ProtoPaper| {
+                        paper
+                            .external_id("DOI")
+                            .is_some_and(|doi| !normalize_doi(doi).starts_with(ARXIV_DOI_PREFIX))
+                    };
+                    let cited = |paper: &ProtoPaper| {
+                        paper.id().map_or(0, |id| self.citations.cited_by_count(id))
+                    };
+                    (journal_doi(a), cited(a))
+                        .cmp(&(journal_doi(b), cited(b)))
+                        .then_with(|| b.id().cmp(&a.id()))
+                })
+                .expect("groups aren't empty");
+            keep.insert(best);
+            let best_id = best.id().expect("grouped papers have ids");
+            for paper in group {
+                if let Some(id) = paper.id().filter(|&id| id != best_id) {
+                    canonical.insert(id.to_string(), best_id.to_string());
+                }
+            }
+        }
+        let keep: HashSet<ProtoPaper> = keep.into_iter().cloned().collect();
+        let merged =
+            papers.len() - papers.iter().filter(|paper| paper.id().is_none()).count() - keep.len();
+        if merged == 0 {
+            return 0;
+        }
+        self.citations.retain_papers(|paper| keep.contains(paper));
+
+        let rename = |id: String| canonical.get(&id).cloned().unwrap_or(id);
+        let rename_id = |id: Id| {
+            canonical
+                .get(&*id)
+                .map_or(id, |canonical| canonical.as_str().into())
+        };
+        let rename_reference = |reference: Reference| Reference {
+            referencer: rename_id(reference.referencer),
+            referencee: rename_id(reference.referencee),
+        };
+        let not_loop = |reference: &Reference| reference.referencer != reference.referencee;
+        self.citations
+            .map_references(|reference| Some(rename_reference(reference)).filter(not_loop));
+        let mut edge_weights = HashMap::new();
+        for (reference, weight) in self.edge_weights.drain() {
+            let reference = rename_reference(reference);
+            if not_loop(&reference) {
+                let merged_weight: &mut usize = edge_weights.entry(reference).or_default();
+                *merged_weight = (*merged_weight).max(weight);
+            }
+        }
+        self.edge_weights = edge_weights;
+        let mut match_confidence = HashMap::<String, f64>::new();
+        for (id, confidence) in self.match_confidence.drain() {
+            let merged_confidence = match_confidence.entry(rename(id)).or_insert(confidence);
+            *merged_confidence = merged_confidence.max(confidence);
+        }
+        self.match_confidence = match_confidence;
+        self.recommended = self.recommended.drain().map(rename).collect();
+        self.have = self.have.drain().map(rename).collect();
+        self.seeds = self.seeds.drain().map(rename).collect();
+        self.added = self.added.drain().map(rename).collect();
+        self.provenance = self
+            .provenance
+            .drain()
+            .map(|(id, provenance)| (rename(id), provenance))
+            .collect();
+        self.bibtex_keys = self
+            .bibtex_keys
+            .drain()
+            .map(|(id, key)| (rename(id), key))
+            .collect();
+        self.added_references = self
+            .added_references
+            .drain()
+            .map(rename_reference)
+            .filter(not_loop)
+            .collect();
+        merged
+    }
+
+    /// Merge in the graph this one updates, marking whatever it didn't
+    /// have as added.
+    ///
+    /// Papers in both are kept as this graph has them.
+    pub fn merge_previous(&mut self, previous: Graph) {
+        self.added = self
+            .citations
+            .ids()
+            .filter(|id| !previous.citations.contains_paper(id))
+            .map(str::to_string)
+            .collect();
+        self.added_references = self
+            .citations
+            .references()
+            .filter(|reference| !previous.citations.contains_reference(reference))
+            .cloned()
+            .collect();
+        let ids: HashSet<String> = self.citations.ids().map(str::to_string).collect();
+        self.citations.extend(
+            previous
+                .citations
+                .papers()
+                .filter(|paper| paper.id().is_none_or(|id| !ids.contains(id)))
+                .cloned(),
+        );
+        self.citations
+            .extend(previous.citations.references().cloned());
+        for (id, confidence) in previous.match_confidence {
+            self.match_confidence.entry(id).or_insert(confidence);
+        }
+        for (reference, weight) in previous.edge_weights {
+            self.edge_weights.entry(reference).or_insert(weight);
+        }
+        for (id, provenance) in previous.provenance {
+            self.provenance.entry(id).or_insert(provenance);
+        }
+        for (id, key) in previous.bibtex_keys {
+            self.bibtex_keys.entry(id).or_insert(key);
+        }
+        self.recommended.extend(previous.recommended);
+        self.have.extend(previous.have);
+        self.seeds.extend(previous.seeds);
+        self.sources.extend(previous.sources);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(referencer: &str, referencee: &str) -> Reference {
+        Reference {
+            referencer: referencer.into(),
+            referencee: referencee.into(),
+        }
+    }
+
+    #[test]
+    fn neighborhoods_follow_citations_either_way() {
+        let mut graph = Graph::default();
+        for id in ["a", "b", "c", "d", "e"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        // a -> b <- c -> d -> e
+        for (from, to) in [("a", "b"), ("c", "b"), ("c", "d"), ("d", "e")] {
+            graph.citations.insert_reference(reference(from, to));
+        }
+        let neighborhood = graph.neighborhood("a", 2);
+        let mut ids: Vec<&str> = neighborhood.citations.ids().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(neighborhood.citations.reference_count(), 2);
+    }
+
+    #[test]
+    fn annotations_find_papers_by_id_or_doi_and_tag_them() {
+        let mut graph = Graph::default();
+        graph
+            .citations
+            .insert_paper(ProtoPaper::new("a", "Paper a"));
+        graph.citations.insert_paper(
+            serde_json::from_value(serde_json::json!({
+                "paperId": "b",
+                "title": "Paper b",
+                "url": null,
+                "externalIds": {"DOI": "10.1000/B"},
+            }))
+            .unwrap(),
+        );
+        let annotations: Annotations = toml::from_str(
+            r#"
+            a = { note = "start here" }
+            "https://doi.org/10.1000/b" = { tags = ["method"] }
+            c = { color = "gold" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(graph.annotate(&annotations), vec!["c"]);
+        assert_eq!(graph.annotations["a"].note.as_deref(), Some("start here"));
+        assert_eq!(graph.annotations["b"].tags, vec!["method"]);
+        let tagged = graph.tagged(&["Method".into()]);
+        assert_eq!(tagged.citations.ids().collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn two_triangles_are_two_communities() {
+        let mut graph = Graph::default();
+        for id in ["a", "b", "c", "x", "y", "z"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        graph.citations.extend([
+            reference("a", "b"),
+            reference("b", "c"),
+            reference("c", "a"),
+            reference("x", "y"),
+            reference("y", "z"),
+            reference("z", "x"),
+            reference("c", "x"),
+        ]);
+        let mut communities = graph.communities();
+        communities.sort();
+        assert_eq!(communities, vec![vec!["a", "b", "c"], vec!["x", "y", "z"]]);
+        let subgraph = graph.subgraph(communities[0].iter().map(String::as_str));
+        assert_eq!(subgraph.citations.paper_count(), 3);
+        assert_eq!(subgraph.citations.reference_count(), 3);
+    }
+
+    #[test]
+    fn shared_references_rank_by_how_many_seeds_cite_them() {
+        let mut graph = Graph::default();
+        for id in ["s1", "s2", "s3", "a", "b", "c"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        graph.citations.extend([
+            reference("s1", "a"),
+            reference("s2", "a"),
+            reference("s3", "a"),
+            reference("s1", "b"),
+            reference("s2", "b"),
+            reference("s3", "c"),
+            reference("a", "c"),
+        ]);
+        graph.seeds.extend(["s1", "s2", "s3"].map(String::from));
+        let common: Vec<(Option<&str>, usize)> = graph
+            .common_references(2)
+            .into_iter()
+            .map(|(paper, count)| (paper.id(), count))
+            .collect();
+        assert_eq!(common, vec![(Some("a"), 3), (Some("b"), 2)]);
+    }
+
+    #[test]
+    fn years_count_the_citations_between_them() {
+        let mut graph = Graph::default();
+        graph.citations.extend([
+            ProtoPaper::new("a", "a").with_year(Some(2020)),
+            ProtoPaper::new("b", "b").with_year(Some(2020)),
+            ProtoPaper::new("c", "c").with_year(Some(2010)),
+            ProtoPaper::new("d", "d"),
+        ]);
+        graph.citations.extend([
+            reference("a", "c"),
+            reference("b", "c"),
+            reference("a", "b"),
+            reference("a", "d"),
+        ]);
+        let by_year = graph.by_year();
+        assert_eq!(
+            by_year.citations.paper("2020").map(ProtoPaper::title),
+            Some("2020 (2 papers)")
+        );
+        assert_eq!(by_year.edge_weights[&reference("2020", "2010")], 2);
+        assert_eq!(by_year.edge_weights[&reference("2020", "2020")], 1);
+        assert_eq!(by_year.citations.reference_count(), 2);
+    }
+
+    #[test]
+    fn seeds_survive_pruning() {
+        let mut graph = Graph::default();
+        for id in ["seed", "a", "b"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        graph
+            .citations
+            .extend([reference("seed", "a"), reference("a", "b")]);
+        graph.seeds.insert("seed".into());
+        graph.prune(false);
+        let ids: Vec<&str> = graph.citations.ids().collect();
+        assert_eq!(ids, vec!["seed"]);
+    }
+
+    #[test]
+    fn depth_weighted_pruning_spares_the_seeds_neighbors() {
+        // the seed cites a and b, which only c also cites; d is three
+        // citations out, cited by c and citing c and e
+        let graph = || {
+            let mut graph = Graph::default();
+            for id in ["seed", "a", "b", "c", "d", "e"] {
+                graph.citations.insert_paper(ProtoPaper::new(id, id));
+            }
+            graph.citations.extend([
+                reference("seed", "a"),
+                reference("seed", "b"),
+                reference("a", "c"),
+                reference("b", "c"),
+                reference("c", "d"),
+                reference("d", "c"),
+                reference("d", "e"),
+            ]);
+            graph.seeds.insert("seed".into());
+            graph
+        };
+        let mut unweighted = graph();
+        unweighted.prune(false);
+        let ids: Vec<&str> = unweighted.citations.ids().collect();
+        assert_eq!(ids, vec!["seed"]);
+
+        let mut weighted = graph();
+        weighted.prune(true);
+        let mut ids: Vec<&str> = weighted.citations.ids().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec!["a", "b", "c", "seed"]);
+    }
+
+    #[test]
+    fn pruning_peels_a_layer_a_pass() {
+        // x and y hang off a triangle, and z hangs off y, which is cited
+        // by z and cites a paper that isn't in the graph
+        let mut graph = Graph::default();
+        for id in ["a", "b", "c", "x", "y", "z"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        graph.citations.extend([
+            reference("a", "b"),
+            reference("b", "c"),
+            reference("c", "a"),
+            reference("b", "a"),
+            reference("c", "b"),
+            reference("a", "c"),
+            reference("x", "a"),
+            reference("y", "a"),
+            reference("y", "missing"),
+            reference("z", "y"),
+        ]);
+        graph.prune(false);
+        let mut ids: Vec<&str> = graph.citations.ids().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(graph.citations.reference_count(), 6);
+    }
+
+    #[test]
+    fn self_citations_and_dangling_citations_are_dropped() {
+        let mut graph = Graph::default();
+        for id in ["a", "b"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        graph.citations.extend([
+            reference("a", "b"),
+            reference("a", "b"),
+            reference("a", "a"),
+            reference("b", "missing"),
+        ]);
+        graph.edge_weights.insert(reference("a", "a"), 2);
+        assert_eq!(graph.sanitize(), 2);
+        assert_eq!(
+            graph.citations.references().collect::<Vec<_>>(),
+            vec![&reference("a", "b")]
+        );
+        assert!(graph.edge_weights.is_empty());
+    }
+
+    #[test]
+    fn updates_mark_only_new_papers_and_citations() {
+        let mut previous = Graph::default();
+        previous
+            .citations
+            .extend([ProtoPaper::new("a", "a"), ProtoPaper::new("b", "b")]);
+        previous.citations.insert_reference(reference("a", "b"));
+        previous.sources.insert("DOI:10.1000/a".into());
+        let mut graph = Graph::default();
+        graph
+            .citations
+            .extend([ProtoPaper::new("c", "c"), ProtoPaper::new("b", "b")]);
+        graph.citations.insert_reference(reference("c", "b"));
+        graph.sources.insert("DOI:10.1000/c".into());
+
+        graph.merge_previous(previous);
+        assert_eq!(graph.citations.paper_count(), 3);
+        assert_eq!(graph.added, HashSet::from(["c".to_string()]));
+        assert_eq!(graph.added_references, HashSet::from([reference("c", "b")]));
+        assert_eq!(graph.citations.reference_count(), 2);
+        assert_eq!(graph.sources.len(), 2);
+    }
+
+    #[test]
+    fn preprints_merge_into_their_journal_versions() {
+        let paper = |id: &str, title: &str, doi: &str| -> ProtoPaper {
+            serde_json::from_value(serde_json::json!({
+                "paperId": id,
+                "title": title,
+                "url": null,
+                "externalIds": {"DOI": doi, "CorpusId": 1},
+            }))
+            .unwrap()
+        };
+        let mut graph = Graph::default();
+        graph.citations.extend([
+            paper(
+                "preprint",
+                "Attention Is All You Need",
+                "10.48550/arXiv.1706.03762",
+            ),
+            paper(
+                "journal",
+                "Attention is all you need.",
+                "10.5555/3295222.3295349",
+            ),
+            ProtoPaper::new("citer", "citer"),
+        ]);
+        graph.citations.extend([
+            reference("citer", "preprint"),
+            reference("citer", "journal"),
+            reference("preprint", "journal"),
+        ]);
+        graph.have.insert("preprint".into());
+
+        assert_eq!(graph.dedupe(), 1);
+        let ids: HashSet<&str> = graph.citations.ids().collect();
+        assert_eq!(ids, HashSet::from(["journal", "citer"]));
+        assert_eq!(
+            graph.citations.references().collect::<Vec<_>>(),
+            vec![&reference("citer", "journal")]
+        );
+        assert_eq!(graph.have, HashSet::from(["journal".to_string()]));
+    }
+}
@@ -0,0 +1,237 @@
+//! The job server's record of every job it's been given, kept in SQLite
+//! so past graphs can be listed, rendered again, and compared after a
+//! restart without crawling for them again.
+//!
+//! Graphs are kept as the JSON `saved` writes, which is what `load`
+//! reads back.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::graph::Graph;
+use crate::jobs::Queued;
+use crate::saved;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (
+        id INTEGER PRIMARY KEY,
+        submitted INTEGER NOT NULL,
+        bibliography TEXT NOT NULL,
+        parameters TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        graph TEXT
+    );
+";
+
+fn to_io_error(err: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(err)
+}
+
+/// A past job, as listed.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub id: u64,
+    /// When it was submitted, in seconds since the Unix epoch.
+    pub submitted: u64,
+    pub parameters: Vec<(String, String)>,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+pub struct History {
+    connection: Mutex<Connection>,
+}
+
+impl History {
+    /// Open the history at `path`, creating it if there's none.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let connection = Connection::open(path).map_err(to_io_error)?;
+        connection.execute_batch(SCHEMA).map_err(to_io_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn insert(&self, job: &Queued) -> std::io::Result<()> {
+        let submitted = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let parameters = serde_json::to_string(&job.parameters)?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO jobs (id, submitted, bibliography, parameters, status)
+                    VALUES (?1, ?2, ?3, ?4, 'queued')",
+                params![job.id as i64, submitted, job.bibliography, parameters],
+            )
+            .map_err(to_io_error)?;
+        Ok(())
+    }
+
+    /// Record job `id`'s status, with its error if it failed or its graph
+    /// if it's done.
+    pub fn set(
+        &self,
+        id: u64,
+        status: &str,
+        error: Option<&str>,
+        graph: Option<&Graph>,
+    ) -> std::io::Result<()> {
+        let graph = match graph {
+            Some(graph) => {
+                let mut json = Vec::new();
+                saved::write_json(&mut json, graph, false)?;
+                Some(String::from_utf8_lossy(&json).into_owned())
+            }
+            None => None,
+        };
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE jobs SET status = ?2, error = ?3, graph = ?4 WHERE id = ?1",
+                params![id as i64, status, error, graph],
+            )
+            .map_err(to_io_error)?;
+        Ok(())
+    }
+
+    /// Every job, oldest first.
+    pub fn list(&self) -> std::io::Result<Vec<Entry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare("SELECT id, submitted, parameters, status, error FROM jobs ORDER BY id")
+            .map_err(to_io_error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u64,
+                    row.get::<_, i64>(1)? as u64,
+                    row.get::<_, String>(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .map_err(to_io_error)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (id, submitted, parameters, status, error) = row.map_err(to_io_error)?;
+            entries.push(Entry {
+                id,
+                submitted,
+                parameters: serde_json::from_str(&parameters)?,
+                status,
+                error,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// The graph job `id` built, if it's done.
+    pub fn graph(&self, id: u64) -> std::io::Result<Option<Graph>> {
+        let json: Option<Option<String>> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT graph FROM jobs WHERE id = ?1", [id as i64], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(to_io_error)?;
+        match json.flatten() {
+            Some(json) => saved::read_json(json.as_bytes())
+                .map(Some)
+                .map_err(std::io::Error::other),
+            None => Ok(None),
+        }
+    }
+
+    /// The jobs that were queued or running when the server stopped, to
+    /// be built again.
+    pub fn unfinished(&self) -> std::io::Result<Vec<Queued>> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare(
+                "SELECT id, bibliography, parameters FROM jobs
+                    WHERE status IN ('queued', 'running') ORDER BY id",
+            )
+            .map_err(to_io_error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u64,
+                    row.get(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(to_io_error)?;
+        let mut queued = Vec::new();
+        for row in rows {
+            let (id, bibliography, parameters) = row.map_err(to_io_error)?;
+            queued.push(Queued {
+                id,
+                bibliography,
+                parameters: serde_json::from_str(&parameters)?,
+            });
+        }
+        Ok(queued)
+    }
+
+    /// The newest job's id, or 0 if there are none.
+    pub fn last_id(&self) -> std::io::Result<u64> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM jobs", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|id| id as u64)
+            .map_err(to_io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_outlive_the_connection() {
+        let path = std::env::temp_dir().join(format!("history-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let history = History::open(&path).unwrap();
+        let job = |id| Queued {
+            id,
+            bibliography: "@misc{a}".into(),
+            parameters: vec![("max_depth".into(), "2".into())],
+        };
+        history.insert(&job(1)).unwrap();
+        history.insert(&job(2)).unwrap();
+        history
+            .set(1, "done", None, Some(&Graph::default()))
+            .unwrap();
+        drop(history);
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.last_id().unwrap(), 2);
+        assert!(history.graph(1).unwrap().is_some());
+        assert!(history.graph(2).unwrap().is_none());
+        let unfinished = history.unfinished().unwrap();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].id, 2);
+        assert_eq!(unfinished[0].parameters, job(2).parameters);
+        let statuses: Vec<_> = history
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.status)
+            .collect();
+        assert_eq!(statuses, ["done", "queued"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,8 +1,47 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use biblatex::ChunksExt;
+use serde::Deserialize;
+
+// `\citation{a,b}`, `\bibdata{refs}`, and `\@input{chapter.aux}` in a
+// LaTeX `.aux` file
+const AUX_COMMAND_REGEX: &str = r"\\(citation|bibdata|@input)\{([^}]*)\}";
+// where each item starts in a `.bbl` file, from BibTeX's `\bibitem[label]{key}`
+// or biblatex's `\entry{key}{type}{}`
+const BBL_ITEM_REGEX: &str = r"\\(?:bibitem(?:\[[^\]]*\])?|entry)\{([^}]*)\}";
+const BBL_DOI_REGEX: &str = r"10\.\d{4,9}/[^\s{}\\]+";
+// BibTeX styles' `\url{...}`, or biblatex's `\verb{url}` then `\verb <url>`
+const BBL_URL_REGEX: &str = r"\\url\{([^}]+)\}|\\verb\{url\}\s*\\verb (\S+)";
+const BBL_TITLE_REGEX: &str = r"\\field\{title\}\{([^}]*)\}";
+// DOIs written anywhere in a note, bare or in a link, stopping short of
+// the Markdown around them
+const NOTE_DOI_REGEX: &str = r#"\b10\.\d{4,9}/[^\s<>\[\]()"'`|]+"#;
+const NOTE_ARXIV_REGEX: &str =
+    r"(?i)(?:arxiv\.org/(?:abs|pdf)/|arxiv:\s*)(\d{4}\.\d{4,5}|[a-z-]+(?:\.[a-z]{2})?/\d{7})";
+// org-ref's `cite:a,b` and `cite:&a;&b`, and their `citep:`, `citet:`,
+// etc., bare or in `[[...]]`
+const ORG_CITE_REGEX: &str = r"(?i)\b(?:cite[a-z]*|[a-z]*cite):([^\s\[\]]+)";
+// `#+BIBLIOGRAPHY: refs.bib` or org-ref's `bibliography:refs.bib` link
+const ORG_BIBLIOGRAPHY_REGEX: &str =
+    r"(?im)(?:^[ \t]*#\+bibliography:[ \t]*|\bbibliography:)([^\s\]]+)";
+// a file's `#+DOI:` or a heading's `:DOI:` property
+const ORG_DOI_REGEX: &str = r"(?im)^[ \t]*(?:#\+doi:|:doi:)[ \t]*(\S+)";
+
 pub enum Error {
     /// A partial error for if any entries in don't have IDs.
     SomeKeysMissing(SomeMissingKeys),
     /// Error on parsing a bibliography string
     Parse(biblatex::ParseError),
+    /// Error on parsing a CSL JSON export
+    Json(serde_json::Error),
+    /// Error on reading the bibliography file
+    Read(std::io::Error),
+    /// There's nothing at the bibliography's path.
+    Missing(std::path::PathBuf),
+    /// An Org file or vault cites papers by key without naming a
+    /// bibliography to look the keys up in.
+    NoBibliography(std::path::PathBuf),
 }
 
 pub struct SomeMissingKeys {
@@ -15,7 +54,7 @@ impl std::fmt::Debug for SomeMissingKeys {
         if !self.ids.is_empty() {
             write!(f, "successes aside, ")?;
         }
-        write!(f, "these keys didn't have a DOI or URL: ")?;
+        write!(f, "these keys didn't have a DOI, URL, or ISBN: ")?;
         write!(
             f,
             "{}",
@@ -33,6 +72,20 @@ impl std::fmt::Debug for Error {
         match self {
             Error::Parse(err) => std::fmt::Debug::fmt(err, f),
             Error::SomeKeysMissing(err) => std::fmt::Debug::fmt(err, f),
+            Error::Json(err) => std::fmt::Debug::fmt(err, f),
+            Error::Read(err) => std::fmt::Debug::fmt(err, f),
+            Error::Missing(path) => {
+                write!(
+                    f,
+                    "there's no bibliography at {}; check the path",
+                    path.display()
+                )
+            }
+            Error::NoBibliography(path) => write!(
+                f,
+                "{} cites papers by key but names no bibliography; add a #+BIBLIOGRAPHY: line",
+                path.display()
+            ),
         }
     }
 }
@@ -52,24 +105,337 @@ impl SomeMissingKeys {
     }
 }
 
-/// Get either a DOI or URL from each BibTeX entry in the bibliography.
+/// The dblp key of an entry exported from dblp.org.
+fn dblp_key(entry: &biblatex::Entry) -> Option<String> {
+    if entry.key.starts_with("DBLP:") {
+        return Some(entry.key.clone());
+    }
+    let biburl = entry.get("biburl")?.format_verbatim();
+    biburl.contains("dblp.").then_some(biburl)
+}
+
+/// The id [`try_from_bibtex`] takes from `entry`.
+fn entry_id(entry: &biblatex::Entry) -> Result<String, biblatex::RetrievalError> {
+    match entry.doi() {
+        Ok(doi) => Ok(doi),
+        Err(err @ biblatex::RetrievalError::TypeError(_)) => Err(err),
+        Err(biblatex::RetrievalError::Missing(_)) => dblp_key(entry)
+            .map_or_else(|| entry.url(), Ok)
+            .or_else(|_| {
+                entry
+                    .isbn()
+                    .map(|isbn| format!("ISBN:{}", isbn.format_verbatim()))
+            }),
+    }
+}
+
+/// The `keywords` of each BibTeX entry that has any, alongside the id
+/// [`try_from_bibtex`] takes from it.  Entries without an id are skipped.
+pub fn keywords_from_bibtex(
+    bibtex_src: impl AsRef<str>,
+) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let bibliography = biblatex::Bibliography::parse(bibtex_src.as_ref()).map_err(Error::Parse)?;
+    Ok(bibliography
+        .iter()
+        .filter_map(|entry| {
+            let keywords: Vec<String> = entry
+                .get("keywords")?
+                .format_verbatim()
+                .split([',', ';'])
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(String::from)
+                .collect();
+            Some((entry_id(entry).ok()?, keywords))
+        })
+        .filter(|(_id, keywords)| !keywords.is_empty())
+        .collect())
+}
+
+/// The BibTeX source of the bibliography at `path`, which may be:
+///
+/// - a `.bib` file, taken as it is;
+/// - a LaTeX `.aux` file, for only the entries the manuscript cites from
+///   the `.bib` files it names;
+/// - a `.bbl` file, whose items are rewritten as BibTeX entries with
+///   whatever DOI, URL, and title can be picked out of them;
+/// - an Org file, for the entries its org-ref `cite:` links cite from the
+///   bibliographies it names, and its `#+DOI:` and `:DOI:` properties;
+/// - or a directory of Markdown or Org notes, like an Obsidian or
+///   org-roam vault, each DOI and arXiv link in which becomes an entry
+///   keyed by its note's name.
+pub fn read_bibliography(path: &Path) -> Result<String, Error> {
+    let mut cited = HashSet::new();
+    let mut bib_paths = Vec::new();
+    let mut bibtex_src = String::new();
+    if path.is_dir() {
+        let mut notes = Vec::new();
+        find_notes(path, &mut notes).map_err(Error::Read)?;
+        notes.sort();
+        for note in notes {
+            let src = std::fs::read_to_string(&note).map_err(Error::Read)?;
+            let name = note.file_stem().unwrap_or_default().to_string_lossy();
+            bibtex_src += &if note.extension().is_some_and(|extension| extension == "org") {
+                read_org(&note, &src, &mut cited, &mut bib_paths)
+            } else {
+                bibtex_from_note(&name, &src)
+            };
+        }
+    } else {
+        let src = std::fs::read_to_string(path).map_err(Error::Read)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("aux") => read_aux(path, &src, &mut cited, &mut bib_paths)?,
+            Some("org") => bibtex_src = read_org(path, &src, &mut cited, &mut bib_paths),
+            Some("bbl") => return Ok(bibtex_from_bbl(&src)),
+            _ => return Ok(src),
+        }
+    }
+    if !cited.is_empty() && bib_paths.is_empty() {
+        return Err(Error::NoBibliography(path.to_path_buf()));
+    }
+    Ok(bibtex_src + &cited_from_bibs(&bib_paths, &cited)?)
+}
+
+/// Gather the keys an `.aux` file cites and the `.bib` files it names,
+/// following `\@input` into the `.aux` files of included chapters.
+fn read_aux(
+    path: &Path,
+    src: &str,
+    cited: &mut HashSet<String>,
+    bib_paths: &mut Vec<std::path::PathBuf>,
+) -> Result<(), Error> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let command_regex = regex::Regex::new(AUX_COMMAND_REGEX).unwrap();
+    for caps in command_regex.captures_iter(src) {
+        let names = caps[2]
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty());
+        match &caps[1] {
+            "citation" => cited.extend(names.map(String::from)),
+            "bibdata" => bib_paths.extend(names.map(|name| {
+                let bib_path = dir.join(name);
+                if bib_path.extension().is_some() {
+                    bib_path
+                } else {
+                    bib_path.with_extension("bib")
+                }
+            })),
+            _ => {
+                let input_path = dir.join(&caps[2]);
+                let input_src = std::fs::read_to_string(&input_path).map_err(Error::Read)?;
+                read_aux(&input_path, &input_src, cited, bib_paths)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gather the keys an Org file's `cite:` links cite and the bibliographies
+/// it names, giving an entry for each DOI property, keyed by the file's
+/// name.
+fn read_org(
+    path: &Path,
+    src: &str,
+    cited: &mut HashSet<String>,
+    bib_paths: &mut Vec<std::path::PathBuf>,
+) -> String {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let cite_regex = regex::Regex::new(ORG_CITE_REGEX).unwrap();
+    let bibliography_regex = regex::Regex::new(ORG_BIBLIOGRAPHY_REGEX).unwrap();
+    let doi_regex = regex::Regex::new(ORG_DOI_REGEX).unwrap();
+    for caps in cite_regex.captures_iter(src) {
+        cited.extend(
+            caps[1]
+                .split([',', ';'])
+                .map(|key| key.trim().trim_start_matches('&'))
+                .filter(|key| !key.is_empty())
+                .map(String::from),
+        );
+    }
+    for caps in bibliography_regex.captures_iter(src) {
+        for name in caps[1].split(',').filter(|name| !name.is_empty()) {
+            let bib_path = dir.join(name);
+            if !bib_paths.contains(&bib_path) {
+                bib_paths.push(bib_path);
+            }
+        }
+    }
+    let dois: Vec<&str> = doi_regex
+        .captures_iter(src)
+        .map(|caps| caps.get(1).unwrap().as_str())
+        .collect();
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    bibtex_from_note(&name, &dois.join("\n"))
+}
+
+/// The entries of the `.bib` files at `bib_paths` with keys in `cited`,
+/// or all of them if the manuscript has a `\nocite{*}`.
+fn cited_from_bibs(
+    bib_paths: &[std::path::PathBuf],
+    cited: &HashSet<String>,
+) -> Result<String, Error> {
+    let mut bibtex_src = String::new();
+    for bib_path in bib_paths {
+        bibtex_src += &std::fs::read_to_string(bib_path).map_err(Error::Read)?;
+        bibtex_src.push('\n');
+    }
+    let bibliography = biblatex::Bibliography::parse(&bibtex_src).map_err(Error::Parse)?;
+    if cited.contains("*") {
+        return Ok(bibliography.to_bibtex_string());
+    }
+    let mut kept = biblatex::Bibliography::new();
+    for entry in bibliography.into_vec() {
+        if cited.contains(&entry.key) {
+            kept.insert(entry);
+        }
+    }
+    Ok(kept.to_bibtex_string())
+}
+
+/// Rewrite the items of a `.bbl` file as BibTeX entries under the same
+/// keys.  Formatted references don't say what's a title, so only
+/// biblatex's items have one.
+fn bibtex_from_bbl(bbl_src: &str) -> String {
+    let item_regex = regex::Regex::new(BBL_ITEM_REGEX).unwrap();
+    let doi_regex = regex::Regex::new(BBL_DOI_REGEX).unwrap();
+    let url_regex = regex::Regex::new(BBL_URL_REGEX).unwrap();
+    let title_regex = regex::Regex::new(BBL_TITLE_REGEX).unwrap();
+    let starts: Vec<_> = item_regex.captures_iter(bbl_src).collect();
+    let mut bibtex_src = String::new();
+    for (i, caps) in starts.iter().enumerate() {
+        let start = caps.get(0).unwrap().end();
+        let end = starts
+            .get(i + 1)
+            .map_or(bbl_src.len(), |next| next.get(0).unwrap().start());
+        let item = &bbl_src[start..end];
+        bibtex_src += &format!("@misc{{{},\n", &caps[1]);
+        if let Some(title) = title_regex.captures(item) {
+            bibtex_src += &format!("  title = {{{}}},\n", &title[1]);
+        }
+        if let Some(doi) = doi_regex.find(item) {
+            bibtex_src += &format!("  doi = {{{}}},\n", doi.as_str().trim_end_matches('.'));
+        }
+        if let Some(url) = url_regex
+            .captures(item)
+            .and_then(|caps| caps.get(1).or(caps.get(2)))
+        {
+            bibtex_src += &format!("  url = {{{}}},\n", url.as_str());
+        }
+        bibtex_src += "}\n";
+    }
+    bibtex_src
+}
+
+/// Gather the Markdown and Org files under `dir`, skipping hidden directories
+/// like Obsidian's `.obsidian` and `.trash`.
+fn find_notes(dir: &Path, notes: &mut Vec<std::path::PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            find_notes(&path, notes)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "md" || extension == "org")
+        {
+            notes.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A BibTeX entry for each paper linked from the note `name`, keyed by
+/// the name, then the name and a number for any after the first.
+pub fn bibtex_from_note(name: &str, src: &str) -> String {
+    let doi_regex = regex::Regex::new(NOTE_DOI_REGEX).unwrap();
+    let arxiv_regex = regex::Regex::new(NOTE_ARXIV_REGEX).unwrap();
+    // BibTeX keys can't hold spaces or commas, which note names often do
+    let key: String = name
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == ',' {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let dois = doi_regex
+        .find_iter(src)
+        .map(|doi| ("doi", doi.as_str().trim_end_matches(['.', ',', ';', ':'])));
+    let arxiv_ids = arxiv_regex
+        .captures_iter(src)
+        .map(|caps| ("url", caps.get(1).unwrap().as_str()));
+    let mut seen = HashSet::new();
+    let mut bibtex_src = String::new();
+    for (field, id) in dois.chain(arxiv_ids) {
+        if !seen.insert(id.to_lowercase()) {
+            continue;
+        }
+        let key = match seen.len() {
+            1 => key.clone(),
+            n => format!("{key}-{n}"),
+        };
+        let value = match field {
+            "url" => format!("https://arxiv.org/abs/{id}"),
+            _ => id.to_string(),
+        };
+        bibtex_src += &format!("@misc{{{key},\n  {field} = {{{value}}},\n}}\n");
+    }
+    bibtex_src
+}
+
+/// A BibTeX entry, as far as it names a paper.
+pub struct Entry {
+    pub key: String,
+    /// The id [`try_from_bibtex`] takes from the entry, if it has one.
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub year: Option<String>,
+    /// The first author's surname.
+    pub author: Option<String>,
+}
+
+/// Every entry in the bibliography, whether or not it has an id.
+pub fn entries_from_bibtex(bibtex_src: impl AsRef<str>) -> Result<Vec<Entry>, Error> {
+    let bibliography = biblatex::Bibliography::parse(bibtex_src.as_ref()).map_err(Error::Parse)?;
+    Ok(bibliography
+        .iter()
+        .map(|entry| Entry {
+            key: entry.key.clone(),
+            id: entry_id(entry).ok(),
+            title: entry.get("title").map(|title| title.format_verbatim()),
+            year: entry.get("year").map(|year| year.format_verbatim()),
+            author: entry
+                .author()
+                .ok()
+                .and_then(|authors| Some(authors.first()?.name.clone())),
+        })
+        .collect())
+}
+
+/// Get either a DOI, dblp key, URL, or ISBN from each BibTeX entry in the
+/// bibliography, in that order of preference.
+///
+/// ISBNs are prefixed with `ISBN:` and dblp keys `DBLP:` to tell them
+/// apart.  dblp keys are found in entries exported from dblp.org, as
+/// either the entry's key or its `biburl`.
 ///
 /// This will error if, in any case, the DOI is malformed or both the DOI
-/// is missing and the URL is either missing or malformed.  When this
-/// occurs, the successful ids can be recovered with [`SomeMissingKeys::get_ids`].
+/// is missing and the URL and ISBN are either missing or malformed.  When
+/// this occurs, the successful ids can be recovered with
+/// [`SomeMissingKeys::get_ids`].
 pub fn try_from_bibtex(bibtex_src: impl AsRef<str>) -> Result<Vec<String>, Error> {
     let bibliography = biblatex::Bibliography::parse(bibtex_src.as_ref()).map_err(Error::Parse)?;
     let maybe_ids = bibliography
         .iter()
-        .map(|entry| match entry.doi() {
-            Ok(doi) => Ok(doi),
-            Err(err) => match err {
-                biblatex::RetrievalError::TypeError(_) => Err((entry.key.clone(), err)),
-                biblatex::RetrievalError::Missing(_) => {
-                    entry.url().map_err(|e| (entry.key.clone(), e))
-                }
-            },
-        })
+        .map(|entry| entry_id(entry).map_err(|err| (entry.key.clone(), err)))
         .collect::<Vec<_>>();
     if maybe_ids.iter().any(|id| id.is_err()) {
         let mut missing_keys = Vec::<(String, biblatex::RetrievalError)>::new();
@@ -87,3 +453,186 @@ pub fn try_from_bibtex(bibtex_src: impl AsRef<str>) -> Result<Vec<String>, Error
     }
     Ok(maybe_ids.into_iter().filter_map(|id| id.ok()).collect())
 }
+
+/// An item in a CSL JSON export, as Zotero makes.
+#[derive(Deserialize)]
+struct CslItem {
+    id: Option<serde_json::Value>,
+    #[serde(rename = "DOI")]
+    doi: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    #[serde(rename = "ISBN")]
+    isbn: Option<String>,
+}
+
+/// Like [`try_from_bibtex`], but for a CSL JSON export.
+pub fn try_from_csl_json(csl_src: impl AsRef<str>) -> Result<Vec<String>, Error> {
+    let items: Vec<CslItem> = serde_json::from_str(csl_src.as_ref()).map_err(Error::Json)?;
+    let mut missing_keys = Vec::<(String, biblatex::RetrievalError)>::new();
+    let mut ids = Vec::<String>::new();
+    for item in items {
+        match (item.doi, item.url, item.isbn) {
+            (Some(doi), _, _) => ids.push(doi),
+            (None, Some(url), _) => ids.push(url),
+            (None, None, Some(isbn)) => ids.push(format!("ISBN:{isbn}")),
+            (None, None, None) => missing_keys.push((
+                item.id.map(|id| id.to_string()).unwrap_or_default(),
+                biblatex::RetrievalError::Missing("DOI".into()),
+            )),
+        }
+    }
+    if !missing_keys.is_empty() {
+        return Err(Error::SomeKeysMissing(SomeMissingKeys {
+            missing_keys,
+            ids,
+        }));
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn books_fall_back_to_isbn() {
+        let bibtex = r#"
+            @book{knuth, title = {The Art of Computer Programming}, isbn = {978-0-201-89683-1}}
+            @article{paper, title = {A Paper}, doi = {10.1000/182}, isbn = {978-0-201-89683-1}}
+        "#;
+        let ids = try_from_bibtex(bibtex).unwrap();
+        assert_eq!(ids, vec!["ISBN:978-0-201-89683-1", "10.1000/182"]);
+    }
+
+    #[test]
+    fn keywords_are_split_and_trimmed() {
+        let bibtex = r#"
+            @article{a, title = {A}, doi = {10.1000/a}, keywords = {methods, survey;hci}}
+            @article{b, title = {B}, doi = {10.1000/b}}
+            @article{c, title = {C}, keywords = {methods}}
+        "#;
+        let keywords = keywords_from_bibtex(bibtex).unwrap();
+        assert_eq!(
+            keywords,
+            vec![(
+                "10.1000/a".to_string(),
+                vec!["methods".to_string(), "survey".into(), "hci".into()]
+            )]
+        );
+    }
+
+    #[test]
+    fn manuscripts_seed_only_what_they_cite() {
+        let dir = std::env::temp_dir().join(format!("aux-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("refs.bib"),
+            r#"
+            @article{a, title = {A}, doi = {10.1000/a}}
+            @article{b, title = {B}, doi = {10.1000/b}}
+            @article{c, title = {C}, doi = {10.1000/c}}
+            "#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("intro.aux"), "\\citation{c}\n").unwrap();
+        std::fs::write(
+            dir.join("paper.aux"),
+            "\\relax\n\\citation{a}\n\\@input{intro.aux}\n\\bibdata{refs}\n",
+        )
+        .unwrap();
+        let ids = try_from_bibtex(read_bibliography(&dir.join("paper.aux")).unwrap()).unwrap();
+        assert_eq!(ids, vec!["10.1000/a", "10.1000/c"]);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let bbl = r"
+            \bibitem[Vaswani et~al.(2017)]{vaswani}
+            A.~Vaswani et~al.
+            \newblock Attention is all you need.
+            \newblock \doi{10.48550/arXiv.1706.03762}.
+            \bibitem{web}
+            \newblock \url{https://example.com/paper}
+            \entry{smith}{article}{}
+              \field{title}{A Title}
+              \verb{doi}
+              \verb 10.1000/smith
+              \endverb
+            \endentry
+        ";
+        let entries = entries_from_bibtex(bibtex_from_bbl(bbl)).unwrap();
+        let ids: Vec<_> = entries.iter().map(|entry| entry.id.as_deref()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                Some("10.48550/arXiv.1706.03762"),
+                Some("https://example.com/paper"),
+                Some("10.1000/smith")
+            ]
+        );
+        assert_eq!(entries[2].title.as_deref(), Some("A Title"));
+    }
+
+    #[test]
+    fn notes_link_their_papers() {
+        let note = "
+            # Attention, revisited
+            Read [the paper](https://doi.org/10.1145/3290605.3300233).
+            Also arXiv:1706.03762 and <https://arxiv.org/abs/1706.03762v2>.
+            Cited as doi:10.1145/3290605.3300233, again.
+        ";
+        let entries = entries_from_bibtex(bibtex_from_note("Attention, revisited", note)).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.id.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("Attention--revisited", Some("10.1145/3290605.3300233")),
+                (
+                    "Attention--revisited-2",
+                    Some("https://arxiv.org/abs/1706.03762")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn org_files_cite_from_their_bibliographies() {
+        let dir = std::env::temp_dir().join(format!("org-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("refs.bib"),
+            r#"
+            @article{a, title = {A}, doi = {10.1000/a}}
+            @article{b, title = {B}, doi = {10.1000/b}}
+            @article{c, title = {C}, doi = {10.1000/c}}
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("notes.org"),
+            "#+TITLE: Notes\n#+BIBLIOGRAPHY: refs.bib\n#+DOI: 10.1000/d\n\
+             * Reading\n:PROPERTIES:\n:DOI: 10.1000/e\n:END:\n\
+             As in cite:a,b and [[citep:&c]].\n",
+        )
+        .unwrap();
+        let ids = try_from_bibtex(read_bibliography(&dir.join("notes.org")).unwrap()).unwrap();
+        assert_eq!(
+            ids,
+            vec![
+                "10.1000/d",
+                "10.1000/e",
+                "10.1000/a",
+                "10.1000/b",
+                "10.1000/c"
+            ]
+        );
+        std::fs::write(dir.join("notes.org"), "cite:a\n").unwrap();
+        assert!(matches!(
+            read_bibliography(&dir.join("notes.org")),
+            Err(Error::NoBibliography(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! Following one paper's influence forward through the papers citing
+//! it, rather than back through references.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+use crate::crawl::{self, Crawl, PaperSource};
+use crate::graph::Graph;
+use crate::policy::Connectivity;
+use crate::semantic_scholar::{self, Paper, PaperId, Resolution, SemanticScholar};
+
+/// How many venues the summary lists.
+const TOP_VENUES: usize = 10;
+
+/// A [`PaperSource`] whose papers' "references" are the papers citing
+/// them, so the crawl walks forward in time.
+pub struct Citations<'a> {
+    api: &'a SemanticScholar,
+    max_per_paper: usize,
+    /// How many more batches need their citations; the crawl fetches one
+    /// batch more than it expands, and citations are costly.
+    levels_left: Cell<usize>,
+}
+
+impl<'a> Citations<'a> {
+    pub fn new(api: &'a SemanticScholar, depth: usize, max_per_paper: usize) -> Self {
+        Citations {
+            api,
+            max_per_paper,
+            levels_left: Cell::new(depth),
+        }
+    }
+}
+
+impl PaperSource for Citations<'_> {
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+        let papers = self.api.get_paper_batch(paper_ids).await?;
+        let levels_left = self.levels_left.get();
+        let mut citations = if levels_left == 0 {
+            HashMap::new()
+        } else {
+            self.levels_left.set(levels_left - 1);
+            let ids = papers
+                .iter()
+                .flatten()
+                .map(|paper| paper.id().to_string())
+                .collect();
+            self.api.get_citations(ids, self.max_per_paper).await?
+        };
+        Ok(papers
+            .into_iter()
+            .map(|paper| {
+                paper.map(|paper| {
+                    let citing = citations.remove(paper.id()).unwrap_or_default();
+                    paper.with_references(citing)
+                })
+            })
+            .collect())
+    }
+
+    async fn get_reference_contexts(
+        &self,
+        _paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Crawl the papers citing `paper_id`, and those citing them, out to
+/// `depth`.  Edges point from citing to cited paper, as in any other
+/// graph.
+pub async fn crawl(
+    api: &SemanticScholar,
+    paper_id: PaperId,
+    depth: usize,
+    max_per_paper: usize,
+) -> Result<Crawl, semantic_scholar::Error> {
+    let options = crawl::Options {
+        prefetch: true,
+        // every paper in the cone is expanded
+        ..crawl::Options::new(depth, Box::new(Connectivity(1.0)))
+    };
+    let source = Citations::new(api, depth, max_per_paper);
+    let mut crawl = crawl::crawl(&source, vec![(paper_id, Resolution::Exact)], &options).await?;
+    crawl.graph.citations = std::mem::take(&mut crawl.graph.citations).reversed();
+    Ok(crawl)
+}
+
+/// How a paper's influence has spread.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    /// Papers in the cone published each year.
+    pub per_year: BTreeMap<u32, usize>,
+    /// The venues publishing the most papers in the cone, most first.
+    pub top_venues: Vec<(String, usize)>,
+}
+
+/// Summarize the papers in `graph` other than `root`.
+pub fn summarize(graph: &Graph, root: &str) -> Summary {
+    let mut seen = HashSet::new();
+    let mut per_year = BTreeMap::new();
+    let mut venues = HashMap::<&str, usize>::new();
+    for paper in graph.citations.papers() {
+        let Some(id) = paper.id() else {
+            continue;
+        };
+        if id == root || !seen.insert(id) {
+            continue;
+        }
+        if let Some(year) = paper.year() {
+            *per_year.entry(year).or_default() += 1;
+        }
+        if let Some(venue) = paper.venue() {
+            *venues.entry(venue).or_default() += 1;
+        }
+    }
+    let mut top_venues: Vec<(String, usize)> = venues
+        .into_iter()
+        .map(|(venue, count)| (venue.to_string(), count))
+        .collect();
+    top_venues.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    top_venues.truncate(TOP_VENUES);
+    Summary {
+        per_year,
+        top_venues,
+    }
+}
+
+/// Write `summary` as plain text tables.
+pub fn write_summary(out: &mut impl Write, summary: &Summary) -> std::io::Result<()> {
+    writeln!(out, "citing papers per year:")?;
+    for (year, count) in &summary.per_year {
+        writeln!(out, "  {year}  {count}")?;
+    }
+    writeln!(out, "top citing venues:")?;
+    for (venue, count) in &summary.top_venues {
+        writeln!(out, "  {count:>5}  {venue}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic_scholar::ProtoPaper;
+
+    #[test]
+    fn summaries_leave_out_the_root_and_duplicates() {
+        let paper = |id: &str, year: u32, venue: &str| -> ProtoPaper {
+            serde_json::from_value(serde_json::json!({
+                "paperId": id,
+                "title": id,
+                "url": null,
+                "year": year,
+                "venue": venue,
+            }))
+            .unwrap()
+        };
+        let mut graph = Graph::default();
+        graph.citations.extend([
+            paper("root", 2000, "Nature"),
+            paper("a", 2001, "Nature"),
+            paper("a", 2001, ""),
+            paper("b", 2001, "Science"),
+            paper("c", 2003, "Science"),
+        ]);
+        let summary = summarize(&graph, "root");
+        assert_eq!(summary.per_year, BTreeMap::from([(2001, 2), (2003, 1)]));
+        assert_eq!(summary.top_venues[0], ("Science".to_string(), 2));
+    }
+}
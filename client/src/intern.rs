@@ -0,0 +1,40 @@
+//! Paper ids shared rather than copied.  A crawl names each paper in its
+//! staging, in every citation to or from it, and in the batches it asks
+//! for, so on crawls of hundreds of thousands of papers one allocation
+//! per id instead of one per mention matters.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A paper id, cheap to clone.
+pub type Id = Arc<str>;
+
+/// Hands out one shared [`Id`] per distinct id.
+#[derive(Debug, Default)]
+pub struct Interner {
+    ids: HashSet<Id>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, id: &str) -> Id {
+        if let Some(interned) = self.ids.get(id) {
+            return interned.clone();
+        }
+        let interned: Id = id.into();
+        self.ids.insert(interned.clone());
+        interned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_id_is_shared() {
+        let mut interner = Interner::default();
+        let a = interner.intern("a");
+        assert!(Arc::ptr_eq(&a, &interner.intern(&String::from("a"))));
+        assert!(!Arc::ptr_eq(&a, &interner.intern("b")));
+    }
+}
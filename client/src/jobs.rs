@@ -0,0 +1,634 @@
+//! Graphs built on request by a long-running server, one at a time, so
+//! a group can share one rate limit.
+//!
+//! `POST /graphs` with a BibTeX bibliography as the body queues a build
+//! and answers with the job's id; `GET /graphs/<id>` answers with how
+//! it's going, or once it's done, the graph, as JSON unless `?format=`
+//! asks for another.  `GET /graphs/<id>/events` streams how the build
+//! goes as server-sent events: every one so far, then each as it
+//! happens, until the job's done.  A bibliography can be at most
+//! [`MAX_BODY`].
+//!
+//! `POST /graphs?max_depth=2&title_match=true` builds with those of
+//! `build`'s options, `true` turning a switch on.  With a history, every
+//! job is kept across restarts: `GET /graphs` lists them,
+//! `GET /graphs/<id>` renders one in any format without crawling again,
+//! and `GET /graphs/<a>/diff/<b>` compares two, as a report or, with
+//! `?format=dot`, a graph.  Without one, only the latest finished jobs
+//! are answered for.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rocket::data::{ByteUnit, Data};
+use rocket::http::{self, ContentType, Header, Method};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::content::RawJson;
+use rocket::response::stream::{Event, EventStream};
+use rocket::{catchers, routes, Build, Responder, Rocket, State};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::crawl::Progress;
+use crate::diff;
+use crate::graph::Graph;
+use crate::history::History;
+use crate::output;
+
+pub enum Status {
+    Queued,
+    Running,
+    Done(Box<Graph>),
+    Failed(String),
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done(_) => "done",
+            Status::Failed(_) => "failed",
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, Status::Done(_) | Status::Failed(_))
+    }
+}
+
+struct Job {
+    status: Status,
+    /// Every event so far, as JSON, for those who ask for them late.
+    events: Vec<String>,
+    /// Where to send events as they happen.
+    listeners: Vec<mpsc::UnboundedSender<String>>,
+}
+
+impl Job {
+    fn new() -> Self {
+        let mut job = Job {
+            status: Status::Queued,
+            events: Vec::new(),
+            listeners: Vec::new(),
+        };
+        job.publish(json!({"event": "status", "status": job.status.name()}).to_string());
+        job
+    }
+
+    fn publish(&mut self, event: String) {
+        self.listeners
+            .retain(|listener| listener.send(event.clone()).is_ok());
+        self.events.push(event);
+    }
+}
+
+/// A bibliography waiting to be built from.
+pub struct Queued {
+    pub id: u64,
+    pub bibliography: String,
+    /// `build`'s options to build with, by name, with `_` for `-`.
+    pub parameters: Vec<(String, String)>,
+}
+
+/// The options of `build` a job may be given, none of which read or write
+/// files on the server.
+const OPTIONS: &[&str] = &[
+    "max_depth",
+    "connectivity",
+    "policy",
+    "fields_of_study",
+    "only_author",
+    "min_citation_count",
+    "max_papers_per_depth",
+    "max_total_papers",
+    "recommend",
+    "max_runtime",
+];
+/// The switches of `build` a job may be given.
+const SWITCHES: &[&str] = &[
+    "edge_weights",
+    "tldr",
+    "title_match",
+    "no_dedupe",
+    "depth_weighted_pruning",
+    "keep_partial",
+];
+
+impl Queued {
+    /// The arguments to `build` for the job's parameters.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, value) in &self.parameters {
+            let flag = format!("--{}", name.replace('_', "-"));
+            if !SWITCHES.contains(&name.as_str()) {
+                args.push(flag);
+                args.push(value.clone());
+            } else if value == "true" {
+                args.push(flag);
+            }
+        }
+        args
+    }
+}
+
+/// The query's parameters as a job's, or what's wrong with them.
+fn parameters(query: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+    for (name, value) in query {
+        if SWITCHES.contains(&name.as_str()) {
+            if value != "true" && value != "false" {
+                return Err(format!("{name} is true or false, not {value:?}"));
+            }
+        } else if !OPTIONS.contains(&name.as_str()) {
+            return Err(format!("a job can't be given {name}"));
+        }
+    }
+    Ok(query.to_vec())
+}
+
+/// How many finished jobs are kept in memory, graph, events, and all.
+/// Older ones are forgotten, or with a history, loaded from it when
+/// they're asked for.
+const FINISHED_KEPT: usize = 16;
+
+/// The jobs since the server started, by id, but for finished ones past
+/// [`FINISHED_KEPT`].
+#[derive(Clone)]
+pub struct Jobs {
+    jobs: Arc<Mutex<BTreeMap<u64, Job>>>,
+    queue: mpsc::UnboundedSender<Queued>,
+    history: Option<Arc<History>>,
+    /// The last id given out, which new ids follow.
+    last_id: Arc<AtomicU64>,
+}
+
+impl Jobs {
+    /// The jobs, and where they're queued to be built.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<Queued>) {
+        let (queue, queued) = mpsc::unbounded_channel();
+        let jobs = Self {
+            jobs: Arc::default(),
+            queue,
+            history: None,
+            last_id: Arc::default(),
+        };
+        (jobs, queued)
+    }
+
+    /// The jobs, kept in `history`, with those it has unfinished queued
+    /// again.
+    pub fn with_history(
+        history: History,
+    ) -> std::io::Result<(Self, mpsc::UnboundedReceiver<Queued>)> {
+        let (mut jobs, queued) = Self::new();
+        jobs.last_id.store(history.last_id()?, Ordering::Relaxed);
+        for job in history.unfinished()? {
+            jobs.jobs.lock().unwrap().insert(job.id, Job::new());
+            let _ = jobs.queue.send(job);
+        }
+        jobs.history = Some(Arc::new(history));
+        Ok((jobs, queued))
+    }
+
+    pub fn set(&self, id: u64, status: Status) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+        let mut event = json!({"event": "status", "status": status.name()});
+        if let Status::Failed(err) = &status {
+            event["error"] = json!(err);
+        }
+        job.publish(event.to_string());
+        if let Some(history) = &self.history {
+            let (error, graph) = match &status {
+                Status::Failed(err) => (Some(err.as_str()), None),
+                Status::Done(graph) => (None, Some(&**graph)),
+                _ => (None, None),
+            };
+            if let Err(err) = history.set(id, status.name(), error, graph) {
+                eprintln!("couldn't record job {id}: {err:?}");
+            }
+        }
+        let finished = status.is_finished();
+        if finished {
+            // ending the listeners' streams
+            job.listeners.clear();
+        }
+        job.status = status;
+        if finished {
+            forget_finished(&mut jobs);
+        }
+    }
+
+    pub fn progress(&self, id: u64, progress: &Progress) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.publish(json!(progress).to_string());
+        }
+    }
+
+    /// Every event of job `id` so far, then each as it happens.
+    fn subscribe(&self, id: u64) -> Option<mpsc::UnboundedReceiver<String>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for event in &job.events {
+            let _ = sender.send(event.clone());
+        }
+        if !job.status.is_finished() {
+            job.listeners.push(sender);
+        }
+        Some(receiver)
+    }
+
+    fn submit(
+        &self,
+        bibliography: String,
+        parameters: Vec<(String, String)>,
+    ) -> std::io::Result<u64> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let queued = Queued {
+            id,
+            bibliography,
+            parameters,
+        };
+        if let Some(history) = &self.history {
+            history.insert(&queued)?;
+        }
+        jobs.insert(id, Job::new());
+        // the builder only hangs up when the server is stopping
+        let _ = self.queue.send(queued);
+        Ok(id)
+    }
+
+    /// Every job there's been, oldest first.
+    fn list(&self) -> std::io::Result<serde_json::Value> {
+        let mut listed = BTreeMap::new();
+        if let Some(history) = &self.history {
+            for entry in history.list()? {
+                let mut job = json!({
+                    "id": entry.id,
+                    "submitted": entry.submitted,
+                    "parameters": BTreeMap::from_iter(entry.parameters),
+                    "status": entry.status,
+                });
+                if let Some(err) = entry.error {
+                    job["error"] = json!(err);
+                }
+                listed.insert(entry.id, job);
+            }
+        }
+        for (id, job) in self.jobs.lock().unwrap().iter() {
+            let listed = listed.entry(*id).or_insert_with(|| json!({"id": id}));
+            listed["status"] = json!(job.status.name());
+        }
+        Ok(json!(listed.into_values().collect::<Vec<_>>()))
+    }
+
+    /// The graph of job `id` if it's been kept, which finished jobs
+    /// from before the server started are.
+    fn kept(&self, id: u64) -> std::io::Result<Option<Graph>> {
+        match &self.history {
+            Some(history) if !self.jobs.lock().unwrap().contains_key(&id) => history.graph(id),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Forget the oldest finished jobs past [`FINISHED_KEPT`].
+fn forget_finished(jobs: &mut BTreeMap<u64, Job>) {
+    let finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_id, job)| job.status.is_finished())
+        .map(|(id, _job)| *id)
+        .collect();
+    for id in &finished[..finished.len().saturating_sub(FINISHED_KEPT)] {
+        jobs.remove(id);
+    }
+}
+
+/// The graph of job `id`, if it's done.
+fn done(jobs: &BTreeMap<u64, Job>, id: u64) -> Option<&Graph> {
+    match jobs.get(&id) {
+        Some(Job {
+            status: Status::Done(graph),
+            ..
+        }) => Some(graph),
+        _ => None,
+    }
+}
+
+/// The most a bibliography posted to the API may be.
+const MAX_BODY: ByteUnit = ByteUnit::Mebibyte(16);
+
+/// Whether finished graphs are written with their attribution.
+struct Attribution(bool);
+
+/// A response's status, and its body with its content type.
+type Answer = (http::Status, (ContentType, Vec<u8>));
+
+/// A job queued, answered with where to find it.
+#[derive(Responder)]
+#[response(status = 202)]
+struct Submitted {
+    body: RawJson<String>,
+    location: Header<'static>,
+}
+
+/// A request's query, decoded, as a job's parameters before they're
+/// checked.
+struct Query(Vec<(String, String)>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Query {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let query = request.uri().query().map(|query| {
+            query
+                .segments()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        });
+        Outcome::Success(Query(query.unwrap_or_default()))
+    }
+}
+
+/// The job API, answering from `jobs` and writing finished graphs as
+/// `attribution` says.
+pub fn rocket(jobs: Jobs, attribution: bool) -> Rocket<Build> {
+    rocket::build()
+        .manage(jobs)
+        .manage(Attribution(attribution))
+        .mount("/graphs", routes![submit, list, graph, events, compare])
+        .register("/", catchers![not_found])
+}
+
+#[rocket::post("/", data = "<bibliography>")]
+async fn submit(
+    jobs: &State<Jobs>,
+    query: Query,
+    bibliography: Data<'_>,
+) -> Result<Submitted, Answer> {
+    let bibliography = match bibliography.open(MAX_BODY).into_string().await {
+        Ok(read) if read.is_complete() => read.into_inner(),
+        Ok(_partial) => {
+            return Err(plain(
+                http::Status::PayloadTooLarge,
+                format!("a bibliography can be at most {MAX_BODY}"),
+            ))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+            return Err(plain(http::Status::BadRequest, "not UTF-8"))
+        }
+        Err(err) => return Err(plain(http::Status::BadRequest, err.to_string())),
+    };
+    let parameters = parameters(&query.0).map_err(|err| plain(http::Status::BadRequest, err))?;
+    let id = jobs
+        .submit(bibliography, parameters)
+        .map_err(|err| server_error(&err))?;
+    Ok(Submitted {
+        body: RawJson(json!({"id": id, "status": Status::Queued.name()}).to_string()),
+        location: Header::new("Location", format!("/graphs/{id}")),
+    })
+}
+
+#[rocket::get("/")]
+fn list(jobs: &State<Jobs>) -> Answer {
+    match jobs.list() {
+        Ok(list) => as_json(http::Status::Ok, &list),
+        Err(err) => server_error(&err),
+    }
+}
+
+#[rocket::get("/<id>?<format>")]
+fn graph(
+    jobs: &State<Jobs>,
+    attribution: &State<Attribution>,
+    id: &str,
+    format: Option<&str>,
+) -> Answer {
+    let format = match format.unwrap_or("json").parse() {
+        Ok(format) => format,
+        Err(err) => return plain(http::Status::BadRequest, err),
+    };
+    let Ok(id) = id.parse() else {
+        return not_found_answer();
+    };
+    let kept = match jobs.kept(id) {
+        Ok(kept) => kept,
+        Err(err) => return server_error(&err),
+    };
+    if let Some(graph) = kept {
+        return render(format, &graph, attribution.0);
+    }
+    let listed = jobs.jobs.lock().unwrap();
+    let Some(job) = listed.get(&id) else {
+        return not_found_answer();
+    };
+    let status = &job.status;
+    match status {
+        Status::Done(graph) => render(format, graph, attribution.0),
+        Status::Failed(err) => as_json(
+            http::Status::InternalServerError,
+            &json!({"id": id, "status": status.name(), "error": err}),
+        ),
+        _ => as_json(
+            http::Status::Accepted,
+            &json!({"id": id, "status": status.name()}),
+        ),
+    }
+}
+
+#[rocket::get("/<id>/events")]
+fn events(jobs: &State<Jobs>, id: &str) -> Option<EventStream![]> {
+    let mut events = jobs.subscribe(id.parse().ok()?)?;
+    Some(EventStream! {
+        while let Some(event) = events.recv().await {
+            yield Event::data(event);
+        }
+    })
+}
+
+#[rocket::get("/<old>/diff/<new>?<format>")]
+fn compare(jobs: &State<Jobs>, old: &str, new: &str, format: Option<&str>) -> Answer {
+    let (Ok(old), Ok(new)) = (old.parse(), new.parse()) else {
+        return not_found_answer();
+    };
+    let (kept_old, kept_new) = match (jobs.kept(old), jobs.kept(new)) {
+        (Ok(kept_old), Ok(kept_new)) => (kept_old, kept_new),
+        (Err(err), _) | (_, Err(err)) => return server_error(&err),
+    };
+    let listed = jobs.jobs.lock().unwrap();
+    let (Some(old), Some(new)) = (
+        kept_old.as_ref().or_else(|| done(&listed, old)),
+        kept_new.as_ref().or_else(|| done(&listed, new)),
+    ) else {
+        return not_found_answer();
+    };
+    let mut body = Vec::new();
+    let written = match format {
+        Some("dot") => diff::write_dot(&mut body, old, new),
+        _ => diff::write_report(&mut body, &diff::diff(old, new)),
+    };
+    match written {
+        Ok(()) => (http::Status::Ok, (ContentType::Plain, body)),
+        Err(err) => server_error(&err),
+    }
+}
+
+/// 405 for the job API's paths asked for with another method, and 404
+/// for anything else that isn't routed.
+#[rocket::catch(404)]
+fn not_found(request: &Request<'_>) -> Answer {
+    let path: Vec<&str> = request.uri().path().segments().collect();
+    let allowed: &[Method] = match path.as_slice() {
+        ["graphs"] => &[Method::Get, Method::Post],
+        ["graphs", _] | ["graphs", _, "events"] | ["graphs", _, "diff", _] => &[Method::Get],
+        _ => &[],
+    };
+    if allowed.is_empty() || allowed.contains(&request.method()) {
+        not_found_answer()
+    } else {
+        plain(http::Status::MethodNotAllowed, "method not allowed")
+    }
+}
+
+fn render(format: output::Format, graph: &Graph, attribution: bool) -> Answer {
+    let mut body = Vec::new();
+    match output::write(&mut body, format, graph, attribution) {
+        Ok(()) => (http::Status::Ok, (content_type(format), body)),
+        Err(err) => plain(http::Status::BadRequest, err.to_string()),
+    }
+}
+
+fn plain(status: http::Status, body: impl Into<String>) -> Answer {
+    (status, (ContentType::Plain, body.into().into_bytes()))
+}
+
+fn as_json(status: http::Status, value: &serde_json::Value) -> Answer {
+    (status, (ContentType::JSON, value.to_string().into_bytes()))
+}
+
+fn not_found_answer() -> Answer {
+    plain(http::Status::NotFound, "not found")
+}
+
+fn server_error(err: &std::io::Error) -> Answer {
+    as_json(
+        http::Status::InternalServerError,
+        &json!({"error": err.to_string()}),
+    )
+}
+
+fn content_type(format: output::Format) -> ContentType {
+    match format {
+        output::Format::Json => ContentType::JSON,
+        output::Format::Ndjson => ContentType::new("application", "x-ndjson"),
+        output::Format::Html => ContentType::HTML,
+        output::Format::Svg => ContentType::SVG,
+        output::Format::GraphMl => ContentType::XML,
+        _ => ContentType::Plain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::local::blocking::Client;
+
+    fn client(jobs: &Jobs) -> Client {
+        Client::untracked(rocket(jobs.clone(), false)).unwrap()
+    }
+
+    #[test]
+    fn jobs_are_queued_then_answered_with_their_graph() {
+        let (jobs, mut queued) = Jobs::new();
+        let client = client(&jobs);
+        let response = client.post("/graphs").body("@misc{a}").dispatch();
+        assert_eq!(response.status(), http::Status::Accepted);
+        assert_eq!(response.headers().get_one("Location"), Some("/graphs/1"));
+        let job = queued.try_recv().unwrap();
+        assert_eq!((job.id, job.bibliography.as_str()), (1, "@misc{a}"));
+        assert_eq!(
+            client
+                .post("/graphs?stream=x")
+                .body("@misc{a}")
+                .dispatch()
+                .status(),
+            http::Status::BadRequest
+        );
+
+        let response = client.get("/graphs/1").dispatch();
+        assert_eq!(response.status(), http::Status::Accepted);
+        assert!(response.into_string().unwrap().contains("queued"));
+
+        jobs.set(1, Status::Done(Box::default()));
+        let response = client.get("/graphs/1?format=dot").dispatch();
+        assert_eq!(response.status(), http::Status::Ok);
+        assert!(response.into_string().unwrap().starts_with("digraph"));
+        assert_eq!(
+            client.get("/graphs/2").dispatch().status(),
+            http::Status::NotFound
+        );
+        assert_eq!(
+            client.get("/graphs/2/events").dispatch().status(),
+            http::Status::NotFound
+        );
+        assert_eq!(
+            client.delete("/graphs/1").dispatch().status(),
+            http::Status::MethodNotAllowed
+        );
+    }
+
+    #[test]
+    fn only_the_latest_finished_jobs_are_kept() {
+        let (jobs, _queued) = Jobs::new();
+        let client = client(&jobs);
+        for id in 1..=FINISHED_KEPT as u64 + 2 {
+            client.post("/graphs").body("@misc{a}").dispatch();
+            jobs.set(id, Status::Done(Box::default()));
+        }
+        let queued = client.post("/graphs").body("@misc{a}").dispatch();
+        assert_eq!(queued.headers().get_one("Location"), Some("/graphs/19"));
+
+        let status = |id: u64| client.get(format!("/graphs/{id}")).dispatch().status();
+        assert_eq!(status(1), http::Status::NotFound);
+        assert_eq!(status(2), http::Status::NotFound);
+        assert_eq!(status(3), http::Status::Ok);
+        assert_eq!(status(18), http::Status::Ok);
+        assert_eq!(status(19), http::Status::Accepted);
+    }
+
+    #[test]
+    fn events_are_replayed_then_streamed_until_done() {
+        let (jobs, _queued) = Jobs::new();
+        let client = client(&jobs);
+        client.post("/graphs").body("@misc{a}").dispatch();
+        jobs.set(1, Status::Running);
+        let events = client.get("/graphs/1/events").dispatch();
+        jobs.progress(
+            1,
+            &Progress::DepthStarted {
+                depth: 0,
+                papers: 3,
+            },
+        );
+        jobs.set(1, Status::Failed("rate limited".into()));
+        // the stream ends with the job
+        assert_eq!(
+            events.into_string().unwrap(),
+            [
+                r#"{"event":"status","status":"queued"}"#,
+                r#"{"event":"status","status":"running"}"#,
+                r#"{"depth":0,"event":"depth_started","papers":3}"#,
+                r#"{"error":"rate limited","event":"status","status":"failed"}"#,
+            ]
+            .map(|event| format!("data:{event}\n\n"))
+            .concat()
+        );
+    }
+}
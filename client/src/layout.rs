@@ -0,0 +1,85 @@
+//! A small force-directed layout, for drawing graphs without Graphviz.
+//!
+//! It's the same simulation the HTML output runs in the browser, run to
+//! rest up front.
+
+const ITERATIONS: usize = 600;
+const REPULSION: f64 = 800.0;
+const SPRING: f64 = 0.02;
+const SPRING_LENGTH: f64 = 60.0;
+const GRAVITY: f64 = 0.01;
+const MAX_STEP: f64 = 10.0;
+/// Keeps the initial spiral spread out.
+const GOLDEN_ANGLE: f64 = 2.399963;
+
+/// Lay out `node_count` nodes joined by `edges`, given as pairs of node
+/// indices, returning each node's position.
+///
+/// The layout is deterministic, so the same graph is always drawn the
+/// same way.
+pub fn force_directed(node_count: usize, edges: &[(usize, usize)]) -> Vec<(f64, f64)> {
+    let mut positions: Vec<(f64, f64)> = (0..node_count)
+        .map(|i| {
+            let angle = i as f64 * GOLDEN_ANGLE;
+            let radius = 10.0 * ((i + 1) as f64).sqrt();
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+    let mut velocities = vec![(0.0, 0.0); node_count];
+    let mut heat = 1.0_f64;
+    for _ in 0..ITERATIONS {
+        for i in 0..node_count {
+            for j in i + 1..node_count {
+                let dx = positions[i].0 - positions[j].0;
+                let dy = positions[i].1 - positions[j].1;
+                let distance2 = dx * dx + dy * dy + 0.01;
+                let distance = distance2.sqrt();
+                let force = REPULSION / distance2;
+                velocities[i].0 += force * dx / distance;
+                velocities[i].1 += force * dy / distance;
+                velocities[j].0 -= force * dx / distance;
+                velocities[j].1 -= force * dy / distance;
+            }
+        }
+        for &(source, target) in edges {
+            let dx = positions[target].0 - positions[source].0;
+            let dy = positions[target].1 - positions[source].1;
+            let distance = (dx * dx + dy * dy).sqrt() + 0.01;
+            let force = SPRING * (distance - SPRING_LENGTH);
+            velocities[source].0 += force * dx / distance;
+            velocities[source].1 += force * dy / distance;
+            velocities[target].0 -= force * dx / distance;
+            velocities[target].1 -= force * dy / distance;
+        }
+        for (position, velocity) in positions.iter_mut().zip(&mut velocities) {
+            velocity.0 -= GRAVITY * position.0;
+            velocity.1 -= GRAVITY * position.1;
+            position.0 += (velocity.0 * heat).clamp(-MAX_STEP, MAX_STEP);
+            position.1 += (velocity.1 * heat).clamp(-MAX_STEP, MAX_STEP);
+            velocity.0 *= 0.5;
+            velocity.1 *= 0.5;
+        }
+        heat = (heat * 0.995).max(0.02);
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linked_nodes_settle_closer_than_unlinked_ones() {
+        let positions = force_directed(3, &[(0, 1)]);
+        let distance = |a: usize, b: usize| {
+            let (dx, dy) = (
+                positions[a].0 - positions[b].0,
+                positions[a].1 - positions[b].1,
+            );
+            (dx * dx + dy * dy).sqrt()
+        };
+        assert!(distance(0, 1) < distance(0, 2));
+        assert!(distance(0, 1) < distance(1, 2));
+        assert_eq!(positions, force_directed(3, &[(0, 1)]));
+    }
+}
@@ -0,0 +1,26 @@
+//! The search through Semantic Scholar and the graph it builds, apart from
+//! the command line in `main.rs`.
+//!
+//! Fetching over HTTP, the response cache, SQLite output, and `.zst` files
+//! need the default `native` feature, which brings in tokio and reqwest.
+//! Without it the rest builds for `wasm32-unknown-unknown`, for a page
+//! that searches through its own [`crawl::PaperSource`]; `cargo check-wasm`
+//! checks it does.
+
+pub mod annotations;
+pub mod books;
+#[cfg(feature = "native")]
+pub mod cache;
+pub mod citation_graph;
+pub mod crawl;
+pub mod graph;
+pub mod intern;
+pub mod layout;
+pub mod output;
+pub mod policy;
+pub mod saved;
+pub mod semantic_scholar;
+#[cfg(feature = "native")]
+pub mod simulate;
+#[cfg(feature = "native")]
+pub mod sqlite;
@@ -0,0 +1,136 @@
+//! Checking a bibliography before a long search from it: that each entry
+//! names a paper, that Semantic Scholar knows that paper, and that it
+//! goes by the entry's title, which catches mistyped DOIs.  Books and
+//! dblp keys are looked up elsewhere, so they aren't checked.
+
+use crate::crawl::PaperSource;
+use crate::graph::normalize_title;
+use crate::id_import::Entry;
+use crate::semantic_scholar::{self, PaperId};
+
+/// What's wrong with an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The entry has no DOI, URL, or ISBN.
+    NoId,
+    /// The entry's id isn't one Semantic Scholar can look up, e.g. a URL
+    /// without a DOI in it.
+    Unrecognized(String),
+    /// Semantic Scholar has no paper by the entry's id.
+    NotFound(String),
+    /// Semantic Scholar knows the entry's id by another title.
+    TitleMismatch { ours: String, theirs: String },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::NoId => write!(f, "no DOI, URL, or ISBN"),
+            Problem::Unrecognized(id) => write!(f, "can't tell what paper {id} is"),
+            Problem::NotFound(id) => write!(f, "Semantic Scholar has no paper {id}"),
+            Problem::TitleMismatch { ours, theirs } => {
+                write!(f, "titled {ours:?}, but Semantic Scholar has {theirs:?}")
+            }
+        }
+    }
+}
+
+/// Whether two titles are the same but for case, punctuation, or one
+/// leaving off a subtitle.
+fn titles_match(ours: &str, theirs: &str) -> bool {
+    let (ours, theirs) = (normalize_title(ours), normalize_title(theirs));
+    ours.is_empty() || theirs.is_empty() || ours.starts_with(&theirs) || theirs.starts_with(&ours)
+}
+
+/// The problems with `entries`, by key, in the entries' order.
+pub async fn lint<'a>(
+    source: &impl PaperSource,
+    entries: &'a [Entry],
+) -> Result<Vec<(&'a str, Problem)>, semantic_scholar::Error> {
+    let mut problems = Vec::new();
+    let mut checked = Vec::<(&Entry, PaperId)>::new();
+    for entry in entries {
+        let Some(id) = &entry.id else {
+            problems.push((entry.key.as_str(), Problem::NoId));
+            continue;
+        };
+        match PaperId::try_from(id.as_str()) {
+            Ok(PaperId::Isbn(_) | PaperId::Dblp(_)) => {}
+            Ok(paper_id) => checked.push((entry, paper_id)),
+            Err(()) => problems.push((entry.key.as_str(), Problem::Unrecognized(id.clone()))),
+        }
+    }
+    let papers = source
+        .get_paper_batch(checked.iter().map(|(_entry, id)| id.clone()).collect())
+        .await?;
+    for ((entry, id), paper) in checked.into_iter().zip(papers) {
+        let problem = match (paper, &entry.title) {
+            (None, _title) => Problem::NotFound(id.to_string()),
+            (Some(paper), Some(title)) if !titles_match(title, paper.title()) => {
+                Problem::TitleMismatch {
+                    ours: title.clone(),
+                    theirs: paper.title().to_string(),
+                }
+            }
+            (Some(_paper), _title) => continue,
+        };
+        problems.push((entry.key.as_str(), problem));
+    }
+    let order = |key: &str| entries.iter().position(|entry| entry.key == key);
+    problems.sort_by_key(|(key, _problem)| order(key));
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Network;
+
+    #[tokio::test]
+    async fn entries_are_checked_against_semantic_scholar() {
+        let mut network = Network::default();
+        network.add("a", &[]);
+        network.add("b", &[]);
+        let entry = |key: &str, id: Option<&str>, title: &str| Entry {
+            key: key.into(),
+            id: id.map(String::from),
+            title: Some(title.into()),
+            year: None,
+            author: None,
+        };
+        let entries = vec![
+            entry(
+                "fine",
+                Some("https://www.semanticscholar.org/paper/a"),
+                "Paper A: a subtitle",
+            ),
+            entry(
+                "typo",
+                Some("https://www.semanticscholar.org/paper/c"),
+                "Paper C",
+            ),
+            entry(
+                "mixed-up",
+                Some("https://www.semanticscholar.org/paper/b"),
+                "Paper A",
+            ),
+            entry("missing", None, "Paper D"),
+            entry("book", Some("ISBN:9780201896831"), "A Book"),
+        ];
+        let problems = lint(&network, &entries).await.unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                ("typo", Problem::NotFound("c".into())),
+                (
+                    "mixed-up",
+                    Problem::TitleMismatch {
+                        ours: "Paper A".into(),
+                        theirs: "Paper b".into()
+                    }
+                ),
+                ("missing", Problem::NoId),
+            ]
+        );
+    }
+}
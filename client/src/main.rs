@@ -1,215 +1,1357 @@
-use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
 
 use argh::FromArgs;
-use semantic_scholar::{Paper, PaperId, ProtoPaper, SemanticScholar};
+use client::{
+    annotations, books, cache, crawl, graph, output, policy, saved, semantic_scholar, simulate,
+    sqlite,
+};
+use crawl::PaperSource;
+use futures::StreamExt;
+use notify::Watcher;
+use semantic_scholar::{PaperId, Resolution, SemanticScholar};
 
+mod aggregate;
+mod bibtex;
+mod compat;
+mod config;
+mod dblp;
+mod diff;
+mod exit;
+mod fixture;
+mod history;
 mod id_import;
-mod semantic_scholar;
+mod impact;
+mod jobs;
+mod lint;
+mod man;
+mod pdfs;
+mod report;
+mod stats;
+mod tui;
+mod validate;
 
 #[derive(FromArgs)]
 /// Generate a citation graph based on the contents of a bibliography.
 pub struct Cli {
-    /// the path to a Bib(La)TeX bibliography
-    #[argh(positional)]
-    bibliography: String,
-    /// what URL will be serving the API
-    #[argh(option, default = "\"api.fletcherporter.com/s2\".into()")]
+    #[argh(subcommand)]
+    command: Command,
+    /// read settings from this TOML file rather than
+    /// citation-graph.toml; options given here win over it
+    #[argh(option)]
+    #[allow(dead_code)] // read before parsing
+    config: Option<String>,
+    /// what URL will be serving the API, without the scheme
+    #[argh(
+        option,
+        default = "\"api.fletcherporter.com/s2\".into()",
+        from_str_fn(validate::base_uri)
+    )]
     base_uri: String,
-    /// how many search iterations should be performed
-    #[argh(option, default = "4")]
+    /// the environment variable holding a Semantic Scholar API key to
+    /// send, for when base-uri is the API itself rather than the rate
+    /// limiter
+    #[argh(option)]
+    api_key_env: Option<String>,
+    /// leave out the Semantic Scholar attribution and license note, e.g.
+    /// when base-uri serves data from another backend
+    #[argh(switch)]
+    no_attribution: bool,
+    /// the format to write the graph in: dot, graphml, html, svg,
+    /// mermaid, json, ndjson, or sqlite (which needs --output); by
+    /// default, guessed from the output file name, or dot
+    #[argh(option)]
+    output_format: Option<output::Format>,
+    /// write the graph to this file instead of stdout, compressed if it
+    /// ends in .gz or .zst
+    #[argh(option)]
+    output: Option<String>,
+    /// the line endings to write text exports with: lf or crlf
+    #[argh(option, default = "output::Newline::Lf")]
+    newline: output::Newline,
+    /// start text exports with a UTF-8 byte order mark
+    #[argh(switch)]
+    bom: bool,
+    /// also write each community of papers to its own file in this
+    /// directory
+    #[argh(option)]
+    split_by_cluster: Option<String>,
+    /// also write a Markdown report embedding the graph to this path
+    #[argh(option)]
+    report_file: Option<String>,
+    /// embed at most this many papers in the report, the most cited
+    #[argh(option, default = "200")]
+    report_max_nodes: usize,
+    /// also print this report on stderr once the graph is written:
+    /// reading-order, a list of the papers each after those it cites, or
+    /// stats, figures on the graph's size and shape
+    #[argh(option)]
+    report: Vec<report::Report>,
+    /// also print on stderr the papers cited by at least this many seed
+    /// papers, most shared first
+    #[argh(option)]
+    common_references: Option<usize>,
+    /// also write figures on the graph's size and shape to this path as
+    /// JSON, as --report stats prints them
+    #[argh(option)]
+    stats: Option<String>,
+    /// fill the papers by this author, e.g. "J. Y. Wong", in red
+    #[argh(option)]
+    highlight_author: Option<String>,
+    /// merge the notes, tags, and colors in this TOML or YAML file, keyed
+    /// by paper id or DOI, into the papers they name
+    #[argh(option)]
+    annotations: Option<String>,
+    /// write only the papers with this tag, from --annotations or the
+    /// bibliography's keywords, and the citations between them; give it
+    /// more than once to keep papers with any of several
+    #[argh(option)]
+    filter_tag: Vec<String>,
+    /// write a condensed graph instead: year for a node per publication
+    /// year and edges weighted by how many citations flow between them
+    #[argh(option)]
+    aggregate: Option<output::Aggregate>,
+    /// keep fetched papers in this directory and reuse them for a month
+    /// rather than fetching them again
+    #[argh(option)]
+    cache_dir: Option<String>,
+    /// fetch nothing, building only from the papers in --cache-dir
+    /// however old, and failing anything else that needs the network
+    #[argh(switch)]
+    offline: bool,
+    /// how many seconds a request to Semantic Scholar may take before
+    /// it's given up on and tried again, 120 unless given
+    #[argh(option, default = "120", from_str_fn(validate::request_timeout))]
+    request_timeout: u64,
+    /// the most requests to Semantic Scholar to have in flight at once,
+    /// 8 unless given
+    #[argh(
+        option,
+        default = "semantic_scholar::DEFAULT_MAX_CONCURRENT_REQUESTS",
+        from_str_fn(validate::max_concurrent_requests)
+    )]
+    max_concurrent_requests: usize,
+    /// how to show a build's progress on stderr: text, or json for a JSON
+    /// object a line with the event, its depth, the papers fetched and
+    /// queued, and the seconds elapsed, in place of the request log
+    #[argh(option, default = "crawl::ProgressFormat::Text")]
+    progress: crawl::ProgressFormat,
+    /// how to write an error before exiting: text, or json for scripts;
+    /// either way, the exit code says what kind of error it was
+    #[argh(option, default = "exit::ErrorFormat::Text")]
+    error_format: exit::ErrorFormat,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+// there's only ever the one, so the build's many options cost nothing
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    Build(Build),
+    Search(Search),
+    Render(Render),
+    Impact(Impact),
+    Diff(Diff),
+    Cache(Cache),
+    Serve(Serve),
+    ExportBib(ExportBib),
+    Neighborhood(Neighborhood),
+    Lint(Lint),
+    Server(Server),
+    Man(Man),
+}
+
+#[derive(FromArgs)]
+/// Search out from the papers in a bibliography through their
+/// references.
+#[argh(subcommand, name = "build")]
+struct Build {
+    /// the path to a Bib(La)TeX bibliography, a LaTeX .aux or .bbl file
+    /// to start from only the papers a manuscript cites, an Org file
+    /// citing with org-ref, a directory of Markdown or Org notes linking
+    /// to DOIs or arXiv, or a graph saved as JSON or NDJSON to render
+    /// again
+    #[argh(positional)]
+    bibliography: Option<String>,
+    /// how many search iterations should be performed, at most 10
+    #[argh(option, default = "4", from_str_fn(validate::max_depth))]
     max_depth: usize,
     /// the citation density your bibliography's reference network.
     ///
     /// Informally, try to tune this so only some dozens of papers are
     /// searched in the last iteration.
-    #[argh(option, default = "3.25")]
+    #[argh(option, default = "3.25", from_str_fn(validate::connectivity))]
     connectivity: f64,
+    /// how to pick the papers to expand: connectivity, which uses the
+    /// heuristic above; top-local or top-global, the most cited within
+    /// the graph or across Semantic Scholar first, up to
+    /// --max-papers-per-depth; or breadth-first, every paper found; by
+    /// default connectivity, or top-local given a budget
+    #[argh(option)]
+    policy: Option<policy::Policy>,
+    /// only expand into papers in these comma-separated fields of study,
+    /// e.g. "Computer Science,Mathematics"
+    #[argh(option)]
+    fields_of_study: Option<String>,
+    /// only expand papers by this author, e.g. "J. Y. Wong", to map what
+    /// influenced their work
+    #[argh(option)]
+    only_author: Option<String>,
+    /// only expand papers that Semantic Scholar reports as cited at
+    /// least this many times overall; replaces the connectivity
+    /// heuristic past the seed papers
+    #[argh(option)]
+    min_citation_count: Option<usize>,
+    /// expand at most this many papers per iteration, picking those most
+    /// cited within the graph; replaces the connectivity heuristic
+    #[argh(option)]
+    max_papers_per_depth: Option<usize>,
+    /// expand at most this many papers over the whole search, picking
+    /// those most cited within the graph; replaces the connectivity
+    /// heuristic
+    #[argh(option)]
+    max_total_papers: Option<usize>,
+    /// weight each citation by how many times it's made in the citing
+    /// paper's text; costs a request per expanded paper
+    #[argh(switch)]
+    edge_weights: bool,
+    /// search a random network of this many papers instead of a
+    /// bibliography, to see how the other options behave offline
+    #[argh(option)]
+    simulate: Option<usize>,
+    /// add this many papers Semantic Scholar recommends based on the
+    /// bibliography, styled apart from the rest
+    #[argh(option)]
+    recommend: Option<usize>,
+    /// fetch Semantic Scholar's TL;DR of each paper, shown under its
+    /// title and in its tooltip
+    #[argh(switch)]
+    tldr: bool,
+    /// approve or reject each paper by hand before it's expanded
+    #[argh(switch)]
+    interactive: bool,
+    /// keep running, and build the graph again whenever the bibliography
+    /// or --scan-pdfs folder changes; pair with --cache-dir so only new
+    /// papers are fetched
+    #[argh(switch)]
+    watch: bool,
+    /// look up bibliography entries without a DOI or URL by their title,
+    /// year, and first author rather than leaving them out
+    #[argh(switch)]
+    title_match: bool,
+    /// leave out entries --title-match can't place rather than asking
+    /// which of Semantic Scholar's closest papers each one is
+    #[argh(switch)]
+    non_interactive: bool,
+    /// a BibTeX or Zotero CSL JSON export of your whole library; papers
+    /// in it or the bibliography are marked as ones you have
+    #[argh(option)]
+    library: Option<String>,
+    /// a graph saved as JSON or NDJSON from an earlier build; only papers
+    /// added to the bibliography since are searched from, and what they
+    /// add to the graph is marked
+    #[argh(option)]
+    update: Option<String>,
+    /// a folder of downloaded papers to search from as well as, or
+    /// instead of, a bibliography, each found by the DOI in its metadata
+    /// or on its first pages
+    #[argh(option)]
+    scan_pdfs: Option<String>,
+    /// comma-separated catalogues to take papers' metadata from, of s2,
+    /// openalex, crossref, and dblp; for each paper the most complete record
+    /// wins, ties going to whichever is listed first.  `fixture <dir>`
+    /// reads canned responses from a directory instead, for testing
+    #[argh(option, default = "String::from(\"s2\")")]
+    source: String,
+    /// keep papers listed under several ids, like a preprint and its
+    /// journal version, apart rather than merging them
+    #[argh(switch)]
+    no_dedupe: bool,
+    /// keep the papers the bibliography cites however sparsely they're
+    /// connected, and prune harder the further out papers are
+    #[argh(switch)]
+    depth_weighted_pruning: bool,
+    /// write papers and citations to this NDJSON file as they're found,
+    /// to watch a long search or keep what it found should it fail; it's
+    /// unpruned, and can be rendered later
+    #[argh(option)]
+    stream: Option<String>,
+    /// if a request fails partway through the search, still write the
+    /// graph found so far, then fail
+    #[argh(switch)]
+    keep_partial: bool,
+    /// stop searching after this many seconds, then write the graph
+    /// found so far and fail, for runs nobody's watching
+    #[argh(option)]
+    max_runtime: Option<u64>,
 }
 
-struct StagingData {
-    citation_count: usize,
-    paper: Paper,
+#[derive(FromArgs)]
+/// Graph the papers citing one paper, and those citing them, and
+/// summarize its influence by year and venue on stderr.
+#[argh(subcommand, name = "impact")]
+struct Impact {
+    /// a DOI or Semantic Scholar URL of the paper
+    #[argh(positional)]
+    paper_id: String,
+    /// how many generations of citing papers to follow
+    #[argh(option, default = "2")]
+    depth: usize,
+    /// follow at most this many citing papers from each paper
+    #[argh(option, default = "1000")]
+    max_citations_per_paper: usize,
 }
 
-type Staging = HashMap<String, StagingData>;
+#[derive(FromArgs)]
+/// Describe how a graph changed between two saved runs, as a Markdown
+/// report on stdout.
+#[argh(subcommand, name = "diff")]
+struct Diff {
+    /// the earlier graph, saved as JSON or NDJSON
+    #[argh(positional)]
+    old: String,
+    /// the later graph, saved as JSON or NDJSON
+    #[argh(positional)]
+    new: String,
+    /// also write both graphs overlaid as DOT to this path, with added
+    /// papers and citations in green and removed ones in red
+    #[argh(option)]
+    dot: Option<String>,
+}
 
-#[derive(Default, PartialEq, Eq, Hash)]
-struct Reference {
-    referencer: String,
-    referencee: String,
+#[derive(FromArgs)]
+/// Search Semantic Scholar for papers, listing each one's id, year, and
+/// title on stdout, tab-separated.
+#[argh(subcommand, name = "search")]
+struct Search {
+    /// what to search for
+    #[argh(positional)]
+    query: Vec<String>,
+    /// list at most this many papers
+    #[argh(option, default = "10")]
+    limit: usize,
+    /// only papers published in this year or range of years, e.g. 2019
+    /// or 2016-2020
+    #[argh(option)]
+    year: Option<String>,
 }
 
-type PaperList = HashSet<ProtoPaper>;
-type ReferenceList = HashSet<Reference>;
+#[derive(FromArgs)]
+/// Write a graph saved as JSON or NDJSON again, e.g. in another format.
+#[argh(subcommand, name = "render")]
+struct Render {
+    /// the saved graph
+    #[argh(positional)]
+    graph: String,
+}
+
+#[derive(FromArgs)]
+/// Show how much is in the paper cache given by --cache-dir, or clear
+/// it.
+#[argh(subcommand, name = "cache")]
+struct Cache {
+    /// remove every cached paper
+    #[argh(switch)]
+    clear: bool,
+}
 
-impl Extend<Paper> for Staging {
-    /// Use the Semantic Scholar ID as the key and set the citation count to 1.
-    fn extend<I: IntoIterator<Item = Paper>>(&mut self, papers: I) {
-        for paper in papers {
-            let id = paper.id().to_owned();
-            if let Some(staged) = self.insert(
-                id.clone(),
-                StagingData {
-                    citation_count: 1,
-                    paper,
-                },
-            ) {
-                self.get_mut(&id).unwrap().citation_count = std::cmp::max(1, staged.citation_count);
+#[derive(FromArgs)]
+/// Serve a graph as an interactive web page, building it first if given
+/// a bibliography.
+#[argh(subcommand, name = "serve")]
+struct Serve {
+    /// a saved graph, or a bibliography to build one from as `build`
+    /// would
+    #[argh(positional)]
+    graph: String,
+    /// the port to listen on
+    #[argh(option, default = "8080")]
+    port: u16,
+    /// the address to listen on, 127.0.0.1 unless given, so only this
+    /// machine can connect; 0.0.0.0 for any
+    #[argh(option, default = "std::net::Ipv4Addr::LOCALHOST.into()")]
+    address: std::net::IpAddr,
+}
+
+#[derive(FromArgs)]
+/// Build graphs on request over HTTP, one at a time so they share the
+/// rate limit: POST a bibliography to /graphs for a job id, then GET
+/// /graphs/<id> for the graph, with ?format= for other than JSON, or
+/// /graphs/<id>/events for server-sent events as it's built.
+#[argh(subcommand, name = "server")]
+struct Server {
+    /// the port to listen on
+    #[argh(option, default = "8080")]
+    port: u16,
+    /// the address to listen on, 127.0.0.1 unless given, so only this
+    /// machine can connect; 0.0.0.0 for any
+    #[argh(option, default = "std::net::Ipv4Addr::LOCALHOST.into()")]
+    address: std::net::IpAddr,
+    /// keep every job and its graph in this SQLite database, so they can
+    /// be listed, rendered again, and compared after a restart
+    #[argh(option)]
+    history: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// Write a man page for the command line on stdout, in roff, for
+/// packaging.
+#[argh(subcommand, name = "man")]
+struct Man {}
+
+#[derive(FromArgs)]
+/// Write the papers in a saved graph as BibTeX entries on stdout.
+#[argh(subcommand, name = "export-bib")]
+struct ExportBib {
+    /// the saved graph
+    #[argh(positional)]
+    graph: String,
+    /// leave out the papers the bibliography already had
+    #[argh(switch)]
+    new: bool,
+    /// only papers cited by at least this many papers in the graph
+    #[argh(option, default = "0")]
+    min_cited_by: usize,
+}
+
+#[derive(FromArgs)]
+/// Write only the part of a saved graph around one paper.
+#[argh(subcommand, name = "neighborhood")]
+struct Neighborhood {
+    /// the saved graph
+    #[argh(positional)]
+    graph: String,
+    /// the paper at the center, by Semantic Scholar id or DOI
+    #[argh(positional)]
+    paper_id: String,
+    /// keep papers at most this many citations away, followed either way
+    #[argh(option, default = "2")]
+    radius: usize,
+}
+
+#[derive(FromArgs)]
+/// Check that each entry in a bibliography names a paper Semantic Scholar
+/// knows by the entry's title, listing any that don't on stdout.
+#[argh(subcommand, name = "lint")]
+struct Lint {
+    /// the path to a Bib(La)TeX bibliography, or a LaTeX .aux or .bbl file
+    #[argh(positional)]
+    bibliography: String,
+}
+
+/// Where and how the finished graph is written.
+struct Outputs {
+    format: output::Format,
+    path: Option<String>,
+    attribution: bool,
+    encoding: output::Encoding,
+    report_file: Option<String>,
+    report_max_nodes: usize,
+    reports: Vec<report::Report>,
+    stats: Option<String>,
+    highlight_author: Option<String>,
+    annotations: annotations::Annotations,
+    filter_tag: Vec<String>,
+    aggregate: Option<output::Aggregate>,
+    split_by_cluster: Option<String>,
+}
+
+impl Outputs {
+    /// Clean up, mark, and cut down `graph` as the options ask, before
+    /// it's written.
+    fn prepare(&self, mut graph: graph::Graph) -> graph::Graph {
+        let dropped = graph.sanitize();
+        if dropped > 0 {
+            eprintln!("dropped {dropped} self-citations and citations of missing papers");
+        }
+        if let Some(author) = &self.highlight_author {
+            graph.highlight_author(author);
+        }
+        for name in graph.annotate(&self.annotations) {
+            eprintln!("no paper in the graph for the annotation of {name}");
+        }
+        if !self.filter_tag.is_empty() {
+            graph = graph.tagged(&self.filter_tag);
+        }
+        if self.aggregate == Some(output::Aggregate::Year) {
+            graph = graph.by_year();
+        }
+        graph
+    }
+
+    fn write(&self, graph: graph::Graph) -> std::io::Result<()> {
+        let graph = &self.prepare(graph);
+        if let Some(path) = &self.report_file {
+            report::write_markdown(
+                path.as_ref(),
+                graph,
+                self.report_max_nodes,
+                self.attribution,
+                self.encoding,
+            )?;
+        }
+        if let Some(directory) = &self.split_by_cluster {
+            output::write_clusters(
+                directory.as_ref(),
+                self.format,
+                graph,
+                self.attribution,
+                self.encoding,
+            )?;
+        }
+        match &self.path {
+            Some(path) if self.format == output::Format::Sqlite => {
+                sqlite::write(path.as_ref(), graph, self.attribution)?
+            }
+            Some(path) => {
+                let mut out = output::Encoded::new(saved::create(path.as_ref())?, self.encoding);
+                output::write(&mut out, self.format, graph, self.attribution)?;
+                out.into_inner().finish()?.flush()?
             }
+            None => output::write(
+                &mut output::Encoded::new(std::io::stdout().lock(), self.encoding),
+                self.format,
+                graph,
+                self.attribution,
+            )?,
+        }
+        for &kind in &self.reports {
+            report::write(&mut std::io::stderr().lock(), kind, graph)?;
         }
+        if let Some(path) = &self.stats {
+            let out = std::io::BufWriter::new(std::fs::File::create(path)?);
+            serde_json::to_writer_pretty(out, &stats::Stats::of(graph))?;
+        }
+        Ok(())
     }
 }
 
-/// Escape `"` and replace `\` with `\\`.
-fn escape<'a>(s: impl Into<&'a str>) -> String {
-    s.into().replace('\\', "\\\\").replace('\"', "\\\"")
+/// Simulated networks are the same from run to run.
+const SIMULATION_RNG_SEED: u64 = 0x5eed;
+const SIMULATION_REFERENCES_PER_PAPER: usize = 20;
+const SIMULATION_SEED_COUNT: usize = 10;
+/// How many of the closest papers are offered for an entry its title
+/// doesn't place.
+const PICK_CANDIDATES: usize = 5;
+/// How long `--watch` waits for a change to settle before building.
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(200);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli: Cli = compat::from_env();
+    let error_format = cli.error_format;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => exit::report(&*err, error_format),
+    }
 }
 
-fn from_staging(staging: &Staging) -> PaperList {
-    staging
-        .iter()
-        .map(|(_, data)| <Paper as Into<ProtoPaper>>::into(data.paper.clone()))
-        .collect()
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = cli.cache_dir.map(cache::Cache::new);
+    let mut api = SemanticScholar::new(cli.base_uri)
+        .with_max_concurrent_requests(cli.max_concurrent_requests)
+        .with_request_timeout(std::time::Duration::from_secs(cli.request_timeout))?;
+    if let Some(cache) = &cache {
+        api = api.with_cache(cache.clone());
+    }
+    if cli.offline {
+        if cache.is_none() {
+            return Err("--offline builds from the cache, so it needs --cache-dir".into());
+        }
+        api = api.offline();
+    }
+    if cli.progress == crawl::ProgressFormat::Json {
+        api = api.quiet();
+    }
+    if let Some(var) = &cli.api_key_env {
+        let api_key = std::env::var(var).map_err(|_err| exit::MissingApiKey(var.clone()))?;
+        api = api.with_api_key(api_key.parse()?)?;
+    }
+    let outputs = Outputs {
+        format: cli
+            .output_format
+            .or_else(|| {
+                cli.output
+                    .as_deref()
+                    .and_then(|path| output::Format::from_path(path.as_ref()))
+            })
+            .unwrap_or(output::Format::Dot),
+        path: cli.output,
+        attribution: !cli.no_attribution,
+        encoding: output::Encoding {
+            newline: cli.newline,
+            bom: cli.bom,
+        },
+        report_file: cli.report_file,
+        report_max_nodes: cli.report_max_nodes,
+        reports: cli
+            .report
+            .into_iter()
+            .chain(cli.common_references.map(report::Report::CommonReferences))
+            .collect(),
+        stats: cli.stats,
+        highlight_author: cli.highlight_author,
+        annotations: match &cli.annotations {
+            Some(path) => annotations::load(path.as_ref())?,
+            None => annotations::Annotations::new(),
+        },
+        filter_tag: cli.filter_tag,
+        aggregate: cli.aggregate,
+        split_by_cluster: cli.split_by_cluster,
+    };
+    match cli.command {
+        Command::Build(build) if build.watch => {
+            run_watch(&api, &build, &outputs, cli.progress).await
+        }
+        Command::Build(build) => run_build(&api, &build, &outputs, cli.progress).await,
+        Command::Search(search) => run_search(&api, search).await,
+        Command::Render(render) => Ok(outputs.write(saved::load(render.graph.as_ref())?)?),
+        Command::Impact(impact) => run_impact(&api, impact, &outputs).await,
+        Command::Diff(diff) => run_diff(diff, outputs.encoding),
+        Command::Cache(command) => run_cache(cache, command),
+        Command::Serve(serve) => {
+            let graph = if saved::is_saved_graph(serve.graph.as_ref()) {
+                saved::load(serve.graph.as_ref())?
+            } else {
+                // built as `build` would with its defaults
+                let build = Build::from_args(&["build"], &[&serve.graph])
+                    .map_err(|early_exit| early_exit.output)?;
+                let (graph, interrupted) = build_graph(&api, &build, &outputs, None).await?;
+                if let Some(err) = interrupted {
+                    eprintln!("{err:?}; serving the papers found before it");
+                }
+                graph
+            };
+            let mut page = Vec::new();
+            output::write_html(&mut page, &outputs.prepare(graph), outputs.attribution)?;
+            let rocket = rocket::build()
+                .manage(Page(page))
+                .mount("/", rocket::routes![page])
+                .mount("/index.html", rocket::routes![page]);
+            listen(rocket, serve.address, serve.port).await
+        }
+        Command::ExportBib(export) => run_export_bib(export, outputs.encoding),
+        Command::Lint(command) => run_lint(&api, command).await,
+        Command::Server(server) => run_server(&api, server, &outputs).await,
+        Command::Man(_man) => Ok(man::write(&mut std::io::stdout().lock())?),
+        Command::Neighborhood(neighborhood) => {
+            let graph = saved::load(neighborhood.graph.as_ref())?;
+            let Some(id) = graph.find(&neighborhood.paper_id) else {
+                return Err(format!("{} isn't in the graph", neighborhood.paper_id).into());
+            };
+            Ok(outputs.write(graph.neighborhood(&id, neighborhood.radius))?)
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli: Cli = argh::from_env();
-
-    let paper_ids = match id_import::try_from_bibtex(std::fs::read_to_string(cli.bibliography)?) {
-        Err(id_import::Error::SomeKeysMissing(err)) => {
-            eprintln!("{err:?}; continuing anyway");
-            Ok(err.get_ids())
-        }
-        other => other,
-    }?;
-    let paper_ids = semantic_scholar::parse_ids(paper_ids);
-
-    let api = SemanticScholar::new(cli.base_uri);
-    let mut staging = Staging::default();
-    // one request before the loop to avoid creating a special cases
-    staging.extend(api.get_paper_batch(paper_ids).await?);
-    let mut paper_list = from_staging(&staging);
-    let mut reference_list = ReferenceList::default();
-
-    // And now the rest of the requests.
-    for depth in 0..cli.max_depth {
-        eprintln!("depth={depth}");
-        let mut staged_paper_list = PaperList::default();
-        let mut staged_reference_list = ReferenceList::default();
-        let mut remove_staged = Vec::<String>::default();
-        let mut batched_papers = Vec::<PaperId>::default();
-
-        let minimum_citations = (depth as f64 * cli.connectivity.ln()).exp().floor() as usize;
-        for (id, staged) in &staging {
-            if staged.citation_count < minimum_citations {
-                continue;
+/// Build the graph, then again each time what it's built from changes,
+/// until interrupted.
+async fn run_watch(
+    api: &SemanticScholar,
+    build: &Build,
+    outputs: &Outputs,
+    progress: crawl::ProgressFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let watched: Vec<std::path::PathBuf> = build
+        .bibliography
+        .iter()
+        .chain(&build.scan_pdfs)
+        .map(|path| std::fs::canonicalize(path).unwrap_or_else(|_err| path.into()))
+        .collect();
+    if watched.is_empty() {
+        return Err("--watch needs a bibliography or --scan-pdfs to watch".into());
+    }
+    let (changed_tx, mut changed) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = {
+        let watched = watched.clone();
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let touches_watched = event
+                .paths
+                .iter()
+                .any(|path| watched.iter().any(|watched| path.starts_with(watched)));
+            if !event.kind.is_access() && touches_watched {
+                let _ = changed_tx.send(());
             }
-            staged_reference_list.extend(
-                staged
-                    .paper
-                    .references()
-                    .iter()
-                    .filter_map(|reference| reference.id())
-                    .map(|ref_id| Reference {
-                        referencer: id.clone(),
-                        referencee: ref_id.to_string(),
-                    }),
-            );
-            staged_paper_list.extend(
-                staged
-                    .paper
-                    .references()
-                    .iter()
-                    .filter(|paper| paper.id().is_some())
-                    .cloned(),
-            );
-            batched_papers.extend(
-                staged
-                    .paper
-                    .references()
-                    .iter()
-                    .filter_map(|reference| reference.id())
-                    .map(|id| PaperId::SemanticScholar(id.to_string())),
-            );
-            remove_staged.push(id.clone());
+        })?
+    };
+    for path in &watched {
+        if path.is_dir() {
+            watcher.watch(path, notify::RecursiveMode::Recursive)?;
+        } else {
+            // editors often save by replacing the file, which would end a
+            // watch on the file itself
+            let folder = path.parent().unwrap_or(std::path::Path::new("."));
+            watcher.watch(folder, notify::RecursiveMode::NonRecursive)?;
         }
-        for id in remove_staged {
-            staging.remove(&id);
+    }
+    loop {
+        // a bibliography saved halfway through an edit shouldn't stop the
+        // watch
+        if let Err(err) = run_build(api, build, outputs, progress).await {
+            eprintln!("error: {err}");
         }
-        let new_papers = api.get_paper_batch(batched_papers).await?;
-        let new_papers_again = new_papers.clone();
-        let reference_increments: Vec<_> = new_papers_again
-            .iter()
-            .flat_map(|paper| paper.references())
-            .collect();
-        staging.extend(new_papers);
-        for reference in reference_increments {
-            let Some(ref_id) = reference.id() else {
-                continue;
+        eprintln!("watching for changes; interrupt to stop");
+        if changed.recv().await.is_none() {
+            return Ok(());
+        }
+        // a save can take several writes, which should build once
+        while let Ok(Some(())) = tokio::time::timeout(WATCH_DEBOUNCE, changed.recv()).await {}
+    }
+}
+
+/// Build each graph asked for through the job API, one after another,
+/// until interrupted.
+async fn run_server(
+    api: &SemanticScholar,
+    server: Server,
+    outputs: &Outputs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (jobs, mut queued) = match &server.history {
+        Some(path) => jobs::Jobs::with_history(history::History::open(path.as_ref())?)?,
+        None => jobs::Jobs::new(),
+    };
+    let api_server = jobs::rocket(jobs.clone(), outputs.attribution);
+    let build_all = async {
+        while let Some(job) = queued.recv().await {
+            jobs.set(job.id, jobs::Status::Running);
+            let (progress_tx, mut progress_rx) = futures::channel::mpsc::unbounded();
+            let forward = async {
+                while let Some(progress) = progress_rx.next().await {
+                    jobs.progress(job.id, &progress);
+                }
             };
-            if let Some((_id, staged)) = staging
-                .iter_mut()
-                .find(|(_id, staged)| staged.paper.id() == ref_id)
-            {
-                staged.citation_count += 1;
-            }
+            let (built, ()) = tokio::join!(build_job(api, &job, outputs, progress_tx), forward);
+            let status = match built {
+                Ok(graph) => jobs::Status::Done(Box::new(graph)),
+                Err(err) => jobs::Status::Failed(err.to_string()),
+            };
+            jobs.set(job.id, status);
         }
-        reference_list.extend(staged_reference_list);
-        paper_list.extend(staged_paper_list);
+    };
+    tokio::select! {
+        listened = listen(api_server, server.address, server.port) => listened,
+        () = build_all => Ok(()),
     }
+}
 
-    // pruning
-    for _ in 0..10 {
-        paper_list.retain(|paper| {
-            !(reference_list
-                .iter()
-                .filter(|reference| Some(reference.referencee.clone()).as_deref() == paper.id())
-                .count()
-                <= 1
-                && reference_list
-                    .iter()
-                    .filter(|reference| Some(reference.referencer.clone()).as_deref() == paper.id())
-                    .count()
-                    <= 1)
-        });
-        reference_list.retain(|reference| {
-            paper_list
-                .iter()
-                .any(|paper| paper.id() == Some(reference.referencer.clone()).as_deref())
-                && paper_list
-                    .iter()
-                    .any(|paper| paper.id() == Some(reference.referencee.clone()).as_deref())
-        });
+/// A graph's web page, for `serve`.
+struct Page(Vec<u8>);
+
+#[rocket::get("/")]
+fn page(page: &rocket::State<Page>) -> rocket::response::content::RawHtml<&[u8]> {
+    rocket::response::content::RawHtml(&page.0)
+}
+
+/// Answer requests with `rocket` on `address` and `port` until
+/// interrupted.
+async fn listen(
+    rocket: rocket::Rocket<rocket::Build>,
+    address: std::net::IpAddr,
+    port: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = rocket::Config {
+        address,
+        port,
+        log_level: rocket::config::LogLevel::Off,
+        cli_colors: false,
+        ..rocket::Config::default()
+    };
+    rocket
+        .configure(config)
+        .attach(rocket::fairing::AdHoc::on_liftoff("Listening", |rocket| {
+            Box::pin(async move {
+                let config = rocket.config();
+                eprintln!("listening at http://{}:{}/", config.address, config.port);
+            })
+        }))
+        .launch()
+        .await
+        // a Rocket error that isn't shown panics when it's dropped
+        .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+/// Build a job's graph as `build` would with its parameters.
+async fn build_job(
+    api: &SemanticScholar,
+    job: &jobs::Queued,
+    outputs: &Outputs,
+    progress: futures::channel::mpsc::UnboundedSender<crawl::Progress>,
+) -> Result<graph::Graph, Box<dyn std::error::Error>> {
+    let path =
+        std::env::temp_dir().join(format!("graph-job-{}-{}.bib", std::process::id(), job.id));
+    std::fs::write(&path, &job.bibliography)?;
+    let mut args = job.args();
+    args.push(path.to_string_lossy().into_owned());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let built = match Build::from_args(&["build"], &args) {
+        Ok(mut build) => {
+            // nobody's at the server's terminal to choose
+            build.non_interactive = true;
+            build_graph(api, &build, outputs, Some(progress)).await
+        }
+        Err(early_exit) => Err(early_exit.output.into()),
+    };
+    std::fs::remove_file(&path)?;
+    let (graph, interrupted) = built?;
+    if let Some(err) = interrupted {
+        eprintln!(
+            "job {}: {err:?}; keeping the papers found before it",
+            job.id
+        );
     }
+    Ok(outputs.prepare(graph))
+}
 
-    // make a DOT file
-    println!("digraph {{");
-    for paper in paper_list {
+async fn run_lint(api: &SemanticScholar, lint: Lint) -> Result<(), Box<dyn std::error::Error>> {
+    validate::bibliography(&lint.bibliography)?;
+    let entries =
+        id_import::entries_from_bibtex(id_import::read_bibliography(lint.bibliography.as_ref())?)?;
+    let problems = lint::lint(api, &entries).await?;
+    let mut out = std::io::stdout().lock();
+    for (key, problem) in &problems {
+        writeln!(out, "{key}: {problem}")?;
+    }
+    if problems.is_empty() {
+        eprintln!("all {} entries look fine", entries.len());
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} entries have problems",
+            problems.len(),
+            entries.len()
+        )
+        .into())
+    }
+}
+
+fn run_export_bib(
+    export: ExportBib,
+    encoding: output::Encoding,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let graph = saved::load(export.graph.as_ref())?;
+    let mut exported = std::collections::HashSet::new();
+    let papers = graph.sorted_papers().into_iter().filter(|paper| {
+        let Some(id) = paper.id() else {
+            return false;
+        };
+        let had = graph.seeds.contains(id) || graph.have.contains(id);
+        !(export.new && had)
+            && graph.citations.cited_by_count(id) >= export.min_cited_by
+            // papers listed under one id more than once are exported once
+            && exported.insert(id)
+    });
+    let mut out = output::Encoded::new(std::io::stdout().lock(), encoding);
+    bibtex::write(&mut out, papers)?;
+    out.flush()?;
+    Ok(())
+}
+
+async fn run_search(
+    api: &SemanticScholar,
+    search: Search,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let query = search.query.join(" ");
+    if query.trim().is_empty() {
+        return Err("nothing to search for".into());
+    }
+    let papers = api
+        .search(&query, search.limit, search.year.as_deref())
+        .await?;
+    let mut out = std::io::stdout().lock();
+    for paper in papers {
+        let year = paper.year().map(|year| year.to_string());
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            paper.id().unwrap_or_default(),
+            year.as_deref().unwrap_or("-"),
+            paper.title()
+        )?;
+    }
+    Ok(())
+}
+
+fn run_cache(
+    cache: Option<cache::Cache>,
+    command: Cache,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(cache) = cache else {
+        return Err("there's no cache unless --cache-dir is given".into());
+    };
+    if command.clear {
+        let cleared = cache.clear()?;
+        eprintln!("removed {cleared} papers from {}", cache.dir().display());
+    } else {
+        let stats = cache.stats()?;
         println!(
-            "    \"{}\" [label=\"{}\",URL=\"{}\"];",
-            paper.id().expect("paper id"),
-            escape(paper.title()),
-            paper.url().unwrap_or_default(),
+            "{} papers, {} bytes, in {}",
+            stats.entries,
+            stats.bytes,
+            cache.dir().display()
         );
     }
-    for Reference {
-        referencer,
-        referencee,
-    } in reference_list
-    {
-        println!("    {referencer:?} -> {referencee:?};");
+    Ok(())
+}
+
+fn run_diff(diff: Diff, encoding: output::Encoding) -> Result<(), Box<dyn std::error::Error>> {
+    let old = saved::load(diff.old.as_ref())?;
+    let new = saved::load(diff.new.as_ref())?;
+    if let Some(path) = &diff.dot {
+        let mut out = output::Encoded::new(
+            std::io::BufWriter::new(std::fs::File::create(path)?),
+            encoding,
+        );
+        diff::write_dot(&mut out, &old, &new)?;
+        out.flush()?;
     }
-    println!("}}");
+    diff::write_report(
+        &mut output::Encoded::new(std::io::stdout().lock(), encoding),
+        &diff::diff(&old, &new),
+    )?;
+    Ok(())
+}
 
+async fn run_impact(
+    api: &SemanticScholar,
+    impact: Impact,
+    outputs: &Outputs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(paper_id) = PaperId::resolve(impact.paper_id.as_str()) else {
+        return Err(format!("{:?} isn't a DOI or Semantic Scholar URL", impact.paper_id).into());
+    };
+    let (paper_ids, _dblp_sources) = dblp::resolve(vec![paper_id], api.is_offline()).await?;
+    let Some((paper_id, _resolution)) = paper_ids.into_iter().next() else {
+        return Err(format!("{:?} wasn't found on dblp", impact.paper_id).into());
+    };
+    let crawl = impact::crawl(api, paper_id, impact.depth, impact.max_citations_per_paper).await?;
+    let Some(root) = crawl.seeds.first() else {
+        return Err(format!("{:?} wasn't found", impact.paper_id).into());
+    };
+    impact::write_summary(
+        &mut std::io::stderr().lock(),
+        &impact::summarize(&crawl.graph, root),
+    )?;
+    outputs.write(crawl.graph)?;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn escape_a_string() {
-        assert_eq!(escape("asdf \"foo\" \\aaa"), "asdf \\\"foo\\\" \\\\aaa");
+async fn run_build(
+    api: &SemanticScholar,
+    build: &Build,
+    outputs: &Outputs,
+    progress: crawl::ProgressFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (graph, interrupted) = match progress {
+        crawl::ProgressFormat::Text => build_graph(api, build, outputs, None).await?,
+        crawl::ProgressFormat::Json => {
+            let (progress_tx, progress_rx) = futures::channel::mpsc::unbounded();
+            let (built, written) = tokio::join!(
+                build_graph(api, build, outputs, Some(progress_tx)),
+                crawl::write_json(progress_rx, std::io::stderr()),
+            );
+            written?;
+            built?
+        }
+    };
+    outputs.write(graph)?;
+    match interrupted {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Build the graph, with what stopped the search early if anything did,
+/// saying how the search goes on `progress`.
+async fn build_graph(
+    api: &SemanticScholar,
+    build: &Build,
+    outputs: &Outputs,
+    progress: Option<futures::channel::mpsc::UnboundedSender<crawl::Progress>>,
+) -> Result<(graph::Graph, Option<semantic_scholar::Error>), Box<dyn std::error::Error>> {
+    if let Some(bibliography) = &build.bibliography {
+        validate::bibliography(bibliography)?;
     }
+    let fields_of_study: Option<Vec<String>> = build.fields_of_study.as_ref().map(|fields| {
+        fields
+            .split(',')
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect()
+    });
+    let source = match build.source.strip_prefix(fixture::SOURCE_PREFIX) {
+        Some(dir) => fixture::Source::Fixture(fixture::Fixture::load(dir.as_ref())?),
+        None => fixture::Source::Api(api),
+    };
+    let providers = match source {
+        fixture::Source::Api(_) => aggregate::parse_providers(&build.source)?,
+        fixture::Source::Fixture(_) => vec![aggregate::Provider::SemanticScholar],
+    };
+    let budgeted = build.max_papers_per_depth.is_some() || build.max_total_papers.is_some();
+    let policy = build.policy.unwrap_or(if budgeted {
+        policy::Policy::TopLocal
+    } else {
+        policy::Policy::Connectivity
+    });
+    let options = crawl::Options {
+        max_depth: build.max_depth,
+        policy: policy.build(build.connectivity),
+        fields_of_study,
+        only_author: build.only_author.clone(),
+        min_citation_count: build.min_citation_count,
+        max_papers_per_depth: build.max_papers_per_depth,
+        max_total_papers: build.max_total_papers,
+        edge_weights: build.edge_weights,
+        keep_partial: build.keep_partial,
+        // what the review turns down would be fetched for nothing
+        prefetch: !build.interactive,
+        progress,
+        max_runtime: build.max_runtime.map(std::time::Duration::from_secs),
+    };
+    let mut legend = graph::Legend {
+        bibliography: match (
+            build.bibliography.as_ref().or(build.scan_pdfs.as_ref()),
+            build.simulate,
+        ) {
+            (_, Some(paper_count)) => format!("a simulated network of {paper_count} papers"),
+            (Some(bibliography), None) => {
+                std::path::Path::new(bibliography).file_name().map_or_else(
+                    || bibliography.clone(),
+                    |name| name.to_string_lossy().into(),
+                )
+            }
+            (None, None) => String::new(),
+        },
+        date: graph::date(std::time::SystemTime::now()),
+        max_depth: options.max_depth,
+        connectivity: build.connectivity,
+        expanded_per_depth: Vec::new(),
+        failed_chunks: Vec::new(),
+    };
+
+    let previous = build
+        .update
+        .as_ref()
+        .map(|path| saved::load(path.as_ref()))
+        .transpose()?;
+    let mut stream = build
+        .stream
+        .as_ref()
+        .map(|path| saved::Stream::create(path.as_ref(), outputs.attribution))
+        .transpose()?;
+    let mut observe = |found: &graph::Graph| {
+        if let Some(stream) = &mut stream {
+            stream.write(found);
+        }
+    };
+    // tags for the seeds from the bibliography's keywords
+    let mut keywords = annotations::Annotations::new();
+    // the seeds Semantic Scholar couldn't find, with their BibTeX keys
+    let mut unresolved = Vec::<(String, Option<String>)>::new();
+    let crawl = if let Some(paper_count) = build.simulate {
+        let network = simulate::Network::random(
+            SIMULATION_RNG_SEED,
+            paper_count,
+            SIMULATION_REFERENCES_PER_PAPER,
+        );
+        let crawl = crawl::crawl_with_review(
+            &network,
+            network.seeds(SIMULATION_SEED_COUNT),
+            &options,
+            |_depth, frontier| vec![true; frontier.len()],
+            &mut observe,
+        )
+        .await?;
+        if options.progress.is_none() {
+            for (depth, expanded) in crawl.expanded_per_depth.iter().enumerate() {
+                eprintln!("depth={depth}: expanded {expanded} papers");
+            }
+        }
+        crawl
+    } else {
+        if build.bibliography.is_none() && build.scan_pdfs.is_none() {
+            return Err("a bibliography or --scan-pdfs is needed unless simulating".into());
+        }
+        let mut bibtex_src = String::new();
+        if let Some(bibliography) = &build.bibliography {
+            if saved::is_saved_graph(bibliography.as_ref()) {
+                eprintln!("warning: building from a saved graph is deprecated; use `render`");
+                return Ok((saved::load(bibliography.as_ref())?, None));
+            }
+            bibtex_src = id_import::read_bibliography(bibliography.as_ref())?;
+        }
+        if let Some(dir) = &build.scan_pdfs {
+            for (path, doi) in pdfs::scan(dir.as_ref())? {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                match doi {
+                    Some(doi) => bibtex_src += &id_import::bibtex_from_note(&name, &doi),
+                    None => eprintln!("warning: found no DOI in {}", path.display()),
+                }
+            }
+        }
+        keywords = id_import::keywords_from_bibtex(&bibtex_src)?
+            .into_iter()
+            .map(|(id, tags)| {
+                let annotation = annotations::Annotation {
+                    tags,
+                    ..Default::default()
+                };
+                (PaperId::canonical(&id), annotation)
+            })
+            .collect();
+        let paper_ids = match id_import::try_from_bibtex(&bibtex_src) {
+            Err(id_import::Error::SomeKeysMissing(err)) => {
+                eprintln!("{err:?}; continuing anyway");
+                Ok(err.get_ids())
+            }
+            other => other,
+        }?;
+        let mut seeds = semantic_scholar::parse_ids(paper_ids);
+        // the BibTeX keys of entries found by their titles, by paper id
+        let mut matched_keys = std::collections::HashMap::<String, String>::new();
+        if build.title_match {
+            // there's nobody to ask when stdin is a file or pipe
+            let asking = !build.non_interactive && std::io::stdin().is_terminal();
+            match source {
+                fixture::Source::Api(api) => {
+                    for entry in id_import::entries_from_bibtex(&bibtex_src)? {
+                        let (None, Some(title)) = (&entry.id, &entry.title) else {
+                            continue;
+                        };
+                        let matched = api
+                            .match_title(title, entry.year.as_deref(), entry.author.as_deref())
+                            .await?;
+                        let (paper, resolution) = match matched {
+                            Some(paper) => (Some(paper), Resolution::TitleMatch),
+                            None if asking => {
+                                let candidates = api.search(title, PICK_CANDIDATES, None).await?;
+                                let picked = tui::pick(
+                                    &mut std::io::stderr(),
+                                    &mut std::io::stdin().lock(),
+                                    &entry.key,
+                                    title,
+                                    &candidates,
+                                )?;
+                                // the user chose it, so it's as good as given
+                                let paper = picked.and_then(|i| candidates.into_iter().nth(i));
+                                (paper, Resolution::Exact)
+                            }
+                            None => (None, Resolution::TitleMatch),
+                        };
+                        let Some(id) = paper.as_ref().and_then(|paper| paper.id()) else {
+                            eprintln!("{}: no paper titled {title:?}", entry.key);
+                            continue;
+                        };
+                        let id = PaperId::SemanticScholar(id.to_string());
+                        if seeds
+                            .iter()
+                            .all(|(seed, _resolution)| seed.to_string() != id.to_string())
+                        {
+                            matched_keys.insert(id.to_string(), entry.key);
+                            seeds.push((id, resolution));
+                        }
+                    }
+                }
+                fixture::Source::Fixture(_) => eprintln!("titles aren't matched from fixtures"),
+            }
+        }
+        let (seeds, dblp_sources) = dblp::resolve(
+            seeds
+                .into_iter()
+                .filter(|(id, _resolution)| {
+                    previous
+                        .as_ref()
+                        .is_none_or(|previous| !previous.sources.contains(&id.to_string()))
+                })
+                .collect(),
+            api.is_offline(),
+        )
+        .await?;
+        let (isbns, seeds): (Vec<_>, Vec<_>) = seeds
+            .into_iter()
+            .partition(|(id, _resolution)| matches!(id, PaperId::Isbn(_)));
+        if previous.is_some() {
+            eprintln!(
+                "{} papers new to the bibliography",
+                isbns.len() + seeds.len()
+            );
+        }
+        let mut reviewing = build.interactive;
+        let mut crawl = crawl::crawl_with_review(
+            &source,
+            seeds,
+            &options,
+            |depth, frontier| {
+                if !reviewing {
+                    return vec![true; frontier.len()];
+                }
+                match tui::review(depth, frontier) {
+                    Ok(tui::Review::Approved(approved)) => approved,
+                    Ok(tui::Review::Finished(approved)) => {
+                        reviewing = false;
+                        approved
+                    }
+                    Err(err) => {
+                        eprintln!("{err:?}; expanding everything from here on");
+                        reviewing = false;
+                        vec![true; frontier.len()]
+                    }
+                }
+            },
+            &mut observe,
+        )
+        .await?;
+        let mut keys: std::collections::HashMap<String, String> =
+            id_import::entries_from_bibtex(&bibtex_src)?
+                .into_iter()
+                .filter_map(|entry| Some((PaperId::canonical(&entry.id?), entry.key)))
+                .collect();
+        keys.extend(matched_keys);
+        crawl.graph.bibtex_keys = crawl
+            .found
+            .iter()
+            .filter_map(|(source, id)| Some((id.clone(), keys.get(source)?.clone())))
+            .collect();
+        unresolved = std::mem::take(&mut crawl.unresolved)
+            .into_iter()
+            .map(|id| {
+                let key = keys.get(&id).cloned();
+                (id, key)
+            })
+            .collect();
+        let isbns: Vec<String> = isbns
+            .into_iter()
+            .filter_map(|(id, _resolution)| match id {
+                PaperId::Isbn(isbn) => Some(isbn),
+                _ => None,
+            })
+            .collect();
+        let books = match source {
+            fixture::Source::Api(api) => {
+                if !isbns.is_empty() {
+                    api.online("looking up books")?;
+                }
+                books::GoogleBooks::new().get_books(isbns).await?
+            }
+            fixture::Source::Fixture(_) => {
+                if !isbns.is_empty() {
+                    eprintln!("books aren't looked up from fixtures");
+                }
+                Vec::new()
+            }
+        };
+        crawl.seeds.extend(
+            books
+                .iter()
+                .map(|book| PaperId::Isbn(book.isbn.clone()).to_string()),
+        );
+        crawl.graph.seeds.extend(
+            books
+                .iter()
+                .map(|book| PaperId::Isbn(book.isbn.clone()).to_string()),
+        );
+        crawl.graph.sources.extend(
+            books
+                .iter()
+                .map(|book| PaperId::Isbn(book.isbn.clone()).to_string()),
+        );
+        crawl.graph.add_books(books);
+        crawl.graph.sources.extend(dblp_sources);
+        crawl
+    };
+    if let Some(stream) = stream {
+        stream.finish()?;
+    }
+    let interrupted = crawl.interrupted;
+    let mut graph = crawl.graph;
+    legend.expanded_per_depth = crawl.expanded_per_depth;
+    legend.failed_chunks = crawl.failed_chunks;
+    graph.legend = Some(legend);
+    if let Some(previous) = previous {
+        graph.merge_previous(previous);
+    }
+    if let (Some(library), None) = (&build.library, build.simulate) {
+        let library_src = std::fs::read_to_string(library)?;
+        let library_ids = match if library.ends_with(".json") {
+            id_import::try_from_csl_json(library_src)
+        } else {
+            id_import::try_from_bibtex(library_src)
+        } {
+            Err(id_import::Error::SomeKeysMissing(err)) => Ok(err.get_ids()),
+            other => other,
+        }?;
+        let (library_ids, _dblp_sources) =
+            dblp::resolve(semantic_scholar::parse_ids(library_ids), api.is_offline()).await?;
+        let (isbns, library_ids): (Vec<_>, Vec<_>) = library_ids
+            .into_iter()
+            .map(|(id, _resolution)| id)
+            .partition(|id| matches!(id, PaperId::Isbn(_)));
+        graph.have.extend(isbns.iter().map(PaperId::to_string));
+        graph.have.extend(
+            source
+                .get_paper_batch(library_ids)
+                .await?
+                .into_iter()
+                .flatten()
+                .map(|paper| paper.id().to_string()),
+        );
+        graph.have.extend(crawl.seeds.iter().cloned());
+    }
+    if !build.no_dedupe {
+        let merged = graph.dedupe();
+        if merged > 0 {
+            eprintln!("merged {merged} papers listed under more than one id");
+        }
+    }
+    graph.prune(build.depth_weighted_pruning);
+    if let (Some(_count), fixture::Source::Fixture(_)) = (build.recommend, &source) {
+        eprintln!("recommendations aren't available from fixtures");
+    } else if let (Some(count), None) = (build.recommend, build.simulate) {
+        let recommended = api.get_recommendations(crawl.seeds, count).await?;
+        let recommended_ids = recommended
+            .iter()
+            .filter_map(|paper| paper.id())
+            .map(|id| PaperId::SemanticScholar(id.to_string()))
+            .collect();
+        graph.add_recommendations(
+            api.get_paper_batch(recommended_ids)
+                .await?
+                .into_iter()
+                .flatten(),
+        );
+    }
+
+    if let (true, fixture::Source::Fixture(_)) = (build.tldr, &source) {
+        eprintln!("TL;DRs aren't available from fixtures");
+    } else if build.tldr && build.simulate.is_none() {
+        let ids = graph.citations.ids().map(str::to_string).collect();
+        graph.add_tldrs(&api.get_tldrs(ids).await?);
+    }
+
+    if build.simulate.is_none() {
+        if providers.len() > 1 {
+            api.online("looking papers up in other catalogues")?;
+        }
+        aggregate::enrich(&mut graph, &providers).await?;
+    }
+
+    graph.annotate(&keywords);
+
+    if !unresolved.is_empty() {
+        eprintln!(
+            "warning: Semantic Scholar couldn't find {} papers in the bibliography:",
+            unresolved.len()
+        );
+        for (id, key) in &unresolved {
+            match key {
+                Some(key) => eprintln!("  {key}: {id}"),
+                None => eprintln!("  {id}"),
+            }
+        }
+    }
+
+    Ok((graph, interrupted))
 }
@@ -0,0 +1,157 @@
+//! A roff man page for packaging, put together from argh's help for the
+//! top level and each subcommand so it never drifts from the options.
+
+use std::io::Write;
+
+use argh::FromArgs;
+
+use crate::Cli;
+
+/// The name the page documents the command under.
+pub const NAME: &str = "citation-graph";
+
+/// One command's `--help`, split into its parts.
+#[derive(Default)]
+struct Help {
+    usage: String,
+    description: String,
+    /// Each heading, like `Options`, with its terms and what they do.
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+/// The `--help` of the subcommand at `path`, e.g. `["cache"]`.
+fn help_text(path: &[&str]) -> String {
+    let mut args = path.to_vec();
+    args.push("--help");
+    match Cli::from_args(&[NAME], &args) {
+        Err(early_exit) => early_exit.output,
+        Ok(_cli) => String::new(),
+    }
+}
+
+fn parse(text: &str) -> Help {
+    let mut help = Help::default();
+    let mut description = Vec::<&str>::new();
+    for line in text.lines() {
+        if let Some(usage) = line.strip_prefix("Usage: ") {
+            help.usage = usage.to_string();
+        } else if !line.starts_with(' ') && line.ends_with(':') {
+            help.sections
+                .push((line.trim_end_matches(':').to_string(), Vec::new()));
+        } else if let Some((_heading, entries)) = help.sections.last_mut() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            // a term is indented by two, what it does by more
+            if line.starts_with("  ") && !line.starts_with("   ") {
+                let (term, text) = trimmed.split_once("  ").unwrap_or((trimmed, ""));
+                entries.push((term.to_string(), text.trim().to_string()));
+            } else if let Some((_term, text)) = entries.last_mut() {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(trimmed);
+            }
+        } else if !line.trim().is_empty() {
+            description.push(line.trim());
+        }
+    }
+    help.description = description.join(" ");
+    for (_heading, entries) in &mut help.sections {
+        entries.retain(|(term, _text)| term != "--help, help");
+    }
+    help.sections
+        .retain(|(_heading, entries)| !entries.is_empty());
+    help
+}
+
+/// `text` with what roff would take for requests or escapes escaped.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with(['.', '\'']) {
+        format!("\\&{escaped}")
+    } else {
+        escaped
+    }
+}
+
+fn write_entries(out: &mut impl Write, entries: &[(String, String)]) -> std::io::Result<()> {
+    for (term, text) in entries {
+        writeln!(out, ".TP\n\\fB{}\\fR\n{}", escape(term), escape(text))?;
+    }
+    Ok(())
+}
+
+/// Write the subcommand at `path` and those under it.
+fn write_subcommand(out: &mut impl Write, path: &[&str]) -> std::io::Result<()> {
+    let help = parse(&help_text(path));
+    writeln!(out, ".SS \"{}\"", escape(&path.join(" ")))?;
+    writeln!(out, "{}", escape(&help.description))?;
+    writeln!(out, ".PP\n\\fB{}\\fR", escape(&help.usage))?;
+    for (heading, entries) in &help.sections {
+        if heading == "Commands" {
+            for (name, _text) in entries {
+                let mut sub = path.to_vec();
+                sub.push(name);
+                write_subcommand(out, &sub)?;
+            }
+        } else {
+            writeln!(out, ".PP\n{}:", escape(heading))?;
+            write_entries(out, entries)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write(out: &mut impl Write) -> std::io::Result<()> {
+    let help = parse(&help_text(&[]));
+    writeln!(out, ".TH {} 1", NAME.to_uppercase())?;
+    writeln!(out, ".SH NAME\n{} \\- {}", NAME, escape(&help.description))?;
+    writeln!(out, ".SH SYNOPSIS\n\\fB{}\\fR", escape(&help.usage))?;
+    for (heading, entries) in &help.sections {
+        writeln!(out, ".SH {}", escape(&heading.to_uppercase()))?;
+        if heading == "Commands" {
+            for (name, _text) in entries {
+                write_subcommand(out, &[name])?;
+            }
+        } else {
+            write_entries(out, entries)?;
+        }
+    }
+    writeln!(
+        out,
+        ".SH ENVIRONMENT\nAny option can be set by a variable named for it, like \
+         \\fBCITATION_GRAPH_BASE_URI\\fR or \\fBCITATION_GRAPH_BUILD_MAX_DEPTH\\fR; \
+         \\fBCITATION_GRAPH_CONFIG\\fR names a config file to read instead of \
+         \\fIcitation\\-graph.toml\\fR."
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_subcommand_has_its_options() {
+        let mut page = Vec::new();
+        write(&mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".TH CITATION-GRAPH 1\n"));
+        assert!(page.contains(".SS \"build\""));
+        assert!(page.contains("\\fB\\-\\-max\\-depth\\fR"));
+        assert!(page.contains("\\fB\\-\\-cache\\-dir\\fR"));
+        assert!(!page.contains("display usage information"));
+        for line in page.lines() {
+            assert!(!line.starts_with('\''), "{line}");
+            if let Some(request) = line.strip_prefix('.') {
+                let macro_name = request.split_whitespace().next().unwrap_or_default();
+                assert!(
+                    ["TH", "SH", "SS", "TP", "PP"].contains(&macro_name),
+                    "{line}"
+                );
+            }
+        }
+    }
+}
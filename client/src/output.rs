@@ -0,0 +1,1125 @@
+//! Writing the finished citation graph out in various formats.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::json;
+
+use crate::graph::{Graph, Reference};
+use crate::layout;
+use crate::saved;
+#[cfg(feature = "native")]
+use crate::sqlite;
+
+/// The Semantic Scholar API license requires this accompany any data
+/// derived from it.
+pub const ATTRIBUTION: &str =
+    "Citation data provided by Semantic Scholar <https://www.semanticscholar.org>";
+pub const LICENSE: &str = "Semantic Scholar data is licensed under ODC-BY 1.0 \
+    <https://opendatacommons.org/licenses/by/1-0/>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dot,
+    GraphMl,
+    Html,
+    Svg,
+    Mermaid,
+    Json,
+    Ndjson,
+    Sqlite,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dot" => Ok(Format::Dot),
+            "graphml" => Ok(Format::GraphMl),
+            "html" => Ok(Format::Html),
+            "svg" => Ok(Format::Svg),
+            "mermaid" => Ok(Format::Mermaid),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "sqlite" => Ok(Format::Sqlite),
+            other => Err(format!(
+                "unknown output format {other:?}; try dot, graphml, html, svg, mermaid, json, ndjson, or sqlite"
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// The usual file extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Dot => "dot",
+            Format::GraphMl => "graphml",
+            Format::Html => "html",
+            Format::Svg => "svg",
+            Format::Mermaid => "mmd",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Sqlite => "sqlite",
+        }
+    }
+
+    /// Guess the format from a file name, looking past any compression
+    /// extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match saved::inner_extension(path)? {
+            "gv" => Some(Format::Dot),
+            "mmd" => Some(Format::Mermaid),
+            "jsonl" => Some(Format::Ndjson),
+            "db" | "sqlite3" => Some(Format::Sqlite),
+            extension => extension.parse().ok(),
+        }
+    }
+}
+
+/// What to condense the graph's papers into before writing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// A node per publication year.
+    Year,
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "year" => Ok(Aggregate::Year),
+            other => Err(format!("unknown aggregate {other:?}; try year")),
+        }
+    }
+}
+
+/// Line endings for text exports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newline {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl FromStr for Newline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(Newline::Lf),
+            "crlf" => Ok(Newline::CrLf),
+            other => Err(format!("unknown newline {other:?}; try lf or crlf")),
+        }
+    }
+}
+
+/// How text exports are encoded on top of UTF-8.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Encoding {
+    pub newline: Newline,
+    /// Start with a byte order mark, which some Windows tools need to
+    /// read the file as UTF-8 at all.
+    pub bom: bool,
+}
+
+/// A writer applying an [`Encoding`] to everything written through it.
+pub struct Encoded<W: Write> {
+    inner: W,
+    newline: Newline,
+    bom_pending: bool,
+}
+
+impl<W: Write> Encoded<W> {
+    pub fn new(inner: W, encoding: Encoding) -> Self {
+        Encoded {
+            inner,
+            newline: encoding.newline,
+            bom_pending: encoding.bom,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Encoded<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.bom_pending {
+            self.inner.write_all("\u{feff}".as_bytes())?;
+            self.bom_pending = false;
+        }
+        match (self.newline, buf.iter().position(|&byte| byte == b'\n')) {
+            (Newline::CrLf, Some(0)) => {
+                self.inner.write_all(b"\r\n")?;
+                Ok(1)
+            }
+            (Newline::CrLf, Some(line_end)) => self.inner.write(&buf[..line_end]),
+            _ => self.inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Escape `"` and replace `\` with `\\`.
+pub fn escape<'a>(s: impl Into<&'a str>) -> String {
+    s.into().replace('\\', "\\\\").replace('\"', "\\\"")
+}
+
+/// Escape the characters XML won't take verbatim in text or attributes.
+pub fn escape_xml<'a>(s: impl Into<&'a str>) -> String {
+    s.into()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Write `graph` to `out` as `format`.
+///
+/// SQLite databases can't be streamed, so need [`crate::sqlite::write`]
+/// and a file instead.
+pub fn write(
+    out: &mut impl Write,
+    format: Format,
+    graph: &Graph,
+    attribution: bool,
+) -> std::io::Result<()> {
+    match format {
+        Format::Dot => write_dot(out, graph, attribution),
+        Format::GraphMl => write_graphml(out, graph, attribution),
+        Format::Html => write_html(out, graph, attribution),
+        Format::Svg => write_svg(out, graph, attribution),
+        Format::Mermaid => write_mermaid(out, graph, attribution),
+        Format::Json => saved::write_json(out, graph, attribution),
+        Format::Ndjson => saved::write_ndjson(out, graph, attribution),
+        Format::Sqlite => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "SQLite output has to go to a file; give one with --output",
+        )),
+    }
+}
+
+/// Write each of the graph's communities to `cluster-<n>.<ext>` in
+/// `directory`, largest first, rendering them in parallel.
+///
+/// Communities of a single paper are left out.
+#[cfg(feature = "native")]
+pub fn write_clusters(
+    directory: &Path,
+    format: Format,
+    graph: &Graph,
+    attribution: bool,
+    encoding: Encoding,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    let communities: Vec<(usize, Vec<String>)> = graph
+        .communities()
+        .into_iter()
+        .filter(|community| community.len() > 1)
+        .enumerate()
+        .collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = communities.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = communities
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    for (n, community) in chunk {
+                        let subgraph = graph.subgraph(community.iter().map(String::as_str));
+                        let path = directory.join(format!("cluster-{n}.{}", format.extension()));
+                        if format == Format::Sqlite {
+                            sqlite::write(&path, &subgraph, attribution)?;
+                            continue;
+                        }
+                        let mut file = Encoded::new(
+                            std::io::BufWriter::new(std::fs::File::create(path)?),
+                            encoding,
+                        );
+                        write(&mut file, format, &subgraph, attribution)?;
+                        file.flush()?;
+                    }
+                    Ok::<_, std::io::Error>(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("cluster writer panicked"))
+    })
+}
+
+fn is_fuzzy(graph: &Graph, reference: &Reference) -> bool {
+    graph.match_confidence.contains_key(&*reference.referencer)
+        || graph.match_confidence.contains_key(&*reference.referencee)
+}
+
+/// The legend's node, named so it can't be taken for a paper's id.
+const LEGEND_NODE: &str = "legend:";
+/// The fill of papers by the author picked out with `--highlight-author`.
+const HIGHLIGHT: &str = "#ff6666";
+
+/// The ids in other catalogues written out as node attributes, each as
+/// the attribute's name and Semantic Scholar's name for the catalogue.
+const EXTERNAL_IDS: [(&str, &str); 3] = [("doi", "DOI"), ("arxiv", "ArXiv"), ("pmid", "PubMed")];
+
+/// How much of a paper's TL;DR fits under its title.
+const TLDR_LENGTH: usize = 80;
+
+/// `text`, cut to `length` characters with an ellipsis if it's longer.
+fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() > length {
+        text.chars().take(length - 1).chain(['…']).collect()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Write the graph as a Graphviz digraph.
+///
+/// If `attribution` is set, the data source's attribution and license
+/// are written as comments at the top.  Seeds that weren't matched
+/// exactly get a `confidence` attribute, and their edges `fuzzy=true`.
+/// Edges with known citation contexts are weighted and thickened by how
+/// many there are.  Recommended papers are dashed, papers the user
+/// already has get a double border, and papers and citations new since
+/// the graph this one updates are green.  Papers whose metadata was
+/// merged from several catalogues note where each field came from in
+/// `provenance`, and papers by a highlighted author are filled red.
+/// Annotated papers are filled with their color, if they have one, and
+/// carry their `note` and `tags`, and papers their ids in other
+/// catalogues, like `doi`.  Seeds are preceded by a comment giving their
+/// key in the bibliography.
+pub fn write_dot(out: &mut impl Write, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    if attribution {
+        writeln!(out, "// {ATTRIBUTION}")?;
+        writeln!(out, "// {LICENSE}")?;
+    }
+    if let Some(legend) = &graph.legend {
+        writeln!(
+            out,
+            "// built from {} on {}, depth {}, connectivity {}: {} papers, {} citations",
+            legend.bibliography,
+            legend.date,
+            legend.max_depth,
+            legend.connectivity,
+            graph.citations.paper_count(),
+            graph.citations.reference_count(),
+        )?;
+    }
+    writeln!(out, "digraph {{")?;
+    if let Some(legend) = &graph.legend {
+        writeln!(out, "    subgraph cluster_legend {{")?;
+        writeln!(out, "        label=\"Legend\";")?;
+        writeln!(
+            out,
+            "        \"{LEGEND_NODE}\" [shape=plaintext,label=\"bibliography: {}\\lbuilt: {}\\lmax depth: {}\\lconnectivity: {}\\lpapers: {}\\lcitations: {}\\l\"];",
+            escape(legend.bibliography.as_str()),
+            legend.date,
+            legend.max_depth,
+            legend.connectivity,
+            graph.citations.paper_count(),
+            graph.citations.reference_count(),
+        )?;
+        writeln!(out, "    }}")?;
+    }
+    for paper in graph.sorted_papers() {
+        let id = paper.id().expect("paper id");
+        if let Some(key) = graph.bibtex_keys.get(id) {
+            writeln!(out, "    // \\cite{{{key}}}")?;
+        }
+        let label = match paper.tldr() {
+            Some(tldr) => format!(
+                "{}\\n{}",
+                escape(paper.title()),
+                escape(truncate(tldr, TLDR_LENGTH).as_str())
+            ),
+            None => escape(paper.title()),
+        };
+        write!(
+            out,
+            "    \"{}\" [label=\"{}\",URL=\"{}\"",
+            id,
+            label,
+            paper.url().unwrap_or_default(),
+        )?;
+        if let Some(abstract_) = paper.abstract_() {
+            write!(
+                out,
+                ",tooltip=\"{}\"",
+                escape(abstract_).replace('\n', "\\n")
+            )?;
+        }
+        if let Some(confidence) = graph.match_confidence.get(id) {
+            write!(out, ",confidence={confidence:.2}")?;
+        }
+        let annotation = graph.annotations.get(id);
+        let fill = annotation
+            .and_then(|annotation| annotation.color.as_deref())
+            .or(graph.highlighted.contains(id).then_some(HIGHLIGHT));
+        match (graph.recommended.contains(id), fill) {
+            (true, Some(fill)) => write!(
+                out,
+                ",style=\"dashed,filled\",fillcolor=\"{}\",recommended=true",
+                escape(fill)
+            )?,
+            (true, None) => write!(out, ",style=dashed,recommended=true")?,
+            (false, Some(fill)) => write!(out, ",style=filled,fillcolor=\"{}\"", escape(fill))?,
+            (false, None) => {}
+        }
+        if graph.highlighted.contains(id) {
+            write!(out, ",highlighted=true")?;
+        }
+        if let Some(note) = annotation.and_then(|annotation| annotation.note.as_deref()) {
+            write!(out, ",note=\"{}\"", escape(note).replace('\n', "\\n"))?;
+        }
+        if let Some(annotation) = annotation.filter(|annotation| !annotation.tags.is_empty()) {
+            write!(
+                out,
+                ",tags=\"{}\"",
+                escape(annotation.tags.join(",").as_str())
+            )?;
+        }
+        if graph.have.contains(id) {
+            write!(out, ",peripheries=2,have=true")?;
+        }
+        if graph.seeds.contains(id) {
+            write!(out, ",shape=box,seed=true")?;
+        }
+        if graph.added.contains(id) {
+            write!(out, ",color=darkgreen,added=true")?;
+        }
+        if let Some(provenance) = graph.provenance.get(id) {
+            write!(out, ",provenance=\"{}\"", provenance_text(provenance))?;
+        }
+        writeln!(out, "];")?;
+    }
+    for reference in graph.sorted_references() {
+        let mut attributes = Vec::<String>::new();
+        if is_fuzzy(graph, reference) {
+            attributes.push("fuzzy=true".into());
+        }
+        if let Some(&weight) = graph.edge_weights.get(reference) {
+            attributes.push(format!("weight={weight}"));
+            attributes.push(format!("penwidth={:.2}", 1.0 + (weight.max(1) as f64).ln()));
+        }
+        if graph.added_references.contains(reference) {
+            attributes.push("color=darkgreen".into());
+            attributes.push("added=true".into());
+        }
+        let Reference {
+            referencer,
+            referencee,
+        } = reference;
+        if attributes.is_empty() {
+            writeln!(out, "    {referencer:?} -> {referencee:?};")?;
+        } else {
+            writeln!(
+                out,
+                "    {referencer:?} -> {referencee:?} [{}];",
+                attributes.join(",")
+            )?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+/// Which catalogue each field came from, like `title=crossref;year=openalex`.
+fn provenance_text(provenance: &BTreeMap<String, String>) -> String {
+    provenance
+        .iter()
+        .map(|(field, provider)| format!("{field}={provider}"))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Write the graph as GraphML, with the same attributes as [`write_dot`].
+pub fn write_graphml(
+    out: &mut impl Write,
+    graph: &Graph,
+    attribution: bool,
+) -> std::io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    if attribution {
+        writeln!(
+            out,
+            "  <desc>{} {}</desc>",
+            escape_xml(ATTRIBUTION),
+            escape_xml(LICENSE)
+        )?;
+    }
+    writeln!(
+        out,
+        r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>
+  <key id="url" for="node" attr.name="url" attr.type="string"/>
+  <key id="abstract" for="node" attr.name="abstract" attr.type="string"/>
+  <key id="tldr" for="node" attr.name="tldr" attr.type="string"/>
+  <key id="doi" for="node" attr.name="doi" attr.type="string"/>
+  <key id="arxiv" for="node" attr.name="arxiv" attr.type="string"/>
+  <key id="pmid" for="node" attr.name="pmid" attr.type="string"/>
+  <key id="confidence" for="node" attr.name="confidence" attr.type="double"/>
+  <key id="recommended" for="node" attr.name="recommended" attr.type="boolean"><default>false</default></key>
+  <key id="have" for="node" attr.name="have" attr.type="boolean"><default>false</default></key>
+  <key id="seed" for="node" attr.name="seed" attr.type="boolean"><default>false</default></key>
+  <key id="highlighted" for="node" attr.name="highlighted" attr.type="boolean"><default>false</default></key>
+  <key id="note" for="node" attr.name="note" attr.type="string"/>
+  <key id="tags" for="node" attr.name="tags" attr.type="string"/>
+  <key id="color" for="node" attr.name="color" attr.type="string"/>
+  <key id="added" for="node" attr.name="added" attr.type="boolean"><default>false</default></key>
+  <key id="provenance" for="node" attr.name="provenance" attr.type="string"/>
+  <key id="bibtex_key" for="node" attr.name="bibtex_key" attr.type="string"/>
+  <key id="added_edge" for="edge" attr.name="added" attr.type="boolean"><default>false</default></key>
+  <key id="fuzzy" for="edge" attr.name="fuzzy" attr.type="boolean"><default>false</default></key>
+  <key id="weight" for="edge" attr.name="weight" attr.type="int"><default>1</default></key>
+  <graph id="citations" edgedefault="directed">"#
+    )?;
+    for paper in graph.sorted_papers() {
+        let id = paper.id().expect("paper id");
+        writeln!(out, r#"    <node id="{}">"#, escape_xml(id))?;
+        writeln!(
+            out,
+            r#"      <data key="label">{}</data>"#,
+            escape_xml(paper.title())
+        )?;
+        if let Some(url) = paper.url() {
+            writeln!(out, r#"      <data key="url">{}</data>"#, escape_xml(url))?;
+        }
+        if let Some(abstract_) = paper.abstract_() {
+            writeln!(
+                out,
+                r#"      <data key="abstract">{}</data>"#,
+                escape_xml(abstract_)
+            )?;
+        }
+        if let Some(tldr) = paper.tldr() {
+            writeln!(out, r#"      <data key="tldr">{}</data>"#, escape_xml(tldr))?;
+        }
+        for (key, source) in EXTERNAL_IDS {
+            if let Some(external_id) = paper.external_id(source) {
+                writeln!(
+                    out,
+                    r#"      <data key="{key}">{}</data>"#,
+                    escape_xml(external_id)
+                )?;
+            }
+        }
+        if let Some(confidence) = graph.match_confidence.get(id) {
+            writeln!(
+                out,
+                r#"      <data key="confidence">{confidence:.2}</data>"#
+            )?;
+        }
+        if graph.recommended.contains(id) {
+            writeln!(out, r#"      <data key="recommended">true</data>"#)?;
+        }
+        if graph.have.contains(id) {
+            writeln!(out, r#"      <data key="have">true</data>"#)?;
+        }
+        if graph.seeds.contains(id) {
+            writeln!(out, r#"      <data key="seed">true</data>"#)?;
+        }
+        if graph.highlighted.contains(id) {
+            writeln!(out, r#"      <data key="highlighted">true</data>"#)?;
+        }
+        if let Some(annotation) = graph.annotations.get(id) {
+            if let Some(note) = annotation.note.as_deref() {
+                writeln!(out, r#"      <data key="note">{}</data>"#, escape_xml(note))?;
+            }
+            if !annotation.tags.is_empty() {
+                writeln!(
+                    out,
+                    r#"      <data key="tags">{}</data>"#,
+                    escape_xml(annotation.tags.join(",").as_str())
+                )?;
+            }
+            if let Some(color) = annotation.color.as_deref() {
+                writeln!(
+                    out,
+                    r#"      <data key="color">{}</data>"#,
+                    escape_xml(color)
+                )?;
+            }
+        }
+        if graph.added.contains(id) {
+            writeln!(out, r#"      <data key="added">true</data>"#)?;
+        }
+        if let Some(provenance) = graph.provenance.get(id) {
+            writeln!(
+                out,
+                r#"      <data key="provenance">{}</data>"#,
+                escape_xml(provenance_text(provenance).as_str())
+            )?;
+        }
+        if let Some(key) = graph.bibtex_keys.get(id) {
+            writeln!(
+                out,
+                r#"      <data key="bibtex_key">{}</data>"#,
+                escape_xml(key.as_str())
+            )?;
+        }
+        writeln!(out, "    </node>")?;
+    }
+    for reference in graph.sorted_references() {
+        let fuzzy = is_fuzzy(graph, reference);
+        let weight = graph.edge_weights.get(reference);
+        let added = graph.added_references.contains(reference);
+        write!(
+            out,
+            r#"    <edge source="{}" target="{}""#,
+            escape_xml(reference.referencer.as_ref()),
+            escape_xml(reference.referencee.as_ref())
+        )?;
+        if !fuzzy && weight.is_none() && !added {
+            writeln!(out, "/>")?;
+            continue;
+        }
+        writeln!(out, ">")?;
+        if fuzzy {
+            writeln!(out, r#"      <data key="fuzzy">true</data>"#)?;
+        }
+        if let Some(weight) = weight {
+            writeln!(out, r#"      <data key="weight">{weight}</data>"#)?;
+        }
+        if added {
+            writeln!(out, r#"      <data key="added_edge">true</data>"#)?;
+        }
+        writeln!(out, "    </edge>")?;
+    }
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")
+}
+
+/// Write the graph as a self-contained web page that lays it out and
+/// lets it be explored in the browser, no Graphviz needed.
+pub fn write_html(out: &mut impl Write, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    const TEMPLATE: &str = include_str!("graph.html");
+    const PLACEHOLDER: &str = "/*GRAPH_DATA*/";
+
+    let nodes: Vec<_> = graph
+        .sorted_papers()
+        .into_iter()
+        .filter_map(|paper| {
+            let id = paper.id()?;
+            let mut node = json!({
+                "id": id,
+                "label": paper.title(),
+                "url": paper.url(),
+                "year": paper.year(),
+                "abstract": paper.abstract_(),
+                "tldr": paper.tldr(),
+                "confidence": graph.match_confidence.get(id),
+                "recommended": graph.recommended.contains(id),
+                "have": graph.have.contains(id),
+                "seed": graph.seeds.contains(id),
+                "highlighted": graph.highlighted.contains(id),
+                "bibtex_key": graph.bibtex_keys.get(id),
+            });
+            if let Some(annotation) = graph.annotations.get(id) {
+                node["note"] = json!(annotation.note);
+                node["tags"] = json!(annotation.tags);
+                node["color"] = json!(annotation.color);
+            }
+            for (key, source) in EXTERNAL_IDS {
+                node[key] = json!(paper.external_id(source));
+            }
+            Some(node)
+        })
+        .collect();
+    let edges: Vec<_> = graph
+        .sorted_references()
+        .into_iter()
+        .map(|reference| {
+            json!({
+                "source": &*reference.referencer,
+                "target": &*reference.referencee,
+                "fuzzy": is_fuzzy(graph, reference),
+                "weight": graph.edge_weights.get(reference),
+            })
+        })
+        .collect();
+    let data = json!({
+        "attribution": if attribution { format!("{ATTRIBUTION}. {LICENSE}.") } else { String::new() },
+        "nodes": nodes,
+        "edges": edges,
+    });
+    // a title containing </script> mustn't end the script early
+    let data = data.to_string().replace("</", "<\\/");
+    let (head, tail) = TEMPLATE
+        .split_once(PLACEHOLDER)
+        .expect("graph.html has a placeholder");
+    write!(out, "{head}{data}{tail}")
+}
+
+/// Write the graph as an SVG picture, laid out by [`layout`] so no
+/// Graphviz is needed.
+///
+/// Papers link to their URLs and show their titles on hover.  Styling
+/// follows the HTML output: recommended papers are green, ones the user
+/// has are blue, and fuzzy edges are dashed.
+pub fn write_svg(out: &mut impl Write, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    const MARGIN: f64 = 40.0;
+    const LABEL_LENGTH: usize = 24;
+
+    let mut papers: Vec<_> = graph
+        .citations
+        .papers()
+        .filter_map(|paper| Some((paper.id()?, paper)))
+        .collect();
+    papers.sort_unstable_by_key(|(id, _paper)| *id);
+    let index: HashMap<&str, usize> = papers
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _paper))| (*id, i))
+        .collect();
+    let mut references: Vec<_> = graph
+        .citations
+        .references()
+        .filter_map(|reference| {
+            Some((
+                *index.get(reference.referencer.as_ref())?,
+                *index.get(reference.referencee.as_ref())?,
+                reference,
+            ))
+        })
+        .collect();
+    references.sort_unstable_by_key(|(source, target, _reference)| (*source, *target));
+    let edges: Vec<_> = references
+        .iter()
+        .map(|(source, target, _reference)| (*source, *target))
+        .collect();
+    let positions = layout::force_directed(papers.len(), &edges);
+    let mut in_degree = vec![0; papers.len()];
+    for (_source, target) in &edges {
+        in_degree[*target] += 1;
+    }
+    let radius = |i: usize| 4.0 + 2.0 * (in_degree[i] as f64).sqrt();
+
+    let (mut left, mut top, mut right, mut bottom) = (0.0_f64, 0.0_f64, 0.0_f64, 0.0_f64);
+    for &(x, y) in &positions {
+        (left, top, right, bottom) = (left.min(x), top.min(y), right.max(x), bottom.max(y));
+    }
+    let (left, top) = (left - MARGIN, top - MARGIN);
+    let (width, height) = (right + MARGIN - left, bottom + 2.0 * MARGIN - top);
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{left:.1} {top:.1} {width:.1} {height:.1}" font-family="sans-serif" font-size="8">"#
+    )?;
+    if attribution {
+        writeln!(
+            out,
+            "  <desc>{} {}</desc>",
+            escape_xml(ATTRIBUTION),
+            escape_xml(LICENSE)
+        )?;
+    }
+    writeln!(
+        out,
+        r##"  <defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="#666"/></marker></defs>"##
+    )?;
+    writeln!(out, r#"  <g stroke="rgba(0,0,0,0.25)">"#)?;
+    for (source, target, reference) in &references {
+        let (x1, y1) = positions[*source];
+        let (x2, y2) = positions[*target];
+        // stop at the edge of the target's circle so the arrow shows
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt().max(0.01);
+        let shorten = radius(*target) / length;
+        let (x2, y2) = (x2 - (x2 - x1) * shorten, y2 - (y2 - y1) * shorten);
+        write!(
+            out,
+            r#"    <line x1="{x1:.1}" y1="{y1:.1}" x2="{x2:.1}" y2="{y2:.1}" marker-end="url(#arrow)""#
+        )?;
+        if is_fuzzy(graph, reference) {
+            write!(out, r#" stroke-dasharray="4 4""#)?;
+        }
+        if let Some(&weight) = graph.edge_weights.get(reference) {
+            write!(
+                out,
+                r#" stroke-width="{:.2}""#,
+                1.0 + (weight.max(1) as f64).ln()
+            )?;
+        }
+        writeln!(out, "/>")?;
+    }
+    writeln!(out, "  </g>")?;
+    for (i, (id, paper)) in papers.iter().enumerate() {
+        let (x, y) = positions[i];
+        let color = graph
+            .annotations
+            .get(*id)
+            .and_then(|annotation| annotation.color.as_deref());
+        let fill = if let Some(color) = color {
+            color
+        } else if graph.highlighted.contains(*id) {
+            HIGHLIGHT
+        } else if graph.recommended.contains(*id) {
+            "#9c6"
+        } else if graph.have.contains(*id) {
+            "#69c"
+        } else {
+            "#ccc"
+        };
+        let title = paper.title();
+        let label = truncate(title, LABEL_LENGTH);
+        match paper.url() {
+            Some(url) => writeln!(out, r#"  <a href="{}">"#, escape_xml(url))?,
+            None => writeln!(out, "  <g>")?,
+        }
+        let summary: Vec<&str> = [Some(title), paper.tldr(), paper.abstract_()]
+            .into_iter()
+            .flatten()
+            .collect();
+        writeln!(
+            out,
+            "    <title>{}</title>",
+            escape_xml(summary.join("\n\n").as_str())
+        )?;
+        writeln!(
+            out,
+            r##"    <circle cx="{x:.1}" cy="{y:.1}" r="{:.1}" fill="{}" stroke="#333"/>"##,
+            radius(i),
+            escape_xml(fill)
+        )?;
+        writeln!(
+            out,
+            r#"    <text x="{x:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            y + radius(i) + 9.0,
+            escape_xml(label.as_str())
+        )?;
+        writeln!(
+            out,
+            "  {}",
+            if paper.url().is_some() {
+                "</a>"
+            } else {
+                "</g>"
+            }
+        )?;
+    }
+    if attribution {
+        writeln!(
+            out,
+            r##"  <text x="{:.1}" y="{:.1}" fill="#666">{}</text>"##,
+            left + 4.0,
+            top + height - 4.0,
+            escape_xml(ATTRIBUTION)
+        )?;
+    }
+    writeln!(out, "</svg>")
+}
+
+/// Give each paper a Mermaid node ID, which may only contain letters,
+/// digits, and underscores, and mustn't be a keyword like `end`.
+fn mermaid_ids<'a>(ids: impl IntoIterator<Item = &'a str>) -> HashMap<&'a str, String> {
+    let mut ids: Vec<&str> = ids.into_iter().collect();
+    ids.sort_unstable();
+    let mut taken = HashSet::new();
+    ids.into_iter()
+        .map(|id| {
+            let sanitized: String = id
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            let mut mermaid_id = format!("p_{sanitized}");
+            let mut n = 1;
+            while !taken.insert(mermaid_id.clone()) {
+                n += 1;
+                mermaid_id = format!("p_{sanitized}_{n}");
+            }
+            (id, mermaid_id)
+        })
+        .collect()
+}
+
+/// Write the graph as a Mermaid flowchart, for pasting into Markdown
+/// that renders Mermaid.
+///
+/// Papers link to their URLs, fuzzy edges are dotted, and weighted edges
+/// are thick and labelled with their weight.  Recommended papers and ones
+/// the user has are styled as in the DOT output.
+pub fn write_mermaid(
+    out: &mut impl Write,
+    graph: &Graph,
+    attribution: bool,
+) -> std::io::Result<()> {
+    let mut papers: Vec<_> = graph
+        .citations
+        .papers()
+        .filter_map(|paper| Some((paper.id()?, paper)))
+        .collect();
+    papers.sort_unstable_by_key(|(id, _paper)| *id);
+    let ids = mermaid_ids(papers.iter().map(|(id, _paper)| *id));
+
+    if attribution {
+        writeln!(out, "%% {ATTRIBUTION}")?;
+        writeln!(out, "%% {LICENSE}")?;
+    }
+    writeln!(out, "graph TD")?;
+    for (id, paper) in &papers {
+        let label = paper.title().replace('"', "#quot;");
+        writeln!(out, "    {}[\"{label}\"]", ids[id])?;
+    }
+    let mut references: Vec<_> = graph
+        .citations
+        .references()
+        .filter_map(|reference| {
+            Some((
+                ids.get(reference.referencer.as_ref())?,
+                ids.get(reference.referencee.as_ref())?,
+                reference,
+            ))
+        })
+        .collect();
+    references.sort_unstable_by_key(|(source, target, _reference)| (*source, *target));
+    for (source, target, reference) in references {
+        match (
+            is_fuzzy(graph, reference),
+            graph.edge_weights.get(reference),
+        ) {
+            (true, _) => writeln!(out, "    {source} -.-> {target}")?,
+            (false, Some(weight)) => writeln!(out, "    {source} ==>|{weight}| {target}")?,
+            (false, None) => writeln!(out, "    {source} --> {target}")?,
+        }
+    }
+    for (id, paper) in &papers {
+        if let Some(url) = paper.url() {
+            writeln!(
+                out,
+                "    click {} href \"{}\"",
+                ids[id],
+                url.replace('"', "%22")
+            )?;
+        }
+    }
+    for (name, style, members) in [
+        ("recommended", "stroke-dasharray:5 5", &graph.recommended),
+        ("have", "stroke-width:3px", &graph.have),
+        ("seed", "fill:#fc6", &graph.seeds),
+        ("highlighted", "fill:#f66", &graph.highlighted),
+    ] {
+        let members: Vec<&str> = papers
+            .iter()
+            .filter(|(id, _paper)| members.contains(*id))
+            .map(|(id, _paper)| ids[id].as_str())
+            .collect();
+        if !members.is_empty() {
+            writeln!(out, "    classDef {name} {style}")?;
+            writeln!(out, "    class {} {name}", members.join(","))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn escape_a_string() {
+        assert_eq!(escape("asdf \"foo\" \\aaa"), "asdf \\\"foo\\\" \\\\aaa");
+    }
+
+    fn empty_graph() -> Graph {
+        Graph::default()
+    }
+
+    #[test]
+    fn attribution_is_optional() {
+        let mut with = Vec::new();
+        write_dot(&mut with, &empty_graph(), true).unwrap();
+        let mut without = Vec::new();
+        write_dot(&mut without, &empty_graph(), false).unwrap();
+        assert!(String::from_utf8(with).unwrap().contains(ATTRIBUTION));
+        assert_eq!(String::from_utf8(without).unwrap(), "digraph {\n}\n");
+    }
+
+    #[test]
+    fn legends_describe_the_build() {
+        let mut graph = empty_graph();
+        graph
+            .citations
+            .insert_paper(crate::semantic_scholar::ProtoPaper::new("a", "Paper a"));
+        graph.legend = Some(crate::graph::Legend {
+            bibliography: "refs.bib".into(),
+            date: crate::graph::date(
+                std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000),
+            ),
+            max_depth: 3,
+            connectivity: 3.25,
+            expanded_per_depth: vec![1, 4],
+            failed_chunks: Vec::new(),
+        });
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, false).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with(
+            "// built from refs.bib on 2023-11-14, depth 3, connectivity 3.25: 1 papers, 0 citations\n"
+        ));
+        assert!(
+            dot.contains(r#"label="bibliography: refs.bib\lbuilt: 2023-11-14\l"#),
+            "{dot}"
+        );
+    }
+
+    #[test]
+    fn edges_carry_weights() {
+        let mut graph = empty_graph();
+        let reference = || Reference {
+            referencer: "a".into(),
+            referencee: "b".into(),
+        };
+        graph.citations.insert_reference(reference());
+        graph.edge_weights.insert(reference(), 3);
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, false).unwrap();
+        assert!(String::from_utf8(dot)
+            .unwrap()
+            .contains(r#""a" -> "b" [weight=3,penwidth=2.10];"#));
+        let mut graphml = Vec::new();
+        write_graphml(&mut graphml, &graph, false).unwrap();
+        assert!(String::from_utf8(graphml)
+            .unwrap()
+            .contains(r#"<data key="weight">3</data>"#));
+    }
+
+    #[test]
+    fn abstracts_become_tooltips() {
+        let mut graph = empty_graph();
+        graph.citations.insert_paper(
+            crate::semantic_scholar::ProtoPaper::new("a", "Paper a")
+                .with_abstract(Some("We \"show\" it.\nTwice.".into())),
+        );
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, false).unwrap();
+        assert!(String::from_utf8(dot)
+            .unwrap()
+            .contains(r#"tooltip="We \"show\" it.\nTwice.""#));
+        let mut graphml = Vec::new();
+        write_graphml(&mut graphml, &graph, false).unwrap();
+        assert!(String::from_utf8(graphml)
+            .unwrap()
+            .contains(r#"<data key="abstract">We &quot;show&quot; it."#));
+    }
+
+    #[test]
+    fn tldrs_are_cut_short_under_titles() {
+        let mut graph = empty_graph();
+        let tldr = "A long summary. ".repeat(10);
+        graph.citations.insert_paper(
+            crate::semantic_scholar::ProtoPaper::new("a", "Paper a").with_tldr(Some(tldr.clone())),
+        );
+        graph.add_tldrs(&HashMap::from([("b".to_string(), "Not a".to_string())]));
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, false).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        let label = format!(r#"label="Paper a\n{}…""#, &tldr[..TLDR_LENGTH - 1]);
+        assert!(dot.contains(&label), "{dot}");
+        let mut graphml = Vec::new();
+        write_graphml(&mut graphml, &graph, false).unwrap();
+        assert!(String::from_utf8(graphml)
+            .unwrap()
+            .contains(&format!(r#"<data key="tldr">{tldr}</data>"#)));
+    }
+
+    #[test]
+    fn external_ids_become_attributes() {
+        let mut graph = empty_graph();
+        graph.citations.insert_paper(
+            serde_json::from_value(json!({
+                "paperId": "a",
+                "title": "Paper a",
+                "url": null,
+                "externalIds": {"DOI": "10.1000/a", "PubMed": "123", "CorpusId": 42},
+            }))
+            .unwrap(),
+        );
+        let mut graphml = Vec::new();
+        write_graphml(&mut graphml, &graph, false).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert!(graphml.contains(r#"<data key="doi">10.1000/a</data>"#));
+        assert!(graphml.contains(r#"<data key="pmid">123</data>"#));
+        assert!(!graphml.contains(r#"<data key="arxiv">"#));
+        let mut html = Vec::new();
+        write_html(&mut html, &graph, false).unwrap();
+        assert!(String::from_utf8(html)
+            .unwrap()
+            .contains(r#""doi":"10.1000/a""#));
+    }
+
+    #[test]
+    fn the_page_can_be_searched_and_filtered_by_year() {
+        let mut graph = empty_graph();
+        graph.citations.insert_paper(
+            crate::semantic_scholar::ProtoPaper::new("a", "Paper a").with_year(Some(2017)),
+        );
+        let mut html = Vec::new();
+        write_html(&mut html, &graph, false).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains(r#""year":2017"#));
+        assert!(html.contains(r#"<input id="search""#));
+        assert!(html.contains(r#"<input id="min-year""#));
+    }
+
+    #[test]
+    fn seeds_name_their_bibtex_keys() {
+        let mut graph = empty_graph();
+        graph
+            .citations
+            .insert_paper(crate::semantic_scholar::ProtoPaper::new("a", "Paper a"));
+        graph.bibtex_keys.insert("a".into(), "wong2020".into());
+        let mut dot = Vec::new();
+        write_dot(&mut dot, &graph, false).unwrap();
+        assert!(String::from_utf8(dot)
+            .unwrap()
+            .contains("    // \\cite{wong2020}\n    \"a\" [label="));
+        let mut graphml = Vec::new();
+        write_graphml(&mut graphml, &graph, false).unwrap();
+        assert!(String::from_utf8(graphml)
+            .unwrap()
+            .contains(r#"<data key="bibtex_key">wong2020</data>"#));
+    }
+
+    #[test]
+    fn svg_escapes_titles_and_links_papers() {
+        let mut graph = empty_graph();
+        graph.citations.insert_paper(
+            crate::semantic_scholar::ProtoPaper::new("a", "Fish & <Chips>")
+                .with_url(Some("https://example.com/a".into())),
+        );
+        let mut svg = Vec::new();
+        write_svg(&mut svg, &graph, false).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("<title>Fish &amp; &lt;Chips&gt;</title>"));
+        assert!(svg.contains(r#"<a href="https://example.com/a">"#));
+    }
+
+    #[test]
+    fn crlf_and_bom_are_applied() {
+        let mut out = Vec::new();
+        let encoding = Encoding {
+            newline: Newline::CrLf,
+            bom: true,
+        };
+        write_dot(&mut Encoded::new(&mut out, encoding), &empty_graph(), false).unwrap();
+        assert_eq!(out, "\u{feff}digraph {\r\n}\r\n".as_bytes());
+    }
+
+    #[test]
+    fn mermaid_ids_are_sanitized_and_unique() {
+        let ids = mermaid_ids(["ISBN:0-201", "ISBN_0_201", "end"]);
+        assert_eq!(ids["ISBN:0-201"], "p_ISBN_0_201");
+        assert_eq!(ids["ISBN_0_201"], "p_ISBN_0_201_2");
+        assert_eq!(ids["end"], "p_end");
+    }
+}
@@ -0,0 +1,118 @@
+//! Finding the DOIs of downloaded papers in a folder of PDFs, for a
+//! bibliography that's never been written down.
+//!
+//! This is no PDF reader: it looks for a DOI the metadata names, then
+//! for the first DOI in the text of the first pages, which is usually
+//! the paper's own, printed in its header or footer.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+
+// a DOI the XMP metadata or document info names as the paper's
+const LABELLED_DOI_REGEX: &str = r"(?i)(?:prism:doi|pdfx:doi|/doi|dc:identifier)\W{0,8}(?:doi:)?(10\.\d{4,9}/[^\s<>()\[\]\x22]+)";
+const DOI_REGEX: &str = r"10\.\d{4,9}/[^\s<>()\[\]\x22\\]+";
+/// How many streams are read for text, which covers the first pages of
+/// most papers without reaching their references.
+const MAX_STREAMS: usize = 32;
+
+/// The data of each of the first `MAX_STREAMS` streams in `pdf`,
+/// inflated if it was compressed.
+fn streams(pdf: &[u8]) -> Vec<Vec<u8>> {
+    let mut streams = Vec::new();
+    let mut rest = pdf;
+    while streams.len() < MAX_STREAMS {
+        let Some(start) = find(rest, b"stream") else {
+            break;
+        };
+        let mut data = &rest[start + b"stream".len()..];
+        // the keyword is followed by CRLF or LF
+        data = data.strip_prefix(b"\r").unwrap_or(data);
+        data = data.strip_prefix(b"\n").unwrap_or(data);
+        let Some(end) = find(data, b"endstream") else {
+            break;
+        };
+        let raw = &data[..end];
+        let mut inflated = Vec::new();
+        streams.push(match ZlibDecoder::new(raw).read_to_end(&mut inflated) {
+            Ok(_) => inflated,
+            Err(_) => raw.to_vec(),
+        });
+        rest = &data[end + b"endstream".len()..];
+    }
+    streams
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The DOI of the paper in `pdf`, if it can be found.
+pub fn doi(pdf: &[u8]) -> Option<String> {
+    let labelled_regex = regex::bytes::Regex::new(LABELLED_DOI_REGEX).unwrap();
+    let doi_regex = regex::bytes::Regex::new(DOI_REGEX).unwrap();
+    let streams = streams(pdf);
+    let found = std::iter::once(pdf)
+        .chain(streams.iter().map(Vec::as_slice))
+        .find_map(|text| labelled_regex.captures(text).map(|caps| caps[1].to_vec()))
+        .or_else(|| {
+            streams
+                .iter()
+                .find_map(|text| doi_regex.find(text).map(|doi| doi.as_bytes().to_vec()))
+        })?;
+    let doi = String::from_utf8_lossy(&found);
+    Some(doi.trim_end_matches(['.', ',', ';']).to_string())
+}
+
+/// Each PDF in `dir` with the DOI found in it, if any, in the order of
+/// their paths.
+pub fn scan(dir: &Path) -> std::io::Result<Vec<(PathBuf, Option<String>)>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let pdf = std::fs::read(&path)?;
+            Ok((path, doi(&pdf)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn pdf(metadata: &str, text: &str) -> Vec<u8> {
+        let mut content = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        content.write_all(text.as_bytes()).unwrap();
+        let mut pdf = b"%PDF-1.7\n1 0 obj\n<< /Type /Metadata >>\nstream\n".to_vec();
+        pdf.extend(metadata.as_bytes());
+        pdf.extend(b"\nendstream\nendobj\n2 0 obj\n<< /Filter /FlateDecode >>\nstream\r\n");
+        pdf.extend(content.finish().unwrap());
+        pdf.extend(b"\r\nendstream\nendobj\n%%EOF\n");
+        pdf
+    }
+
+    #[test]
+    fn the_metadata_is_trusted_over_the_text() {
+        let text = "BT (Published as doi:10.1000/text.) Tj ET";
+        assert_eq!(doi(&pdf("", text)).as_deref(), Some("10.1000/text"));
+        let xmp = "<x:xmpmeta><prism:doi>10.1000/xmp</prism:doi></x:xmpmeta>";
+        assert_eq!(doi(&pdf(xmp, text)).as_deref(), Some("10.1000/xmp"));
+        assert_eq!(doi(&pdf("", "BT (No DOI here) Tj ET")), None);
+    }
+}
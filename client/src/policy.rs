@@ -0,0 +1,163 @@
+//! Which staged papers a search expands next.  A policy says whether a
+//! paper may be expanded at a depth and, when a budget only allows some,
+//! which to expand first.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use crate::semantic_scholar::Paper;
+
+/// A staged paper the search could expand.
+pub struct Candidate<'a> {
+    pub paper: &'a Paper,
+    /// How many papers in the graph so far cite it.
+    pub citations: usize,
+}
+
+pub trait ExpansionPolicy {
+    /// Whether `candidate` may be expanded at `depth`.  Citations within
+    /// the graph only grow, so this mustn't turn false as they do: the
+    /// search fetches the references of papers that qualify for the next
+    /// depth early.
+    fn qualifies(&self, depth: usize, candidate: &Candidate) -> bool;
+
+    /// Which of two qualifying papers to expand first, when a budget
+    /// doesn't allow both; by default the most cited within the graph.
+    fn prefer(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        most_cited_within(a, b)
+    }
+}
+
+fn most_cited_within(a: &Candidate, b: &Candidate) -> Ordering {
+    b.citations
+        .cmp(&a.citations)
+        .then_with(|| a.paper.id().cmp(b.paper.id()))
+}
+
+/// How many times a paper must be cited within the graph to be expanded
+/// at `depth`, growing geometrically by `connectivity`.
+pub fn minimum_citations(depth: usize, connectivity: f64) -> usize {
+    (depth as f64 * connectivity.ln()).exp().floor() as usize
+}
+
+/// Expand papers cited within the graph at least [`minimum_citations`]
+/// times.
+pub struct Connectivity(pub f64);
+
+impl ExpansionPolicy for Connectivity {
+    fn qualifies(&self, depth: usize, candidate: &Candidate) -> bool {
+        candidate.citations >= minimum_citations(depth, self.0)
+    }
+}
+
+/// Expand every paper, most cited within the graph first.
+pub struct TopLocal;
+
+impl ExpansionPolicy for TopLocal {
+    fn qualifies(&self, _depth: usize, _candidate: &Candidate) -> bool {
+        true
+    }
+}
+
+/// Expand every paper, most cited across Semantic Scholar first.
+pub struct TopGlobal;
+
+impl ExpansionPolicy for TopGlobal {
+    fn qualifies(&self, _depth: usize, _candidate: &Candidate) -> bool {
+        true
+    }
+
+    fn prefer(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        let global = |candidate: &Candidate| candidate.paper.citation_count().unwrap_or_default();
+        global(b)
+            .cmp(&global(a))
+            .then_with(|| most_cited_within(a, b))
+    }
+}
+
+/// Expand every paper found, depth by depth.
+pub struct BreadthFirst;
+
+impl ExpansionPolicy for BreadthFirst {
+    fn qualifies(&self, _depth: usize, _candidate: &Candidate) -> bool {
+        true
+    }
+
+    fn prefer(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        a.paper.id().cmp(b.paper.id())
+    }
+}
+
+/// The built-in policies, as named on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Connectivity,
+    TopLocal,
+    TopGlobal,
+    BreadthFirst,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "connectivity" => Ok(Policy::Connectivity),
+            "top-local" => Ok(Policy::TopLocal),
+            "top-global" => Ok(Policy::TopGlobal),
+            "breadth-first" => Ok(Policy::BreadthFirst),
+            other => Err(format!(
+                "unknown policy {other:?}; try connectivity, top-local, top-global, or breadth-first"
+            )),
+        }
+    }
+}
+
+impl Policy {
+    pub fn build(self, connectivity: f64) -> Box<dyn ExpansionPolicy> {
+        match self {
+            Policy::Connectivity => Box::new(Connectivity(connectivity)),
+            Policy::TopLocal => Box::new(TopLocal),
+            Policy::TopGlobal => Box::new(TopGlobal),
+            Policy::BreadthFirst => Box::new(BreadthFirst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_grow_geometrically() {
+        let thresholds: Vec<usize> = (0..4).map(|depth| minimum_citations(depth, 3.25)).collect();
+        assert_eq!(thresholds, vec![1, 3, 10, 34]);
+    }
+
+    #[test]
+    fn policies_prefer_different_papers() {
+        let paper = |id: &str, citation_count: usize| -> Paper {
+            serde_json::from_value(serde_json::json!({
+                "paperId": id,
+                "title": id,
+                "url": "",
+                "citationCount": citation_count,
+                "references": [],
+            }))
+            .unwrap()
+        };
+        let (famous, local) = (paper("famous", 10_000), paper("local", 5));
+        let famous = Candidate {
+            paper: &famous,
+            citations: 1,
+        };
+        let local = Candidate {
+            paper: &local,
+            citations: 4,
+        };
+        assert_eq!(TopLocal.prefer(&famous, &local), Ordering::Greater);
+        assert_eq!(TopGlobal.prefer(&famous, &local), Ordering::Less);
+        assert!(!Connectivity(3.25).qualifies(1, &famous));
+        assert!(Connectivity(3.25).qualifies(1, &local));
+    }
+}
@@ -0,0 +1,188 @@
+//! A human-readable summary of the graph to go alongside it.
+
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::graph::Graph;
+use crate::output::{self, Encoded, Encoding, Format, ATTRIBUTION, LICENSE};
+use crate::semantic_scholar::ProtoPaper;
+use crate::stats::Stats;
+
+/// A report printed after the graph is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    /// The papers in an order to read them, each after what it cites.
+    ReadingOrder,
+    /// The papers cited by at least this many seed papers.
+    CommonReferences(usize),
+    /// Figures on the graph's size and shape.
+    Stats,
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reading-order" => Ok(Report::ReadingOrder),
+            "stats" => Ok(Report::Stats),
+            other => Err(format!(
+                "unknown report {other:?}; try reading-order or stats"
+            )),
+        }
+    }
+}
+
+/// Write `report` on `graph` to `out`.
+pub fn write(out: &mut impl Write, report: Report, graph: &Graph) -> std::io::Result<()> {
+    match report {
+        Report::ReadingOrder => write_reading_order(out, graph),
+        Report::CommonReferences(min_seeds) => write_common_references(out, graph, min_seeds),
+        Report::Stats => Stats::of(graph).write(out),
+    }
+}
+
+/// Write `paper`'s title, year, and link on the rest of a line.
+fn write_paper(out: &mut impl Write, paper: &ProtoPaper) -> std::io::Result<()> {
+    write!(out, "{}", paper.title())?;
+    if let Some(year) = paper.year() {
+        write!(out, " ({year})")?;
+    }
+    match paper.url().filter(|url| !url.is_empty()) {
+        Some(url) => writeln!(out, " {url}"),
+        None => writeln!(out),
+    }
+}
+
+/// A numbered reading list, oldest and most cited foundations first.
+fn write_reading_order(out: &mut impl Write, graph: &Graph) -> std::io::Result<()> {
+    writeln!(out, "Suggested reading order:")?;
+    for (i, paper) in graph.citations.reading_order().into_iter().enumerate() {
+        write!(out, "{:>4}. ", i + 1)?;
+        write_paper(out, paper)?;
+    }
+    out.flush()
+}
+
+/// The references the seed papers share, most shared first.
+fn write_common_references(
+    out: &mut impl Write,
+    graph: &Graph,
+    min_seeds: usize,
+) -> std::io::Result<()> {
+    writeln!(out, "Cited by at least {min_seeds} seed papers:")?;
+    for (paper, count) in graph.common_references(min_seeds) {
+        write!(out, "{count:>4} seeds  ")?;
+        write_paper(out, paper)?;
+    }
+    out.flush()
+}
+
+/// Write a Markdown report with the graph embedded as DOT to `path`.
+///
+/// Past `max_nodes` papers, a rendered graph stops being legible (or
+/// renderable), so only the `max_nodes` most cited are embedded and the
+/// full graph is written next to the report as GraphML and linked.
+pub fn write_markdown(
+    path: &Path,
+    graph: &Graph,
+    max_nodes: usize,
+    attribution: bool,
+    encoding: Encoding,
+) -> std::io::Result<()> {
+    let mut out = Encoded::new(
+        std::io::BufWriter::new(std::fs::File::create(path)?),
+        encoding,
+    );
+    writeln!(out, "# Citation graph")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} papers, {} citations.",
+        graph.citations.paper_count(),
+        graph.citations.reference_count()
+    )?;
+    writeln!(out)?;
+
+    let truncated;
+    let embedded = if graph.citations.paper_count() > max_nodes {
+        let full_path = path.with_extension(Format::GraphMl.extension());
+        let mut full = Encoded::new(
+            std::io::BufWriter::new(std::fs::File::create(&full_path)?),
+            encoding,
+        );
+        output::write(&mut full, Format::GraphMl, graph, attribution)?;
+        full.flush()?;
+        let file_name = full_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "Showing the {max_nodes} most cited papers; the full graph is in [{file_name}]({file_name}).",
+        )?;
+        writeln!(out)?;
+        truncated = graph.subgraph(graph.ranked_ids().into_iter().take(max_nodes));
+        &truncated
+    } else {
+        graph
+    };
+    writeln!(out, "```dot")?;
+    output::write(&mut out, Format::Dot, embedded, false)?;
+    writeln!(out, "```")?;
+    if attribution {
+        writeln!(out)?;
+        writeln!(out, "_{ATTRIBUTION}. {LICENSE}._")?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Reference;
+
+    /// `a` and `b` cite `c`, `a` cites `b`, and `d` stands alone.
+    fn graph() -> Graph {
+        let mut graph = Graph::default();
+        for id in ["a", "b", "c", "d"] {
+            graph
+                .citations
+                .insert_paper(ProtoPaper::new(id, &format!("Paper {id}")));
+        }
+        for (from, to) in [("a", "c"), ("b", "c"), ("a", "b")] {
+            graph.citations.insert_reference(Reference {
+                referencer: from.into(),
+                referencee: to.into(),
+            });
+        }
+        graph
+    }
+
+    #[test]
+    fn large_graphs_are_cut_to_the_most_cited_with_the_rest_alongside() {
+        let dir = std::env::temp_dir().join(format!("report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.md");
+        let full_path = dir.join("report.graphml");
+
+        write_markdown(&path, &graph(), 4, false, Encoding::default()).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(report.contains("Paper d"));
+        assert!(!report.contains("most cited papers"));
+        assert!(!full_path.exists());
+
+        write_markdown(&path, &graph(), 2, false, Encoding::default()).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(report.contains("the full graph is in [report.graphml](report.graphml)"));
+        assert!(report.contains("Paper c"));
+        assert!(report.contains("Paper b"));
+        assert!(!report.contains("Paper a"));
+        assert!(!report.contains("Paper d"));
+        let full = std::fs::read_to_string(&full_path).unwrap();
+        assert!(full.contains("Paper a"));
+        assert!(full.contains("Paper d"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,505 @@
+//! Saving finished graphs as JSON or NDJSON and reading them back, with
+//! `.gz` and `.zst` files compressed transparently.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{Graph, Reference};
+use crate::semantic_scholar::{PaperId, ProtoPaper};
+
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file's extension isn't one a graph can be read from.
+    UnknownFormat(String),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => std::fmt::Debug::fmt(err, f),
+            Error::Json(err) => std::fmt::Debug::fmt(err, f),
+            Error::UnknownFormat(path) => write!(
+                f,
+                "can't tell how to read {path:?}; saved graphs end in .json or .ndjson, optionally followed by .gz or .zst"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::UnknownFormat(_path) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// How `path` is compressed, going by its extension, and the path with
+/// that extension taken off.
+fn compression(path: &Path) -> (Compression, &Path) {
+    let compression = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => Compression::Gzip,
+        Some("zst") => Compression::Zstd,
+        _ => return (Compression::None, path),
+    };
+    (compression, path.file_stem().map_or(path, Path::new))
+}
+
+/// The extension of `path` once any compression extension is taken off,
+/// e.g. `json` for `crawl.json.zst`.
+pub fn inner_extension(path: &Path) -> Option<&str> {
+    compression(path).1.extension()?.to_str()
+}
+
+/// A file being written, compressed as its name says.
+pub enum Compressed<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "native")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Write for Compressed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Compressed::Plain(out) => out.write(buf),
+            Compressed::Gzip(out) => out.write(buf),
+            #[cfg(feature = "native")]
+            Compressed::Zstd(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Compressed::Plain(out) => out.flush(),
+            Compressed::Gzip(out) => out.flush(),
+            #[cfg(feature = "native")]
+            Compressed::Zstd(out) => out.flush(),
+        }
+    }
+}
+
+impl<W: Write> Compressed<W> {
+    /// Write out whatever the compressor is holding on to.
+    pub fn finish(self) -> std::io::Result<W> {
+        match self {
+            Compressed::Plain(out) => Ok(out),
+            Compressed::Gzip(out) => out.finish(),
+            #[cfg(feature = "native")]
+            Compressed::Zstd(out) => out.finish(),
+        }
+    }
+}
+
+/// Create the file at `path`, compressing what's written to it if its
+/// extension is `.gz` or `.zst`.
+pub fn create(path: &Path) -> std::io::Result<Compressed<BufWriter<File>>> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(match compression(path).0 {
+        Compression::None => Compressed::Plain(file),
+        Compression::Gzip => Compressed::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+        #[cfg(feature = "native")]
+        Compression::Zstd => Compressed::Zstd(zstd::Encoder::new(file, 0)?),
+        #[cfg(not(feature = "native"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+/// Open the file at `path`, decompressing it if its extension is `.gz`
+/// or `.zst`.
+pub fn open(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match compression(path).0 {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        #[cfg(feature = "native")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?)),
+        #[cfg(not(feature = "native"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+/// zstd is C, so isn't there to build for the web.
+#[cfg(not(feature = "native"))]
+fn zstd_unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        ".zst files need the native feature",
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedReference {
+    from: String,
+    to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    weight: Option<usize>,
+}
+
+/// A whole graph as one JSON document.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SavedGraph {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attribution: Option<String>,
+    papers: Vec<ProtoPaper>,
+    references: Vec<SavedReference>,
+    #[serde(default)]
+    match_confidence: BTreeMap<String, f64>,
+    #[serde(default)]
+    recommended: Vec<String>,
+    #[serde(default)]
+    have: Vec<String>,
+    #[serde(default)]
+    seeds: Vec<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    added_references: Vec<SavedReference>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    provenance: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    bibtex_keys: BTreeMap<String, String>,
+}
+
+/// One line of an NDJSON graph.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Record {
+    Attribution(String),
+    Paper(ProtoPaper),
+    Reference(SavedReference),
+    MatchConfidence {
+        id: String,
+        confidence: f64,
+    },
+    Recommended(String),
+    Have(String),
+    Seed(String),
+    Source(String),
+    Added(String),
+    AddedReference(SavedReference),
+    Provenance {
+        id: String,
+        fields: BTreeMap<String, String>,
+    },
+    BibtexKey {
+        id: String,
+        key: String,
+    },
+}
+
+impl SavedGraph {
+    /// Everything in `graph`, sorted so the same graph always saves the
+    /// same way.
+    fn new(graph: &Graph, attribution: Option<String>) -> Self {
+        let papers: Vec<ProtoPaper> = graph.sorted_papers().into_iter().cloned().collect();
+        let mut references: Vec<SavedReference> = graph
+            .citations
+            .references()
+            .map(|reference| SavedReference {
+                from: reference.referencer.to_string(),
+                to: reference.referencee.to_string(),
+                weight: graph.edge_weights.get(reference).copied(),
+            })
+            .collect();
+        references.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+        let sorted = |ids: &HashSet<String>| {
+            let mut ids: Vec<String> = ids.iter().cloned().collect();
+            ids.sort_unstable();
+            ids
+        };
+        let mut added_references: Vec<SavedReference> = graph
+            .added_references
+            .iter()
+            .map(|reference| SavedReference {
+                from: reference.referencer.to_string(),
+                to: reference.referencee.to_string(),
+                weight: None,
+            })
+            .collect();
+        added_references.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+        SavedGraph {
+            attribution,
+            papers,
+            references,
+            match_confidence: graph
+                .match_confidence
+                .iter()
+                .map(|(id, confidence)| (id.clone(), *confidence))
+                .collect(),
+            recommended: sorted(&graph.recommended),
+            have: sorted(&graph.have),
+            seeds: sorted(&graph.seeds),
+            sources: sorted(&graph.sources),
+            added: sorted(&graph.added),
+            added_references,
+            provenance: graph
+                .provenance
+                .iter()
+                .map(|(id, fields)| (id.clone(), fields.clone()))
+                .collect(),
+            bibtex_keys: graph
+                .bibtex_keys
+                .iter()
+                .map(|(id, key)| (id.clone(), key.clone()))
+                .collect(),
+        }
+    }
+
+    fn records(self) -> impl Iterator<Item = Record> {
+        self.attribution
+            .into_iter()
+            .map(Record::Attribution)
+            .chain(self.papers.into_iter().map(Record::Paper))
+            .chain(self.references.into_iter().map(Record::Reference))
+            .chain(
+                self.match_confidence
+                    .into_iter()
+                    .map(|(id, confidence)| Record::MatchConfidence { id, confidence }),
+            )
+            .chain(self.recommended.into_iter().map(Record::Recommended))
+            .chain(self.have.into_iter().map(Record::Have))
+            .chain(self.seeds.into_iter().map(Record::Seed))
+            .chain(self.sources.into_iter().map(Record::Source))
+            .chain(self.added.into_iter().map(Record::Added))
+            .chain(
+                self.added_references
+                    .into_iter()
+                    .map(Record::AddedReference),
+            )
+            .chain(
+                self.provenance
+                    .into_iter()
+                    .map(|(id, fields)| Record::Provenance { id, fields }),
+            )
+            .chain(
+                self.bibtex_keys
+                    .into_iter()
+                    .map(|(id, key)| Record::BibtexKey { id, key }),
+            )
+    }
+}
+
+impl From<SavedGraph> for Graph {
+    fn from(saved: SavedGraph) -> Self {
+        let mut graph = Graph::default();
+        saved.records().for_each(|record| graph.add_record(record));
+        graph
+    }
+}
+
+impl Graph {
+    fn add_record(&mut self, record: Record) {
+        match record {
+            Record::Attribution(_attribution) => {}
+            Record::Paper(paper) => {
+                self.citations.insert_paper(paper);
+            }
+            Record::Reference(SavedReference { from, to, weight }) => {
+                let reference = Reference {
+                    referencer: from.into(),
+                    referencee: to.into(),
+                };
+                if let Some(weight) = weight {
+                    self.edge_weights.insert(reference.clone(), weight);
+                }
+                self.citations.insert_reference(reference);
+            }
+            Record::MatchConfidence { id, confidence } => {
+                self.match_confidence.insert(id, confidence);
+            }
+            Record::Recommended(id) => {
+                self.recommended.insert(id);
+            }
+            Record::Have(id) => {
+                self.have.insert(id);
+            }
+            Record::Seed(id) => {
+                self.seeds.insert(id);
+            }
+            Record::Source(id) => {
+                // graphs saved before DOIs were normalized
+                self.sources.insert(PaperId::canonical(&id));
+            }
+            Record::Added(id) => {
+                self.added.insert(id);
+            }
+            Record::AddedReference(SavedReference { from, to, .. }) => {
+                self.added_references.insert(Reference {
+                    referencer: from.into(),
+                    referencee: to.into(),
+                });
+            }
+            Record::Provenance { id, fields } => {
+                self.provenance.insert(id, fields);
+            }
+            Record::BibtexKey { id, key } => {
+                self.bibtex_keys.insert(id, key);
+            }
+        }
+    }
+}
+
+fn attribution_text(attribution: bool) -> Option<String> {
+    use crate::output::{ATTRIBUTION, LICENSE};
+    attribution.then(|| format!("{ATTRIBUTION}. {LICENSE}."))
+}
+
+/// Write the graph as a single JSON document.
+pub fn write_json(out: &mut impl Write, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    serde_json::to_writer(
+        &mut *out,
+        &SavedGraph::new(graph, attribution_text(attribution)),
+    )?;
+    writeln!(out)
+}
+
+/// Write the graph as newline-delimited JSON, one paper, reference, or
+/// annotation per line, so it can be streamed and grepped.
+pub fn write_ndjson(out: &mut impl Write, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    for record in SavedGraph::new(graph, attribution_text(attribution)).records() {
+        serde_json::to_writer(&mut *out, &record)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// A graph written as NDJSON while it's still being found, so a long
+/// crawl can be watched and a crash doesn't lose it.  What's written is
+/// unpruned, and can be rendered with everything else once it's done.
+pub struct Stream {
+    out: BufWriter<File>,
+    /// The first write that failed, after which nothing more is written.
+    failed: Option<std::io::Error>,
+}
+
+impl Stream {
+    pub fn create(path: &Path, attribution: bool) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if let Some(attribution) = attribution_text(attribution) {
+            serde_json::to_writer(&mut out, &Record::Attribution(attribution))?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(Self { out, failed: None })
+    }
+
+    /// Append what `found` adds to the graph.
+    pub fn write(&mut self, found: &Graph) {
+        if self.failed.is_some() {
+            return;
+        }
+        let result = write_ndjson(&mut self.out, found, false).and_then(|()| self.out.flush());
+        if let Err(err) = result {
+            eprintln!("couldn't stream the graph: {err:?}");
+            self.failed = Some(err);
+        }
+    }
+
+    /// Whether everything was written.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self.failed {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Read a graph from JSON.
+pub fn read_json(input: impl Read) -> Result<Graph, Error> {
+    serde_json::from_reader::<_, SavedGraph>(input)
+        .map(Graph::from)
+        .map_err(Error::Json)
+}
+
+/// Read a graph from NDJSON.
+pub fn read_ndjson(input: impl BufRead) -> Result<Graph, Error> {
+    let mut graph = Graph::default();
+    for line in input.lines() {
+        let line = line.map_err(Error::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        graph.add_record(serde_json::from_str(&line).map_err(Error::Json)?);
+    }
+    Ok(graph)
+}
+
+/// Whether `path` looks like a graph [`load`] can read.
+pub fn is_saved_graph(path: &Path) -> bool {
+    matches!(inner_extension(path), Some("json" | "ndjson" | "jsonl"))
+}
+
+/// Read the graph saved at `path`, going by its extension for the format
+/// and compression.
+pub fn load(path: &Path) -> Result<Graph, Error> {
+    let input = open(path).map_err(Error::Io)?;
+    match inner_extension(path) {
+        Some("json") => read_json(input),
+        Some("ndjson" | "jsonl") => read_ndjson(input),
+        _ => Err(Error::UnknownFormat(path.display().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_graphs_round_trip() {
+        let mut graph = Graph::default();
+        graph.citations.insert_paper(ProtoPaper::new("a", "A"));
+        graph.citations.insert_paper(ProtoPaper::new("b", "B"));
+        let reference = Reference {
+            referencer: "a".into(),
+            referencee: "b".into(),
+        };
+        graph.edge_weights.insert(reference.clone(), 2);
+        graph.citations.insert_reference(reference);
+        graph.have.insert("b".into());
+
+        let directory = std::env::temp_dir().join(format!("saved-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in ["graph.json.gz", "graph.ndjson.zst", "graph.jsonl"] {
+            let path = directory.join(name);
+            let mut out = create(&path).unwrap();
+            if inner_extension(&path) == Some("json") {
+                write_json(&mut out, &graph, true).unwrap();
+            } else {
+                write_ndjson(&mut out, &graph, true).unwrap();
+            }
+            out.finish().unwrap().flush().unwrap();
+
+            let loaded = load(&path).unwrap();
+            assert_eq!(loaded.citations, graph.citations, "{name}");
+            assert_eq!(loaded.edge_weights, graph.edge_weights, "{name}");
+            assert_eq!(loaded.have, graph.have, "{name}");
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,59 +1,95 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
-use serde::Deserialize;
-use tokio::task::JoinSet;
+pub use endpoints::{Paper, ProtoPaper};
 
-use endpoints::PAPER_BATCH;
-
-const MAX_PAPERS_PER_BATCH_CALL: usize = 500;
+#[cfg(feature = "native")]
+mod client;
+#[cfg(feature = "native")]
+pub use client::{SemanticScholar, DEFAULT_MAX_CONCURRENT_REQUESTS};
 
 // from https://www.crossref.org/blog/dois-and-matching-regular-expressions/
-const DOI_REGEX: &str = r#"(?<id>10.\d{4,9}/[-._;()/:A-Z0-9]+)$"#;
+const DOI_REGEX: &str = r#"(?i)(?<id>10.\d{4,9}/[-._;()/:A-Z0-9]+)$"#;
+/// Ways of writing a DOI that still name it exactly.
+const DOI_PREFIX_REGEX: &str = r#"^(?i)(doi:\s*|(https?://)?(dx\.)?doi\.org/)"#;
 const SEMANTIC_SCHOLAR_REGEX: &str =
     r#"^(https?://)?(www\.)?semanticscholar.org/paper/(?<id>[0-9a-f]+)$"#;
+const DBLP_REGEX: &str = r#"^(DBLP:|(https?://)?(www\.)?dblp\.(org|uni-trier\.de)/rec/(bibtex/)?)(?<id>[a-z]+(/[^/\s]+?)+?)(\.html|\.bib|\.xml)?$"#;
+const ISBN_REGEX: &str = r#"^ISBN:(?<id>[0-9Xx -]+)$"#;
+// new-style ids like 1706.03762 and old-style ones like hep-th/9901001,
+// either bare after `arXiv:` or in an abstract or PDF link
+const ARXIV_REGEX: &str = r#"^(?i)(arxiv:\s*|(https?://)?(www\.)?arxiv\.org/(abs|pdf)/)(?<id>\d{4}\.\d{4,5}|[a-z-]+(\.[a-z]{2})?/\d{7})(v\d+)?(\.pdf)?$"#;
 const ID_CAPTURE: &str = "id";
 
-pub struct SemanticScholar {
-    base_uri: String,
-    client: reqwest::Client,
-}
-
 #[derive(Debug, Clone)]
 pub enum PaperId {
     Doi(String),
     SemanticScholar(String),
+    /// Books, which have to be looked up elsewhere.
+    Isbn(String),
+    /// dblp keys, like `conf/nips/VaswaniSPUJGKP17`, which have to be
+    /// turned into one of the others through dblp first.
+    Dblp(String),
+    ArXiv(String),
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
-pub struct ProtoPaper {
-    #[serde(rename = "paperId")]
-    id: Option<String>,
-    title: String,
-    url: Option<String>,
-}
-
-#[derive(Deserialize, Clone, PartialEq, Eq, Hash)]
-pub struct Paper {
-    title: String,
-    url: String,
-    #[serde(rename = "paperId")]
-    id: String,
-    references: Vec<ProtoPaper>,
+/// How a seed paper's id was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    /// The id was given as-is, e.g. a bare DOI.
+    Exact,
+    /// The id was picked out of a longer string, like a publisher's URL.
+    UrlHeuristic,
+    /// The paper was found by its title, as the entry had no id.
+    TitleMatch,
 }
 
 pub enum Error {
+    #[cfg(feature = "native")]
     Request(reqwest::Error),
+    #[cfg(feature = "native")]
     Join(tokio::task::JoinError),
     Serialization(serde_json::Error, String),
+    /// Why Semantic Scholar or the proxy refused a request.
+    Refused(String),
+    /// Semantic Scholar or the proxy asked for fewer requests.
+    RateLimited(String),
+    /// What couldn't be done without the network.
+    Offline(&'static str),
+    /// The search ran past its time limit.
+    OutOfTime(Duration),
+}
+
+impl Error {
+    /// Whether the same request might succeed if sent again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // a dropped connection or a truncated body
+            #[cfg(feature = "native")]
+            Error::Request(_) => true,
+            Error::Serialization(..) | Error::RateLimited(_) => true,
+            #[cfg(feature = "native")]
+            Error::Join(_) => false,
+            Error::Refused(_) | Error::Offline(_) | Error::OutOfTime(_) => false,
+        }
+    }
 }
 
 impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "native")]
             Error::Request(err) => std::fmt::Debug::fmt(err, f),
+            #[cfg(feature = "native")]
             Error::Join(err) => std::fmt::Debug::fmt(err, f),
             Error::Serialization(err, text) => write!(f, "{text}\n{err:?}"),
+            Error::Refused(reason) => write!(f, "refused: {reason}"),
+            Error::RateLimited(reason) => write!(f, "rate limited: {reason}"),
+            Error::Offline(what) => write!(f, "{what} needs the network, but --offline was given"),
+            Error::OutOfTime(limit) => {
+                write!(f, "the search ran past its limit of {}s", limit.as_secs())
+            }
         }
     }
 }
@@ -67,9 +103,14 @@ impl std::fmt::Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
+            #[cfg(feature = "native")]
             Error::Request(err) => Some(err),
+            #[cfg(feature = "native")]
             Error::Join(err) => Some(err),
             Error::Serialization(err, _text) => Some(err),
+            Error::Refused(_reason) | Error::RateLimited(_reason) => None,
+            Error::Offline(_what) => None,
+            Error::OutOfTime(_limit) => None,
         }
     }
 }
@@ -80,137 +121,138 @@ impl Display for PaperId {
         match self {
             PaperId::Doi(id) => write!(f, "DOI:{id}"),
             PaperId::SemanticScholar(id) => write!(f, "{id}"),
+            PaperId::Isbn(id) => write!(f, "ISBN:{id}"),
+            PaperId::Dblp(id) => write!(f, "DBLP:{id}"),
+            PaperId::ArXiv(id) => write!(f, "ARXIV:{id}"),
         }
     }
 }
 
-impl TryFrom<&str> for PaperId {
-    type Error = ();
-
-    fn try_from(s: &str) -> Result<Self, Self::Error> {
+impl PaperId {
+    /// Find an id in `s` and note how directly it was found.
+    pub fn resolve(s: &str) -> Option<(Self, Resolution)> {
         let doi_regex = regex::Regex::new(DOI_REGEX).unwrap();
         let semantic_scholar_regex = regex::Regex::new(SEMANTIC_SCHOLAR_REGEX).unwrap();
-        if let Some(caps) = doi_regex.captures(s) {
-            return Ok(Self::Doi(caps[ID_CAPTURE].to_string()));
+        let isbn_regex = regex::Regex::new(ISBN_REGEX).unwrap();
+        let dblp_regex = regex::Regex::new(DBLP_REGEX).unwrap();
+        let arxiv_regex = regex::Regex::new(ARXIV_REGEX).unwrap();
+        if let Some(caps) = isbn_regex.captures(s) {
+            let isbn = caps[ID_CAPTURE].replace(['-', ' '], "").to_uppercase();
+            return Some((Self::Isbn(isbn), Resolution::Exact));
+        }
+        if let Some(caps) = dblp_regex.captures(s) {
+            // dblp's URLs contain the key verbatim
+            return Some((Self::Dblp(caps[ID_CAPTURE].to_string()), Resolution::Exact));
+        }
+        if let Some(caps) = arxiv_regex.captures(s.trim()) {
+            // arXiv's links contain the id verbatim, versions aside
+            return Some((Self::ArXiv(caps[ID_CAPTURE].to_string()), Resolution::Exact));
+        }
+        let doi_prefix_regex = regex::Regex::new(DOI_PREFIX_REGEX).unwrap();
+        let unprefixed = doi_prefix_regex.replace(s.trim(), "");
+        if let Some(caps) = doi_regex.captures(&unprefixed) {
+            let resolution = if caps[0].len() == unprefixed.len() {
+                Resolution::Exact
+            } else {
+                Resolution::UrlHeuristic
+            };
+            return Some((Self::Doi(normalize_doi(&caps[ID_CAPTURE])), resolution));
         }
         if let Some(caps) = semantic_scholar_regex.captures(s) {
-            return Ok(Self::SemanticScholar(caps[ID_CAPTURE].to_string()));
+            // Semantic Scholar's URLs contain the id verbatim.
+            return Some((
+                Self::SemanticScholar(caps[ID_CAPTURE].to_string()),
+                Resolution::Exact,
+            ));
         }
-        Err(())
+        None
     }
-}
 
-impl Paper {
-    pub fn references(&self) -> &[ProtoPaper] {
-        &self.references
+    /// Rewrite an id as written by [`Display`] in its canonical form, e.g.
+    /// `DOI:10.1000/X` as `DOI:10.1000/x`, leaving anything unrecognized
+    /// alone.
+    pub fn canonical(id: &str) -> String {
+        PaperId::try_from(id).map_or_else(|()| id.to_string(), |id| id.to_string())
     }
+}
 
-    pub fn id(&self) -> &str {
-        self.id.as_str()
-    }
+/// DOIs are case-insensitive, so they're kept in lowercase to compare
+/// equal however they were written.
+pub fn normalize_doi(doi: &str) -> String {
+    doi.to_lowercase()
+}
 
-    pub fn title(&self) -> &str {
-        self.title.as_str()
-    }
+impl TryFrom<&str> for PaperId {
+    type Error = ();
 
-    pub fn url(&self) -> &str {
-        self.url.as_str()
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::resolve(s).map(|(id, _resolution)| id).ok_or(())
     }
 }
 
-impl ProtoPaper {
-    pub fn id(&self) -> Option<&str> {
-        self.id.as_deref()
-    }
-
-    pub fn title(&self) -> &str {
-        self.title.as_str()
+impl Resolution {
+    /// How sure we are that the id refers to the intended paper, from 0
+    /// to 1.
+    pub fn confidence(&self) -> f64 {
+        match self {
+            Resolution::Exact => 1.0,
+            Resolution::UrlHeuristic => 0.8,
+            Resolution::TitleMatch => 0.6,
+        }
     }
+}
 
-    pub fn url(&self) -> Option<&str> {
-        self.url.as_deref()
-    }
+/// Resolve each of `ids`, dropping any that can't be and any naming the
+/// same paper as one before it.
+pub fn parse_ids(ids: Vec<String>) -> Vec<(PaperId, Resolution)> {
+    let mut seen = HashSet::new();
+    ids.into_iter()
+        .filter_map(|id: String| PaperId::resolve(id.as_str()))
+        .filter(|(id, _resolution)| seen.insert(id.to_string()))
+        .collect()
 }
 
-impl From<Paper> for ProtoPaper {
-    fn from(paper: Paper) -> Self {
-        Self {
-            id: Some(paper.id),
-            title: paper.title,
-            url: Some(paper.url),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dois_in_urls_are_heuristic() {
+        let (_id, resolution) = PaperId::resolve("10.1145/3290605.3300233").unwrap();
+        assert_eq!(resolution, Resolution::Exact);
+        let (id, resolution) =
+            PaperId::resolve("https://dl.acm.org/doi/10.1145/3290605.3300233").unwrap();
+        assert_eq!(id.to_string(), "DOI:10.1145/3290605.3300233");
+        assert_eq!(resolution, Resolution::UrlHeuristic);
     }
-}
 
-impl SemanticScholar {
-    pub fn new(base_uri: String) -> Self {
-        Self {
-            base_uri,
-            client: reqwest::Client::new(),
+    #[test]
+    fn arxiv_ids_are_found_in_links() {
+        for written in [
+            "arXiv:1706.03762",
+            "https://arxiv.org/abs/1706.03762v7",
+            "arxiv.org/pdf/1706.03762.pdf",
+        ] {
+            let (id, _resolution) = PaperId::resolve(written).unwrap();
+            assert_eq!(id.to_string(), "ARXIV:1706.03762", "{written}");
         }
+        let (id, _resolution) = PaperId::resolve("https://arxiv.org/abs/hep-th/9901001").unwrap();
+        assert_eq!(id.to_string(), "ARXIV:hep-th/9901001");
     }
 
-    pub async fn get_paper_batch(&self, paper_ids: Vec<PaperId>) -> Result<Vec<Paper>, Error> {
-        if paper_ids.is_empty() {
-            eprintln!("no papers requested");
-            return Ok(vec![]);
-        }
-        let params = [(
-            "fields",
-            "title,url,references.paperId,references.title,references.url",
-        )];
-        let mut requests = JoinSet::new();
-        for i in 0..paper_ids.len().div_ceil(MAX_PAPERS_PER_BATCH_CALL) {
-            let low_index = i * MAX_PAPERS_PER_BATCH_CALL;
-            let high_index = std::cmp::min((1 + i) * MAX_PAPERS_PER_BATCH_CALL, paper_ids.len());
-            eprintln!(
-                "POST /graph/v1/paper/batch: {} papers",
-                high_index - low_index
-            );
-            let mut ids = HashMap::<&str, Vec<String>>::new();
-            ids.insert(
-                "ids",
-                paper_ids[low_index..high_index]
-                    .iter()
-                    .map(|id| id.to_string())
-                    .collect(),
-            );
-
-            requests.spawn(
-                self.client
-                    .post(format!("http://{}{}", self.base_uri, PAPER_BATCH))
-                    .json(&ids)
-                    .query(&params)
-                    .send(),
-            );
-        }
-        let mut responses = JoinSet::new();
-        while let Some(response) = requests.join_next().await {
-            responses.spawn(
-                response
-                    .map_err(Error::Join)?
-                    .map_err(Error::Request)?
-                    .text(),
-            );
+    #[test]
+    fn dois_are_the_same_however_written() {
+        for written in [
+            "https://doi.org/10.1000/X",
+            "doi:10.1000/x",
+            "DOI: 10.1000/X",
+            "10.1000/X",
+        ] {
+            let (id, resolution) = PaperId::resolve(written).unwrap();
+            assert_eq!(id.to_string(), "DOI:10.1000/x", "{written}");
+            assert_eq!(resolution, Resolution::Exact, "{written}");
         }
-        let mut papers = Vec::<Paper>::new();
-        while let Some(paper_txt) = responses.join_next().await {
-            let paper_txt = paper_txt.map_err(Error::Join)?.map_err(Error::Request)?;
-            papers.extend(
-                serde_json::from_str::<Vec<Option<Paper>>>(paper_txt.as_ref())
-                    .map_err(|err| Error::Serialization(err, paper_txt))?
-                    .into_iter()
-                    .flatten()
-                    .collect::<Vec<Paper>>(),
-            );
-        }
-        Ok(papers)
+        assert_eq!(PaperId::canonical("DOI:10.1000/X"), "DOI:10.1000/x");
+        assert_eq!(PaperId::canonical("649def34f8be52c8"), "649def34f8be52c8");
     }
 }
-
-pub fn parse_ids(ids: Vec<String>) -> Vec<PaperId> {
-    ids.into_iter()
-        .map(|id: String| id.as_str().try_into())
-        .filter_map(|id: Result<PaperId, ()>| id.ok())
-        .collect()
-}
@@ -0,0 +1,735 @@
+//! Fetching from Semantic Scholar, or the proxy in front of it, over HTTP.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use endpoints::{
+    batch_chunks, BatchRequest, CitationPage, ErrorEnvelope, PaperTldr, Recommendations,
+    RecommendationsRequest, ReferencePage, SearchResults, PAPER, PAPER_BATCH, PAPER_SEARCH,
+    PAPER_SEARCH_MATCH, RECOMMENDATIONS,
+};
+
+use super::{Error, Paper, PaperId, ProtoPaper};
+use crate::cache::Cache;
+
+// matches the rate limiter's 1 req/s, slowed a little for safety
+const BATCH_REQUEST_PERIOD: Duration = Duration::from_millis(1100);
+/// How many batches can wait between each stage of the fetch pipeline.
+const PIPELINE_DEPTH: usize = 4;
+/// How many requests may be in flight at once unless set otherwise.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+const MAX_REFERENCES_PER_PAGE: usize = 1000;
+const MAX_RECOMMENDATIONS: usize = 500;
+const MAX_SEARCH_RESULTS: usize = 100;
+
+pub struct SemanticScholar {
+    base_uri: String,
+    client: reqwest::Client,
+    /// Sent with every request.
+    headers: reqwest::header::HeaderMap,
+    /// How long a request may take before it's given up on.
+    timeout: Option<Duration>,
+    /// Spaces out batch requests across the whole crawl so they arrive at
+    /// the proxy's rate instead of piling up behind it.
+    pacer: Arc<Mutex<Interval>>,
+    /// Bounds how many requests are in flight at once, so a deep crawl
+    /// doesn't open a connection for every batch it's queued.
+    in_flight: Arc<Semaphore>,
+    cache: Option<Cache>,
+    /// Answer only from the cache, never asking Semantic Scholar.
+    offline: bool,
+    /// Don't note each request on stderr.
+    quiet: bool,
+}
+
+impl SemanticScholar {
+    pub fn new(base_uri: String) -> Self {
+        let mut pacer = tokio::time::interval(BATCH_REQUEST_PERIOD);
+        pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            base_uri,
+            client: reqwest::Client::new(),
+            headers: reqwest::header::HeaderMap::new(),
+            timeout: None,
+            pacer: Arc::new(Mutex::new(pacer)),
+            in_flight: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_REQUESTS)),
+            cache: None,
+            offline: false,
+            quiet: false,
+        }
+    }
+
+    /// Fetch nothing, answering batches from the cache however old it is
+    /// and failing anything else with [`Error::Offline`].  Papers that
+    /// aren't cached are treated as ones Semantic Scholar doesn't have.
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    /// Don't note each request on stderr, for when progress is shown
+    /// another way.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Fail with [`Error::Offline`] for `what` if the network's not to be
+    /// used.
+    pub fn online(&self, what: &'static str) -> Result<(), Error> {
+        if self.offline {
+            return Err(Error::Offline(what));
+        }
+        Ok(())
+    }
+
+    /// Send at most `max` requests at once.
+    pub fn with_max_concurrent_requests(mut self, max: usize) -> Self {
+        self.in_flight = Arc::new(Semaphore::new(max.max(1)));
+        self
+    }
+
+    /// Keep papers fetched in `cache`, and fetch only those it lacks.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Send `api_key` with every request, for talking to Semantic Scholar
+    /// directly rather than through the rate limiter.
+    pub fn with_api_key(mut self, api_key: reqwest::header::HeaderValue) -> Result<Self, Error> {
+        self.headers.insert("x-api-key", api_key);
+        self.rebuild_client()
+    }
+
+    /// Give up on any request that takes longer than `timeout`, as
+    /// [`Error::Request`], so a hung connection can't stall a search.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Result<Self, Error> {
+        self.timeout = Some(timeout);
+        self.rebuild_client()
+    }
+
+    fn rebuild_client(mut self) -> Result<Self, Error> {
+        let mut builder = reqwest::Client::builder().default_headers(self.headers.clone());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        self.client = builder.build().map_err(Error::Request)?;
+        Ok(self)
+    }
+
+    /// Get the papers in the same order as `paper_ids`, with `None` for
+    /// any Semantic Scholar couldn't find.
+    pub async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, Error> {
+        let Some(cache) = &self.cache else {
+            return self.fetch_paper_batch(paper_ids).await;
+        };
+        let mut papers: Vec<Option<Paper>> = paper_ids
+            .iter()
+            .map(|id| match self.offline {
+                // stale is better than nothing
+                true => cache.get_within(&id.to_string(), Duration::MAX),
+                false => cache.get(&id.to_string()),
+            })
+            .collect();
+        if self.offline {
+            let uncached = papers.iter().filter(|paper| paper.is_none()).count();
+            if uncached > 0 {
+                eprintln!("{uncached} papers aren't cached and are left out");
+            }
+            return Ok(papers);
+        }
+        let missing: Vec<(usize, PaperId)> = paper_ids
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _id)| papers[*i].is_none())
+            .collect();
+        let fetched = self
+            .fetch_paper_batch(missing.iter().map(|(_i, id)| id.clone()).collect())
+            .await?;
+        for ((i, id), paper) in missing.into_iter().zip(fetched) {
+            if let Some(paper) = &paper {
+                if let Err(err) = cache.put(&[&id.to_string(), paper.id()], paper) {
+                    eprintln!("couldn't cache {id}: {err:?}");
+                }
+            }
+            papers[i] = paper;
+        }
+        Ok(papers)
+    }
+
+    async fn fetch_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, Error> {
+        if paper_ids.is_empty() {
+            if !self.quiet {
+                eprintln!("no papers requested");
+            }
+            return Ok(vec![]);
+        }
+        self.online("fetching papers")?;
+        const FIELDS: &str = "title,url,fieldsOfStudy,citationCount,abstract,year,venue,externalIds,authors,references.paperId,references.title,references.url,references.fieldsOfStudy,references.externalIds,references.authors,references.abstract";
+        let chunk_count = batch_chunks(&paper_ids).len();
+
+        // The fetch is a pipeline: the scheduler splits the ids into
+        // batches, the request pool sends them at a steady pace, each
+        // response is parsed on the blocking pool as it arrives, since a
+        // full batch with references is a lot of JSON to hold up the
+        // runtime with, and this task collects them rather than waiting
+        // on the slowest.
+        let (batch_tx, mut batch_rx) = mpsc::channel::<(usize, Vec<String>)>(PIPELINE_DEPTH);
+        let (response_tx, mut response_rx) =
+            mpsc::channel::<(usize, Result<Vec<Option<Paper>>, Error>)>(PIPELINE_DEPTH);
+        let batches: Vec<Vec<String>> = batch_chunks(&paper_ids)
+            .map(|chunk| chunk.iter().map(|id| id.to_string()).collect())
+            .collect();
+        tokio::spawn(async move {
+            for batch in batches.into_iter().enumerate() {
+                if batch_tx.send(batch).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let client = self.client.clone();
+        let uri = format!("http://{}{}", self.base_uri, PAPER_BATCH);
+        let pacer = self.pacer.clone();
+        let in_flight = self.in_flight.clone();
+        let quiet = self.quiet;
+        // this fetch being dropped, as when a search runs out of time,
+        // hangs up the responses, and nothing more is sent
+        let hung_up = response_tx.clone();
+        let pool = async move {
+            while let Some((i, ids)) = batch_rx.recv().await {
+                // a slot first, so the pace isn't spent on a request that
+                // then has to wait for one
+                let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                    break;
+                };
+                pacer.lock().await.tick().await;
+                if !quiet {
+                    eprintln!("POST {PAPER_BATCH}: {} papers", ids.len());
+                }
+                let body = BatchRequest { ids };
+                let request = client
+                    .post(&uri)
+                    .json(&body)
+                    .query(&[("fields", FIELDS)])
+                    .send();
+                let response_tx = response_tx.clone();
+                tokio::spawn(async move {
+                    let papers = async {
+                        match request.await {
+                            Ok(response) => parse_streamed::<Vec<Option<Paper>>>(response).await,
+                            Err(err) => Err(Error::Request(err)),
+                        }
+                    };
+                    let papers = tokio::select! {
+                        papers = papers => papers,
+                        () = response_tx.closed() => return,
+                    };
+                    drop(permit);
+                    let _ = response_tx.send((i, papers)).await;
+                });
+            }
+        };
+        tokio::spawn(async move {
+            tokio::select! {
+                () = pool => {}
+                () = hung_up.closed() => {}
+            }
+        });
+
+        let mut chunks = Vec::<(usize, Vec<Option<Paper>>)>::with_capacity(chunk_count);
+        while chunks.len() < chunk_count {
+            let Some((i, papers)) = response_rx.recv().await else {
+                break;
+            };
+            chunks.push((i, papers?));
+        }
+        // the chunks finish in whatever order the network pleases
+        chunks.sort_by_key(|(i, _papers)| *i);
+        Ok(chunks.into_iter().flat_map(|(_i, papers)| papers).collect())
+    }
+
+    /// Search for papers matching `query`, best matches first, optionally
+    /// only those published in `year`, e.g. `2019` or `2016-2020`.
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        year: Option<&str>,
+    ) -> Result<Vec<ProtoPaper>, Error> {
+        self.online("searching")?;
+        if !self.quiet {
+            eprintln!("GET {PAPER_SEARCH}: {query:?}");
+        }
+        let mut params = vec![
+            ("query", query.to_string()),
+            (
+                "fields",
+                "paperId,title,url,year,venue,externalIds".to_string(),
+            ),
+            ("limit", limit.min(MAX_SEARCH_RESULTS).to_string()),
+        ];
+        if let Some(year) = year {
+            params.push(("year", year.to_string()));
+        }
+        let results_txt = self
+            .client
+            .get(format!("http://{}{}", self.base_uri, PAPER_SEARCH))
+            .query(&params)
+            .send()
+            .await
+            .map_err(Error::Request)?
+            .body()
+            .await?;
+        Ok(parse::<SearchResults>(results_txt)?.data)
+    }
+
+    /// Find the paper best matching `title`, if Semantic Scholar has one
+    /// published in `year` and, given an `author`'s surname, written by
+    /// them.
+    pub async fn match_title(
+        &self,
+        title: &str,
+        year: Option<&str>,
+        author: Option<&str>,
+    ) -> Result<Option<ProtoPaper>, Error> {
+        self.online("matching titles")?;
+        if !self.quiet {
+            eprintln!("GET {PAPER_SEARCH_MATCH}: {title:?}");
+        }
+        let mut params = vec![
+            ("query", title.to_string()),
+            ("fields", "paperId,title,url,year,authors".to_string()),
+        ];
+        if let Some(year) = year {
+            params.push(("year", year.to_string()));
+        }
+        let response = self
+            .client
+            .get(format!("http://{}{}", self.base_uri, PAPER_SEARCH_MATCH))
+            .query(&params)
+            .send()
+            .await
+            .map_err(Error::Request)?;
+        // no paper is close enough
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let results_txt = response.body().await?;
+        Ok(parse::<SearchResults>(results_txt)?
+            .data
+            .into_iter()
+            .next()
+            .filter(|paper| author.is_none_or(|author| has_author(paper, author))))
+    }
+
+    /// Get up to `count` papers Semantic Scholar thinks are related to
+    /// those in `paper_ids`.
+    pub async fn get_recommendations(
+        &self,
+        paper_ids: Vec<String>,
+        count: usize,
+    ) -> Result<Vec<ProtoPaper>, Error> {
+        if paper_ids.is_empty() || count == 0 {
+            return Ok(vec![]);
+        }
+        self.online("recommending papers")?;
+        if !self.quiet {
+            eprintln!("POST {RECOMMENDATIONS}: {} papers", paper_ids.len());
+        }
+        let body = RecommendationsRequest {
+            positive_paper_ids: paper_ids,
+            negative_paper_ids: vec![],
+        };
+        let recommendations_txt = self
+            .client
+            .post(format!("http://{}{}", self.base_uri, RECOMMENDATIONS))
+            .json(&body)
+            .query(&[
+                ("fields", "paperId,title,url".to_string()),
+                ("limit", count.min(MAX_RECOMMENDATIONS).to_string()),
+            ])
+            .send()
+            .await
+            .map_err(Error::Request)?
+            .body()
+            .await?;
+        Ok(parse::<Recommendations>(recommendations_txt)?.recommended_papers)
+    }
+
+    /// Get Semantic Scholar's TL;DR of each paper in `paper_ids` that has
+    /// one, keyed by id.
+    pub async fn get_tldrs(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.online("fetching TL;DRs")?;
+        let mut tldrs = HashMap::new();
+        for ids in batch_chunks(&paper_ids) {
+            self.pacer.lock().await.tick().await;
+            if !self.quiet {
+                eprintln!("POST {PAPER_BATCH}: TL;DRs of {} papers", ids.len());
+            }
+            let body = BatchRequest { ids: ids.to_vec() };
+            let tldrs_txt = self
+                .client
+                .post(format!("http://{}{}", self.base_uri, PAPER_BATCH))
+                .json(&body)
+                .query(&[("fields", "tldr")])
+                .send()
+                .await
+                .map_err(Error::Request)?
+                .body()
+                .await?;
+            tldrs.extend(
+                parse::<Vec<Option<PaperTldr>>>(tldrs_txt)?
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|paper| Some((paper.id, paper.tldr?.text?))),
+            );
+        }
+        Ok(tldrs)
+    }
+
+    /// For each paper in `paper_ids`, count how many times it cites each
+    /// of its references in its text.
+    ///
+    /// The result is keyed by `(citing id, cited id)`.
+    pub async fn get_reference_contexts(
+        &self,
+        paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, Error> {
+        self.online("weighing edges by citation contexts")?;
+        let mut requests = JoinSet::new();
+        for paper_id in paper_ids {
+            let client = self.client.clone();
+            let uri = format!("http://{}{}/{}/references", self.base_uri, PAPER, paper_id);
+            let in_flight = self.in_flight.clone();
+            requests.spawn(async move {
+                let _permit = in_flight.acquire_owned().await;
+                let mut contexts = Vec::<(String, usize)>::new();
+                let mut offset = Some(0);
+                while let Some(page_offset) = offset {
+                    let page_txt = client
+                        .get(&uri)
+                        .query(&[
+                            ("fields", "paperId,contexts".to_string()),
+                            ("offset", page_offset.to_string()),
+                            ("limit", MAX_REFERENCES_PER_PAGE.to_string()),
+                        ])
+                        .send()
+                        .await
+                        .map_err(Error::Request)?
+                        .body()
+                        .await?;
+                    let page = parse::<ReferencePage>(page_txt)?;
+                    contexts.extend(page.data.into_iter().filter_map(|reference| {
+                        let count = reference.contexts.map_or(0, |contexts| contexts.len());
+                        reference.cited_paper.id.map(|id| (id, count))
+                    }));
+                    offset = page.next;
+                }
+                Ok::<_, Error>((paper_id, contexts))
+            });
+        }
+        let mut weights = HashMap::new();
+        while let Some(contexts) = requests.join_next().await {
+            let (paper_id, contexts) = contexts.map_err(Error::Join)??;
+            weights.extend(
+                contexts
+                    .into_iter()
+                    .map(|(cited_id, count)| ((paper_id.clone(), cited_id), count)),
+            );
+        }
+        Ok(weights)
+    }
+
+    /// For each paper in `paper_ids`, get up to `max_per_paper` of the
+    /// papers citing it.
+    pub async fn get_citations(
+        &self,
+        paper_ids: Vec<String>,
+        max_per_paper: usize,
+    ) -> Result<HashMap<String, Vec<ProtoPaper>>, Error> {
+        self.online("fetching citations")?;
+        let mut requests = JoinSet::new();
+        for paper_id in paper_ids {
+            let client = self.client.clone();
+            let uri = format!("http://{}{}/{}/citations", self.base_uri, PAPER, paper_id);
+            let in_flight = self.in_flight.clone();
+            requests.spawn(async move {
+                let _permit = in_flight.acquire_owned().await;
+                let mut citing = Vec::<ProtoPaper>::new();
+                let mut offset = Some(0);
+                while let Some(page_offset) = offset.filter(|_| citing.len() < max_per_paper) {
+                    let limit = MAX_REFERENCES_PER_PAGE.min(max_per_paper - citing.len());
+                    let page_txt = client
+                        .get(&uri)
+                        .query(&[
+                            (
+                                "fields",
+                                "paperId,title,url,year,venue,externalIds,authors".to_string(),
+                            ),
+                            ("offset", page_offset.to_string()),
+                            ("limit", limit.to_string()),
+                        ])
+                        .send()
+                        .await
+                        .map_err(Error::Request)?
+                        .body()
+                        .await?;
+                    let page = parse::<CitationPage>(page_txt)?;
+                    citing.extend(page.data.into_iter().map(|citation| citation.citing_paper));
+                    offset = page.next;
+                }
+                Ok::<_, Error>((paper_id, citing))
+            });
+        }
+        let mut citations = HashMap::new();
+        while let Some(citing) = requests.join_next().await {
+            let (paper_id, citing) = citing.map_err(Error::Join)??;
+            citations.insert(paper_id, citing);
+        }
+        Ok(citations)
+    }
+}
+
+trait Body {
+    /// The text of a response, unless it asks for fewer requests.
+    async fn body(self) -> Result<String, Error>;
+}
+
+impl Body for reqwest::Response {
+    async fn body(self) -> Result<String, Error> {
+        let status = self.status();
+        let text = self.text().await.map_err(Error::Request)?;
+        match status {
+            // the proxy answers 503 when too many requests are waiting
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                let reason = match serde_json::from_str::<ErrorEnvelope>(&text) {
+                    Ok(envelope) => envelope.error,
+                    Err(_) => text,
+                };
+                Err(Error::RateLimited(reason))
+            }
+            _ => Ok(text),
+        }
+    }
+}
+
+/// Whether one of `paper`'s authors goes by `surname`.  Papers without
+/// authors are given the benefit of the doubt.
+fn has_author(paper: &ProtoPaper, surname: &str) -> bool {
+    paper.authors().is_empty()
+        || paper.authors().iter().any(|author| {
+            author
+                .name()
+                .split_whitespace()
+                .any(|name| name.eq_ignore_ascii_case(surname))
+        })
+}
+
+/// Read a response as `T`, or as the error it explains instead.
+fn parse<T: DeserializeOwned>(text: String) -> Result<T, Error> {
+    serde_json::from_str(&text).map_err(|err| match serde_json::from_str::<ErrorEnvelope>(&text) {
+        Ok(envelope) => Error::Refused(envelope.error),
+        Err(_) => Error::Serialization(err, text),
+    })
+}
+
+/// How many chunks of a streamed response may wait to be parsed.
+const STREAMED_CHUNKS: usize = 16;
+/// How much of a streamed response is kept to show if it can't be parsed.
+const STREAMED_SHOWN: usize = 1024;
+
+/// A response's body as it arrives, read on a blocking thread.
+struct Chunks {
+    receiver: mpsc::Receiver<Result<Vec<u8>, reqwest::Error>>,
+    chunk: Vec<u8>,
+    read: usize,
+    /// The start of the body, to show if it can't be parsed.
+    start: Vec<u8>,
+}
+
+impl Chunks {
+    fn new(receiver: mpsc::Receiver<Result<Vec<u8>, reqwest::Error>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            read: 0,
+            start: Vec::new(),
+        }
+    }
+
+    fn shown(&self) -> String {
+        let mut shown = String::from_utf8_lossy(&self.start).into_owned();
+        if self.start.len() == STREAMED_SHOWN {
+            shown.push('…');
+        }
+        shown
+    }
+}
+
+impl std::io::Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.read == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(Ok(chunk)) => {
+                    let room = STREAMED_SHOWN - self.start.len();
+                    self.start.extend(&chunk[..room.min(chunk.len())]);
+                    self.chunk = chunk;
+                    self.read = 0;
+                }
+                Some(Err(err)) => return Err(std::io::Error::other(err)),
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.read);
+        buf[..len].copy_from_slice(&self.chunk[self.read..self.read + len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+/// Parse a successful response as it arrives, so a large batch's text is
+/// never held whole beside the papers parsed from it.  Anything else is
+/// read whole, as `body` and `parse` would.
+async fn parse_streamed<T: DeserializeOwned + Send + 'static>(
+    mut response: reqwest::Response,
+) -> Result<T, Error> {
+    if !response.status().is_success() {
+        let text = response.body().await?;
+        return tokio::task::spawn_blocking(move || parse(text))
+            .await
+            .map_err(Error::Join)?;
+    }
+    let (chunk_tx, receiver) = mpsc::channel(STREAMED_CHUNKS);
+    let parsing = tokio::task::spawn_blocking(move || {
+        let mut chunks = Chunks::new(receiver);
+        serde_json::from_reader(std::io::BufReader::new(&mut chunks))
+            .map_err(|err| Error::Serialization(err, chunks.shown()))
+    });
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => Ok(chunk.to_vec()),
+            Ok(None) => break,
+            Err(err) => Err(err),
+        };
+        let failed = chunk.is_err();
+        // the parser only hangs up once it's failed, which it reports
+        if chunk_tx.send(chunk).await.is_err() || failed {
+            break;
+        }
+    }
+    drop(chunk_tx);
+    parsing.await.map_err(Error::Join)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_matches_are_checked_against_the_authors() {
+        let paper = ProtoPaper::new("a", "Attention Is All You Need").with_authors(vec![
+            endpoints::Author::new("Ashish Vaswani"),
+            endpoints::Author::new("Noam Shazeer"),
+        ]);
+        assert!(has_author(&paper, "vaswani"));
+        assert!(has_author(&paper, "Shazeer"));
+        assert!(!has_author(&paper, "Hinton"));
+        assert!(has_author(&ProtoPaper::new("b", "Anonymous"), "Hinton"));
+    }
+
+    #[tokio::test]
+    async fn offline_papers_come_only_from_the_cache() {
+        let dir = std::env::temp_dir().join(format!("offline-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let paper = Paper::new("a", "A", Vec::new());
+        cache.put(&["a"], &paper).unwrap();
+        // nothing listens here, so any request would fail
+        let api = SemanticScholar::new("127.0.0.1:1".into())
+            .with_cache(cache.clone())
+            .offline();
+
+        let papers = api
+            .get_paper_batch(vec![
+                PaperId::SemanticScholar("a".into()),
+                PaperId::SemanticScholar("b".into()),
+            ])
+            .await
+            .unwrap();
+        assert!(papers == vec![Some(paper), None]);
+        assert!(matches!(
+            api.search("a", 1, None).await,
+            Err(Error::Offline(_what))
+        ));
+        cache.clear().unwrap();
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn some_request_is_always_let_through() {
+        let api = SemanticScholar::new("127.0.0.1:1".into()).with_max_concurrent_requests(0);
+        assert_eq!(api.in_flight.available_permits(), 1);
+        let api = api.with_max_concurrent_requests(3);
+        assert_eq!(api.in_flight.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_dropped_fetch_sends_no_more_requests() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_string("[]")
+                    .set_delay(Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+        let api = SemanticScholar::new(server.address().to_string());
+        let paper_ids = (0..3 * endpoints::MAX_BATCH_IDS)
+            .map(|i| PaperId::SemanticScholar(i.to_string()))
+            .collect();
+        let fetch = api.get_paper_batch(paper_ids);
+        assert!(tokio::time::timeout(Duration::from_millis(100), fetch)
+            .await
+            .is_err());
+        // past when the next chunk would have been paced out
+        tokio::time::sleep(BATCH_REQUEST_PERIOD * 2).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn chunks_are_read_across_their_boundaries() {
+        let (chunk_tx, receiver) = mpsc::channel(STREAMED_CHUNKS);
+        for chunk in [&b"[nu"[..], b"ll, {\"a\": ", b"", b"1}]"] {
+            chunk_tx.send(Ok(chunk.to_vec())).await.unwrap();
+        }
+        drop(chunk_tx);
+        let parsed = tokio::task::spawn_blocking(move || {
+            serde_json::from_reader::<_, Vec<Option<HashMap<String, u32>>>>(Chunks::new(receiver))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(parsed, vec![None, Some(HashMap::from([("a".into(), 1)]))]);
+    }
+}
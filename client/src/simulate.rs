@@ -0,0 +1,238 @@
+//! Synthetic citation networks, for seeing how the search parameters
+//! behave without spending any requests.
+
+use std::collections::HashMap;
+
+use crate::crawl::PaperSource;
+use crate::semantic_scholar::{self, Paper, PaperId, ProtoPaper, Resolution};
+
+/// A citation network held entirely in memory.
+#[derive(Default)]
+pub struct Network {
+    references: HashMap<String, Vec<String>>,
+    /// The fields of study of the papers classified, which the rest
+    /// aren't.
+    fields_of_study: HashMap<String, Vec<String>>,
+    /// How often each paper counted is cited across all of Semantic
+    /// Scholar, which the rest aren't.
+    citation_counts: HashMap<String, usize>,
+}
+
+/// A small, deterministic xorshift generator so simulations are
+/// repeatable without pulling in `rand`.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+impl Network {
+    /// Add a paper citing `references`.
+    pub fn add(&mut self, id: &str, references: &[&str]) {
+        self.references.insert(
+            id.to_string(),
+            references.iter().map(|id| id.to_string()).collect(),
+        );
+    }
+
+    /// File paper `id` under `fields`.
+    pub fn classify(&mut self, id: &str, fields: &[&str]) {
+        self.fields_of_study.insert(
+            id.to_string(),
+            fields.iter().map(|field| field.to_string()).collect(),
+        );
+    }
+
+    /// Say paper `id` is cited `count` times in all.
+    pub fn count_citations(&mut self, id: &str, count: usize) {
+        self.citation_counts.insert(id.to_string(), count);
+    }
+
+    /// A random network of papers `"0"` through `"<paper_count - 1>"`,
+    /// newest first, each citing up to `references_per_paper` older
+    /// papers.  Older papers are more likely to be cited, as they've had
+    /// longer to accumulate citations.
+    pub fn random(rng_seed: u64, paper_count: usize, references_per_paper: usize) -> Self {
+        let mut rng = XorShift(rng_seed.max(1));
+        let mut network = Network::default();
+        for paper in 0..paper_count {
+            let older = paper_count - paper - 1;
+            let mut references = Vec::<String>::new();
+            for _ in 0..references_per_paper.min(older) {
+                // the larger of two draws skews toward the oldest papers
+                let draw = (rng.next() % older as u64).max(rng.next() % older as u64);
+                let reference = (paper + 1 + draw as usize).to_string();
+                if !references.contains(&reference) {
+                    references.push(reference);
+                }
+            }
+            network.references.insert(paper.to_string(), references);
+        }
+        network
+    }
+
+    /// The first `count` papers, as though they made up a bibliography.
+    pub fn seeds(&self, count: usize) -> Vec<(PaperId, Resolution)> {
+        (0..count)
+            .map(|paper| paper.to_string())
+            .filter(|id| self.references.contains_key(id))
+            .map(|id| (PaperId::SemanticScholar(id), Resolution::Exact))
+            .collect()
+    }
+
+    fn paper(&self, id: &str) -> Option<Paper> {
+        let references = self.references.get(id)?;
+        let paper = Paper::new(
+            id,
+            &format!("Paper {id}"),
+            references
+                .iter()
+                .map(|reference| {
+                    ProtoPaper::new(reference, &format!("Paper {reference}"))
+                        .with_fields_of_study(self.fields_of_study.get(reference).cloned())
+                })
+                .collect(),
+        );
+        Some(
+            paper
+                .with_fields_of_study(self.fields_of_study.get(id).cloned())
+                .with_citation_count(self.citation_counts.get(id).copied()),
+        )
+    }
+}
+
+impl PaperSource for Network {
+    async fn get_paper_batch(
+        &self,
+        paper_ids: Vec<PaperId>,
+    ) -> Result<Vec<Option<Paper>>, semantic_scholar::Error> {
+        Ok(paper_ids
+            .iter()
+            .map(|id| self.paper(&id.to_string()))
+            .collect())
+    }
+
+    async fn get_reference_contexts(
+        &self,
+        _paper_ids: Vec<String>,
+    ) -> Result<HashMap<(String, String), usize>, semantic_scholar::Error> {
+        Ok(HashMap::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawl::{crawl, Options};
+
+    fn options() -> Options {
+        Options::new(4, Box::new(crate::policy::Connectivity(3.25)))
+    }
+
+    #[tokio::test]
+    async fn unconnected_references_are_not_expanded() {
+        let mut network = Network::default();
+        network.add("0", &["a", "b", "c", "d"]);
+        for id in ["a", "b", "c", "d"] {
+            network.add(id, &[]);
+        }
+        let crawl = crawl(&network, network.seeds(1), &options()).await.unwrap();
+        assert_eq!(crawl.expanded_per_depth, vec![1, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn cliques_are_expanded_until_the_threshold_outgrows_them() {
+        // Each member of a clique of 5 is cited by the other 4 and the
+        // seed, clearing depth 1's threshold of 3.  Expanding them
+        // requests each member once per clique-mate, and every copy's
+        // references are counted, giving 1 + 4 * 4 = 17: enough for depth
+        // 2's threshold of 10 but not depth 3's 34.
+        let clique = ["a", "b", "c", "d", "e"];
+        let mut network = Network::default();
+        network.add("0", &clique);
+        for id in clique {
+            let others: Vec<&str> = clique.into_iter().filter(|&other| other != id).collect();
+            network.add(id, &others);
+        }
+        let crawl = crawl(&network, network.seeds(1), &options()).await.unwrap();
+        assert_eq!(crawl.expanded_per_depth, vec![1, 5, 5, 0]);
+    }
+
+    /// A seed citing papers `"a"` through `"e"`, most cited first, each
+    /// citing one of its own.
+    fn ranked() -> Network {
+        let cited = ["a", "b", "c", "d", "e"];
+        let mut network = Network::default();
+        network.add("0", &cited);
+        for (id, count) in cited.into_iter().zip([50, 40, 30, 20, 10]) {
+            network.add(id, &[&format!("{id}'s reference")]);
+            network.count_citations(id, count);
+        }
+        network
+    }
+
+    #[tokio::test]
+    async fn a_depths_budget_keeps_the_most_cited() {
+        let network = ranked();
+        let options = Options {
+            max_papers_per_depth: Some(2),
+            ..Options::new(2, Box::new(crate::policy::TopGlobal))
+        };
+        let crawl = crawl(&network, network.seeds(1), &options).await.unwrap();
+        assert_eq!(crawl.expanded_per_depth, vec![1, 2]);
+        let citations = &crawl.graph.citations;
+        assert!(citations.contains_paper("a's reference"));
+        assert!(citations.contains_paper("b's reference"));
+        assert!(!citations.contains_paper("c's reference"));
+    }
+
+    #[tokio::test]
+    async fn the_total_budget_spans_every_depth() {
+        let network = ranked();
+        let options = Options {
+            max_papers_per_depth: Some(2),
+            max_total_papers: Some(4),
+            ..Options::new(4, Box::new(crate::policy::TopGlobal))
+        };
+        let crawl = crawl(&network, network.seeds(1), &options).await.unwrap();
+        // those turned down at one depth are considered again at the next
+        assert_eq!(crawl.expanded_per_depth, vec![1, 2, 1, 0]);
+        let citations = &crawl.graph.citations;
+        assert!(citations.contains_paper("c's reference"));
+        assert!(!citations.contains_paper("d's reference"));
+    }
+
+    #[tokio::test]
+    async fn random_networks_are_repeatable() {
+        let first = Network::random(7, 500, 20);
+        let second = Network::random(7, 500, 20);
+        assert_eq!(first.references, second.references);
+        let crawl = crawl(&first, first.seeds(10), &options()).await.unwrap();
+        assert_eq!(crawl.expanded_per_depth, vec![10, 106, 97, 37]);
+    }
+
+    #[tokio::test]
+    async fn prefetching_finds_the_same_graph() {
+        let network = Network::random(7, 500, 20);
+        let fetched_in_turn = crawl(&network, network.seeds(10), &options())
+            .await
+            .unwrap();
+        let prefetching = Options {
+            prefetch: true,
+            ..options()
+        };
+        let prefetched = crawl(&network, network.seeds(10), &prefetching)
+            .await
+            .unwrap();
+        assert_eq!(
+            prefetched.expanded_per_depth,
+            fetched_in_turn.expanded_per_depth
+        );
+        assert_eq!(prefetched.graph.citations, fetched_in_turn.graph.citations);
+    }
+}
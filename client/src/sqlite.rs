@@ -0,0 +1,144 @@
+//! Writing the graph as an SQLite database, for querying and joining
+//! against other data.
+//!
+//! Papers go in `papers` and references in `citations`, since
+//! `REFERENCES` is reserved in SQL.  Edges point from `citing` to `cited`.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::graph::Graph;
+use crate::output::{ATTRIBUTION, LICENSE};
+
+const SCHEMA: &str = "
+    CREATE TABLE metadata (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE papers (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        url TEXT,
+        year INTEGER,
+        venue TEXT,
+        confidence REAL,
+        recommended INTEGER NOT NULL,
+        have INTEGER NOT NULL
+    );
+    CREATE TABLE citations (
+        citing TEXT NOT NULL,
+        cited TEXT NOT NULL,
+        weight INTEGER,
+        PRIMARY KEY (citing, cited)
+    );
+    CREATE INDEX citations_by_cited ON citations (cited);
+    CREATE INDEX papers_by_year ON papers (year);
+";
+
+fn to_io_error(err: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(err)
+}
+
+/// Write the graph to a new database at `path`, replacing any file
+/// already there.
+pub fn write(path: &Path, graph: &Graph, attribution: bool) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut connection = Connection::open(path).map_err(to_io_error)?;
+    let transaction = connection.transaction().map_err(to_io_error)?;
+    transaction.execute_batch(SCHEMA).map_err(to_io_error)?;
+    if attribution {
+        let mut insert = transaction
+            .prepare("INSERT INTO metadata (key, value) VALUES (?1, ?2)")
+            .map_err(to_io_error)?;
+        insert
+            .execute(params!["attribution", ATTRIBUTION])
+            .map_err(to_io_error)?;
+        insert
+            .execute(params!["license", LICENSE])
+            .map_err(to_io_error)?;
+    }
+    {
+        let mut insert = transaction
+            .prepare(
+                "INSERT OR IGNORE INTO papers
+                    (id, title, url, year, venue, confidence, recommended, have)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(to_io_error)?;
+        for paper in graph.sorted_papers() {
+            let Some(id) = paper.id() else {
+                continue;
+            };
+            insert
+                .execute(params![
+                    id,
+                    paper.title(),
+                    paper.url().filter(|url| !url.is_empty()),
+                    paper.year(),
+                    paper.venue(),
+                    graph.match_confidence.get(id),
+                    graph.recommended.contains(id),
+                    graph.have.contains(id),
+                ])
+                .map_err(to_io_error)?;
+        }
+        let mut insert = transaction
+            .prepare("INSERT OR IGNORE INTO citations (citing, cited, weight) VALUES (?1, ?2, ?3)")
+            .map_err(to_io_error)?;
+        for reference in graph.sorted_references() {
+            insert
+                .execute(params![
+                    reference.referencer,
+                    reference.referencee,
+                    graph
+                        .edge_weights
+                        .get(reference)
+                        .map(|&weight| weight as i64),
+                ])
+                .map_err(to_io_error)?;
+        }
+    }
+    transaction.commit().map_err(to_io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Reference;
+    use crate::semantic_scholar::ProtoPaper;
+
+    #[test]
+    fn papers_and_citations_are_queryable() {
+        let mut graph = Graph::default();
+        graph.citations.insert_paper(ProtoPaper::new("a", "A"));
+        graph.citations.insert_paper(ProtoPaper::new("b", "B"));
+        graph.citations.insert_reference(Reference {
+            referencer: "a".into(),
+            referencee: "b".into(),
+        });
+        graph.have.insert("b".into());
+        let path = std::env::temp_dir().join(format!("graph-{}.sqlite", std::process::id()));
+        write(&path, &graph, true).unwrap();
+        // writing again replaces rather than appends
+        write(&path, &graph, true).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let cited_and_had: Vec<String> = connection
+            .prepare(
+                "SELECT papers.title FROM citations
+                    JOIN papers ON papers.id = citations.cited
+                    WHERE papers.have",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(cited_and_had, vec!["B".to_string()]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
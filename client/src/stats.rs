@@ -0,0 +1,188 @@
+//! Figures describing a graph's shape, to judge whether the search that
+//! built it went too far or not far enough.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::crawl::FailedChunk;
+use crate::graph::Graph;
+
+/// How many of the most cited papers are listed.
+const MOST_CITED: usize = 10;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Stats {
+    pub papers: usize,
+    pub citations: usize,
+    /// How many papers have each number of citations, made or received.
+    pub degree_distribution: BTreeMap<usize, usize>,
+    /// The sizes of the parts of the graph not connected to each other,
+    /// largest first, following citations either way.
+    pub components: Vec<usize>,
+    /// The longest of the shortest paths in the largest component, found
+    /// by searching twice, so it may fall short of the true diameter.
+    pub diameter_estimate: usize,
+    pub most_cited: Vec<Cited>,
+    /// How many papers the search expanded at each depth, if the graph
+    /// was just built.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expanded_per_depth: Vec<usize>,
+    /// The chunks of papers the search left out because they couldn't
+    /// be fetched.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_chunks: Vec<FailedChunk>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Cited {
+    pub id: String,
+    pub title: String,
+    pub cited_by: usize,
+}
+
+impl Stats {
+    pub fn of(graph: &Graph) -> Self {
+        let citations = &graph.citations;
+        let mut degree_distribution = BTreeMap::new();
+        for id in citations.ids() {
+            *degree_distribution
+                .entry(citations.neighbors(id).count())
+                .or_default() += 1;
+        }
+
+        let mut seen = HashSet::<String>::new();
+        let mut components = Vec::<(usize, &str)>::new();
+        for id in citations.ids() {
+            if seen.contains(id) {
+                continue;
+            }
+            let component = citations.distances([id]);
+            let size = component
+                .keys()
+                .filter(|&reached| citations.contains_paper(reached))
+                .count();
+            seen.extend(component.into_keys());
+            components.push((size, id));
+        }
+        components.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+        // the farthest paper from any paper is at one end of a long path,
+        // so the farthest from it is a good guess at the diameter
+        let farthest = |from: &str| {
+            citations
+                .distances([from])
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        };
+        let diameter_estimate = components
+            .first()
+            .and_then(|&(_size, id)| farthest(id))
+            .and_then(|(end, _distance)| farthest(&end))
+            .map_or(0, |(_end, distance)| distance);
+
+        let most_cited = graph
+            .ranked_ids()
+            .into_iter()
+            .take(MOST_CITED)
+            .map(|id| Cited {
+                id: id.to_string(),
+                title: citations
+                    .paper(id)
+                    .map(|paper| paper.title().to_string())
+                    .unwrap_or_default(),
+                cited_by: citations.cited_by_count(id),
+            })
+            .collect();
+
+        Stats {
+            papers: citations.paper_count(),
+            citations: citations.reference_count(),
+            degree_distribution,
+            components: components.into_iter().map(|(size, _id)| size).collect(),
+            diameter_estimate,
+            most_cited,
+            expanded_per_depth: graph
+                .legend
+                .as_ref()
+                .map(|legend| legend.expanded_per_depth.clone())
+                .unwrap_or_default(),
+            failed_chunks: graph
+                .legend
+                .as_ref()
+                .map(|legend| legend.failed_chunks.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Write the figures as text, a line or a short table each.
+    pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{} papers, {} citations", self.papers, self.citations)?;
+        writeln!(out, "Papers by citations made or received:")?;
+        for (degree, count) in &self.degree_distribution {
+            writeln!(out, "{degree:>6}: {count}")?;
+        }
+        let largest = self.components.first().copied().unwrap_or_default();
+        writeln!(
+            out,
+            "{} connected components, the largest of {largest} papers",
+            self.components.len()
+        )?;
+        writeln!(out, "Diameter: at least {}", self.diameter_estimate)?;
+        writeln!(out, "Most cited within the graph:")?;
+        for cited in &self.most_cited {
+            writeln!(out, "{:>6}  {}", cited.cited_by, cited.title)?;
+        }
+        if !self.expanded_per_depth.is_empty() {
+            writeln!(out, "Papers expanded at each depth:")?;
+            for (depth, expanded) in self.expanded_per_depth.iter().enumerate() {
+                writeln!(out, "{depth:>6}: {expanded}")?;
+            }
+        }
+        if !self.failed_chunks.is_empty() {
+            writeln!(out, "Chunks left out after failing:")?;
+            for chunk in &self.failed_chunks {
+                writeln!(
+                    out,
+                    "{:>6}: {} papers, {}",
+                    chunk.depth, chunk.papers, chunk.error
+                )?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Reference;
+    use crate::semantic_scholar::ProtoPaper;
+
+    #[test]
+    fn stats_describe_the_shape() {
+        let mut graph = Graph::default();
+        for id in ["a", "b", "c", "d", "e", "f"] {
+            graph.citations.insert_paper(ProtoPaper::new(id, id));
+        }
+        // a chain a -> b -> c -> d, c also cited by e, and f alone
+        for (from, to) in [("a", "b"), ("b", "c"), ("c", "d"), ("e", "c")] {
+            graph.citations.insert_reference(Reference {
+                referencer: from.into(),
+                referencee: to.into(),
+            });
+        }
+        let stats = Stats::of(&graph);
+        assert_eq!(stats.papers, 6);
+        assert_eq!(stats.citations, 4);
+        assert_eq!(
+            stats.degree_distribution,
+            BTreeMap::from([(0, 1), (1, 3), (2, 1), (3, 1)])
+        );
+        assert_eq!(stats.components, vec![5, 1]);
+        assert_eq!(stats.diameter_estimate, 3);
+        assert_eq!(stats.most_cited[0].id, "c");
+        assert_eq!(stats.most_cited[0].cited_by, 2);
+    }
+}
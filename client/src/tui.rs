@@ -0,0 +1,195 @@
+//! A terminal interface for hand-picking which papers get expanded, and
+//! a plainer prompt for picking which paper a bibliography entry means.
+
+use std::io::{BufRead, Stderr, Write};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    Terminal,
+};
+
+use crate::semantic_scholar::{Paper, ProtoPaper};
+
+/// What the user decided about a frontier.
+pub enum Review {
+    /// Expand the papers marked `true`.
+    Approved(Vec<bool>),
+    /// Expand the papers marked `true`, and stop asking.
+    Finished(Vec<bool>),
+}
+
+const HELP: &str =
+    "↑/↓ move · space toggle · a all · n none · enter expand · q expand and stop asking";
+
+/// Show the papers about to be expanded at `depth` and let the user
+/// approve or reject each.  Everything starts approved.
+///
+/// The interface is drawn on stderr so the graph can still be piped out
+/// of stdout.
+pub fn review(depth: usize, frontier: &[&Paper]) -> std::io::Result<Review> {
+    terminal::enable_raw_mode()?;
+    execute!(std::io::stderr(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
+    let review = run(&mut terminal, depth, frontier);
+    terminal::disable_raw_mode()?;
+    execute!(std::io::stderr(), LeaveAlternateScreen)?;
+    review
+}
+
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    depth: usize,
+    frontier: &[&Paper],
+) -> std::io::Result<Review> {
+    let mut approved = vec![true; frontier.len()];
+    let mut state = ListState::default().with_selected(Some(0));
+    loop {
+        terminal.draw(|frame| {
+            let [list_area, abstract_area, help_area] = Layout::vertical([
+                Constraint::Min(3),
+                Constraint::Length(10),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+            let items: Vec<ListItem> = frontier
+                .iter()
+                .zip(&approved)
+                .map(|(paper, &approved)| {
+                    let mark = if approved { "[x]" } else { "[ ]" };
+                    ListItem::new(format!("{mark} {}", paper.title()))
+                })
+                .collect();
+            let title = format!(
+                "depth {depth}: expand {} of {} papers",
+                approved.iter().filter(|&&approved| approved).count(),
+                frontier.len()
+            );
+            let list = List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, list_area, &mut state);
+            let summary = state
+                .selected()
+                .and_then(|i| frontier.get(i))
+                .and_then(|paper| paper.abstract_())
+                .unwrap_or("no abstract");
+            frame.render_widget(
+                Paragraph::new(summary)
+                    .wrap(Wrap { trim: true })
+                    .block(Block::bordered().title("abstract")),
+                abstract_area,
+            );
+            frame.render_widget(Line::from(HELP), help_area);
+        })?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Char(' ') => {
+                if let Some(approved) = state.selected().and_then(|i| approved.get_mut(i)) {
+                    *approved = !*approved;
+                }
+            }
+            KeyCode::Char('a') => approved.fill(true),
+            KeyCode::Char('n') => approved.fill(false),
+            KeyCode::Enter => return Ok(Review::Approved(approved)),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Review::Finished(approved)),
+            _ => {}
+        }
+    }
+}
+
+/// Ask which of `candidates` the entry `key`, titled `title`, means,
+/// giving the index picked, or `None` if the user skips it or there's
+/// nothing to pick from.
+pub fn pick(
+    out: &mut impl Write,
+    input: &mut impl BufRead,
+    key: &str,
+    title: &str,
+    candidates: &[ProtoPaper],
+) -> std::io::Result<Option<usize>> {
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    writeln!(out, "{key}: which of these is {title:?}?")?;
+    for (i, paper) in candidates.iter().enumerate() {
+        let about: Vec<String> = paper
+            .year()
+            .map(|year| year.to_string())
+            .into_iter()
+            .chain(
+                paper
+                    .venue()
+                    .filter(|venue| !venue.is_empty())
+                    .map(String::from),
+            )
+            .collect();
+        writeln!(
+            out,
+            "{:>4}. {} ({})",
+            i + 1,
+            paper.title(),
+            about.join(", ")
+        )?;
+    }
+    loop {
+        write!(out, "number, or enter to leave it out: ")?;
+        out.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=candidates.len()).contains(&n) => return Ok(Some(n - 1)),
+            _ => writeln!(out, "{answer:?} isn't one of 1 to {}", candidates.len())?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picking_asks_again_until_answered() {
+        let candidates = vec![
+            ProtoPaper::new("a", "Attention").with_year(Some(2017)),
+            ProtoPaper::new("b", "Attention, Again"),
+        ];
+        let mut out = Vec::new();
+        let picked = pick(
+            &mut out,
+            &mut "7\nb\n2\n".as_bytes(),
+            "vaswani2017",
+            "Attention",
+            &candidates,
+        )
+        .unwrap();
+        assert_eq!(picked, Some(1));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("   1. Attention (2017)"), "{out}");
+        assert!(out.contains("\"7\" isn't one of 1 to 2"), "{out}");
+        let skipped = pick(&mut Vec::new(), &mut "\n".as_bytes(), "k", "t", &candidates);
+        assert_eq!(skipped.unwrap(), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Checks on option values, so a bad value is reported before any
+//! request is sent.  Most are argh `from_str_fn`s, run as the command
+//! line is parsed, and so also check values from the config file and
+//! environment.
+
+use std::path::Path;
+
+use crate::id_import;
+
+/// The deepest a search may go; each depth multiplies the papers fetched,
+/// so past this a search won't finish in any reasonable time.
+pub const MAX_DEPTH: usize = 10;
+
+/// A `--max-depth` of at most [`MAX_DEPTH`].
+pub fn max_depth(value: &str) -> Result<usize, String> {
+    let depth: usize = value
+        .parse()
+        .map_err(|_err| format!("{value:?} isn't a whole number of depths"))?;
+    if depth > MAX_DEPTH {
+        return Err(format!(
+            "{depth} is deeper than the {MAX_DEPTH} a search can finish; \
+             try a smaller depth, with a lower --connectivity to expand more at each"
+        ));
+    }
+    Ok(depth)
+}
+
+/// A `--connectivity` above zero, which the citations needed to expand a
+/// paper are raised by at each depth.
+pub fn connectivity(value: &str) -> Result<f64, String> {
+    let connectivity: f64 = value
+        .parse()
+        .map_err(|_err| format!("{value:?} isn't a number"))?;
+    if !connectivity.is_finite() || connectivity <= 0.0 {
+        return Err(format!(
+            "{value} should be a number above 0, like the default 3.25; \
+             higher expands fewer papers"
+        ));
+    }
+    Ok(connectivity)
+}
+
+/// A `--base-uri` of a host, with an optional port and path but without
+/// a scheme, since requests are sent over `http://`.
+pub fn base_uri(value: &str) -> Result<String, String> {
+    if let Some((scheme, rest)) = value.split_once("://") {
+        return Err(format!(
+            "leave off the {scheme}:// and give only the host and path, e.g. {rest:?}"
+        ));
+    }
+    match reqwest::Url::parse(&format!("http://{value}")) {
+        Ok(url) if url.host_str().is_some_and(|host| !host.is_empty()) => Ok(value.to_string()),
+        _ => Err(format!(
+            "{value:?} isn't a host, e.g. localhost:8000 or api.example.org/s2"
+        )),
+    }
+}
+
+/// A `--request-timeout` of at least a second.
+pub fn request_timeout(value: &str) -> Result<u64, String> {
+    match value.parse() {
+        Ok(0) => Err("a request can't finish in 0 seconds; \
+                      give it at least 1, or leave it at the default 120"
+            .to_string()),
+        Ok(seconds) => Ok(seconds),
+        Err(_err) => Err(format!("{value:?} isn't a whole number of seconds")),
+    }
+}
+
+/// A `--max-concurrent-requests` of at least one.
+pub fn max_concurrent_requests(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("with 0 requests at once nothing would be fetched; \
+                      use 1 to send them one at a time"
+            .to_string()),
+        Ok(max) => Ok(max),
+        Err(_err) => Err(format!("{value:?} isn't a whole number of requests")),
+    }
+}
+
+/// Check there's a bibliography at `path` before searching from it.
+pub fn bibliography(path: &str) -> Result<(), id_import::Error> {
+    if !Path::new(path).exists() {
+        return Err(id_import::Error::Missing(path.into()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_values_are_turned_down() {
+        assert_eq!(max_depth("3"), Ok(3));
+        assert!(max_depth("11").is_err());
+        assert!(max_depth("-1").is_err());
+        assert_eq!(connectivity("3.25"), Ok(3.25));
+        for bad in ["0", "-2", "NaN", "inf", "many"] {
+            assert!(connectivity(bad).is_err(), "{bad}");
+        }
+        assert!(base_uri("localhost:8000").is_ok());
+        assert!(base_uri("api.fletcherporter.com/s2").is_ok());
+        assert!(base_uri("https://api.fletcherporter.com/s2")
+            .unwrap_err()
+            .contains("\"api.fletcherporter.com/s2\""));
+        assert!(base_uri("").is_err());
+        assert!(base_uri("local host").is_err());
+        assert_eq!(request_timeout("30"), Ok(30));
+        assert!(request_timeout("0").is_err());
+        assert_eq!(max_concurrent_requests("1"), Ok(1));
+        assert!(max_concurrent_requests("0").is_err());
+        assert!(bibliography(".").is_ok());
+        assert!(bibliography("no/such/bibliography.bib").is_err());
+    }
+}
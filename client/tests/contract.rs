@@ -0,0 +1,267 @@
+//! The real client and rate limiter, run against a fake Semantic
+//! Scholar to check that all three agree on the API between them.
+
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+use endpoints::{PAPER, PAPER_BATCH, PAPER_SEARCH};
+use harness::Fixture;
+use wiremock::matchers::{header, method, path, query_param, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_KEY: &str = "contract-test-key";
+
+/// `a` cites everything, `b` cites everything but `a`, and so on.
+fn fake_semantic_scholar() -> Fixture {
+    Fixture::new()
+        .with("DOI:10.1000/a", harness::paper("a", &["b", "c", "d"]))
+        .with_paper("b", &["c", "d"])
+        .with_paper("c", &["d"])
+        .with_paper("d", &[])
+}
+
+/// Launch the rate limiter in front of `upstream`, returning its address.
+async fn start_proxy(upstream: &MockServer) -> String {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("a free port")
+        .port();
+    let figment = rocket::Config::figment()
+        .merge(("address", "127.0.0.1"))
+        .merge(("port", port))
+        .merge(("log_level", "off"))
+        .merge(("upstream", upstream.uri()));
+    let proxy = rate_limiter::build(rate_limiter::keys::Keys::new(vec![API_KEY.into()]), None)
+        .configure(figment);
+    tokio::spawn(proxy.launch());
+    let address = format!("127.0.0.1:{port}");
+    while tokio::net::TcpStream::connect(&address).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    address
+}
+
+/// Run the client binary on a one-paper bibliography through `proxy`.
+async fn run_client(name: &str, proxy: String, args: &[&str]) -> Output {
+    let bibliography = std::env::temp_dir().join(format!("contract-{name}.bib"));
+    std::fs::write(
+        &bibliography,
+        "@article{a, title = {Paper a}, doi = {10.1000/a}}",
+    )
+    .expect("bibliography written");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_client"));
+    command
+        .arg(&bibliography)
+        .args(["--base-uri", &proxy])
+        .args(args);
+    tokio::task::spawn_blocking(move || command.output().expect("client ran"))
+        .await
+        .expect("client joined")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batches_pass_through_with_the_api_key_at_the_rate_limit() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(PAPER_BATCH))
+        .and(header("x-api-key", API_KEY))
+        .and(query_param_contains("fields", "references.paperId"))
+        .respond_with(fake_semantic_scholar())
+        // the seeds, then once for each of the two depths
+        .expect(3)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+
+    let start = Instant::now();
+    let output = run_client(
+        "batches",
+        proxy,
+        &[
+            "--connectivity",
+            "1",
+            "--max-depth",
+            "2",
+            "--no-attribution",
+        ],
+    )
+    .await;
+    let elapsed = start.elapsed();
+
+    assert!(output.status.success(), "{output:?}");
+    let dot = String::from_utf8(output.stdout).unwrap();
+    for edge in [
+        r#""a" -> "b""#,
+        r#""a" -> "c""#,
+        r#""a" -> "d""#,
+        r#""b" -> "c""#,
+        r#""b" -> "d""#,
+        r#""c" -> "d""#,
+    ] {
+        assert!(dot.contains(edge), "{edge} missing from {dot}");
+    }
+    // one request per 1.1 s, and the first waits too
+    assert!(elapsed >= Duration::from_millis(3300), "{elapsed:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn persistent_rate_limiting_upstream_fails_the_client() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(PAPER_BATCH))
+        .respond_with(ResponseTemplate::new(429))
+        // the proxy retries until five in a row open its circuit breaker
+        .expect(5)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+
+    let output = run_client("rate-limited", proxy, &[]).await;
+
+    assert!(!output.status.success(), "{output:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upstream_errors_fail_the_client() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(PAPER_BATCH))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+
+    let output = run_client("upstream-error", proxy, &[]).await;
+
+    assert!(!output.status.success(), "{output:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn searches_pass_through_with_the_api_key() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(PAPER_SEARCH))
+        .and(header("x-api-key", API_KEY))
+        .and(query_param("query", "attention is all you need"))
+        .and(query_param("year", "2017"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "total": 1,
+            "data": [{"paperId": "a", "title": "Attention Is All You Need", "year": 2017}],
+        })))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_client"));
+    command.args([
+        "--base-uri",
+        proxy.as_str(),
+        "search",
+        "attention",
+        "is",
+        "all",
+        "you",
+        "need",
+        "--year",
+        "2017",
+    ]);
+    let output = tokio::task::spawn_blocking(move || command.output().expect("client ran"))
+        .await
+        .expect("client joined");
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "a\t2017\tAttention Is All You Need\n"
+    );
+    let metrics = reqwest::get(format!("http://{proxy}/metrics"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        metrics.contains("route=\"paper_search\",status=\"200\"} 1\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("upstream_responses_total{status=\"200\"} 1\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("cache_lookups_total{result=\"miss\"} 1\n"),
+        "{metrics}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dois_with_slashes_reach_the_paper_and_its_references() {
+    let upstream = MockServer::start().await;
+    let doi = "DOI:10.1000/a.b";
+    Mock::given(method("GET"))
+        .and(path(format!("{PAPER}/{doi}")))
+        .and(query_param("fields", "title"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "paperId": "a", "title": "Paper a",
+        })))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{PAPER}/{doi}/references")))
+        .and(query_param("limit", "100"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "offset": 0, "data": [],
+        })))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+    let client = reqwest::Client::new();
+
+    let paper = client
+        .get(format!("http://{proxy}{PAPER}/{doi}?fields=title"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(paper.status(), 200);
+    assert!(paper.text().await.unwrap().contains("Paper a"));
+    // without fields, which Semantic Scholar defaults
+    let references = client
+        .get(format!("http://{proxy}{PAPER}/{doi}/references"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(references.status(), 200);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn responses_are_passed_on_as_they_arrive_and_kept_once_they_have() {
+    let upstream = MockServer::start().await;
+    let papers: Vec<_> = (0..2000)
+        .map(|i| serde_json::json!({"paperId": i.to_string(), "title": format!("Paper {i}")}))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path(PAPER_SEARCH))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"total": papers.len(), "data": papers})),
+        )
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let proxy = start_proxy(&upstream).await;
+    let search = || reqwest::get(format!("http://{proxy}{PAPER_SEARCH}?query=paper"));
+
+    let streamed = search().await.unwrap();
+    assert_eq!(streamed.headers()["x-cache"], "miss");
+    // sent as it arrived, so without a length up front
+    assert!(streamed.content_length().is_none());
+    let streamed = streamed.text().await.unwrap();
+    let kept = search().await.unwrap();
+    assert_eq!(kept.headers()["x-cache"], "hit");
+    assert_eq!(kept.text().await.unwrap(), streamed);
+    assert!(streamed.contains("Paper 1999"));
+}
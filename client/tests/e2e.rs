@@ -0,0 +1,152 @@
+//! Whole builds against a fake Semantic Scholar, straight from the client
+//! with no rate limiter between, checking the output for what the API
+//! sent, including when it misbehaves.
+
+use std::process::{Command, Output};
+
+use endpoints::PAPER_BATCH;
+use harness::Fixture;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// `a` cites `b`, `c`, and `x`, which Semantic Scholar doesn't have, and
+/// `b` and `c` cite each other and `x`.
+fn fake_semantic_scholar() -> Fixture {
+    Fixture::new()
+        .with("DOI:10.1000/a", harness::paper("a", &["b", "c", "x"]))
+        .with_paper("b", &["c", "x"])
+        .with_paper("c", &["b", "x"])
+}
+
+async fn serve(response: impl wiremock::Respond + 'static) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(PAPER_BATCH))
+        .respond_with(response)
+        .mount(&server)
+        .await;
+    server
+}
+
+/// Run the client on a one-paper bibliography against `server`.
+async fn run_client(name: &str, server: &MockServer, args: &[&str]) -> Output {
+    let bibliography = std::env::temp_dir().join(format!("e2e-{name}-{}.bib", std::process::id()));
+    std::fs::write(
+        &bibliography,
+        "@article{a, title = {Paper a}, doi = {10.1000/a}}",
+    )
+    .expect("bibliography written");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_client"));
+    command
+        .arg("build")
+        .arg(&bibliography)
+        .args(["--base-uri", &server.address().to_string()])
+        .args([
+            "--connectivity",
+            "1",
+            "--max-depth",
+            "2",
+            "--no-attribution",
+        ])
+        .args(args);
+    let output = tokio::task::spawn_blocking(move || command.output().expect("client ran"))
+        .await
+        .expect("client joined");
+    std::fs::remove_file(bibliography).expect("bibliography removed");
+    output
+}
+
+fn sorted_lines(output: &[u8], pattern: &str) -> Vec<String> {
+    let mut lines: Vec<String> = String::from_utf8_lossy(output)
+        .lines()
+        .filter(|line| line.contains(pattern))
+        .map(|line| line.trim().to_string())
+        .collect();
+    lines.sort_unstable();
+    lines
+}
+
+/// The edges of the graph built from [`fake_semantic_scholar`].
+const EDGES: [&str; 7] = [
+    r#""a" -> "b";"#,
+    r#""a" -> "c";"#,
+    r#""a" -> "x";"#,
+    r#""b" -> "c";"#,
+    r#""b" -> "x";"#,
+    r#""c" -> "b";"#,
+    r#""c" -> "x";"#,
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn papers_the_api_returns_null_for_are_kept_from_references() {
+    let server = serve(fake_semantic_scholar()).await;
+    let output = run_client("dot", &server, &[]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(sorted_lines(&output.stdout, "->"), EDGES);
+    // x is known only from the references to it
+    assert!(sorted_lines(&output.stdout, "label=")
+        .iter()
+        .any(|line| line.starts_with(r#""x" [label="Paper x""#)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn graphml_has_the_same_graph() {
+    let server = serve(fake_semantic_scholar()).await;
+    let output = run_client("graphml", &server, &["--output-format", "graphml"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        sorted_lines(&output.stdout, "<edge "),
+        [
+            r#"<edge source="a" target="b"/>"#,
+            r#"<edge source="a" target="c"/>"#,
+            r#"<edge source="a" target="x"/>"#,
+            r#"<edge source="b" target="c"/>"#,
+            r#"<edge source="b" target="x"/>"#,
+            r#"<edge source="c" target="b"/>"#,
+            r#"<edge source="c" target="x"/>"#,
+        ]
+    );
+    assert_eq!(sorted_lines(&output.stdout, "<node ").len(), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_responses_fail_the_build() {
+    let server = serve(ResponseTemplate::new(200).set_body_string("[{\"paperId\": ")).await;
+    let output = run_client("malformed", &server, &[]).await;
+    assert!(!output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(r#"[{"paperId": "#));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limiting_without_the_proxy_fails_the_build() {
+    let server =
+        serve(ResponseTemplate::new(429).set_body_string(r#"{"message": "Too Many Requests"}"#))
+            .await;
+    let output = run_client("rate-limited", &server, &["--error-format", "json"]).await;
+    // EX_TEMPFAIL, so wrapping scripts know to try again later
+    assert_eq!(output.status.code(), Some(75), "{output:?}");
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(r#""error":"rate_limited""#), "{stderr}");
+    assert!(stderr.contains("Too Many Requests"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_chunk_rate_limited_once_is_tried_again() {
+    let server = serve(fake_semantic_scholar()).await;
+    // the references of a, asked for after the seed
+    Mock::given(method("POST"))
+        .and(path(PAPER_BATCH))
+        .and(body_string_contains(r#""b""#))
+        .respond_with(ResponseTemplate::new(429))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    let output = run_client("rate-limited-once", &server, &[]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("trying a chunk again"));
+    assert_eq!(sorted_lines(&output.stdout, "->"), EDGES);
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+}
@@ -0,0 +1,94 @@
+//! Whole builds run against canned responses, checking the crawl,
+//! pruning, and output formats without the network.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use harness::Fixture;
+
+/// `a` cites `b`, `c`, and `d`, and `b` and `c` cite each other and `d`,
+/// so only `e`, cited once by `d`, is pruned.
+fn fixture() -> Fixture {
+    Fixture::new()
+        .with("DOI:10.1000/a", harness::paper("a", &["b", "c", "d"]))
+        .with_paper("b", &["c", "d"])
+        .with_paper("c", &["b", "d"])
+        .with_paper("d", &["e"])
+        .with_paper("e", &[])
+        .with_context("a", "b", 3)
+}
+
+/// Build from the fixture written under a directory named for `name`,
+/// returning stdout.
+fn build(name: &str, args: &[&str]) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("fixture-{name}-{}", std::process::id()));
+    fixture().write(&dir).expect("fixture written");
+    let bibliography = dir.join("refs.bib");
+    std::fs::write(
+        &bibliography,
+        "@article{a, title = {Paper a}, doi = {10.1000/a}}",
+    )
+    .expect("bibliography written");
+    let output = Command::new(env!("CARGO_BIN_EXE_client"))
+        .arg("build")
+        .arg(&bibliography)
+        .arg("--source")
+        .arg("fixture")
+        .arg(&dir)
+        .args([
+            "--connectivity",
+            "1",
+            "--max-depth",
+            "3",
+            "--no-attribution",
+        ])
+        .args(args)
+        .output()
+        .expect("client ran");
+    std::fs::remove_dir_all(&dir).expect("fixture removed");
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).expect("UTF-8 output")
+}
+
+#[test]
+fn builds_are_deterministic_and_pruned() {
+    let json = build("json", &["--output-format", "json", "--edge-weights"]);
+    assert_eq!(
+        json,
+        build("json-again", &["--output-format", "json", "--edge-weights"])
+    );
+    let graph: serde_json::Value = serde_json::from_str(&json).expect("JSON output");
+    let ids: Vec<&str> = graph["papers"]
+        .as_array()
+        .expect("papers")
+        .iter()
+        .map(|paper| paper["paperId"].as_str().expect("an id"))
+        .collect();
+    assert_eq!(ids, ["a", "b", "c", "d"]);
+    assert_eq!(graph["references"][0]["weight"], 3);
+
+    let dot = build("dot", &[]);
+    assert_eq!(dot, build("dot-again", &[]));
+    assert!(dot.contains(r#""a" -> "b";"#), "{dot}");
+    assert!(dot.contains(",shape=box,seed=true"), "{dot}");
+    assert!(!dot.contains(r#""e""#), "{dot}");
+}
+
+#[test]
+fn streams_hold_the_unpruned_graph() {
+    let stream = std::env::temp_dir().join(format!("fixture-stream-{}.ndjson", std::process::id()));
+    build("stream", &["--stream", stream.to_str().unwrap()]);
+    let lines = std::fs::read_to_string(&stream).expect("stream written");
+    std::fs::remove_file(&stream).expect("stream removed");
+
+    let records: Vec<serde_json::Value> = lines
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("an NDJSON record"))
+        .collect();
+    assert!(records
+        .iter()
+        .any(|record| record["source"] == "DOI:10.1000/a"));
+    assert!(records
+        .iter()
+        .any(|record| record["reference"]["from"] == "d" && record["reference"]["to"] == "e"));
+}
@@ -0,0 +1,30 @@
+//! Splitting ids into the batches `/paper/batch` takes.
+
+/// The most ids Semantic Scholar takes in one `/paper/batch` request.
+pub const MAX_BATCH_IDS: usize = 500;
+
+/// `items` in batches of at most [`MAX_BATCH_IDS`], none empty and none
+/// left out, however many there are.  Its `len` is how many requests
+/// they take.
+pub fn batch_chunks<T>(items: &[T]) -> std::slice::Chunks<'_, T> {
+    items.chunks(MAX_BATCH_IDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_item_is_in_a_batch() {
+        for len in [0, 1, 499, 500, 501, 1000, 1001, 1250] {
+            let items: Vec<usize> = (0..len).collect();
+            let batches: Vec<&[usize]> = batch_chunks(&items).collect();
+            assert_eq!(batches.len(), len.div_ceil(MAX_BATCH_IDS), "{len} items");
+            assert_eq!(batch_chunks(&items).len(), batches.len());
+            assert!(batches
+                .iter()
+                .all(|batch| !batch.is_empty() && batch.len() <= MAX_BATCH_IDS));
+            assert_eq!(batches.concat(), items);
+        }
+    }
+}
@@ -1 +1,22 @@
+//! What the client and the rate limiter agree on about Semantic
+//! Scholar's API: where each endpoint is and what it's sent and answers.
+
+mod batch;
+mod messages;
+mod paper;
+
+pub use batch::{batch_chunks, MAX_BATCH_IDS};
+pub use messages::{
+    BatchRequest, CitationPage, CitedPaper, ErrorEnvelope, PaperTldr, Recommendations,
+    RecommendationsRequest, ReferenceContexts, ReferencePage, SearchResults, Tldr,
+};
+pub use paper::{Author, Paper, ProtoPaper};
+
 pub const PAPER_BATCH: &str = "/graph/v1/paper/batch";
+pub const PAPER_SEARCH: &str = "/graph/v1/paper/search";
+/// Answers with the one paper best matching a title, or 404.
+pub const PAPER_SEARCH_MATCH: &str = "/graph/v1/paper/search/match";
+/// Offset by `/<paper_id>/references` for a paper's references.
+pub const PAPER: &str = "/graph/v1/paper";
+pub const AUTHOR: &str = "/graph/v1/author";
+pub const RECOMMENDATIONS: &str = "/recommendations/v1/papers";
@@ -0,0 +1,95 @@
+//! The bodies sent to and received from each endpoint, other than the
+//! papers themselves.
+
+use serde::{Deserialize, Serialize};
+
+use crate::paper::ProtoPaper;
+
+/// What's posted to [`PAPER_BATCH`](crate::PAPER_BATCH) and the author
+/// batch endpoint.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BatchRequest {
+    pub ids: Vec<String>,
+}
+
+/// What's posted to [`RECOMMENDATIONS`](crate::RECOMMENDATIONS).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RecommendationsRequest {
+    #[serde(rename = "positivePaperIds")]
+    pub positive_paper_ids: Vec<String>,
+    #[serde(rename = "negativePaperIds", default)]
+    pub negative_paper_ids: Vec<String>,
+}
+
+/// A page of `/paper/search`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SearchResults {
+    #[serde(default)]
+    pub data: Vec<ProtoPaper>,
+}
+
+/// A page of `/paper/{id}/references`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReferencePage {
+    pub next: Option<usize>,
+    pub data: Vec<ReferenceContexts>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReferenceContexts {
+    #[serde(rename = "citedPaper")]
+    pub cited_paper: CitedPaper,
+    #[serde(default)]
+    pub contexts: Option<Vec<String>>,
+}
+
+/// A page of `/paper/{id}/citations`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CitationPage {
+    pub next: Option<usize>,
+    pub data: Vec<Citation>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Citation {
+    #[serde(rename = "citingPaper")]
+    pub citing_paper: ProtoPaper,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CitedPaper {
+    #[serde(rename = "paperId")]
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Recommendations {
+    #[serde(rename = "recommendedPapers")]
+    pub recommended_papers: Vec<ProtoPaper>,
+}
+
+/// A paper from [`PAPER_BATCH`](crate::PAPER_BATCH) asked only for its
+/// `tldr`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PaperTldr {
+    #[serde(rename = "paperId")]
+    pub id: String,
+    #[serde(default)]
+    pub tldr: Option<Tldr>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Tldr {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// How Semantic Scholar, and the proxy in front of it, explain a
+/// request they won't answer.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ErrorEnvelope {
+    #[serde(alias = "message")]
+    pub error: String,
+}
@@ -0,0 +1,358 @@
+//! Papers as Semantic Scholar describes them.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct ProtoPaper {
+    #[serde(rename = "paperId")]
+    id: Option<String>,
+    title: String,
+    url: Option<String>,
+    #[serde(
+        rename = "fieldsOfStudy",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    fields_of_study: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    year: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    venue: Option<String>,
+    #[serde(
+        rename = "externalIds",
+        default,
+        deserialize_with = "external_ids",
+        skip_serializing_if = "Option::is_none"
+    )]
+    external_ids: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<Author>,
+    #[serde(rename = "abstract", default, skip_serializing_if = "Option::is_none")]
+    abstract_: Option<String>,
+    /// Semantic Scholar's one-sentence summary, fetched separately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tldr: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct Author {
+    #[serde(rename = "authorId", default)]
+    id: Option<String>,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct Paper {
+    title: String,
+    url: String,
+    #[serde(rename = "paperId")]
+    id: String,
+    #[serde(rename = "fieldsOfStudy", default)]
+    fields_of_study: Option<Vec<String>>,
+    #[serde(rename = "citationCount", default)]
+    citation_count: Option<usize>,
+    #[serde(rename = "abstract", default)]
+    abstract_: Option<String>,
+    #[serde(default)]
+    year: Option<u32>,
+    #[serde(default)]
+    venue: Option<String>,
+    #[serde(rename = "externalIds", default, deserialize_with = "external_ids")]
+    external_ids: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    authors: Vec<Author>,
+    references: Vec<ProtoPaper>,
+}
+
+/// Semantic Scholar gives most external ids as strings, but some, like
+/// `CorpusId`, as numbers.
+fn external_ids<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<String, String>>, D::Error> {
+    let ids = Option::<BTreeMap<String, serde_json::Value>>::deserialize(deserializer)?;
+    Ok(ids.map(|ids| {
+        ids.into_iter()
+            .filter_map(|(source, id)| match id {
+                serde_json::Value::String(id) => Some((source, id)),
+                serde_json::Value::Number(id) => Some((source, id.to_string())),
+                _ => None,
+            })
+            .collect()
+    }))
+}
+
+impl Paper {
+    pub fn new(id: &str, title: &str, references: Vec<ProtoPaper>) -> Self {
+        Self {
+            title: title.into(),
+            url: String::new(),
+            id: id.into(),
+            fields_of_study: None,
+            citation_count: None,
+            abstract_: None,
+            year: None,
+            venue: None,
+            external_ids: None,
+            authors: Vec::new(),
+            references,
+        }
+    }
+
+    /// Replace the paper's references, e.g. to walk the graph another way.
+    pub fn with_references(mut self, references: Vec<ProtoPaper>) -> Self {
+        self.references = references;
+        self
+    }
+
+    pub fn with_fields_of_study(mut self, fields_of_study: Option<Vec<String>>) -> Self {
+        self.fields_of_study = fields_of_study;
+        self
+    }
+
+    pub fn with_citation_count(mut self, citation_count: Option<usize>) -> Self {
+        self.citation_count = citation_count;
+        self
+    }
+
+    pub fn references(&self) -> &[ProtoPaper] {
+        &self.references
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// How many times this paper has been cited across all of Semantic
+    /// Scholar, not just in this graph.
+    pub fn citation_count(&self) -> Option<usize> {
+        self.citation_count
+    }
+
+    pub fn abstract_(&self) -> Option<&str> {
+        self.abstract_.as_deref()
+    }
+
+    /// Whether any of the paper's authors goes by `name`.
+    pub fn has_author(&self, name: &str) -> bool {
+        self.authors.iter().any(|author| author.is(name))
+    }
+}
+
+impl Author {
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: None,
+            name: name.into(),
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Whether the author goes by `name`, which matches if the surnames
+    /// are the same and its initials start the author's, ignoring case and
+    /// punctuation, so "J. Y. Wong" is "Jun Yi Wong" but not "Jun Wong".
+    pub fn is(&self, name: &str) -> bool {
+        let words = |name: &str| -> Vec<String> {
+            name.split(|c: char| c.is_whitespace() || c == '.' || c == '-')
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect()
+        };
+        let (theirs, ours) = (words(&self.name), words(name));
+        let (Some((their_surname, their_given)), Some((our_surname, our_given))) =
+            (theirs.split_last(), ours.split_last())
+        else {
+            return false;
+        };
+        their_surname == our_surname
+            && our_given.len() <= their_given.len()
+            && our_given
+                .iter()
+                .zip(their_given)
+                .all(|(ours, theirs)| ours.chars().next() == theirs.chars().next())
+    }
+}
+
+impl ProtoPaper {
+    pub fn new(id: &str, title: &str) -> Self {
+        Self {
+            id: Some(id.into()),
+            title: title.into(),
+            url: None,
+            fields_of_study: None,
+            year: None,
+            venue: None,
+            external_ids: None,
+            authors: Vec::new(),
+            abstract_: None,
+            tldr: None,
+        }
+    }
+
+    pub fn with_url(mut self, url: Option<String>) -> Self {
+        self.url = url;
+        self
+    }
+
+    pub fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
+    pub fn with_fields_of_study(mut self, fields_of_study: Option<Vec<String>>) -> Self {
+        self.fields_of_study = fields_of_study;
+        self
+    }
+
+    pub fn with_year(mut self, year: Option<u32>) -> Self {
+        self.year = year;
+        self
+    }
+
+    pub fn with_venue(mut self, venue: Option<String>) -> Self {
+        self.venue = venue;
+        self
+    }
+
+    pub fn with_authors(mut self, authors: Vec<Author>) -> Self {
+        self.authors = authors;
+        self
+    }
+
+    pub fn with_abstract(mut self, abstract_: Option<String>) -> Self {
+        self.abstract_ = abstract_;
+        self
+    }
+
+    pub fn with_tldr(mut self, tldr: Option<String>) -> Self {
+        self.tldr = tldr;
+        self
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn year(&self) -> Option<u32> {
+        self.year
+    }
+
+    pub fn venue(&self) -> Option<&str> {
+        self.venue.as_deref().filter(|venue| !venue.is_empty())
+    }
+
+    pub fn authors(&self) -> &[Author] {
+        &self.authors
+    }
+
+    /// The abstract, if Semantic Scholar may share it.
+    pub fn abstract_(&self) -> Option<&str> {
+        self.abstract_
+            .as_deref()
+            .filter(|abstract_| !abstract_.is_empty())
+    }
+
+    pub fn tldr(&self) -> Option<&str> {
+        self.tldr.as_deref().filter(|tldr| !tldr.is_empty())
+    }
+
+    /// Whether any of the paper's authors goes by `name`.
+    pub fn has_author(&self, name: &str) -> bool {
+        self.authors.iter().any(|author| author.is(name))
+    }
+
+    /// The paper's id in another catalogue, e.g. `DOI` or `ArXiv`.
+    pub fn external_id(&self, source: &str) -> Option<&str> {
+        self.external_ids.as_ref()?.get(source).map(String::as_str)
+    }
+
+    /// Whether any of the paper's fields of study are in `fields`.
+    ///
+    /// Papers that Semantic Scholar hasn't classified are given the
+    /// benefit of the doubt.
+    pub fn is_in_fields_of_study(&self, fields: &[String]) -> bool {
+        match &self.fields_of_study {
+            None => true,
+            Some(fields_of_study) => fields_of_study
+                .iter()
+                .any(|field| fields.iter().any(|f| f.eq_ignore_ascii_case(field))),
+        }
+    }
+}
+
+impl From<Paper> for ProtoPaper {
+    fn from(paper: Paper) -> Self {
+        Self {
+            id: Some(paper.id),
+            title: paper.title,
+            url: Some(paper.url),
+            fields_of_study: paper.fields_of_study,
+            year: paper.year,
+            venue: paper.venue,
+            external_ids: paper.external_ids,
+            authors: paper.authors,
+            abstract_: paper.abstract_,
+            tldr: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn papers_read_what_semantic_scholar_sends() {
+        let paper: Paper = serde_json::from_str(
+            r#"{
+                "paperId": "a",
+                "title": "A",
+                "url": "https://example.com/a",
+                "externalIds": {"DOI": "10.1000/a", "CorpusId": 42},
+                "references": [{"paperId": null, "title": "Unknown"}]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(paper.id(), "a");
+        assert_eq!(paper.references()[0].id(), None);
+        let proto = ProtoPaper::from(paper);
+        assert_eq!(proto.external_id("CorpusId"), Some("42"));
+        assert_eq!(proto.external_id("DOI"), Some("10.1000/a"));
+    }
+
+    #[test]
+    fn authors_match_by_surname_and_initials() {
+        let author = Author::new("Jun Yi Wong");
+        assert!(author.is("J. Y. Wong"));
+        assert!(author.is("jun wong"));
+        assert!(author.is("Jun-Yi Wong"));
+        assert!(!author.is("K. Wong"));
+        assert!(!author.is("J. Y. Z. Wong"));
+        assert!(!author.is("J. Y. Wang"));
+    }
+}
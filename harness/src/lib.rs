@@ -0,0 +1,96 @@
+//! Canned Semantic Scholar responses for testing the client without the
+//! network or an API key.
+//!
+//! A [`Fixture`] is a set of papers, each stored under every id it can be
+//! requested by.  It can be written to a directory for the client's
+//! `--source fixture <dir>` mode, or answer `/paper/batch` requests as a
+//! wiremock responder.
+//!
+//! The directory holds `papers.json`, an object from requested id, like
+//! `DOI:10.1000/a` or a Semantic Scholar id, to the paper as `/paper/batch`
+//! returns it, and optionally `contexts.json`, a list of `{"citing",
+//! "cited", "count"}` objects giving how many times each citation is made.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::{json, Value};
+use wiremock::{Request, Respond, ResponseTemplate};
+
+pub const PAPERS_FILE: &str = "papers.json";
+pub const CONTEXTS_FILE: &str = "contexts.json";
+
+#[derive(Clone, Default)]
+pub struct Fixture {
+    papers: BTreeMap<String, Value>,
+    contexts: Vec<Value>,
+}
+
+/// A paper titled `Paper <id>` citing `references`.
+pub fn paper(id: &str, references: &[&str]) -> Value {
+    json!({
+        "paperId": id,
+        "title": format!("Paper {id}"),
+        "url": format!("https://example.com/{id}"),
+        "references": references
+            .iter()
+            .map(|reference| json!({"paperId": reference, "title": format!("Paper {reference}")}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `paper`, requested as `requested_id`.
+    pub fn with(mut self, requested_id: &str, paper: Value) -> Self {
+        self.papers.insert(requested_id.into(), paper);
+        self
+    }
+
+    /// Add a [`paper`] requested by its own id.
+    pub fn with_paper(self, id: &str, references: &[&str]) -> Self {
+        self.with(id, paper(id, references))
+    }
+
+    /// Have `citing` cite `cited` `count` times in its text.
+    pub fn with_context(mut self, citing: &str, cited: &str, count: usize) -> Self {
+        self.contexts
+            .push(json!({"citing": citing, "cited": cited, "count": count}));
+        self
+    }
+
+    /// Write the fixture into `dir`, creating it if needed.
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(
+            dir.join(PAPERS_FILE),
+            serde_json::to_vec_pretty(&self.papers)?,
+        )?;
+        std::fs::write(
+            dir.join(CONTEXTS_FILE),
+            serde_json::to_vec_pretty(&self.contexts)?,
+        )
+    }
+}
+
+impl Respond for Fixture {
+    /// Answer a `/paper/batch` request, with `null` for unknown papers.
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).expect("JSON body");
+        let papers: Vec<Value> = body["ids"]
+            .as_array()
+            .expect("an ids list")
+            .iter()
+            .map(|id| {
+                self.papers
+                    .get(id.as_str().expect("string ids"))
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect();
+        ResponseTemplate::new(200).set_body_json(papers)
+    }
+}
@@ -0,0 +1,153 @@
+//! A line for each request answered, as text or as JSON for log
+//! collectors.  Keys and tokens are never written: clients are named by
+//! their token's user or their address, and query parameters that look
+//! like secrets are redacted.
+
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use rocket::serde::json::json;
+use rocket::serde::Deserialize;
+
+const REDACTED: &str = "REDACTED";
+/// Query parameters whose values aren't logged.
+const SECRET_PARAMETERS: [&str; 4] = ["key", "token", "secret", "password"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("{other:?} isn't a log format; use text or json")),
+        }
+    }
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// One request, as it's logged.
+pub struct Entry<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    /// The route that answered, if any did.
+    pub route: Option<&'a str>,
+    pub client: &'a str,
+    pub status: u16,
+    pub latency: Duration,
+    /// Whether the answer came from the cache, for proxied requests.
+    pub cache: Option<&'a str>,
+}
+
+/// `query` with the values of [`SECRET_PARAMETERS`] replaced.
+fn redact(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _value))
+                if SECRET_PARAMETERS
+                    .iter()
+                    .any(|secret| name.to_lowercase().contains(secret)) =>
+            {
+                format!("{name}={REDACTED}")
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+impl Entry<'_> {
+    pub fn format(&self, format: LogFormat) -> String {
+        let target = match self.query {
+            Some(query) => format!("{}?{}", self.path, redact(query)),
+            None => self.path.to_string(),
+        };
+        let latency_ms = self.latency.as_secs_f64() * 1000.0;
+        match format {
+            LogFormat::Text => format!(
+                "{} {} {} {} {latency_ms:.1}ms client={} route={} cache={}",
+                timestamp(SystemTime::now()),
+                self.method,
+                target,
+                self.status,
+                self.client,
+                self.route.unwrap_or("-"),
+                self.cache.unwrap_or("-"),
+            ),
+            LogFormat::Json => json!({
+                "time": timestamp(SystemTime::now()),
+                "method": self.method,
+                "path": target,
+                "route": self.route,
+                "client": self.client,
+                "status": self.status,
+                "latency_ms": latency_ms,
+                "cache": self.cache,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// Seconds since the epoch, which log collectors all understand.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "{}.{:03}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let entry = Entry {
+            method: "GET",
+            path: "/graph/v1/author/1",
+            query: Some("fields=name&api_key=hunter2&token=abc"),
+            route: Some("author"),
+            client: "alice",
+            status: 200,
+            latency: Duration::from_millis(12),
+            cache: Some("miss"),
+        };
+
+        let line = entry.format(LogFormat::Json);
+        assert!(!line.contains("hunter2"), "{line}");
+        assert!(!line.contains("abc"), "{line}");
+        let line: rocket::serde::json::Value = rocket::serde::json::from_str(&line).unwrap();
+        assert_eq!(
+            line["path"],
+            "/graph/v1/author/1?fields=name&api_key=REDACTED&token=REDACTED"
+        );
+        assert_eq!(line["client"], "alice");
+        assert_eq!(line["latency_ms"], 12.0);
+        assert!(entry
+            .format(LogFormat::Text)
+            .ends_with(" GET /graph/v1/author/1?fields=name&api_key=REDACTED&token=REDACTED 200 12.0ms client=alice route=author cache=miss"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Merging `/paper/batch` requests that wait on the rate limit together,
+//! so several clients' small batches cost one request upstream.
+//!
+//! The first request for a set of fields leads a batch: while it waits
+//! its turn, later requests for the same fields add their ids to its
+//! batch instead of queueing, and once it's let through it fetches them
+//! all at once and hands each request its part of the answer.  A request
+//! that finds the limit free goes alone, so nothing waits to be merged.
+//!
+//! The fetch runs as a task of its own, so a batch under way is still
+//! answered if its leader's client hangs up.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use endpoints::MAX_BATCH_IDS;
+use rocket::http::Status;
+use rocket::serde::json::{self, Value};
+use rocket::tokio::{self, sync::oneshot};
+
+use crate::queue::Refused;
+use crate::Body;
+
+type Answer = Result<Body, Refused>;
+
+struct Batch {
+    ids: Vec<String>,
+    /// Each request's part of `ids`, and where its answer goes.
+    waiting: Vec<(Range<usize>, oneshot::Sender<Answer>)>,
+}
+
+type Shared = Arc<Mutex<Option<Batch>>>;
+
+#[derive(Default)]
+pub struct Batcher {
+    /// The batch still taking ids, by fields.
+    open: Mutex<HashMap<String, Shared>>,
+    /// How many requests were answered by another's request upstream.
+    pub merged: AtomicU64,
+}
+
+impl Batcher {
+    /// Get the papers `ids` with `fields`, merged into the batch waiting
+    /// for them if there's room, or else leading a new one: waiting for
+    /// `turn`, then getting every id in the batch with `fetch`.
+    pub async fn fetch<Fetched>(
+        &self,
+        fields: &str,
+        ids: Vec<String>,
+        turn: impl Future<Output = Result<(), Refused>>,
+        fetch: impl FnOnce(Vec<String>) -> Fetched,
+    ) -> Answer
+    where
+        Fetched: Future<Output = Answer> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let led = self.join(fields, ids, sender);
+        match led {
+            Some(shared) => {
+                let leader = Leader {
+                    batcher: self,
+                    fields,
+                    shared,
+                };
+                let turn = turn.await;
+                let batch = leader.close();
+                match turn {
+                    Ok(()) => {
+                        let fetched = fetch(batch.ids);
+                        tokio::spawn(async move { hand_out(fetched.await, batch.waiting).await });
+                    }
+                    Err(refused) => split(Err(refused), batch.waiting),
+                }
+            }
+            None => {
+                self.merged.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        receiver
+            .await
+            .unwrap_or(Err(Status::InternalServerError.into()))
+    }
+
+    /// Add `ids` to the open batch for `fields` if they fit, or else open
+    /// a new one, returned for its leader.
+    fn join(
+        &self,
+        fields: &str,
+        ids: Vec<String>,
+        sender: oneshot::Sender<Answer>,
+    ) -> Option<Shared> {
+        let mut open = self.open.lock().unwrap();
+        if let Some(shared) = open.get(fields) {
+            if let Some(batch) = shared.lock().unwrap().as_mut() {
+                if batch.ids.len() + ids.len() <= MAX_BATCH_IDS {
+                    let start = batch.ids.len();
+                    batch.ids.extend(ids);
+                    batch.waiting.push((start..batch.ids.len(), sender));
+                    return None;
+                }
+            }
+        }
+        let batch = Batch {
+            waiting: vec![(0..ids.len(), sender)],
+            ids,
+        };
+        let shared = Arc::new(Mutex::new(Some(batch)));
+        open.insert(fields.to_string(), shared.clone());
+        Some(shared)
+    }
+
+    /// Stop `shared` taking ids, and take them, unless they've been
+    /// taken already.
+    fn close(&self, fields: &str, shared: &Shared) -> Option<Batch> {
+        let mut open = self.open.lock().unwrap();
+        if open
+            .get(fields)
+            .is_some_and(|current| Arc::ptr_eq(current, shared))
+        {
+            open.remove(fields);
+        }
+        shared.lock().unwrap().take()
+    }
+}
+
+/// The request leading a batch.  Dropped before it's let through, as
+/// when its client hangs up, it closes the batch and turns away those
+/// waiting on it, who would otherwise never be answered.
+struct Leader<'a> {
+    batcher: &'a Batcher,
+    fields: &'a str,
+    shared: Shared,
+}
+
+impl Leader<'_> {
+    fn close(self) -> Batch {
+        // only its leader takes a batch
+        self.batcher.close(self.fields, &self.shared).unwrap()
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        if let Some(batch) = self.batcher.close(self.fields, &self.shared) {
+            split(Err(Status::ServiceUnavailable.into()), batch.waiting);
+        }
+    }
+}
+
+/// Answer each waiting request with its part of the batch's `answer`.
+async fn hand_out(answer: Answer, mut waiting: Vec<(Range<usize>, oneshot::Sender<Answer>)>) {
+    // alone, a request's answer is passed on untouched, as it arrives
+    if waiting.len() == 1 {
+        let (_range, sender) = waiting.pop().unwrap();
+        let _ = sender.send(answer);
+        return;
+    }
+    let text = match answer {
+        Ok(body) => body.text().await.map_err(|err| {
+            eprintln!("couldn't read a merged batch: {err}");
+            Refused::from(Status::BadGateway)
+        }),
+        Err(refused) => Err(refused),
+    };
+    split(text, waiting);
+}
+
+/// Answer each waiting request with its part of the batch's whole
+/// answer, `text`.
+fn split(text: Result<String, Refused>, waiting: Vec<(Range<usize>, oneshot::Sender<Answer>)>) {
+    let papers = match text {
+        Ok(body) => match json::from_str::<Vec<Value>>(&body) {
+            Ok(papers) => Ok(papers),
+            Err(err) => {
+                eprintln!("couldn't split a merged batch: {err}");
+                Err(Refused::from(Status::BadGateway))
+            }
+        },
+        Err(refused) => Err(refused),
+    };
+    for (range, sender) in waiting {
+        let part = match &papers {
+            Ok(papers) => match papers.get(range) {
+                Some(part) => Ok(Body::Whole(json::to_string(&part).unwrap_or_default())),
+                None => Err(Status::BadGateway.into()),
+            },
+            Err(refused) => Err(refused.clone()),
+        };
+        // a request given up on doesn't need its answer
+        let _ = sender.send(part);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::futures::future;
+
+    async fn text(answer: Answer) -> String {
+        match answer {
+            Ok(body) => body.text().await.unwrap(),
+            Err(refused) => panic!("refused: {refused:?}"),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn requests_waiting_together_are_fetched_together() {
+        let batcher = Batcher::default();
+        let (let_through, turn) = oneshot::channel::<()>();
+        let fetched = Mutex::new(Vec::new());
+        let fetch = |ids: Vec<String>| {
+            fetched.lock().unwrap().push(ids.clone());
+            let papers: Vec<Value> = ids.iter().map(|id| json::json!({"paperId": id})).collect();
+            async move { Ok(Body::Whole(json::to_string(&papers).unwrap())) }
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect();
+        let (first, second, ()) = future::join3(
+            batcher.fetch(
+                "title",
+                ids(&["a", "b"]),
+                async { turn.await.map_err(|_| Status::InternalServerError.into()) },
+                fetch,
+            ),
+            batcher.fetch("title", ids(&["c"]), async { Ok(()) }, fetch),
+            async {
+                let_through.send(()).unwrap();
+            },
+        )
+        .await;
+
+        assert_eq!(*fetched.lock().unwrap(), vec![ids(&["a", "b", "c"])]);
+        assert_eq!(text(first).await, r#"[{"paperId":"a"},{"paperId":"b"}]"#);
+        assert_eq!(text(second).await, r#"[{"paperId":"c"}]"#);
+        assert_eq!(batcher.merged.load(Ordering::Relaxed), 1);
+
+        let mut leader = Box::pin(batcher.fetch("title", ids(&["d"]), future::pending(), fetch));
+        assert!(future::poll_immediate(&mut leader).await.is_none());
+        let mut follower = Box::pin(batcher.fetch("title", ids(&["e"]), async { Ok(()) }, fetch));
+        assert!(future::poll_immediate(&mut follower).await.is_none());
+        drop(leader);
+        assert!(follower.await.is_err());
+        let after = batcher.fetch("title", ids(&["f"]), async { Ok(()) }, fetch);
+        assert_eq!(text(after.await).await, r#"[{"paperId":"f"}]"#);
+    }
+
+    #[rocket::async_test]
+    async fn a_fetch_under_way_outlives_its_leader() {
+        let batcher = Batcher::default();
+        let (let_through, turn) = oneshot::channel::<()>();
+        let (answer, answered) = oneshot::channel::<()>();
+        let fetch = move |ids: Vec<String>| async move {
+            answered.await.unwrap();
+            let papers: Vec<Value> = ids.iter().map(|id| json::json!({"paperId": id})).collect();
+            Ok(Body::Whole(json::to_string(&papers).unwrap()))
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect();
+        let mut leader = Box::pin(batcher.fetch(
+            "title",
+            ids(&["a"]),
+            async { turn.await.map_err(|_| Status::InternalServerError.into()) },
+            fetch,
+        ));
+        assert!(future::poll_immediate(&mut leader).await.is_none());
+        let follower = batcher.fetch("title", ids(&["b"]), async { Ok(()) }, |_ids| async {
+            unreachable!("a follower doesn't fetch")
+        });
+        let mut follower = Box::pin(follower);
+        assert!(future::poll_immediate(&mut follower).await.is_none());
+
+        // let through, the leader starts the fetch, then hangs up
+        let_through.send(()).unwrap();
+        assert!(future::poll_immediate(&mut leader).await.is_none());
+        drop(leader);
+        answer.send(()).unwrap();
+        assert_eq!(text(follower.await).await, r#"[{"paperId":"b"}]"#);
+    }
+}
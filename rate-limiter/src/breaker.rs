@@ -0,0 +1,127 @@
+//! A circuit breaker in front of Semantic Scholar, so that while it's
+//! failing or throttling the key, clients are told to come back later
+//! instead of retrying into it and getting the key throttled longer.
+//!
+//! After `breaker_threshold` 5xx or 429 responses in a row, the breaker
+//! opens: requests are answered 503 with a `Retry-After` for
+//! `breaker_cooldown_ms`.  Then one request is let through to probe; if
+//! it succeeds the breaker closes, and if not it opens again.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::queue::Refused;
+
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe went out then; if it's never answered, another may go
+    /// after the cooldown.
+    Probing {
+        since: Instant,
+    },
+}
+
+pub struct Breaker {
+    state: Mutex<State>,
+    threshold: u32,
+    cooldown: Duration,
+    /// How many times the breaker has opened.
+    pub trips: AtomicU64,
+}
+
+impl Breaker {
+    /// Open after `threshold` failures in a row, for `cooldown`.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(State::Closed { failures: 0 }),
+            threshold,
+            cooldown,
+            trips: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether a request may go upstream, which when the cooldown's over
+    /// makes it the probe.
+    pub fn check(&self) -> Result<(), Refused> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let retry_at = match *state {
+            State::Closed { .. } => return Ok(()),
+            State::Open { until } => until,
+            State::Probing { since } => since + self.cooldown,
+        };
+        if now < retry_at {
+            return Err(Refused::Tripped(retry_at - now));
+        }
+        *state = State::Probing { since: now };
+        Ok(())
+    }
+
+    /// How long until the breaker lets a request through, if it's open.
+    pub fn open_for(&self) -> Option<Duration> {
+        match *self.state.lock().unwrap() {
+            State::Open { until } => Some(until.saturating_duration_since(Instant::now())),
+            _ => None,
+        }
+    }
+
+    /// Count an answer from upstream, `failed` if it was a 5xx or 429 or
+    /// never came.
+    pub fn record(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            _ if !failed => 0,
+            State::Closed { failures } => failures + 1,
+            // a failed probe opens it again at once
+            State::Open { .. } | State::Probing { .. } => self.threshold,
+        };
+        *state = if failures >= self.threshold.max(1) {
+            if !matches!(*state, State::Open { .. }) {
+                self.trips.fetch_add(1, Ordering::Relaxed);
+            }
+            State::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_in_a_row_open_it_until_a_probe_succeeds() {
+        let breaker = Breaker::new(2, Duration::ZERO);
+        breaker.record(true);
+        breaker.record(false);
+        breaker.record(true);
+        assert!(breaker.check().is_ok());
+        breaker.record(true);
+        assert_eq!(breaker.trips.load(Ordering::Relaxed), 1);
+        assert!(breaker.open_for().is_some());
+
+        // with no cooldown, the next request is the probe
+        assert!(breaker.check().is_ok());
+        breaker.record(true);
+        assert_eq!(breaker.trips.load(Ordering::Relaxed), 2);
+        assert!(breaker.check().is_ok());
+        breaker.record(false);
+        assert!(breaker.open_for().is_none());
+
+        let cooling = Breaker::new(1, Duration::from_secs(60));
+        cooling.record(true);
+        assert!(matches!(
+            cooling.check(),
+            Err(Refused::Tripped(retry_after)) if retry_after > Duration::from_secs(59)
+        ));
+    }
+}
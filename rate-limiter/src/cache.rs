@@ -0,0 +1,139 @@
+//! Upstream responses kept for a while, so identical requests, like the
+//! same bibliography built twice, don't spend the rate limit again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+/// Identifies a request by its endpoint, query, and body.  The body,
+/// which can be a long list of ids, is kept only as a hash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    path: String,
+    query: Vec<(String, String)>,
+    body: u64,
+}
+
+impl Key {
+    pub fn new(path: &str, query: &[(&str, &str)], body: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        Key {
+            path: path.to_string(),
+            query: query
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: hasher.finish(),
+        }
+    }
+}
+
+struct Entry {
+    body: String,
+    stored: Instant,
+}
+
+/// The least recently used responses, up to a capacity, each kept until
+/// it's older than the TTL.
+pub struct ResponseCache {
+    entries: Mutex<LruCache<Key, Entry>>,
+    capacity: usize,
+    ttl: Duration,
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let entries = LruCache::new(NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN));
+        Self {
+            entries: Mutex::new(entries),
+            capacity,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The response for `key` and how much longer it's good for, if it's
+    /// cached and fresh.
+    pub fn get(&self, key: &Key) -> Option<(String, Duration)> {
+        let found = self.find(key);
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    fn find(&self, key: &Key) -> Option<(String, Duration)> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        let age = entry.stored.elapsed();
+        if age >= self.ttl {
+            entries.pop(key);
+            return None;
+        }
+        Some((entry.body.clone(), self.ttl - age))
+    }
+
+    /// Keep `body` as the response for `key`, making room if needed by
+    /// dropping the least recently used.
+    pub fn put(&self, key: Key, body: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let entry = Entry {
+            body,
+            stored: Instant::now(),
+        };
+        self.entries.lock().unwrap().put(key, entry);
+    }
+
+    /// Forget everything, returning how many responses there were.
+    pub fn flush(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let flushed = entries.len();
+        entries.clear();
+        flushed
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_least_recently_used_response_makes_room() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        let a = Key::new("/paper/batch", &[("fields", "title")], r#"{"ids":["a"]}"#);
+        let b = Key::new("/paper/batch", &[("fields", "title")], r#"{"ids":["b"]}"#);
+        let c = Key::new("/paper/batch", &[("fields", "url")], r#"{"ids":["a"]}"#);
+        cache.put(a.clone(), "A".into());
+        cache.put(b.clone(), "B".into());
+        assert_eq!(cache.get(&a).map(|(body, _ttl)| body).as_deref(), Some("A"));
+        cache.put(c.clone(), "C".into());
+
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&c).is_some());
+        assert_eq!(cache.flush(), 2);
+        assert!(cache.get(&a).is_none());
+
+        let stale = ResponseCache::new(2, Duration::ZERO);
+        stale.put(a.clone(), "A".into());
+        assert!(stale.get(&a).is_none());
+    }
+}
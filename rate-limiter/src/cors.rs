@@ -0,0 +1,80 @@
+//! Letting pages on other origins call the proxy from the browser.
+//!
+//! Origins are listed in the `allowed_origins` setting, with `*` for
+//! any.  A page on one of them gets the `Access-Control-*` headers that
+//! let it read responses and send `x-api-key`, and its preflight
+//! `OPTIONS` requests are answered without needing a token.
+
+use rocket::http::{Header, Method};
+use rocket::{Request, Response};
+
+use crate::HEADER_API_KEY;
+
+const ALLOWED_METHODS: &str = "GET, POST, DELETE, OPTIONS";
+/// Headers a page may read besides the simple ones, for backing off and
+/// telling cached responses apart.
+const EXPOSED_HEADERS: &str = "Retry-After, X-Cache";
+/// How long, in seconds, a browser may remember a preflight's answer.
+const MAX_AGE: &str = "86400";
+
+/// The origins allowed to call the proxy from the browser.
+pub struct Cors {
+    origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self { origins }
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/') == origin)
+    }
+
+    /// Add the headers `request` should get to `response`, if it's from
+    /// an allowed origin.
+    pub fn apply(&self, request: &Request<'_>, response: &mut Response<'_>) {
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        if !self.allows(origin) {
+            return;
+        }
+        response.set_header(Header::new(
+            "Access-Control-Allow-Origin",
+            origin.to_string(),
+        ));
+        // the answer depends on who's asking, so caches must not share it
+        response.set_header(Header::new("Vary", "Origin"));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            EXPOSED_HEADERS,
+        ));
+        if request.method() == Method::Options {
+            let headers = request
+                .headers()
+                .get_one("Access-Control-Request-Headers")
+                .unwrap_or(HEADER_API_KEY)
+                .to_string();
+            response.set_header(Header::new("Access-Control-Allow-Methods", ALLOWED_METHODS));
+            response.set_header(Header::new("Access-Control-Allow-Headers", headers));
+            response.set_header(Header::new("Access-Control-Max-Age", MAX_AGE));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_listed_origins_are_allowed() {
+        let cors = Cors::new(vec!["https://example.org/".into()]);
+        assert!(cors.allows("https://example.org"));
+        assert!(!cors.allows("https://example.com"));
+        assert!(!Cors::new(Vec::new()).allows("https://example.org"));
+        assert!(Cors::new(vec!["*".into()]).allows("https://example.com"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Several Semantic Scholar API keys, so a shared proxy isn't held to one
+//! key's allowance.
+//!
+//! `API_KEYS_FILE` names a file with a key on each line; blank lines and
+//! lines starting with `#` are skipped.  Requests take the keys in turn,
+//! passing over any Semantic Scholar has lately answered 429, and the
+//! rate limits in the settings are for each key, so three keys go three
+//! times as fast.  The file is read again when it changes, so keys can be
+//! rotated without a restart, though the rate limits keep the number of
+//! keys the proxy started with.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use rocket::http::Status;
+
+/// The environment variable naming the keys file.  Without it, the one
+/// key in `API_KEY` is used.
+pub const ENV_API_KEYS: &str = "API_KEYS_FILE";
+/// How long a key answered 429 is passed over.
+const BENCH: Duration = Duration::from_secs(60);
+/// How often the keys file is checked for changes.
+const RELOAD_PERIOD: Duration = Duration::from_secs(5);
+
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    /// The file has no keys in it.
+    Empty(PathBuf),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "couldn't read {}: {err}", path.display()),
+            Error::Empty(path) => write!(f, "{} has no API keys in it", path.display()),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_path, err) => Some(err),
+            Error::Empty(_path) => None,
+        }
+    }
+}
+
+/// Where the keys were read from, to read them again when it changes.
+struct File {
+    path: PathBuf,
+    /// When it was last changed, and when that was last checked.
+    modified: Mutex<(Option<SystemTime>, Instant)>,
+}
+
+pub struct Keys {
+    keys: RwLock<Vec<String>>,
+    next: AtomicUsize,
+    /// When each key answered 429 may be used again.
+    benched: Mutex<HashMap<String, Instant>>,
+    file: Option<File>,
+}
+
+fn parse(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn read(path: &Path) -> Result<(Vec<String>, Option<SystemTime>), Error> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let text = std::fs::read_to_string(path).map_err(|err| Error::Io(path.into(), err))?;
+    let keys = parse(&text);
+    if keys.is_empty() {
+        return Err(Error::Empty(path.into()));
+    }
+    Ok((keys, modified))
+}
+
+impl Keys {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self {
+            keys: RwLock::new(keys),
+            next: AtomicUsize::new(0),
+            benched: Mutex::default(),
+            file: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let (keys, modified) = read(path)?;
+        Ok(Self {
+            file: Some(File {
+                path: path.into(),
+                modified: Mutex::new((modified, Instant::now())),
+            }),
+            ..Self::new(keys)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.read().unwrap().iter().any(|known| known == key)
+    }
+
+    /// The key to send the next request with.
+    pub fn pick(&self) -> String {
+        self.reload_if_changed();
+        let keys = self.keys.read().unwrap();
+        let benched = self.benched.lock().unwrap();
+        let now = Instant::now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut turns = (0..keys.len()).map(|offset| &keys[(start + offset) % keys.len()]);
+        let first = turns.clone().next();
+        turns
+            .find(|key| benched.get(*key).is_none_or(|until| *until <= now))
+            // when every key's been throttled, they're still taken in turn
+            .or(first)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Count Semantic Scholar's answer to a request sent with `key`.
+    pub fn record(&self, key: &str, status: Status) {
+        if status == Status::TooManyRequests {
+            self.benched
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), Instant::now() + BENCH);
+        }
+    }
+
+    /// Read the keys file again if it's changed since it was last read,
+    /// checking at most every `RELOAD_PERIOD`.
+    fn reload_if_changed(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut modified = file.modified.lock().unwrap();
+        if modified.1.elapsed() < RELOAD_PERIOD {
+            return;
+        }
+        modified.1 = Instant::now();
+        let now_modified = std::fs::metadata(&file.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if now_modified == modified.0 {
+            return;
+        }
+        // a bad edit keeps the keys there were
+        match read(&file.path) {
+            Ok((keys, now_modified)) => {
+                eprintln!("read {} API keys from {}", keys.len(), file.path.display());
+                *self.keys.write().unwrap() = keys;
+                modified.0 = now_modified;
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttled_keys_are_passed_over() {
+        let keys = Keys::new(parse("# ours\na\n\nb\n c \n"));
+        let picked: Vec<String> = (0..4).map(|_turn| keys.pick()).collect();
+        assert_eq!(picked, ["a", "b", "c", "a"]);
+
+        keys.record("c", Status::TooManyRequests);
+        keys.record("a", Status::Ok);
+        let picked: Vec<String> = (0..3).map(|_turn| keys.pick()).collect();
+        assert_eq!(picked, ["b", "a", "a"]);
+        assert!(keys.contains("c"));
+        assert!(!keys.contains("d"));
+    }
+}
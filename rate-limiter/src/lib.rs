@@ -0,0 +1,1083 @@
+//! My API key for Semantic Scholar has certain rate limits.  In order
+//! to enforce these limits and to not share my secret key, I need to
+//! have this server between the client and Semantic Scholar to enforce
+//! those rate limits.
+//!
+//! The rate limits are
+//! - 1 request/sec for /paper/batch, /paper/search, /recommendations,
+//! - 10 request/sec for everything else.
+//!
+//! These and the rest of the [`settings`] can be changed in `Rocket.toml`,
+//! where a certificate and key can also be given to serve HTTPS.
+//!
+//! At most `queue_depth` requests, 64 by default, wait on each limit.
+//! Past that, requests are answered 503 with a `Retry-After` for when the
+//! queue should have room.
+//!
+//! All of them are proxied: /paper/batch, /paper/search, /paper/{id},
+//! /paper/{id}/references, /paper/{id}/citations, /author/*, and
+//! /recommendations.
+//!
+//! Batch requests that arrive while another waits on the rate limit are
+//! merged into it and sent upstream as one; see [`batcher`].
+//!
+//! Successful responses are passed on as they arrive, and cached for a
+//! day once they have, so the same request made again doesn't wait on or
+//! spend the rate limit.  `DELETE /admin/cache`
+//! with the proxy's key as `x-api-key` flushes the cache.
+//!
+//! `GET /metrics` reports requests, upstream latency and statuses, waits
+//! on the rate limits, and cache hits for Prometheus.
+//!
+//! When Semantic Scholar answers with errors or 429s again and again,
+//! requests are answered 503 with a `Retry-After` until it recovers,
+//! rather than adding to the storm; see [`breaker`].
+//!
+//! On SIGTERM or ctrl-C, requests waiting on a limit are answered 503 so
+//! their clients retry after the restart, those already sent upstream are
+//! given Rocket's `shutdown.grace` to finish, and users' quotas and how
+//! much of each rate limit is left are saved to `state_file`, for the
+//! proxy to pick up from when it starts again.
+//!
+//! Each request is logged with its route, client, latency, and status,
+//! which for proxied requests is Semantic Scholar's, as text or, with
+//! `--log-format json` or the `log_format` setting, as JSON.
+//!
+//! Browser pages on the origins in `allowed_origins` may call the proxy
+//! directly; see [`cors`].
+//!
+//! To go faster than one key allows, give the proxy several; see
+//! [`keys`].
+//!
+//! To share the proxy, give each user a token in the file named by
+//! `TOKENS_FILE`; see [`tokens`].
+
+#[macro_use]
+extern crate rocket;
+
+pub mod access_log;
+pub mod batcher;
+pub mod breaker;
+mod cache;
+pub mod cors;
+pub mod keys;
+mod metrics;
+mod queue;
+pub mod settings;
+pub mod tokens;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rocket::{
+    fairing::AdHoc,
+    futures::stream::{self, BoxStream, StreamExt},
+    http::{
+        uri::{fmt, Origin, Segments},
+        ContentType, Header, Status, StatusClass,
+    },
+    request::{FromParam, FromRequest, Outcome, Request},
+    response::{self, content::RawJson, stream::ReaderStream, Responder, Response},
+    serde::{json::Json, Deserialize, Serialize},
+    Build, Rocket, State,
+};
+
+use crate::access_log::LogFormat;
+use crate::batcher::Batcher;
+use crate::breaker::Breaker;
+use crate::cache::{Key, ResponseCache};
+use crate::cors::Cors;
+use crate::keys::Keys;
+use crate::metrics::{Counters, Histogram};
+use crate::queue::{Limiter, Refused};
+use crate::settings::Settings;
+use crate::tokens::{Authorized, RequestUser, Tokens};
+use endpoints::{
+    BatchRequest, RecommendationsRequest, AUTHOR, PAPER, PAPER_BATCH, PAPER_SEARCH,
+    PAPER_SEARCH_MATCH, RECOMMENDATIONS,
+};
+
+const ENV_API_KEY: &str = "API_KEY";
+pub(crate) const HEADER_API_KEY: &str = "x-api-key";
+
+pub const SEMANTIC_SCHOLAR_BASE_URI: &str = "https://api.semanticscholar.org";
+/// How many responses are cached, and for how long; Semantic Scholar
+/// updates papers about weekly.
+const CACHE_CAPACITY: usize = 10_000;
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Where the proxy's own endpoints are mounted.
+pub const ADMIN: &str = "/admin";
+
+/// The limiter for endpoints outside the 1 req/s group.
+struct GeneralLimiter(Limiter);
+
+/// Where requests are forwarded to, normally [`SEMANTIC_SCHOLAR_BASE_URI`],
+/// and how it's been answering.
+struct Upstream {
+    uri: String,
+    latency: Histogram,
+    /// By status code, to watch for 429s.
+    responses: Counters,
+    breaker: Breaker,
+}
+
+impl Upstream {
+    fn record(&self, start: Instant, status: Status) {
+        self.latency.observe(start.elapsed());
+        self.responses
+            .increment(format!("status=\"{}\"", status.code));
+        self.breaker.record(
+            status == Status::TooManyRequests || status.class() == StatusClass::ServerError,
+        );
+    }
+
+    /// Count a request that was never answered.
+    fn unanswered(&self, err: reqwest::Error) -> reqwest::Error {
+        self.breaker.record(true);
+        err
+    }
+}
+
+/// Where quotas and rate limit levels are saved on shutdown.
+struct StateFile(PathBuf);
+
+/// Requests to the proxy, by route and status.
+#[derive(Default)]
+struct Requests(Counters);
+
+/// Which way along the citation graph `/paper/{id}/...` looks.
+enum Relation {
+    References,
+    Citations,
+}
+
+impl<'a> rocket::request::FromParam<'a> for Relation {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        match param {
+            "references" => Ok(Relation::References),
+            "citations" => Ok(Relation::Citations),
+            other => Err(other),
+        }
+    }
+}
+
+impl Relation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Relation::References => "references",
+            Relation::Citations => "citations",
+        }
+    }
+}
+
+pub struct ApiKeyMissing {}
+
+impl std::fmt::Debug for ApiKeyMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "you must set {ENV_API_KEY}=<semantic-scholar-api-key>")
+    }
+}
+
+impl std::fmt::Display for ApiKeyMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ApiKeyMissing {}
+
+async fn s2_response(
+    upstream: &Upstream,
+    path: &str,
+    query: &[(&str, &str)],
+    body: &impl Serialize,
+    keys: &Keys,
+    client: &reqwest::Client,
+) -> reqwest::Result<(Status, Body)> {
+    let api_key = keys.pick();
+    let start = Instant::now();
+    let response = client
+        .post(format!("{}{}", upstream.uri, path))
+        .header(HEADER_API_KEY, &api_key)
+        .query(query)
+        .json(body)
+        .send()
+        .await
+        .map_err(|err| upstream.unanswered(err))?;
+    let status_code = Status::new(response.status().as_u16());
+    upstream.record(start, status_code);
+    keys.record(&api_key, status_code);
+    if matches!(
+        status_code.class(),
+        StatusClass::ClientError | StatusClass::ServerError
+    ) {
+        // no point in awaiting an invalid body
+        return Ok((status_code, Body::Whole(String::new())));
+    }
+    Ok((status_code, Body::Arriving(response)))
+}
+
+async fn s2_get_response(
+    upstream: &Upstream,
+    path: &str,
+    query: &[(&str, &str)],
+    keys: &Keys,
+    client: &reqwest::Client,
+) -> reqwest::Result<(Status, Body)> {
+    let api_key = keys.pick();
+    let start = Instant::now();
+    let response = client
+        .get(format!("{}{}", upstream.uri, path))
+        .header(HEADER_API_KEY, &api_key)
+        .query(query)
+        .send()
+        .await
+        .map_err(|err| upstream.unanswered(err))?;
+    let status_code = Status::new(response.status().as_u16());
+    upstream.record(start, status_code);
+    keys.record(&api_key, status_code);
+    if matches!(
+        status_code.class(),
+        StatusClass::ClientError | StatusClass::ServerError
+    ) {
+        return Ok((status_code, Body::Whole(String::new())));
+    }
+    Ok((status_code, Body::Arriving(response)))
+}
+
+/// Pass an upstream response on, errors included.
+fn relay(response: reqwest::Result<(Status, Body)>) -> Result<Body, Refused> {
+    match response {
+        Err(err) => {
+            eprintln!("response error: {err:?}");
+            Err(Status::InternalServerError.into())
+        }
+        Ok((status, _body))
+            if matches!(
+                status.class(),
+                StatusClass::ClientError | StatusClass::ServerError
+            ) =>
+        {
+            Err(status.into())
+        }
+        Ok((_status, body)) => Ok(body),
+    }
+}
+
+/// An upstream response's body: whole, as it was cached or put together
+/// here, or still arriving, to be passed on as it does.
+pub enum Body {
+    Whole(String),
+    Arriving(reqwest::Response),
+}
+
+impl Body {
+    /// The body, waiting for all of it.
+    pub async fn text(self) -> reqwest::Result<String> {
+        match self {
+            Body::Whole(text) => Ok(text),
+            Body::Arriving(response) => response.text().await,
+        }
+    }
+
+    /// The body for a client, kept in `cache` as `key` once it's all
+    /// arrived.  A body cut short is passed on as far as it got, but not
+    /// kept.
+    fn sent(self, cache: Arc<ResponseCache>, key: Key) -> Sent {
+        let response = match self {
+            Body::Whole(text) => {
+                cache.put(key, text.clone());
+                return Sent::Whole(RawJson(text));
+            }
+            Body::Arriving(response) => response,
+        };
+        let chunks = stream::unfold(
+            Some((response, Vec::new(), cache, key)),
+            |arriving| async move {
+                let (mut response, mut whole, cache, key) = arriving?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        whole.extend_from_slice(&chunk);
+                        Some((chunk.to_vec(), Some((response, whole, cache, key))))
+                    }
+                    Ok(None) => {
+                        match String::from_utf8(whole) {
+                            Ok(text) => cache.put(key, text),
+                            Err(err) => eprintln!("couldn't cache a response: {err}"),
+                        }
+                        None
+                    }
+                    Err(err) => {
+                        eprintln!("response cut short: {err:?}");
+                        None
+                    }
+                }
+            },
+        );
+        Sent::Streamed(chunks.boxed())
+    }
+}
+
+/// A body as it's sent to a client.
+enum Sent {
+    Whole(RawJson<String>),
+    Streamed(BoxStream<'static, Vec<u8>>),
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Sent {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Sent::Whole(body) => body.respond_to(request),
+            Sent::Streamed(chunks) => Response::build()
+                .header(ContentType::JSON)
+                .streamed_body(ReaderStream::from(chunks.map(std::io::Cursor::new)))
+                .ok(),
+        }
+    }
+}
+
+/// A response, with how long clients may reuse it.
+#[derive(Responder)]
+struct Cached {
+    body: Sent,
+    cache_control: Header<'static>,
+    /// `hit` or `miss`.
+    x_cache: Header<'static>,
+}
+
+const HEADER_X_CACHE: &str = "X-Cache";
+
+impl Cached {
+    fn new(body: Sent, max_age: Duration, hit: bool) -> Self {
+        Cached {
+            body,
+            cache_control: Header::new("Cache-Control", format!("max-age={}", max_age.as_secs())),
+            x_cache: Header::new(HEADER_X_CACHE, if hit { "hit" } else { "miss" }),
+        }
+    }
+}
+
+/// When a request arrived, for logging its latency.
+struct Arrived(Instant);
+
+/// Answer from the cache if it has `key`, or else, unless the breaker's
+/// open, `fetch` and pass on a successful response as it arrives,
+/// keeping it once it has.  Cached answers skip
+/// the rate limit, which `fetch` is expected to wait on itself.
+async fn through_cache(
+    cache: &Arc<ResponseCache>,
+    upstream: &Upstream,
+    key: Key,
+    fetch: impl Future<Output = Result<Body, Refused>>,
+) -> Result<Cached, Refused> {
+    if let Some((body, max_age)) = cache.get(&key) {
+        return Ok(Cached::new(Sent::Whole(RawJson(body)), max_age, true));
+    }
+    upstream.breaker.check()?;
+    let body = fetch.await?;
+    Ok(Cached::new(
+        body.sent(cache.clone(), key),
+        cache.ttl(),
+        false,
+    ))
+}
+
+/// The query string of `uri` as pairs, to forward it whole.
+fn query_pairs<'a>(uri: &'a Origin<'_>) -> Vec<(&'a str, &'a str)> {
+    uri.query()
+        .map(|query| query.segments().collect())
+        .unwrap_or_default()
+}
+
+// This will be offset to PAPER_SEARCH when mounted
+#[get("/?<query>&<fields>&<offset>&<limit>&<year>")]
+#[allow(clippy::too_many_arguments)]
+async fn paper_search(
+    _authorized: Authorized,
+    query: &'_ str,
+    fields: Option<&'_ str>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    year: Option<&'_ str>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<Limiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let offset = offset.unwrap_or_default().to_string();
+    let limit = limit.unwrap_or(100).to_string();
+    let mut params = vec![
+        ("query", query),
+        ("offset", offset.as_str()),
+        ("limit", limit.as_str()),
+    ];
+    params.extend(fields.map(|fields| ("fields", fields)));
+    params.extend(year.map(|year| ("year", year)));
+    let key = Key::new(PAPER_SEARCH, &params, "");
+    through_cache(cache, upstream, key, async {
+        limiter.acquire().await?;
+        relay(
+            s2_get_response(
+                upstream,
+                PAPER_SEARCH,
+                &params,
+                keys.inner(),
+                client.inner(),
+            )
+            .await,
+        )
+    })
+    .await
+}
+
+// This will be offset to PAPER_SEARCH_MATCH when mounted
+#[get("/?<query>&<fields>&<year>")]
+#[allow(clippy::too_many_arguments)]
+async fn paper_search_match(
+    _authorized: Authorized,
+    query: &'_ str,
+    fields: Option<&'_ str>,
+    year: Option<&'_ str>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<Limiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let mut params = vec![("query", query)];
+    params.extend(fields.map(|fields| ("fields", fields)));
+    params.extend(year.map(|year| ("year", year)));
+    let key = Key::new(PAPER_SEARCH_MATCH, &params, "");
+    through_cache(cache, upstream, key, async {
+        limiter.acquire().await?;
+        relay(
+            s2_get_response(
+                upstream,
+                PAPER_SEARCH_MATCH,
+                &params,
+                keys.inner(),
+                client.inner(),
+            )
+            .await,
+        )
+    })
+    .await
+}
+
+// This will be offset to PAPER when mounted.  Ids can have slashes in
+// them, as DOIs do, so the path is taken whole, and a last segment of
+// `references` or `citations` asks for those rather than the paper.
+#[get("/<path..>?<fields>&<offset>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+async fn paper(
+    _authorized: Authorized,
+    path: Segments<'_, fmt::Path>,
+    fields: Option<&'_ str>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<GeneralLimiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let mut segments: Vec<&str> = path.collect();
+    let relation = match segments.split_last() {
+        Some((last, paper_id)) if !paper_id.is_empty() => Relation::from_param(last).ok(),
+        _ => None,
+    };
+    if relation.is_some() {
+        segments.pop();
+    }
+    if segments.is_empty() {
+        return Err(Status::NotFound.into());
+    }
+    let paper_id = segments.join("/");
+    let offset = offset.unwrap_or_default().to_string();
+    let limit = limit.unwrap_or(100).to_string();
+    let mut query: Vec<_> = fields
+        .map(|fields| ("fields", fields))
+        .into_iter()
+        .collect();
+    let path = match relation {
+        Some(relation) => {
+            query.extend([("offset", offset.as_str()), ("limit", limit.as_str())]);
+            format!("{PAPER}/{paper_id}/{}", relation.as_str())
+        }
+        None => format!("{PAPER}/{paper_id}"),
+    };
+    through_cache(cache, upstream, Key::new(&path, &query, ""), async {
+        limiter.0.acquire().await?;
+        relay(s2_get_response(upstream, &path, &query, keys.inner(), client.inner()).await)
+    })
+    .await
+}
+
+// This will be offset to AUTHOR when mounted.  Author lookups take
+// assorted parameters, so the query string is forwarded as it is.
+#[get("/<path..>")]
+#[allow(clippy::too_many_arguments)]
+async fn author(
+    _authorized: Authorized,
+    path: std::path::PathBuf,
+    uri: &Origin<'_>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<GeneralLimiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let path = format!("{AUTHOR}/{}", path.display());
+    let query = query_pairs(uri);
+    through_cache(cache, upstream, Key::new(&path, &query, ""), async {
+        limiter.0.acquire().await?;
+        relay(s2_get_response(upstream, &path, &query, keys.inner(), client.inner()).await)
+    })
+    .await
+}
+
+// This will be offset to AUTHOR when mounted
+#[post("/batch", data = "<ids>")]
+#[allow(clippy::too_many_arguments)]
+async fn author_batch(
+    _authorized: Authorized,
+    ids: Json<BatchRequest>,
+    uri: &Origin<'_>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<GeneralLimiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let path = format!("{AUTHOR}/batch");
+    let query = query_pairs(uri);
+    let ids = ids.into_inner();
+    let key = Key::new(&path, &query, &ids.ids.join("\n"));
+    through_cache(cache, upstream, key, async {
+        limiter.0.acquire().await?;
+        relay(s2_response(upstream, &path, &query, &ids, keys.inner(), client.inner()).await)
+    })
+    .await
+}
+
+// This will be offset to RECOMMENDATIONS when mounted
+#[post("/?<fields>&<limit>", data = "<papers>")]
+#[allow(clippy::too_many_arguments)]
+async fn recommendations(
+    _authorized: Authorized,
+    fields: Option<&'_ str>,
+    limit: Option<usize>,
+    papers: Json<RecommendationsRequest>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<Limiter>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let limit = limit.unwrap_or(100).to_string();
+    let mut query: Vec<_> = fields
+        .map(|fields| ("fields", fields))
+        .into_iter()
+        .collect();
+    query.push(("limit", limit.as_str()));
+    let papers = papers.into_inner();
+    let key = Key::new(
+        RECOMMENDATIONS,
+        &query,
+        &format!(
+            "{}\n\n{}",
+            papers.positive_paper_ids.join("\n"),
+            papers.negative_paper_ids.join("\n")
+        ),
+    );
+    through_cache(cache, upstream, key, async {
+        limiter.acquire().await?;
+        relay(
+            s2_response(
+                upstream,
+                RECOMMENDATIONS,
+                &query,
+                &papers,
+                keys.inner(),
+                client.inner(),
+            )
+            .await,
+        )
+    })
+    .await
+}
+
+/// Get a batch of papers, trying again while Semantic Scholar is busy.
+async fn fetch_batch(
+    upstream: &Upstream,
+    fields: &str,
+    ids: BatchRequest,
+    keys: &Keys,
+    client: &reqwest::Client,
+) -> Result<Body, Refused> {
+    let max_tries = 10;
+    let mut tries = 0;
+    while tries < max_tries {
+        match s2_response(
+            upstream,
+            PAPER_BATCH,
+            &[("fields", fields)],
+            &ids,
+            keys,
+            client,
+        )
+        .await
+        {
+            Err(err) => {
+                eprintln!("response error: {err:?}");
+                return Err(Status::InternalServerError.into());
+            }
+            Ok((status, body)) => match status {
+                // Status::Constant can't be a pattern because it has a
+                // manual impl ParitalEq, instead of #[derive].
+                status
+                    if (status == Status::TooManyRequests || status == Status::GatewayTimeout) =>
+                {
+                    // try again, unless that's tripped the breaker
+                    if let Some(retry_after) = upstream.breaker.open_for() {
+                        return Err(Refused::Tripped(retry_after));
+                    }
+                }
+                status
+                    if matches!(
+                        status.class(),
+                        StatusClass::ClientError | StatusClass::ServerError
+                    ) =>
+                {
+                    return Err(status.into())
+                }
+                _ => return Ok(body),
+            },
+        }
+        tries += 1;
+    }
+    Err(Status::GatewayTimeout.into())
+}
+
+// This will be offset to PAPER_BATCH when mounted
+#[post("/?<fields>", data = "<ids>")]
+#[allow(clippy::too_many_arguments)]
+async fn paper_batch(
+    _authorized: Authorized,
+    fields: &'_ str,
+    ids: Json<BatchRequest>,
+    keys: &State<Arc<Keys>>,
+    limiter: &State<Limiter>,
+    batcher: &State<Batcher>,
+    client: &State<reqwest::Client>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<Cached, Refused> {
+    let ids = ids.into_inner();
+    let key = Key::new(PAPER_BATCH, &[("fields", fields)], &ids.ids.join("\n"));
+    through_cache(cache, upstream, key, async {
+        // the fetch is a task of its own, outliving this request
+        let (upstream, keys, client) = (
+            upstream.inner().clone(),
+            keys.inner().clone(),
+            client.inner().clone(),
+        );
+        let owned_fields = fields.to_string();
+        let fetch = |ids| async move {
+            fetch_batch(
+                &upstream,
+                &owned_fields,
+                BatchRequest { ids },
+                &keys,
+                &client,
+            )
+            .await
+        };
+        batcher
+            .fetch(fields, ids.ids, limiter.acquire(), fetch)
+            .await
+    })
+    .await
+}
+
+/// Forget every cached response.  Only whoever holds one of the proxy's
+/// own Semantic Scholar keys, given as `x-api-key`, may.
+#[delete("/cache")]
+fn flush_cache(
+    given_key: AdminKey<'_>,
+    keys: &State<Arc<Keys>>,
+    cache: &State<Arc<ResponseCache>>,
+) -> Result<String, Status> {
+    if !keys.contains(given_key.0) {
+        return Err(Status::Forbidden);
+    }
+    Ok(format!("flushed {} responses\n", cache.flush()))
+}
+
+/// Answer a browser's preflight check; the headers that allow the
+/// request it's checking for are added with everything else's.
+#[options("/<_path..>")]
+fn preflight(_path: PathBuf) -> Status {
+    Status::NoContent
+}
+
+/// How the proxy is doing, for Prometheus.
+#[get("/metrics")]
+fn report_metrics(
+    requests: &State<Requests>,
+    upstream: &State<Arc<Upstream>>,
+    cache: &State<Arc<ResponseCache>>,
+    limiter: &State<Limiter>,
+    general_limiter: &State<GeneralLimiter>,
+    batcher: &State<Batcher>,
+) -> String {
+    let mut out = String::new();
+    let requests_total = "proxy_requests_total";
+    metrics::describe(
+        &mut out,
+        requests_total,
+        "counter",
+        "Requests to the proxy.",
+    );
+    requests.0.write(&mut out, requests_total);
+
+    let latency = "upstream_request_duration_seconds";
+    metrics::describe(
+        &mut out,
+        latency,
+        "histogram",
+        "How long Semantic Scholar took to answer.",
+    );
+    upstream.latency.write(&mut out, latency, "");
+    let responses = "upstream_responses_total";
+    metrics::describe(
+        &mut out,
+        responses,
+        "counter",
+        "Responses from Semantic Scholar, by status; 429s mean its limit was hit.",
+    );
+    upstream.responses.write(&mut out, responses);
+    let trips = "circuit_breaker_trips_total";
+    metrics::describe(
+        &mut out,
+        trips,
+        "counter",
+        "Times Semantic Scholar failed often enough to stop sending it requests.",
+    );
+    let count = upstream.breaker.trips.load(Ordering::Relaxed);
+    out.push_str(&format!("{trips} {count}\n"));
+
+    let wait = "rate_limit_wait_seconds";
+    metrics::describe(
+        &mut out,
+        wait,
+        "histogram",
+        "How long requests waited on the rate limit.",
+    );
+    limiter.waits.write(&mut out, wait, "limit=\"1/s\"");
+    general_limiter
+        .0
+        .waits
+        .write(&mut out, wait, "limit=\"10/s\"");
+    let refused = "rate_limit_refused_total";
+    metrics::describe(
+        &mut out,
+        refused,
+        "counter",
+        "Requests turned away by a full queue.",
+    );
+    for (label, limiter) in [("1/s", &**limiter), ("10/s", &general_limiter.0)] {
+        let count = limiter.refused.load(Ordering::Relaxed);
+        out.push_str(&format!("{refused}{{limit=\"{label}\"}} {count}\n"));
+    }
+    let merged = "batch_requests_merged_total";
+    metrics::describe(
+        &mut out,
+        merged,
+        "counter",
+        "Batch requests sent upstream as part of another's.",
+    );
+    let count = batcher.merged.load(Ordering::Relaxed);
+    out.push_str(&format!("{merged} {count}\n"));
+
+    let lookups = "cache_lookups_total";
+    metrics::describe(
+        &mut out,
+        lookups,
+        "counter",
+        "Lookups in the response cache, by whether they hit.",
+    );
+    let hits = cache.hits.load(Ordering::Relaxed);
+    let misses = cache.misses.load(Ordering::Relaxed);
+    out.push_str(&format!("{lookups}{{result=\"hit\"}} {hits}\n"));
+    out.push_str(&format!("{lookups}{{result=\"miss\"}} {misses}\n"));
+    out
+}
+
+/// The `x-api-key` a request was made with.
+struct AdminKey<'r>(&'r str);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one(HEADER_API_KEY) {
+            Some(key) => Outcome::Success(AdminKey(key)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Get the Semantic Scholar API key from the environment.
+pub fn api_key_from_env() -> Result<String, ApiKeyMissing> {
+    match std::env::var(ENV_API_KEY) {
+        Ok(empty) if empty == *"" => Err(ApiKeyMissing {}),
+        Ok(key) => Ok(key),
+        Err(_) => Err(ApiKeyMissing {}),
+    }
+}
+
+/// The Semantic Scholar API keys in the file named by
+/// [`keys::ENV_API_KEYS`] if there is one, or else the one key in
+/// `API_KEY`.
+pub fn keys_from_env() -> Result<Keys, Box<dyn std::error::Error>> {
+    match std::env::var_os(keys::ENV_API_KEYS) {
+        Some(path) => Ok(Keys::load(path.as_ref())?),
+        None => Ok(Keys::new(vec![api_key_from_env()?])),
+    }
+}
+
+/// Read the tokens file named by [`tokens::ENV_TOKENS`], if there is one.
+pub fn tokens_from_env() -> Result<Option<Tokens>, tokens::Error> {
+    match std::env::var_os(tokens::ENV_TOKENS) {
+        Some(path) => Tokens::load(path.as_ref()).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// What's kept in the state file over a restart.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Saved {
+    /// Each user's quota spent, and on which day.
+    usage: BTreeMap<String, (u64, u64)>,
+    limits: Option<Levels>,
+}
+
+/// How many turns each rate limit had left when it was saved.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Levels {
+    /// When, in milliseconds since the Unix epoch.
+    saved_ms: u64,
+    batch: usize,
+    general: usize,
+}
+
+impl Levels {
+    /// How long ago the levels were saved.
+    fn age(&self) -> Duration {
+        let saved = UNIX_EPOCH + Duration::from_millis(self.saved_ms);
+        SystemTime::now()
+            .duration_since(saved)
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Read what the last run saved to `path`, if it's there.
+fn restore_state(path: &Path) -> Saved {
+    let state = match std::fs::read_to_string(path) {
+        Ok(state) => state,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Saved::default(),
+        Err(err) => {
+            eprintln!("couldn't read {}: {err}", path.display());
+            return Saved::default();
+        }
+    };
+    rocket::serde::json::from_str(&state).unwrap_or_else(|err| {
+        eprintln!("couldn't read {}: {err}", path.display());
+        Saved::default()
+    })
+}
+
+fn save_state(saved: &Saved, path: &Path) {
+    let result = rocket::serde::json::to_string(saved)
+        .map_err(std::io::Error::other)
+        .and_then(|state| std::fs::write(path, state));
+    if let Err(err) = result {
+        eprintln!("couldn't save {}: {err}", path.display());
+    }
+}
+
+/// `limiter` at the level it was saved at, if it was.
+fn resume(limiter: Limiter, levels: &Option<Levels>, level: fn(&Levels) -> usize) -> Limiter {
+    match levels {
+        Some(levels) => limiter.resume(level(levels), levels.age()),
+        None => limiter,
+    }
+}
+
+/// The proxy, forwarding with `keys`, and open only to holders of
+/// `tokens` if there are any.  The rest of its [`Settings`] are read from
+/// its configuration when it's ignited.
+pub fn build(keys: Keys, tokens: Option<Tokens>) -> Rocket<Build> {
+    let request_client = reqwest::Client::new();
+    rocket::build()
+        .manage(Arc::new(keys))
+        .attach(AdHoc::try_on_ignite("Settings", |rocket| async {
+            let settings: Settings = match rocket.figment().extract() {
+                Ok(settings) => settings,
+                Err(err) => {
+                    eprintln!("invalid settings: {err}");
+                    return Err(rocket);
+                }
+            };
+            if let Err(problem) = settings.check() {
+                eprintln!("invalid settings: {problem}");
+                return Err(rocket);
+            }
+            let saved = settings
+                .state_file
+                .as_deref()
+                .map(restore_state)
+                .unwrap_or_default();
+            if let Some(Some(tokens)) = rocket.state::<Option<Tokens>>() {
+                tokens.restore(&saved.usage);
+            }
+            // the limits are each key's
+            let key_count = rocket
+                .state::<Arc<Keys>>()
+                .map_or(1, |keys| keys.len())
+                .max(1);
+            let limiter = Limiter::new(
+                settings.rate_limit_period(),
+                settings.rate_limit_count * key_count,
+                settings.queue_depth,
+            );
+            let general_limiter = Limiter::new(
+                settings.general_rate_limit_period(),
+                settings.general_rate_limit_count * key_count,
+                settings.queue_depth,
+            );
+            Ok(rocket
+                .manage(settings.log_format)
+                .manage(Cors::new(settings.allowed_origins.clone()))
+                .manage(settings.state_file.clone().map(StateFile))
+                .manage(resume(limiter, &saved.limits, |levels| levels.batch))
+                .manage(GeneralLimiter(resume(
+                    general_limiter,
+                    &saved.limits,
+                    |levels| levels.general,
+                )))
+                .manage(Arc::new(Upstream {
+                    breaker: Breaker::new(settings.breaker_threshold, settings.breaker_cooldown()),
+                    uri: settings.upstream,
+                    latency: Histogram::default(),
+                    responses: Counters::default(),
+                })))
+        }))
+        .attach(AdHoc::on_shutdown("Drain", |rocket| {
+            Box::pin(async move {
+                // requests already sent upstream are left to finish
+                // within Rocket's grace period
+                if let Some(limiter) = rocket.state::<Limiter>() {
+                    limiter.close();
+                }
+                if let Some(limiter) = rocket.state::<GeneralLimiter>() {
+                    limiter.0.close();
+                }
+                if let Some(Some(StateFile(path))) = rocket.state::<Option<StateFile>>() {
+                    let saved = Saved {
+                        usage: match rocket.state::<Option<Tokens>>() {
+                            Some(Some(tokens)) => tokens.usage(),
+                            _ => BTreeMap::new(),
+                        },
+                        limits: match (rocket.state::<Limiter>(), rocket.state::<GeneralLimiter>())
+                        {
+                            (Some(limiter), Some(general_limiter)) => Some(Levels {
+                                saved_ms: SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .map_or(0, |since| since.as_millis() as u64),
+                                batch: limiter.level(),
+                                general: general_limiter.0.level(),
+                            }),
+                            _ => None,
+                        },
+                    };
+                    save_state(&saved, path);
+                }
+            })
+        }))
+        .manage(Requests::default())
+        .attach(AdHoc::on_request("Arrival", |request, _data| {
+            Box::pin(async move {
+                request.local_cache(|| Arrived(Instant::now()));
+            })
+        }))
+        .attach(AdHoc::on_response("Request counts", |request, response| {
+            Box::pin(async move {
+                let route = request
+                    .route()
+                    .and_then(|route| route.name.as_deref())
+                    .unwrap_or("none");
+                if let Some(requests) = request.rocket().state::<Requests>() {
+                    requests.0.increment(format!(
+                        "route=\"{route}\",status=\"{}\"",
+                        response.status().code
+                    ));
+                }
+            })
+        }))
+        .attach(AdHoc::on_response("CORS", |request, response| {
+            Box::pin(async move {
+                if let Some(cors) = request.rocket().state::<Cors>() {
+                    cors.apply(request, response);
+                }
+            })
+        }))
+        .attach(AdHoc::on_response("Access log", |request, response| {
+            Box::pin(async move {
+                let format = request
+                    .rocket()
+                    .state::<LogFormat>()
+                    .copied()
+                    .unwrap_or_default();
+                let user = &request.local_cache(|| RequestUser(String::new())).0;
+                let address = request
+                    .client_ip()
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| "-".into());
+                let entry = access_log::Entry {
+                    method: request.method().as_str(),
+                    path: request.uri().path().as_str(),
+                    query: request.uri().query().map(|query| query.as_str()),
+                    route: request.route().and_then(|route| route.name.as_deref()),
+                    client: if user.is_empty() { &address } else { user },
+                    status: response.status().code,
+                    latency: request.local_cache(|| Arrived(Instant::now())).0.elapsed(),
+                    cache: response.headers().get_one(HEADER_X_CACHE),
+                };
+                println!("{}", entry.format(format));
+            })
+        }))
+        .manage(tokens)
+        .manage(request_client)
+        .manage(Batcher::default())
+        .manage(Arc::new(ResponseCache::new(CACHE_CAPACITY, CACHE_TTL)))
+        .mount(PAPER_BATCH, routes![paper_batch])
+        .mount(PAPER_SEARCH, routes![paper_search])
+        .mount(PAPER_SEARCH_MATCH, routes![paper_search_match])
+        .mount(PAPER, routes![paper])
+        .mount(AUTHOR, routes![author, author_batch])
+        .mount(RECOMMENDATIONS, routes![recommendations])
+        .mount(ADMIN, routes![flush_cache])
+        .mount("/", routes![report_metrics, preflight])
+}
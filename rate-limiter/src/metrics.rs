@@ -0,0 +1,118 @@
+//! Counters and histograms for `/metrics`, written in Prometheus's text
+//! format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the histogram buckets.  Waits on the
+/// rate limit can run long when the queue is deep.
+const BUCKETS: [f64; 13] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// How long things took.
+pub struct Histogram {
+    counts: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    /// The total in microseconds.
+    sum: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: Default::default(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bound, count) in BUCKETS.iter().zip(&self.counts) {
+            if seconds <= *bound {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Write the histogram as `name`, with `labels` like `limit="1/s"`
+    /// or nothing.
+    pub fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in BUCKETS.iter().zip(&self.counts) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {}",
+                count.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+/// Counts of things, by the labels they're counted under.
+#[derive(Default)]
+pub struct Counters(Mutex<BTreeMap<String, u64>>);
+
+impl Counters {
+    /// Count one more of `labels`, like `endpoint="paper_batch"`.
+    pub fn increment(&self, labels: String) {
+        *self.0.lock().unwrap().entry(labels).or_default() += 1;
+    }
+
+    pub fn write(&self, out: &mut String, name: &str) {
+        for (labels, count) in self.0.lock().unwrap().iter() {
+            let _ = writeln!(out, "{name}{{{labels}}} {count}");
+        }
+    }
+}
+
+/// Write a metric's help and type lines.
+pub fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(2));
+        let mut out = String::new();
+        histogram.write(&mut out, "wait_seconds", "limit=\"1/s\"");
+
+        assert!(out.contains("wait_seconds_bucket{limit=\"1/s\",le=\"0.01\"} 0\n"));
+        assert!(out.contains("wait_seconds_bucket{limit=\"1/s\",le=\"0.025\"} 1\n"));
+        assert!(out.contains("wait_seconds_bucket{limit=\"1/s\",le=\"2.5\"} 2\n"));
+        assert!(out.contains("wait_seconds_bucket{limit=\"1/s\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("wait_seconds_sum{limit=\"1/s\"} 2.02\n"));
+        assert!(out.contains("wait_seconds_count{limit=\"1/s\"} 2\n"));
+
+        let counters = Counters::default();
+        counters.increment("status=\"429\"".into());
+        counters.increment("status=\"429\"".into());
+        let mut out = String::new();
+        counters.write(&mut out, "upstream_responses_total");
+        assert_eq!(out, "upstream_responses_total{status=\"429\"} 2\n");
+    }
+}
@@ -0,0 +1,221 @@
+//! A bound on how many requests can wait for the rate limit, so a crowd
+//! of clients is told to come back later instead of waiting without end.
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use endpoints::ErrorEnvelope;
+use leaky_bucket::RateLimiter;
+use rocket::futures::future::{self, Either};
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::sync::Notify;
+
+use crate::metrics::Histogram;
+
+/// How many requests may wait on each rate limit unless set otherwise.
+pub const DEFAULT_DEPTH: usize = 64;
+/// When clients turned away by a shutdown should try again, which is
+/// about how long a restart takes.
+const RESTART_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Why a request wasn't answered.
+#[derive(Debug, Clone)]
+pub enum Refused {
+    Status(Status),
+    /// The queue was full; try again after this long.
+    Full(Duration),
+    /// The proxy is shutting down.
+    ShuttingDown,
+    /// Semantic Scholar has been failing; try again after this long.
+    Tripped(Duration),
+}
+
+impl From<Status> for Refused {
+    fn from(status: Status) -> Self {
+        Refused::Status(status)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Refused {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            Refused::Status(status) => status.respond_to(request),
+            Refused::Full(retry_after) => unavailable(
+                "too many requests are waiting; try again later",
+                retry_after,
+            ),
+            Refused::ShuttingDown => unavailable(
+                "the proxy is restarting; try again shortly",
+                RESTART_RETRY_AFTER,
+            ),
+            Refused::Tripped(retry_after) => {
+                unavailable("Semantic Scholar is failing; try again later", retry_after)
+            }
+        }
+    }
+}
+
+fn unavailable(reason: &str, retry_after: Duration) -> response::Result<'static> {
+    let body = rocket::serde::json::to_string(&ErrorEnvelope {
+        error: reason.into(),
+    })
+    .map_err(|_err| Status::InternalServerError)?;
+    Response::build()
+        .status(Status::ServiceUnavailable)
+        .header(ContentType::JSON)
+        .raw_header("Retry-After", retry_after.as_secs().max(1).to_string())
+        .sized_body(body.len(), Cursor::new(body))
+        .ok()
+}
+
+/// A rate limit with a bounded queue in front of it.
+pub struct Limiter {
+    bucket: RateLimiter,
+    period: Duration,
+    depth: usize,
+    waiting: AtomicUsize,
+    /// How long requests waited for their turn.
+    pub waits: Histogram,
+    /// How many requests were turned away by a full queue.
+    pub refused: AtomicU64,
+    closed: AtomicBool,
+    closing: Notify,
+}
+
+/// A place in the queue, given up when dropped.
+struct Place<'a>(&'a AtomicUsize);
+
+impl Drop for Place<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Limiter {
+    /// Allow `count` requests per `period`, with up to `depth` waiting.
+    pub fn new(period: Duration, count: usize, depth: usize) -> Self {
+        Self {
+            bucket: bucket(period, count, 0),
+            period,
+            depth,
+            waiting: AtomicUsize::new(0),
+            waits: Histogram::default(),
+            refused: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            closing: Notify::new(),
+        }
+    }
+
+    /// Start from `level` turns, saved `ago` by the last run, and what's
+    /// been refilled since, rather than from none.
+    pub fn resume(mut self, level: usize, ago: Duration) -> Self {
+        let count = self.bucket.max();
+        let refills = ago.as_nanos() / self.period.as_nanos().max(1);
+        let refilled = usize::try_from(refills)
+            .unwrap_or(usize::MAX)
+            .saturating_mul(count);
+        self.bucket = bucket(
+            self.period,
+            count,
+            level.saturating_add(refilled).min(count),
+        );
+        self
+    }
+
+    /// How many turns could be had at once right now, to save over a
+    /// restart.
+    pub fn level(&self) -> usize {
+        self.bucket.balance()
+    }
+
+    /// Let no more requests through, turning away those waiting.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.closing.notify_waiters();
+    }
+
+    fn join(&self) -> Result<Place<'_>, Refused> {
+        let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+        let place = Place(&self.waiting);
+        if ahead >= self.depth {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            return Err(Refused::Full(self.period * ahead as u32));
+        }
+        Ok(place)
+    }
+
+    /// Wait for a turn, unless the queue is full or the limiter closes.
+    pub async fn acquire(&self) -> Result<(), Refused> {
+        let closing = self.closing.notified();
+        let mut closing = std::pin::pin!(closing);
+        // so a close between checking and waiting isn't missed
+        closing.as_mut().enable();
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Refused::ShuttingDown);
+        }
+        let _place = self.join()?;
+        let start = Instant::now();
+        let turn = std::pin::pin!(self.bucket.acquire_one());
+        match future::select(turn, closing).await {
+            Either::Left(((), _closing)) => {
+                self.waits.observe(start.elapsed());
+                Ok(())
+            }
+            Either::Right(((), _turn)) => Err(Refused::ShuttingDown),
+        }
+    }
+}
+
+/// A bucket of `count` turns a `period`, starting with `initial`.
+fn bucket(period: Duration, count: usize, initial: usize) -> RateLimiter {
+    RateLimiter::builder()
+        .initial(initial)
+        .max(count)
+        .refill(count)
+        .interval(period)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_full_queue_refuses_until_a_place_frees() {
+        let limiter = Limiter::new(Duration::from_secs(2), 1, 2);
+        let first = limiter.join().unwrap();
+        let _second = limiter.join().unwrap();
+
+        assert!(matches!(
+            limiter.join(),
+            Err(Refused::Full(retry_after)) if retry_after == Duration::from_secs(4)
+        ));
+        drop(first);
+        assert!(limiter.join().is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn closing_turns_away_waiting_requests() {
+        let limiter = Limiter::new(Duration::from_secs(60), 1, 2);
+        let (waiting, ()) = future::join(limiter.acquire(), async { limiter.close() }).await;
+
+        assert!(matches!(waiting, Err(Refused::ShuttingDown)));
+        assert!(matches!(
+            limiter.acquire().await,
+            Err(Refused::ShuttingDown)
+        ));
+    }
+
+    #[test]
+    fn a_resumed_limit_picks_up_where_it_left_off() {
+        let period = Duration::from_secs(60);
+        let limiter = Limiter::new(period, 10, 2);
+        assert_eq!(limiter.level(), 0);
+        assert_eq!(limiter.resume(3, Duration::from_secs(1)).level(), 3);
+        let limiter = Limiter::new(period, 10, 2);
+        assert_eq!(limiter.resume(3, period).level(), 10);
+    }
+}
@@ -0,0 +1,171 @@
+//! What can be adjusted without recompiling, e.g. for a key with higher
+//! limits.  Settings are read from Rocket's configuration, so they can be
+//! given in `Rocket.toml` or as `ROCKET_*` environment variables alongside
+//! Rocket's own `address` and `port`:
+//!
+//! ```toml
+//! [default]
+//! address = "0.0.0.0"
+//! port = 8000
+//! rate_limit_period_ms = 550
+//! rate_limit_count = 1
+//! state_file = "/var/lib/rate-limiter/state.json"
+//! log_format = "json"
+//! allowed_origins = ["https://example.org"]
+//!
+//! [default.tls]
+//! certs = "/etc/rate-limiter/cert.pem"
+//! key = "/etc/rate-limiter/key.pem"
+//! ```
+//!
+//! With `tls` set, the proxy serves HTTPS itself, with the PEM
+//! certificate chain and private key at those paths, rather than plain
+//! HTTP for a reverse proxy to encrypt.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rocket::serde::Deserialize;
+
+use crate::access_log::LogFormat;
+use crate::queue::DEFAULT_DEPTH;
+use crate::SEMANTIC_SCHOLAR_BASE_URI;
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Settings {
+    /// Where requests are forwarded to.
+    pub upstream: String,
+    /// Each period, this many requests are let through to /paper/batch,
+    /// /paper/search, and /recommendations.
+    pub rate_limit_period_ms: u64,
+    pub rate_limit_count: usize,
+    /// And the same for everything else.
+    pub general_rate_limit_period_ms: u64,
+    pub general_rate_limit_count: usize,
+    /// How many requests may wait on each limit.
+    pub queue_depth: usize,
+    /// Where the users' quotas and the rate limits' levels are kept over
+    /// a restart.
+    pub state_file: Option<PathBuf>,
+    /// After this many 5xx or 429 responses in a row, requests are
+    /// turned away for `breaker_cooldown_ms` before one is let through
+    /// to see whether Semantic Scholar has recovered.
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_ms: u64,
+    /// How access logs are written, `text` or `json`.
+    pub log_format: LogFormat,
+    /// The origins whose pages may call the proxy, `*` for any.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            upstream: SEMANTIC_SCHOLAR_BASE_URI.into(),
+            // the rate limit is 1 req/s.  I'll slow it by a little for safety.
+            rate_limit_period_ms: 1100,
+            rate_limit_count: 1,
+            // and the same for the 10 req/s endpoints
+            general_rate_limit_period_ms: 110,
+            general_rate_limit_count: 1,
+            queue_depth: DEFAULT_DEPTH,
+            state_file: None,
+            breaker_threshold: 5,
+            breaker_cooldown_ms: 30_000,
+            log_format: LogFormat::Text,
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn rate_limit_period(&self) -> Duration {
+        Duration::from_millis(self.rate_limit_period_ms)
+    }
+
+    pub fn general_rate_limit_period(&self) -> Duration {
+        Duration::from_millis(self.general_rate_limit_period_ms)
+    }
+
+    pub fn breaker_cooldown(&self) -> Duration {
+        Duration::from_millis(self.breaker_cooldown_ms)
+    }
+
+    /// What's wrong with the settings, if they'd make a limiter that
+    /// lets nothing through or can't be built.
+    pub fn check(&self) -> Result<(), String> {
+        for (name, zero) in [
+            ("rate_limit_period_ms", self.rate_limit_period_ms == 0),
+            ("rate_limit_count", self.rate_limit_count == 0),
+            (
+                "general_rate_limit_period_ms",
+                self.general_rate_limit_period_ms == 0,
+            ),
+            (
+                "general_rate_limit_count",
+                self.general_rate_limit_count == 0,
+            ),
+        ] {
+            if zero {
+                return Err(format!("{name} should be at least 1"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::providers::{Format, Toml};
+    use rocket::figment::Figment;
+
+    #[test]
+    fn unset_settings_keep_their_defaults() {
+        let settings: Settings = Figment::from(Toml::string(
+            "rate_limit_period_ms = 550\nupstream = \"http://localhost:9000\"\nport = 8000",
+        ))
+        .extract()
+        .unwrap();
+
+        assert_eq!(
+            settings,
+            Settings {
+                upstream: "http://localhost:9000".into(),
+                rate_limit_period_ms: 550,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn tls_is_configured_alongside_the_settings() {
+        let figment = rocket::Config::figment().merge(Toml::string(
+            "rate_limit_count = 2
+[tls]
+certs = \"cert.pem\"
+key = \"key.pem\"",
+        ));
+        let config: rocket::Config = figment.extract().unwrap();
+        assert!(config.tls_enabled());
+        let settings: Settings = figment.extract().unwrap();
+        assert_eq!(settings.rate_limit_count, 2);
+    }
+
+    #[test]
+    fn limits_that_let_nothing_through_are_turned_down() {
+        assert!(Settings::default().check().is_ok());
+        for setting in [
+            "rate_limit_period_ms",
+            "rate_limit_count",
+            "general_rate_limit_period_ms",
+            "general_rate_limit_count",
+        ] {
+            let settings: Settings = Figment::from(Toml::string(&format!("{setting} = 0")))
+                .extract()
+                .unwrap();
+            assert!(settings.check().unwrap_err().contains(setting));
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Tokens for sharing the proxy, each with its own rate limit and daily
+//! quota, so no one user can spend the whole Semantic Scholar allowance.
+//!
+//! They're read from a TOML file with a table per user, e.g.
+//!
+//! ```toml
+//! [alice]
+//! token = "a long random string"
+//! per_second = 0.5
+//! daily_quota = 5000
+//! ```
+//!
+//! Both limits are optional.  A client gives its token as `x-api-key`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use leaky_bucket::RateLimiter;
+use rocket::figment::providers::{Format, Toml};
+use rocket::figment::Figment;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::Deserialize;
+
+use crate::HEADER_API_KEY;
+
+/// The environment variable naming the tokens file.  Without it, the
+/// proxy is open to anyone who can reach it.
+pub const ENV_TOKENS: &str = "TOKENS_FILE";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub enum Error {
+    Config(Box<rocket::figment::Error>),
+    /// A token given to more than one user.
+    Duplicate(String),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Config(err) => std::fmt::Display::fmt(err, f),
+            Error::Duplicate(user) => write!(f, "{user}'s token is also someone else's"),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(err) => Some(err),
+            Error::Duplicate(_user) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct UserConfig {
+    token: String,
+    per_second: Option<f64>,
+    daily_quota: Option<u64>,
+}
+
+struct User {
+    name: String,
+    limiter: Option<RateLimiter>,
+    daily_quota: Option<u64>,
+    /// The day, counted from the epoch, and how many requests were made
+    /// on it.
+    used: Mutex<(u64, u64)>,
+}
+
+pub struct Tokens {
+    users: HashMap<String, User>,
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / SECONDS_PER_DAY
+}
+
+/// A limiter letting through `per_second` requests, if that's a rate
+/// the limiter can keep.
+fn limiter(name: &str, per_second: f64) -> Result<RateLimiter, Error> {
+    let interval = (per_second.is_finite() && per_second > 0.0)
+        .then(|| Duration::from_secs_f64(1.0 / per_second))
+        .filter(|interval| interval.as_millis() > 0);
+    let Some(interval) = interval else {
+        return Err(Error::Config(Box::new(
+            format!("{name}'s per_second should be above 0 and at most 1000, not {per_second}")
+                .into(),
+        )));
+    };
+    Ok(RateLimiter::builder()
+        .initial(1)
+        .max(1)
+        .interval(interval)
+        .build())
+}
+
+impl Tokens {
+    pub fn parse(figment: Figment) -> Result<Self, Error> {
+        let config: HashMap<String, UserConfig> = figment
+            .extract()
+            .map_err(|err| Error::Config(Box::new(err)))?;
+        let mut users = HashMap::new();
+        for (name, config) in config {
+            let limiter = match config.per_second {
+                Some(per_second) => Some(limiter(&name, per_second)?),
+                None => None,
+            };
+            let user = User {
+                name: name.clone(),
+                limiter,
+                daily_quota: config.daily_quota,
+                used: Mutex::new((today(), 0)),
+            };
+            if users.insert(config.token, user).is_some() {
+                return Err(Error::Duplicate(name));
+            }
+        }
+        Ok(Self { users })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(Figment::from(Toml::file_exact(path)))
+    }
+
+    /// How many requests each user, by name, made on which day, to carry
+    /// over a restart.
+    pub fn usage(&self) -> BTreeMap<String, (u64, u64)> {
+        self.users
+            .values()
+            .map(|user| (user.name.clone(), *user.used.lock().unwrap()))
+            .collect()
+    }
+
+    /// Pick up counting from `usage`, ignoring days other than today.
+    pub fn restore(&self, usage: &BTreeMap<String, (u64, u64)>) {
+        let today = today();
+        for user in self.users.values() {
+            if let Some(&(day, count)) = usage.get(&user.name) {
+                if day == today {
+                    *user.used.lock().unwrap() = (day, count);
+                }
+            }
+        }
+    }
+
+    /// Count a request against `token`'s quota, returning who it belongs
+    /// to, or the status to refuse it with.
+    fn spend(&self, token: &str) -> Result<&User, Status> {
+        let user = self.users.get(token).ok_or(Status::Unauthorized)?;
+        let mut used = user.used.lock().unwrap();
+        let today = today();
+        if used.0 != today {
+            *used = (today, 0);
+        }
+        if user.daily_quota.is_some_and(|quota| used.1 >= quota) {
+            eprintln!("{} has used up today's quota", user.name);
+            return Err(Status::TooManyRequests);
+        }
+        used.1 += 1;
+        Ok(user)
+    }
+}
+
+/// Who made a request, kept with it for logging.
+pub struct RequestUser(pub String);
+
+/// A request allowed through by its token, after waiting on the token's
+/// rate limit, or any request if there are no tokens.
+pub struct Authorized;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(tokens) = request.rocket().state::<Option<Tokens>>() else {
+            return Outcome::Success(Authorized);
+        };
+        let Some(tokens) = tokens else {
+            return Outcome::Success(Authorized);
+        };
+        let Some(token) = request.headers().get_one(HEADER_API_KEY) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+        match tokens.spend(token) {
+            Err(status) => Outcome::Error((status, ())),
+            Ok(user) => {
+                request.local_cache(|| RequestUser(user.name.clone()));
+                if let Some(limiter) = &user.limiter {
+                    limiter.acquire_one().await;
+                }
+                Outcome::Success(Authorized)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotas_run_out_per_token() {
+        let tokens = Tokens::parse(Figment::from(Toml::string(
+            r#"
+                [alice]
+                token = "a"
+                daily_quota = 2
+                [bob]
+                token = "b"
+            "#,
+        )))
+        .unwrap();
+
+        assert!(tokens.spend("a").is_ok());
+        assert!(tokens.spend("a").is_ok());
+        assert_eq!(tokens.spend("a").err(), Some(Status::TooManyRequests));
+        assert!(tokens.spend("b").is_ok());
+        assert_eq!(tokens.spend("c").err(), Some(Status::Unauthorized));
+        let restarted = Tokens::parse(Figment::from(Toml::string(
+            "[alice]\ntoken = \"a\"\ndaily_quota = 2",
+        )))
+        .unwrap();
+        restarted.restore(&tokens.usage());
+        assert_eq!(restarted.spend("a").err(), Some(Status::TooManyRequests));
+        assert!(matches!(
+            Tokens::parse(Figment::from(Toml::string(
+                "[alice]\ntoken = \"a\"\n[bob]\ntoken = \"a\""
+            ))),
+            Err(Error::Duplicate(_))
+        ));
+        for per_second in ["0", "-1.5", "inf", "nan", "5000"] {
+            assert!(
+                matches!(
+                    Tokens::parse(Figment::from(Toml::string(&format!(
+                        "[alice]\ntoken = \"a\"\nper_second = {per_second}"
+                    )))),
+                    Err(Error::Config(_))
+                ),
+                "{per_second}"
+            );
+        }
+    }
+}